@@ -1,9 +1,13 @@
 use std::{collections::HashMap, fmt};
 
-use self::{coordinates::CubeCoords, tile::HexMapTile};
+use self::{
+    coordinates::{CubeCoords, CubeCoordsScalar, CUBE_COORDS_CACHED_DIRECTIONS},
+    tile::HexMapTile,
+};
 
 pub mod coordinates;
 pub mod layers;
+pub mod pathfinding;
 pub mod tile;
 
 pub type HexMapStorage = HashMap<CubeCoords, HexMapTile>;
@@ -32,11 +36,68 @@ impl HexMap {
     pub fn from_tiles(tiles: HexMapStorage) -> Self {
         Self { tiles }
     }
+
+    pub fn tile(&self, coords: &CubeCoords) -> Option<&HexMapTile> {
+        self.tiles.get(coords)
+    }
+
+    pub fn tile_mut(&mut self, coords: &CubeCoords) -> Option<&mut HexMapTile> {
+        self.tiles.get_mut(coords)
+    }
+
+    pub fn contains(&self, coords: &CubeCoords) -> bool {
+        self.tiles.contains_key(coords)
+    }
+
+    pub fn tiles(&self) -> impl Iterator<Item = (&CubeCoords, &HexMapTile)> {
+        self.tiles.iter()
+    }
+
+    /// Iterate over the coordinates of the immediate neighbors of a tile which are inside the map.
+    pub fn neighbors_of<'m>(
+        &'m self,
+        coords: &CubeCoords,
+    ) -> impl Iterator<Item = CubeCoords> + 'm {
+        let origin = *coords;
+        CUBE_COORDS_CACHED_DIRECTIONS
+            .into_iter()
+            .map(move |direction| origin + direction)
+            .filter(move |neighbor| self.tiles.contains_key(neighbor))
+    }
+
+    /// Iterate over the coordinates of the tiles inside the map within `radius` of `center`, excluding it.
+    pub fn within_radius<'m>(
+        &'m self,
+        center: &CubeCoords,
+        radius: u16,
+    ) -> impl Iterator<Item = CubeCoords> + 'm {
+        let center = *center;
+        let radius = radius as CubeCoordsScalar;
+        (-radius..=radius)
+            .flat_map(move |q| {
+                (((-radius).max(-q - radius))..=(radius.min(-q + radius)))
+                    .map(move |r| CubeCoords::from_axial_coords(q, r))
+            })
+            .map(move |delta| center + delta)
+            .filter(move |coords| *coords != center && self.tiles.contains_key(coords))
+    }
+
+    /// Is the given tile on land while being adjacent to at least one water tile?
+    pub fn is_coastal(&self, coords: &CubeCoords) -> bool {
+        self.tile(coords)
+            .is_some_and(|tile| !tile.terrain().is_water())
+            && self.neighbors_of(coords).any(|neighbor| {
+                self.tile(&neighbor)
+                    .is_some_and(|tile| tile.terrain().is_water())
+            })
+    }
 }
 
 impl fmt::Debug for HexMap {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        todo!()
+        f.debug_struct("HexMap")
+            .field("tiles_count", &self.tiles.len())
+            .finish()
     }
 }
 
@@ -44,6 +105,8 @@ impl fmt::Debug for HexMap {
 mod tests {
     use crate::hex_map::{
         coordinates::{CubeCoords, CubeCoordsScalar},
+        layers::natural::HexMapTerrain,
+        pathfinding::{find_path, reachable_tiles},
         tile::HexMapTile,
         HexMap, HexMapStorage,
     };
@@ -69,5 +132,45 @@ mod tests {
         HexMap { tiles }
     }
 
-    // TODO:
+    #[test]
+    fn test_hex_map_neighbors() {
+        let map = generate_small_rectangular_hashmap(0, 4, 0, 4);
+        let corner = CubeCoords::from_axial_coords(0, 0);
+        let center = CubeCoords::from_axial_coords(2, 1);
+        assert!(map.contains(&center));
+        assert_eq!(map.neighbors_of(&center).count(), 6);
+        assert!(map.neighbors_of(&corner).count() < 6);
+        assert!(!map.is_coastal(&center));
+        assert_eq!(map.within_radius(&center, 1).count(), 6);
+        assert_eq!(map.within_radius(&center, 2).count(), 18);
+    }
+
+    #[test]
+    fn test_hex_map_pathfinding() {
+        let mut map = generate_small_rectangular_hashmap(0, 4, 0, 4);
+        let start = CubeCoords::from_axial_coords(0, 2);
+        let goal = CubeCoords::from_axial_coords(4, 0);
+        let path = find_path(&map, start, goal, |_, _| Some(1)).unwrap();
+        assert_eq!(path.cost(), 4);
+        assert_eq!(path.tiles().len(), 5);
+
+        // wall of water in the middle column, except for its bottom tile
+        for r in -1..=2 {
+            map.tiles.insert(
+                CubeCoords::from_axial_coords(2, r),
+                HexMapTile::from_terrain(-10, HexMapTerrain::Lake),
+            );
+        }
+        let on_land = |_: &CubeCoords, tile: &HexMapTile| (!tile.terrain().is_water()).then_some(1);
+        let detour = find_path(&map, start, goal, on_land).unwrap();
+        assert!(detour.cost() > path.cost());
+        assert!(detour
+            .tiles()
+            .contains(&CubeCoords::from_axial_coords(2, 3)));
+        assert!(map.is_coastal(&CubeCoords::from_axial_coords(1, 1)));
+
+        let reachable = reachable_tiles(&map, start, 1, on_land);
+        assert_eq!(reachable.get(&start), Some(&0));
+        assert!(reachable.values().all(|cost| *cost <= 1));
+    }
 }
@@ -7,12 +7,29 @@ use std::{
     ops::{Add, Sub},
 };
 
-/// TODO:
+/// Relative direction of an immediate neighbor, for a flat-topped hexagonal grid.
+///
+/// Declared clockwise starting from the north-west neighbor.
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum HexMapDirection {
     NW = 0,
+    N = 1,
+    NE = 2,
+    SE = 3,
+    S = 4,
+    SW = 5,
 }
 
+/// All the directions, in the same order as their declaration in `HexMapDirection`.
+pub const HEX_MAP_DIRECTIONS: [HexMapDirection; 6] = [
+    HexMapDirection::NW,
+    HexMapDirection::N,
+    HexMapDirection::NE,
+    HexMapDirection::SE,
+    HexMapDirection::S,
+    HexMapDirection::SW,
+];
+
 /// TODO: for computation results (distance etc)
 pub type HexMapCoordinatesCommonComputeScalar = f64;
 
@@ -35,7 +52,15 @@ pub trait HexMapCoordinatesSystem:
         *self + direction.into()
     }
 
-    // TODO: neighbors returning an iterator?
+    /// Iterate over the coordinates of the six immediate neighbors, in `HEX_MAP_DIRECTIONS` order.
+    ///
+    /// Map bounds are not checked.
+    fn neighbors(&self) -> impl Iterator<Item = Self> {
+        let origin = *self;
+        HEX_MAP_DIRECTIONS
+            .into_iter()
+            .map(move |direction| origin + direction.into())
+    }
 }
 
 /// Scalar used for storage and computations for `CubeCoords`.
@@ -46,7 +71,7 @@ pub trait HexMapCoordinatesSystem:
 /// It is a `i16`, which should be more than enough, even in computations.
 pub type CubeCoordsScalar = i16;
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct CubeCoords {
     q: CubeCoordsScalar,
     r: CubeCoordsScalar,
@@ -71,16 +96,28 @@ impl CubeCoords {
         assert!(q + r + s == 0, "q + r + s == 0");
         Self { q, r, s }
     }
+
+    pub fn q(&self) -> CubeCoordsScalar {
+        self.q
+    }
+
+    pub fn r(&self) -> CubeCoordsScalar {
+        self.r
+    }
+
+    pub fn s(&self) -> CubeCoordsScalar {
+        self.s
+    }
 }
 
-/// TODO: same order as `HexMapDirection`
+/// Relative coordinates of the neighbors, in the same order as `HexMapDirection`.
 pub const CUBE_COORDS_CACHED_DIRECTIONS: [CubeCoords; 6] = [
-    CubeCoords { q: 1, r: 0, s: -1 },
-    CubeCoords { q: 1, r: -1, s: 0 },
-    CubeCoords { q: 0, r: -1, s: 1 },
     CubeCoords { q: -1, r: 0, s: 1 },
-    CubeCoords { q: -1, r: 1, s: 0 },
+    CubeCoords { q: 0, r: -1, s: 1 },
+    CubeCoords { q: 1, r: -1, s: 0 },
+    CubeCoords { q: 1, r: 0, s: -1 },
     CubeCoords { q: 0, r: 1, s: -1 },
+    CubeCoords { q: -1, r: 1, s: 0 },
 ];
 
 impl From<HexMapDirection> for CubeCoords {
@@ -95,8 +132,8 @@ impl Add for CubeCoords {
     fn add(self, rhs: Self) -> Self::Output {
         Self {
             q: self.q + rhs.q,
-            r: self.q + rhs.q,
-            s: self.q + rhs.q,
+            r: self.r + rhs.r,
+            s: self.s + rhs.s,
         }
     }
 }
@@ -107,8 +144,8 @@ impl Sub for CubeCoords {
     fn sub(self, rhs: Self) -> Self::Output {
         Self {
             q: self.q - rhs.q,
-            r: self.q - rhs.q,
-            s: self.q - rhs.q,
+            r: self.r - rhs.r,
+            s: self.s - rhs.s,
         }
     }
 }
@@ -119,7 +156,7 @@ impl HexMapCoordinatesSystem for CubeCoords {
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum HexMapCoordinates {
     Cube(CubeCoords),
     Offset(),
@@ -128,7 +165,18 @@ pub enum HexMapCoordinates {
 
 impl HexMapCoordinates {
     pub fn distance_from(&self, other: &Self) -> HexMapCoordinatesCommonComputeScalar {
-        todo!()
+        match (self, other) {
+            (Self::Cube(from), Self::Cube(to)) => from.distance_to(*to),
+            _ => todo!(),
+        }
+    }
+
+    /// Get the underlying `CubeCoords`, if stored as such.
+    pub fn as_cube_coords(&self) -> Option<CubeCoords> {
+        match self {
+            Self::Cube(coords) => Some(*coords),
+            _ => None,
+        }
     }
 }
 
@@ -136,7 +184,7 @@ impl HexMapCoordinates {
 mod tests {
     use crate::hex_map::coordinates::HexMapCoordinatesSystem;
 
-    use super::CUBE_COORDS_CACHED_DIRECTIONS;
+    use super::{CubeCoords, CUBE_COORDS_CACHED_DIRECTIONS};
 
     #[test]
     fn test_cube_coords_directions_cache() {
@@ -146,5 +194,18 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_cube_coords_neighbors() {
+        let origin = CubeCoords::from_axial_coords(2, -1);
+        let neighbors: Vec<CubeCoords> = origin.neighbors().collect();
+        assert_eq!(neighbors.len(), 6);
+        for neighbor in neighbors {
+            assert_eq!(origin.distance_to(neighbor), 1f64);
+            assert_eq!(neighbor.q() + neighbor.r() + neighbor.s(), 0);
+        }
+        let far = CubeCoords::from_axial_coords(-1, 2);
+        assert_eq!(origin.distance_to(far), 3f64);
+    }
+
     // TODO: more tests
 }
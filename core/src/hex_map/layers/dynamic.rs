@@ -1,8 +1,8 @@
 //! The dynamic layer of an `HexMap` will involve geographically static, artificially built objects like buildings.
 
-use std::fmt::Debug;
+use std::{any::Any, fmt::Debug};
 
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct HexMapArtificialTileData {
     supply_node: Option<Box<dyn HexMapTileSupplyNode>>,
     infrastructure: Option<Vec<Box<dyn HexMapTileInfrastructure>>>,
@@ -11,16 +11,22 @@ pub struct HexMapArtificialTileData {
 }
 
 /// A built supply node on the tile.
-pub trait HexMapTileSupplyNode: Debug {}
+///
+/// Can be downcast back to its concrete simulation type through `Any`.
+pub trait HexMapTileSupplyNode: Debug + Any {}
 
 /// A built infrastructure on the tile.
-pub trait HexMapTileInfrastructure: Debug {}
+///
+/// Can be downcast back to its concrete simulation type through `Any`.
+pub trait HexMapTileInfrastructure: Debug + Any {}
 
 /// A lived-in settlement on the tile, regardless of size or status (from tiny remote outpost or village, to State capital).
 pub trait HexMapTileSettlement: Debug {}
 
 /// A built building on the tile.
-pub trait HexMapTileBuilding: Debug {}
+///
+/// Can be downcast back to its concrete simulation type through `Any`.
+pub trait HexMapTileBuilding: Debug + Any {}
 
 impl HexMapArtificialTileData {
     pub fn new() -> Self {
@@ -31,4 +37,83 @@ impl HexMapArtificialTileData {
             buildings: None,
         }
     }
+
+    pub fn has_supply_node(&self) -> bool {
+        self.supply_node.is_some()
+    }
+
+    pub fn supply_node(&self) -> Option<&dyn HexMapTileSupplyNode> {
+        self.supply_node.as_deref()
+    }
+
+    pub fn supply_node_mut(&mut self) -> Option<&mut dyn HexMapTileSupplyNode> {
+        self.supply_node.as_deref_mut()
+    }
+
+    pub fn set_supply_node(&mut self, supply_node: Box<dyn HexMapTileSupplyNode>) {
+        self.supply_node = Some(supply_node);
+    }
+
+    pub fn has_infrastructure(&self) -> bool {
+        self.infrastructure
+            .as_ref()
+            .is_some_and(|infrastructure| !infrastructure.is_empty())
+    }
+
+    pub fn has_settlement(&self) -> bool {
+        self.settlement.is_some()
+    }
+
+    pub fn has_buildings(&self) -> bool {
+        self.buildings
+            .as_ref()
+            .is_some_and(|buildings| !buildings.is_empty())
+    }
+
+    pub fn infrastructure(&self) -> impl Iterator<Item = &dyn HexMapTileInfrastructure> {
+        self.infrastructure
+            .iter()
+            .flatten()
+            .map(|infrastructure| infrastructure.as_ref())
+    }
+
+    pub fn add_infrastructure(&mut self, infrastructure: Box<dyn HexMapTileInfrastructure>) {
+        self.infrastructure
+            .get_or_insert_with(Vec::new)
+            .push(infrastructure);
+    }
+
+    pub fn buildings(&self) -> impl Iterator<Item = &dyn HexMapTileBuilding> {
+        self.buildings
+            .iter()
+            .flatten()
+            .map(|building| building.as_ref())
+    }
+
+    pub fn buildings_mut(&mut self) -> impl Iterator<Item = &mut dyn HexMapTileBuilding> {
+        self.buildings
+            .iter_mut()
+            .flatten()
+            .map(|building| building.as_mut())
+    }
+
+    pub fn add_building(&mut self, building: Box<dyn HexMapTileBuilding>) {
+        self.buildings.get_or_insert_with(Vec::new).push(building);
+    }
+
+    /// Remove and return the first building matching the predicate.
+    pub fn remove_building<P>(&mut self, predicate: P) -> Option<Box<dyn HexMapTileBuilding>>
+    where
+        P: Fn(&dyn HexMapTileBuilding) -> bool,
+    {
+        let buildings = self.buildings.as_mut()?;
+        let index = buildings
+            .iter()
+            .position(|building| predicate(building.as_ref()))?;
+        Some(buildings.remove(index))
+    }
+
+    pub fn set_settlement(&mut self, settlement: Box<dyn HexMapTileSettlement>) {
+        self.settlement = Some(settlement);
+    }
 }
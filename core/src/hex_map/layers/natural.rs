@@ -4,8 +4,33 @@
 
 use std::fmt::Debug;
 
+use serde::Deserialize;
+
+/// The dominant natural terrain of a tile.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Deserialize)]
+pub enum HexMapTerrain {
+    /// Open sea or ocean.
+    Sea,
+    /// Inland body of water.
+    Lake,
+    Plains,
+    Forest,
+    Hills,
+    Mountains,
+    Desert,
+    Marsh,
+}
+
+impl HexMapTerrain {
+    /// Is the terrain covered by water (i.e. navigable by ships, and not by land units)?
+    pub fn is_water(&self) -> bool {
+        matches!(self, Self::Sea | Self::Lake)
+    }
+}
+
 #[derive(Debug)]
 pub struct HexMapNaturalTileData {
+    terrain: HexMapTerrain,
     deposits: Option<Vec<Box<dyn HexMapTileDeposit>>>,
 }
 
@@ -13,7 +38,24 @@ pub struct HexMapNaturalTileData {
 pub trait HexMapTileDeposit: Debug {}
 
 impl HexMapNaturalTileData {
-    pub fn new() -> Self {
-        Self { deposits: None }
+    pub fn new(terrain: HexMapTerrain) -> Self {
+        Self {
+            terrain,
+            deposits: None,
+        }
+    }
+
+    pub fn terrain(&self) -> HexMapTerrain {
+        self.terrain
+    }
+
+    pub fn add_deposit(&mut self, deposit: Box<dyn HexMapTileDeposit>) {
+        self.deposits.get_or_insert_with(Vec::new).push(deposit);
+    }
+
+    pub fn has_deposits(&self) -> bool {
+        self.deposits
+            .as_ref()
+            .is_some_and(|deposits| !deposits.is_empty())
     }
 }
@@ -0,0 +1,161 @@
+//! Path-finding on an `HexMap`.
+//!
+//! Callers provide the cost of entering each tile, which allows the same machinery to be used by very
+//! different systems (trade routes, logistics, military movement...).
+//!
+//! See: https://www.redblobgames.com/pathfinding/a-star/introduction.html
+
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap},
+};
+
+use super::{
+    coordinates::{CubeCoords, HexMapCoordinatesSystem},
+    tile::HexMapTile,
+    HexMap,
+};
+
+/// Scalar for the cost of moving along a path.
+pub type HexMapPathCost = u32;
+
+/// A path found on an `HexMap`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HexMapPath {
+    /// Ordered tiles from start to goal, both included.
+    tiles: Vec<CubeCoords>,
+    /// Sum of the costs of entering every tile but the start one.
+    cost: HexMapPathCost,
+}
+
+impl HexMapPath {
+    pub fn tiles(&self) -> &[CubeCoords] {
+        &self.tiles
+    }
+
+    pub fn cost(&self) -> HexMapPathCost {
+        self.cost
+    }
+
+    pub fn start(&self) -> &CubeCoords {
+        &self.tiles[0]
+    }
+
+    pub fn goal(&self) -> &CubeCoords {
+        &self.tiles[self.tiles.len() - 1]
+    }
+}
+
+/// Find the cheapest path between two tiles with the A* algorithm.
+///
+/// `entering_cost` returns the cost of entering a tile, or `None` if it cannot be entered.
+/// It must never return less than `1` for the hex distance heuristic to stay admissible.
+pub fn find_path<F>(
+    map: &HexMap,
+    start: CubeCoords,
+    goal: CubeCoords,
+    entering_cost: F,
+) -> Option<HexMapPath>
+where
+    F: Fn(&CubeCoords, &HexMapTile) -> Option<HexMapPathCost>,
+{
+    find_path_by_steps(map, start, goal, |_, next, tile| entering_cost(next, tile))
+}
+
+/// Find the cheapest path between two tiles with the A* algorithm, when the cost of a step also depends on the tile
+/// it leaves (e.g. to follow a road).
+///
+/// `step_cost` returns the cost of moving from a tile to a neighboring one, or `None` if it cannot be done.
+/// Same contract as `find_path` otherwise.
+pub fn find_path_by_steps<F>(
+    map: &HexMap,
+    start: CubeCoords,
+    goal: CubeCoords,
+    step_cost: F,
+) -> Option<HexMapPath>
+where
+    F: Fn(&CubeCoords, &CubeCoords, &HexMapTile) -> Option<HexMapPathCost>,
+{
+    if !map.contains(&start) || !map.contains(&goal) {
+        return None;
+    }
+
+    let mut frontier = BinaryHeap::new();
+    let mut came_from: HashMap<CubeCoords, CubeCoords> = HashMap::new();
+    let mut cost_so_far: HashMap<CubeCoords, HexMapPathCost> = HashMap::new();
+    frontier.push(Reverse((0, start)));
+    cost_so_far.insert(start, 0);
+
+    while let Some(Reverse((_, current))) = frontier.pop() {
+        if current == goal {
+            break;
+        }
+        let current_cost = cost_so_far[&current];
+        for next in map.neighbors_of(&current) {
+            let Some(step_cost) = map
+                .tile(&next)
+                .and_then(|tile| step_cost(&current, &next, tile))
+            else {
+                continue;
+            };
+            let new_cost = current_cost + step_cost;
+            if cost_so_far.get(&next).is_none_or(|&known| new_cost < known) {
+                cost_so_far.insert(next, new_cost);
+                came_from.insert(next, current);
+                let priority = new_cost + next.distance_to(goal) as HexMapPathCost;
+                frontier.push(Reverse((priority, next)));
+            }
+        }
+    }
+
+    let cost = *cost_so_far.get(&goal)?;
+    let mut tiles = vec![goal];
+    let mut current = goal;
+    while current != start {
+        current = came_from[&current];
+        tiles.push(current);
+    }
+    tiles.reverse();
+    Some(HexMapPath { tiles, cost })
+}
+
+/// Compute the cheapest cost of reaching every tile reachable from `start` within `max_cost`.
+///
+/// Same contract for `entering_cost` as `find_path`.
+pub fn reachable_tiles<F>(
+    map: &HexMap,
+    start: CubeCoords,
+    max_cost: HexMapPathCost,
+    entering_cost: F,
+) -> HashMap<CubeCoords, HexMapPathCost>
+where
+    F: Fn(&CubeCoords, &HexMapTile) -> Option<HexMapPathCost>,
+{
+    let mut cost_so_far = HashMap::new();
+    if !map.contains(&start) {
+        return cost_so_far;
+    }
+
+    let mut frontier = BinaryHeap::new();
+    frontier.push(Reverse((0, start)));
+    cost_so_far.insert(start, 0);
+
+    while let Some(Reverse((current_cost, current))) = frontier.pop() {
+        if current_cost > cost_so_far[&current] {
+            continue;
+        }
+        for next in map.neighbors_of(&current) {
+            let Some(step_cost) = map.tile(&next).and_then(|tile| entering_cost(&next, tile))
+            else {
+                continue;
+            };
+            let new_cost = current_cost + step_cost;
+            if new_cost <= max_cost && cost_so_far.get(&next).is_none_or(|&known| new_cost < known)
+            {
+                cost_so_far.insert(next, new_cost);
+                frontier.push(Reverse((new_cost, next)));
+            }
+        }
+    }
+    cost_so_far
+}
@@ -1,4 +1,7 @@
-use super::layers::{dynamic::HexMapArtificialTileData, natural::HexMapNaturalTileData};
+use super::layers::{
+    dynamic::HexMapArtificialTileData,
+    natural::{HexMapNaturalTileData, HexMapTerrain},
+};
 
 #[derive(Debug)]
 pub struct HexMapTile {
@@ -10,10 +13,38 @@ pub struct HexMapTile {
 
 impl HexMapTile {
     pub fn from_properties(elevation: i16) -> Self {
+        Self::from_terrain(elevation, HexMapTerrain::Plains)
+    }
+
+    pub fn from_terrain(elevation: i16, terrain: HexMapTerrain) -> Self {
         Self {
             elevation,
-            layer_natural: HexMapNaturalTileData::new(),
+            layer_natural: HexMapNaturalTileData::new(terrain),
             layer_artificial: HexMapArtificialTileData::new(),
         }
     }
+
+    pub fn elevation(&self) -> i16 {
+        self.elevation
+    }
+
+    pub fn terrain(&self) -> HexMapTerrain {
+        self.layer_natural.terrain()
+    }
+
+    pub fn layer_natural(&self) -> &HexMapNaturalTileData {
+        &self.layer_natural
+    }
+
+    pub fn layer_natural_mut(&mut self) -> &mut HexMapNaturalTileData {
+        &mut self.layer_natural
+    }
+
+    pub fn layer_artificial(&self) -> &HexMapArtificialTileData {
+        &self.layer_artificial
+    }
+
+    pub fn layer_artificial_mut(&mut self) -> &mut HexMapArtificialTileData {
+        &mut self.layer_artificial
+    }
 }
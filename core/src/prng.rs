@@ -1,9 +1,66 @@
 //! Central Pseudo-Random Number Generator module.
+//!
+//! Every random decision of the simulation must go through `CoreRandom`, so that a game can be replayed from its
+//! seed.
 
-pub struct RandomGenerator {}
+/// Seeded generator of the simulation (SplitMix64).
+///
+/// See: https://prng.di.unimi.it/splitmix64.c
+#[derive(Clone, Debug)]
+pub struct RandomGenerator {
+    state: u64,
+}
+
+impl RandomGenerator {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+}
+
+impl CoreRandom for RandomGenerator {
+    fn random_maxed_value_u32(&mut self, max: u32) -> u32 {
+        assert!(max > 0);
+        ((self.next_u64() >> 32) % max as u64) as u32
+    }
+}
 
-pub struct TestingHarnessRandomGenerator {}
+/// Generator replaying a fixed sequence of values, looping over it, for tests.
+#[derive(Clone, Debug)]
+pub struct TestingHarnessRandomGenerator {
+    values: Vec<u32>,
+    index: usize,
+}
+
+impl TestingHarnessRandomGenerator {
+    pub fn new(values: Vec<u32>) -> Self {
+        assert!(!values.is_empty());
+        Self { values, index: 0 }
+    }
+}
+
+impl CoreRandom for TestingHarnessRandomGenerator {
+    fn random_maxed_value_u32(&mut self, max: u32) -> u32 {
+        assert!(max > 0);
+        let value = self.values[self.index % self.values.len()];
+        self.index += 1;
+        value % max
+    }
+}
 
 pub trait CoreRandom {
-    fn random_maxed_value_u32(&mut self, _max: u32) {}
+    /// Random value from `0` to `max` excluded.
+    fn random_maxed_value_u32(&mut self, max: u32) -> u32;
+
+    /// Random ratio from `0.0` to `1.0`, by steps of a thousandth.
+    fn random_ratio(&mut self) -> f64 {
+        self.random_maxed_value_u32(1001) as f64 / 1000.0
+    }
 }
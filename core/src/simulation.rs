@@ -2,14 +2,26 @@
 //! is in charge of representing and simulating (at world generation or turn-by-turn in game) all the different
 //! entities/concepts and their interactions in Project Unshrouded.
 
+pub mod ai;
 pub mod buildings;
+pub mod construction;
+pub mod diplomacy;
 pub mod economy;
+pub mod fog;
 pub mod ids;
 pub mod infrastructure;
 pub mod logistics;
 pub mod military;
 pub mod nations;
 pub mod people;
+pub mod production;
 pub mod properties;
 pub mod resources;
 pub mod settlements;
+pub mod templates;
+pub mod territory;
+#[cfg(test)]
+mod testing;
+pub mod trade;
+pub mod victory;
+pub mod weather;
@@ -0,0 +1,1004 @@
+//! Computer-controlled nations.
+//!
+//! Every turn, a `NationAi` reads the `WorldView` of its nation, that is what the nation knows of the world through
+//! its `FogOfWar`, and scores by utility every order it could give: construction, recruitment, movement and
+//! diplomacy. Utilities are weighed by the `Personality` of the nation, and the best options are kept within the
+//! limits of its `Difficulty` and of its treasury.
+//!
+//! The AI does not change the world: it returns the same orders a human player would give, to be carried out
+//! through the same functions (see `AiOrder`), which the `AiDriver` does when no player is in the loop. It needs
+//! nothing but the state of the simulation, so that whole games can be played headless for balancing, and the utility
+//! of every considered option is reported to explain its decisions.
+
+pub mod driver;
+pub mod operations;
+pub mod personality;
+
+use std::collections::HashMap;
+
+use crate::hex_map::{
+    coordinates::{CubeCoords, HexMapCoordinatesSystem},
+    HexMap,
+};
+
+use self::{
+    operations::{OperationalModel, OperationalPlan},
+    personality::{Difficulty, Personality, PersonalityModel},
+};
+
+use super::{
+    buildings::Building,
+    construction::{ConstructionQueueKey, ConstructionQueues},
+    diplomacy::{Diplomacy, DiplomaticState, RELATION_MAX},
+    fog::FogOfWar,
+    ids::{SimulationID, WithSimulationID},
+    military::{
+        combat::{unit_attribute, ATTACK_PROPERTY, DEFENSE_PROPERTY},
+        domains::{unit_domain, UnitDomain},
+        movement::MovementOrder,
+        recruitment::{unit_full_health_points, unit_manpower, unit_required_building},
+        Unit, UnitTemplate,
+    },
+    nations::Nation,
+    resources::{Resource, ResourceQuantity},
+    templates::TemplateRegistry,
+};
+
+/// Share of the treasury a nation of neutral caution keeps in reserve.
+pub const TREASURY_RESERVE_RATIO: f64 = 0.2;
+/// Turns a building should take to produce as much as it costs to be worth constructing.
+pub const CONSTRUCTION_PAYBACK_TURNS: f64 = 20.0;
+/// Value, per turn, of a building needed to recruit units.
+pub const RECRUITMENT_BUILDING_VALUE: f64 = 5.0;
+/// Strength a nation of neutral militarism wants to garrison each of its settlements with.
+pub const GARRISON_STRENGTH: f64 = 100.0;
+/// Turns of war after which weariness makes peace appealing to a nation of neutral caution.
+pub const WAR_WEARINESS_TURNS: f64 = 20.0;
+/// Distance, in tiles, within which enemy units threaten a settlement or defend it.
+pub const THREAT_RADIUS: u32 = 4;
+/// Distance, in tiles, at which the utility of moving to a destination is halved.
+pub const MOVEMENT_HORIZON: f64 = 10.0;
+
+/// Map `[0, ∞)` to `[0, 1)`, `1.0` giving `0.5`.
+fn saturate(value: f64) -> f64 {
+    let value = value.max(0.0);
+    value / (1.0 + value)
+}
+
+/// Combat strength of a unit, as in `OrderOfBattleStrength::combat_strength`.
+fn unit_strength(unit: &Unit) -> f64 {
+    unit.health_points() as f64 * unit.combat_effectiveness()
+}
+
+/// Combat strength of a new unit of the template, scaled by its attack and defense.
+fn template_strength(template: &UnitTemplate) -> f64 {
+    let attack = unit_attribute(template, ATTACK_PROPERTY, 10).max(0);
+    let defense = unit_attribute(template, DEFENSE_PROPERTY, 10).max(0);
+    unit_full_health_points(template) as f64 * (attack + defense) as f64 / 20.0
+}
+
+fn total_cost(costs: &HashMap<Resource, ResourceQuantity>) -> f64 {
+    costs.values().map(|quantity| *quantity as f64).sum()
+}
+
+/// A foreign unit seen by a nation.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Sighting {
+    /// Must be the `SimulationID` of a `Nation`.
+    nation: SimulationID,
+    /// Must be the `SimulationID` of a `Unit`.
+    unit: SimulationID,
+    position: CubeCoords,
+    strength: f64,
+}
+
+impl Sighting {
+    pub fn nation(&self) -> &SimulationID {
+        &self.nation
+    }
+
+    pub fn unit(&self) -> &SimulationID {
+        &self.unit
+    }
+
+    pub fn position(&self) -> &CubeCoords {
+        &self.position
+    }
+
+    pub fn strength(&self) -> f64 {
+        self.strength
+    }
+}
+
+/// A foreign settlement known to a nation.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ForeignSettlement {
+    /// Must be the `SimulationID` of a `Nation`.
+    nation: SimulationID,
+    /// Must be the `SimulationID` of a `Settlement`.
+    settlement: SimulationID,
+    position: CubeCoords,
+    capital: bool,
+}
+
+impl ForeignSettlement {
+    pub fn nation(&self) -> &SimulationID {
+        &self.nation
+    }
+
+    pub fn settlement(&self) -> &SimulationID {
+        &self.settlement
+    }
+
+    pub fn position(&self) -> &CubeCoords {
+        &self.position
+    }
+
+    pub fn is_capital(&self) -> bool {
+        self.capital
+    }
+}
+
+/// What a nation knows of the world: its own state, the diplomatic relations, and the foreign units and settlements
+/// its fog of war lets it see.
+pub struct WorldView<'v, 'a> {
+    nation: &'v Nation<'a>,
+    diplomacy: &'v Diplomacy,
+    fog: &'v FogOfWar,
+    map: &'v HexMap,
+    construction: Option<&'v ConstructionQueues<'v>>,
+    /// Foreign units on visible tiles.
+    sightings: Vec<Sighting>,
+    /// Foreign settlements on explored tiles.
+    settlements: Vec<ForeignSettlement>,
+}
+
+impl<'v, 'a> WorldView<'v, 'a> {
+    /// `fog` must be the fog of war of `nation`, up to date.
+    pub fn new(
+        nation: &'v Nation<'a>,
+        nations: &'v [Nation<'a>],
+        diplomacy: &'v Diplomacy,
+        fog: &'v FogOfWar,
+        map: &'v HexMap,
+    ) -> Self {
+        assert!(fog.nation() == nation.id());
+        let mut sightings = vec![];
+        let mut settlements = vec![];
+        for other in nations.iter().filter(|other| other.id() != nation.id()) {
+            for unit in other.order_of_battle().units() {
+                let Some(position) = unit.position().as_cube_coords() else {
+                    continue;
+                };
+                if !unit.is_destroyed() && fog.is_visible(&position) {
+                    sightings.push(Sighting {
+                        nation: other.id().clone(),
+                        unit: unit.id().clone(),
+                        position,
+                        strength: unit_strength(unit),
+                    });
+                }
+            }
+            for settlement in other.settlements() {
+                if fog.is_explored(settlement.position()) {
+                    settlements.push(ForeignSettlement {
+                        nation: other.id().clone(),
+                        settlement: settlement.id().clone(),
+                        position: *settlement.position(),
+                        capital: other
+                            .capital()
+                            .is_some_and(|capital| capital.id() == settlement.id()),
+                    });
+                }
+            }
+        }
+        Self {
+            nation,
+            diplomacy,
+            fog,
+            map,
+            construction: None,
+            sightings,
+            settlements,
+        }
+    }
+
+    /// Let the nation know of its ongoing constructions, so that it does not queue more than one at a time per
+    /// settlement.
+    pub fn with_construction_queues(mut self, construction: &'v ConstructionQueues<'v>) -> Self {
+        self.construction = Some(construction);
+        self
+    }
+
+    pub fn nation(&self) -> &'v Nation<'a> {
+        self.nation
+    }
+
+    pub fn diplomacy(&self) -> &'v Diplomacy {
+        self.diplomacy
+    }
+
+    pub fn fog(&self) -> &'v FogOfWar {
+        self.fog
+    }
+
+    pub fn map(&self) -> &'v HexMap {
+        self.map
+    }
+
+    pub fn sightings(&self) -> &[Sighting] {
+        &self.sightings
+    }
+
+    pub fn foreign_settlements(&self) -> &[ForeignSettlement] {
+        &self.settlements
+    }
+
+    /// Combat strength of all the units of the nation.
+    pub fn own_strength(&self) -> f64 {
+        self.nation
+            .order_of_battle()
+            .units()
+            .filter(|unit| !unit.is_destroyed())
+            .map(unit_strength)
+            .sum()
+    }
+
+    /// Combat strength of the units of a foreign nation the nation can see.
+    pub fn known_strength_of(&self, nation: &SimulationID) -> f64 {
+        self.sightings
+            .iter()
+            .filter(|sighting| &sighting.nation == nation)
+            .map(|sighting| sighting.strength)
+            .sum()
+    }
+
+    /// Visible units of the nations at war with the nation.
+    pub fn hostile_sightings(&self) -> impl Iterator<Item = &Sighting> {
+        self.sightings.iter().filter(|sighting| {
+            self.diplomacy
+                .are_hostile(self.nation.id(), &sighting.nation)
+        })
+    }
+
+    /// Combat strength of the visible hostile units within `THREAT_RADIUS` of a tile.
+    pub fn hostile_strength_near(&self, position: &CubeCoords) -> f64 {
+        self.hostile_sightings()
+            .filter(|sighting| sighting.position.distance_to(*position) <= THREAT_RADIUS as f64)
+            .map(|sighting| sighting.strength)
+            .sum()
+    }
+
+    /// Foreign nations the nation has relations with or has seen, in order of discovery.
+    pub fn known_nations(&self) -> Vec<SimulationID> {
+        let own = self.nation.id();
+        let mut known: Vec<SimulationID> = vec![];
+        let others = self
+            .diplomacy
+            .relations()
+            .iter()
+            .filter(|relation| relation.involves(own))
+            .map(|relation| relation.other(own))
+            .chain(self.sightings.iter().map(|sighting| &sighting.nation))
+            .chain(self.settlements.iter().map(|settlement| &settlement.nation));
+        for other in others {
+            if other != own && !known.contains(other) {
+                known.push(other.clone());
+            }
+        }
+        known
+    }
+}
+
+/// An order of a computer-controlled nation.
+#[derive(Clone, Debug, PartialEq)]
+pub enum AiOrder {
+    /// To queue with `ConstructionQueues::enqueue`, in the queue of the settlement.
+    Construct {
+        /// Must be the `SimulationID` of a `Settlement`.
+        settlement: SimulationID,
+        position: CubeCoords,
+        /// Must be the `SimulationID` of a `BuildingTemplate`.
+        template: SimulationID,
+    },
+    /// To queue with `RecruitmentQueue::enqueue`.
+    Recruit {
+        /// Must be the `SimulationID` of a `Settlement`.
+        settlement: SimulationID,
+        /// Must be the `SimulationID` of an `UnitTemplate`.
+        template: SimulationID,
+        /// Must be the `SimulationID` of an `HqUnit`.
+        headquarters: SimulationID,
+    },
+    /// To resolve with `MovementModel::resolve`.
+    Move(MovementOrder),
+    /// See `Diplomacy::declare_war`.
+    DeclareWar {
+        /// Must be the `SimulationID` of a `Nation`.
+        target: SimulationID,
+    },
+    /// A peace treaty without terms, see `Diplomacy::negotiate_peace`.
+    ProposePeace {
+        /// Must be the `SimulationID` of a `Nation`.
+        target: SimulationID,
+    },
+}
+
+/// An option considered by a nation, and its utility.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScoredOrder {
+    order: AiOrder,
+    /// From `0.0` to `1.0`.
+    utility: f64,
+    chosen: bool,
+}
+
+impl ScoredOrder {
+    pub fn order(&self) -> &AiOrder {
+        &self.order
+    }
+
+    pub fn utility(&self) -> f64 {
+        self.utility
+    }
+
+    pub fn is_chosen(&self) -> bool {
+        self.chosen
+    }
+}
+
+/// Decisions of a nation for a turn.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AiPlan {
+    /// Must be the `SimulationID` of a `Nation`.
+    nation: SimulationID,
+    /// Every considered option, diplomacy first, then spending, then movement, each by decreasing utility.
+    options: Vec<ScoredOrder>,
+    /// Fronts and influence maps, if the nation plans its operations.
+    operations: Option<OperationalPlan>,
+}
+
+impl AiPlan {
+    fn new(nation: SimulationID) -> Self {
+        Self {
+            nation,
+            options: vec![],
+            operations: None,
+        }
+    }
+
+    pub fn nation(&self) -> &SimulationID {
+        &self.nation
+    }
+
+    pub fn options(&self) -> &[ScoredOrder] {
+        &self.options
+    }
+
+    pub fn operations(&self) -> Option<&OperationalPlan> {
+        self.operations.as_ref()
+    }
+
+    /// The orders to carry out, in order.
+    pub fn orders(&self) -> impl Iterator<Item = &AiOrder> {
+        self.options
+            .iter()
+            .filter(|option| option.chosen)
+            .map(|option| &option.order)
+    }
+
+    fn push_sorted(&mut self, mut options: Vec<ScoredOrder>) {
+        options.sort_by(|a, b| b.utility.total_cmp(&a.utility));
+        self.options.extend(options);
+    }
+}
+
+/// Brain of a computer-controlled nation.
+#[derive(Debug)]
+pub struct NationAi {
+    /// Must be the `SimulationID` of a `Nation`.
+    nation: SimulationID,
+    personality: Personality,
+    difficulty: Difficulty,
+    /// Plans the movements by fronts rather than unit by unit.
+    operations: Option<OperationalModel>,
+}
+
+impl NationAi {
+    pub fn new(nation: SimulationID, personality: Personality, difficulty: Difficulty) -> Self {
+        assert!(matches!(nation, SimulationID::Abstract(_)));
+        Self {
+            nation,
+            personality,
+            difficulty,
+            operations: None,
+        }
+    }
+
+    pub fn with_operations(mut self, operations: OperationalModel) -> Self {
+        self.operations = Some(operations);
+        self
+    }
+
+    /// AI with the personality of the leader of the nation.
+    pub fn from_leader(nation: &Nation, model: &PersonalityModel, difficulty: Difficulty) -> Self {
+        Self::new(
+            nation.id().clone(),
+            model.personality_of(nation.leader()),
+            difficulty,
+        )
+    }
+
+    pub fn nation(&self) -> &SimulationID {
+        &self.nation
+    }
+
+    pub fn personality(&self) -> &Personality {
+        &self.personality
+    }
+
+    pub fn difficulty(&self) -> Difficulty {
+        self.difficulty
+    }
+
+    /// Called every turn, for every computer-controlled nation. Choose the orders of the nation.
+    ///
+    /// `templates` are the buildings and units the nation may construct and recruit.
+    pub fn decide(&self, view: &WorldView, templates: &TemplateRegistry) -> AiPlan {
+        assert!(view.nation().id() == &self.nation);
+        let mut plan = AiPlan::new(self.nation.clone());
+        self.plan_diplomacy(view, &mut plan);
+        match &self.operations {
+            Some(model) => {
+                let operations = model.plan(view, &self.personality);
+                self.plan_spending(
+                    view,
+                    templates,
+                    operations.reinforcement_headquarters(view.nation().order_of_battle()),
+                    &mut plan,
+                );
+                let mut options: Vec<ScoredOrder> = operations
+                    .scored_orders()
+                    .iter()
+                    .map(|(order, urgency)| ScoredOrder {
+                        order: order.clone(),
+                        utility: *urgency,
+                        chosen: false,
+                    })
+                    .collect();
+                self.choose_best(&mut options);
+                plan.push_sorted(options);
+                plan.operations = Some(operations);
+            }
+            None => {
+                self.plan_spending(view, templates, None, &mut plan);
+                self.plan_movement(view, &mut plan);
+            }
+        }
+        plan
+    }
+
+    /// Choose the options of greatest utility, within the limits of the difficulty.
+    fn choose_best(&self, options: &mut [ScoredOrder]) {
+        options.sort_by(|a, b| b.utility.total_cmp(&a.utility));
+        for option in options.iter_mut().take(self.difficulty.orders_per_turn()) {
+            option.chosen = option.utility >= self.difficulty.min_utility();
+        }
+    }
+
+    /// At most one diplomatic order per turn: make peace when losing or weary, declare war when clearly stronger.
+    fn plan_diplomacy(&self, view: &WorldView, plan: &mut AiPlan) {
+        let own = self.nation.clone();
+        let diplomacy = view.diplomacy();
+        let own_strength = view.own_strength();
+        let mut options = vec![];
+        for target in view.known_nations() {
+            let their_strength = view.known_strength_of(&target);
+            if diplomacy.are_hostile(&own, &target) {
+                let weariness = diplomacy
+                    .relation(&own, &target)
+                    .map_or(0.0, |relation| relation.turns_in_state() as f64)
+                    / WAR_WEARINESS_TURNS;
+                let odds = (their_strength + 1.0) / (own_strength + 1.0);
+                options.push(ScoredOrder {
+                    order: AiOrder::ProposePeace { target },
+                    utility: saturate(self.personality.caution() * odds * weariness),
+                    chosen: false,
+                });
+            } else if diplomacy.state(&own, &target) == DiplomaticState::Peace
+                && diplomacy.overlord_of(&own).is_none()
+                && diplomacy.treaties_between(&own, &target).next().is_none()
+                && view
+                    .foreign_settlements()
+                    .iter()
+                    .any(|settlement| settlement.nation() == &target)
+            {
+                let odds = own_strength / (their_strength + 1.0);
+                if own_strength <= 0.0 || odds < self.difficulty.war_strength_ratio() {
+                    continue;
+                }
+                let justification = if diplomacy.has_casus_belli(&own, &target) {
+                    1.0
+                } else {
+                    0.5
+                };
+                let hostility = 1.0 - diplomacy.score(&own, &target) as f64 / RELATION_MAX as f64;
+                options.push(ScoredOrder {
+                    order: AiOrder::DeclareWar { target },
+                    utility: saturate(
+                        self.personality.aggression()
+                            * 0.5
+                            * (odds / self.difficulty.war_strength_ratio())
+                            * justification
+                            * hostility,
+                    ),
+                    chosen: false,
+                });
+            }
+        }
+        let best = options
+            .iter_mut()
+            .reduce(|best, option| {
+                if option.utility > best.utility {
+                    option
+                } else {
+                    best
+                }
+            })
+            .filter(|best| best.utility >= self.difficulty.min_utility());
+        if let Some(best) = best {
+            best.chosen = true;
+        }
+        plan.push_sorted(options);
+    }
+
+    /// Construction and recruitment, sharing the treasury left after the reserve of the nation.
+    ///
+    /// Recruits join `reinforcement` if given, otherwise the headquarters closest to their settlement.
+    fn plan_spending(
+        &self,
+        view: &WorldView,
+        templates: &TemplateRegistry,
+        reinforcement: Option<&SimulationID>,
+        plan: &mut AiPlan,
+    ) {
+        let nation = view.nation();
+        let map = view.map();
+        let reserve = (TREASURY_RESERVE_RATIO * self.personality.caution()).min(0.9);
+        let mut budget: HashMap<Resource, f64> = nation
+            .resources()
+            .iter()
+            .map(|(resource, datum)| (*resource, datum.quantity() as f64 * (1.0 - reserve)))
+            .collect();
+
+        // strength the nation wants: garrisons, and enough to face the hostile units it sees
+        let hostile_strength: f64 = view.hostile_sightings().map(|s| s.strength()).sum();
+        let desired_strength =
+            GARRISON_STRENGTH * nation.settlements().len() as f64 * self.personality.militarism()
+                + hostile_strength * self.personality.caution();
+        let mut planned_strength = view.own_strength();
+
+        let mut options: Vec<(ScoredOrder, &HashMap<Resource, ResourceQuantity>, f64)> = vec![];
+        let recruitment_buildings: Vec<SimulationID> = templates
+            .units()
+            .filter_map(unit_required_building)
+            .collect();
+        let efficiency = |template: &UnitTemplate| {
+            template_strength(template) / (total_cost(template.cost()) + 1.0)
+        };
+        let best_efficiency = templates.units().map(&efficiency).fold(0.0, f64::max);
+
+        for settlement in nation.settlements() {
+            let position = *settlement.position();
+            let Some(tile) = map.tile(&position) else {
+                continue;
+            };
+            let built: Vec<&SimulationID> = Building::all_on_tile(tile)
+                .map(|building| building.template().id())
+                .collect();
+
+            let busy = view.construction.is_some_and(|queues| {
+                queues
+                    .queue(&ConstructionQueueKey::Settlement(settlement.id().clone()))
+                    .is_some_and(|queue| queue.orders().next().is_some())
+            });
+            if !busy {
+                for template in templates.buildings() {
+                    if built.contains(&template.id())
+                        || template.validate_placement(tile, &[]).is_err()
+                    {
+                        continue;
+                    }
+                    let production = total_cost(template.production())
+                        + template
+                            .recipes()
+                            .iter()
+                            .map(|recipe| {
+                                total_cost(recipe.outputs()) - total_cost(recipe.inputs())
+                            })
+                            .sum::<f64>()
+                            .max(0.0);
+                    let unlocks = if recruitment_buildings.contains(template.id()) {
+                        RECRUITMENT_BUILDING_VALUE * self.personality.militarism()
+                    } else {
+                        0.0
+                    };
+                    let value =
+                        (production - total_cost(template.maintenance_costs())).max(0.0) + unlocks;
+                    let utility = saturate(
+                        self.personality.development() * value * CONSTRUCTION_PAYBACK_TURNS
+                            / (total_cost(template.cost()) + 1.0),
+                    );
+                    options.push((
+                        ScoredOrder {
+                            order: AiOrder::Construct {
+                                settlement: settlement.id().clone(),
+                                position,
+                                template: template.id().clone(),
+                            },
+                            utility,
+                            chosen: false,
+                        },
+                        template.cost(),
+                        0.0,
+                    ));
+                }
+            }
+
+            let closest = || {
+                nation
+                    .order_of_battle()
+                    .headquarters()
+                    .iter()
+                    .filter(|hq| hq.command_load() < hq.command_capacity())
+                    .filter_map(|hq| Some((hq, hq.position().as_cube_coords()?)))
+                    .min_by(|(_, a), (_, b)| {
+                        a.distance_to(position).total_cmp(&b.distance_to(position))
+                    })
+                    .map(|(hq, _)| hq.id().clone())
+            };
+            let Some(headquarters) = reinforcement.cloned().or_else(closest) else {
+                continue;
+            };
+            if desired_strength <= planned_strength || best_efficiency <= 0.0 {
+                continue;
+            }
+            let deficit = (desired_strength - planned_strength) / desired_strength;
+            for template in templates.units() {
+                let missing_building = unit_required_building(template)
+                    .is_some_and(|required| !built.contains(&&required));
+                if missing_building || settlement.available_manpower() < unit_manpower(template) {
+                    continue;
+                }
+                let utility = saturate(self.personality.militarism() * 2.0 * deficit)
+                    * efficiency(template)
+                    / best_efficiency;
+                options.push((
+                    ScoredOrder {
+                        order: AiOrder::Recruit {
+                            settlement: settlement.id().clone(),
+                            template: template.id().clone(),
+                            headquarters: headquarters.clone(),
+                        },
+                        utility,
+                        chosen: false,
+                    },
+                    template.cost(),
+                    template_strength(template),
+                ));
+            }
+        }
+
+        // greedily take the best options: one per settlement, within the budget and the wanted strength
+        options.sort_by(|(a, _, _), (b, _, _)| b.utility.total_cmp(&a.utility));
+        let mut served: Vec<SimulationID> = vec![];
+        let mut taken = 0;
+        for (option, cost, strength) in &mut options {
+            if taken >= self.difficulty.orders_per_turn()
+                || option.utility < self.difficulty.min_utility()
+            {
+                break;
+            }
+            let settlement = match &option.order {
+                AiOrder::Construct { settlement, .. } | AiOrder::Recruit { settlement, .. } => {
+                    settlement
+                }
+                _ => unreachable!(),
+            };
+            let recruit = matches!(option.order, AiOrder::Recruit { .. });
+            let affordable = cost.iter().all(|(resource, quantity)| {
+                budget.get(resource).copied().unwrap_or(0.0) >= *quantity as f64
+            });
+            if served.contains(settlement)
+                || !affordable
+                || (recruit && planned_strength >= desired_strength)
+            {
+                continue;
+            }
+            for (resource, quantity) in cost.iter() {
+                *budget.entry(*resource).or_insert(0.0) -= *quantity as f64;
+            }
+            served.push(settlement.clone());
+            planned_strength += *strength;
+            option.chosen = true;
+            taken += 1;
+        }
+        plan.push_sorted(options.into_iter().map(|(option, _, _)| option).collect());
+    }
+
+    /// Land units march on the hostile settlements they can take, or back to the settlements under threat.
+    ///
+    /// Naval and air units hold their bases.
+    fn plan_movement(&self, view: &WorldView, plan: &mut AiPlan) {
+        let nation = view.nation();
+        let own = nation.id();
+        let proximity = |from: CubeCoords, to: CubeCoords| {
+            1.0 / (1.0 + from.distance_to(to) / MOVEMENT_HORIZON)
+        };
+        let targets: Vec<(&ForeignSettlement, f64)> = view
+            .foreign_settlements()
+            .iter()
+            .filter(|settlement| view.diplomacy().are_hostile(own, settlement.nation()))
+            .map(|settlement| {
+                (
+                    settlement,
+                    view.hostile_strength_near(settlement.position()),
+                )
+            })
+            .collect();
+        let threats: Vec<(CubeCoords, f64)> = nation
+            .settlements()
+            .iter()
+            .map(|settlement| {
+                (
+                    *settlement.position(),
+                    view.hostile_strength_near(settlement.position()),
+                )
+            })
+            .filter(|(_, threat)| *threat > 0.0)
+            .collect();
+
+        let mut options = vec![];
+        for unit in nation.order_of_battle().units() {
+            let Some(position) = unit.position().as_cube_coords() else {
+                continue;
+            };
+            if unit.is_destroyed()
+                || unit.is_routed()
+                || unit_domain(unit.template()) != UnitDomain::Land
+            {
+                continue;
+            }
+            let strength = unit_strength(unit);
+            let attacks = targets.iter().map(|(settlement, defenders)| {
+                let capital = if settlement.is_capital() { 1.5 } else { 1.0 };
+                let odds = strength / (strength + defenders + 1.0);
+                (
+                    *settlement.position(),
+                    self.personality.aggression()
+                        * capital
+                        * odds
+                        * proximity(position, *settlement.position()),
+                )
+            });
+            let defenses = threats.iter().map(|(settlement, threat)| {
+                (
+                    *settlement,
+                    self.personality.caution()
+                        * (threat / (threat + strength + 1.0))
+                        * proximity(position, *settlement),
+                )
+            });
+            let mut unit_options: Vec<ScoredOrder> = attacks
+                .chain(defenses)
+                .filter(|(destination, _)| *destination != position)
+                .map(|(destination, value)| ScoredOrder {
+                    order: AiOrder::Move(MovementOrder::new(unit.id().clone(), destination)),
+                    utility: value.clamp(0.0, 1.0),
+                    chosen: false,
+                })
+                .collect();
+            let best = unit_options
+                .iter_mut()
+                .reduce(|best, option| {
+                    if option.utility > best.utility {
+                        option
+                    } else {
+                        best
+                    }
+                })
+                .filter(|best| best.utility >= self.difficulty.min_utility());
+            if let Some(best) = best {
+                best.chosen = true;
+            }
+            options.extend(unit_options);
+        }
+        plan.push_sorted(options);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::{
+        hex_map::{
+            coordinates::{CubeCoords, HexMapCoordinates},
+            layers::natural::HexMapTerrain,
+            tile::HexMapTile,
+            HexMap, HexMapStorage,
+        },
+        simulation::{
+            buildings::{Building, BuildingTemplate},
+            diplomacy::Diplomacy,
+            fog::FogOfWar,
+            ids::{SimulationID, WithSimulationID},
+            military::{HqUnit, Unit, UnitTemplate},
+            nations::Nation,
+            people::leaders::{Trait, TraitPersonality},
+            properties::SimulationPropertyStorage,
+            resources::{Resource, ResourceDataStore},
+            settlements::Settlement,
+            templates::{TemplateOrigin, TemplateRegistry},
+            territory::Territory,
+            testing::build_mock_leader,
+        },
+    };
+
+    use super::{
+        operations::OperationalModel,
+        personality::{Difficulty, PersonalityModel},
+        AiOrder, NationAi, WorldView,
+    };
+
+    fn build_mock_trait(id: &str) -> Trait {
+        Trait::TraitPersonality(TraitPersonality::new(SimulationID::new_abstract_id(id)))
+    }
+
+    #[test]
+    fn test_nation_ai_decide() {
+        let mut tiles = HexMapStorage::new();
+        for q in 0..=8 {
+            tiles.insert(
+                CubeCoords::from_axial_coords(q, 0),
+                HexMapTile::from_terrain(0, HexMapTerrain::Plains),
+            );
+        }
+        let mut map = HexMap::from_tiles(tiles);
+        let tile = CubeCoords::from_axial_coords;
+        let mut registry = TemplateRegistry::new();
+        let farm = BuildingTemplate::new(
+            SimulationID::new_abstract_id("farm"),
+            "agriculture".into(),
+            HashMap::from([(Resource::Credits, 50)]),
+            HashMap::new(),
+            HashMap::from([(Resource::Food, 10)]),
+        );
+        registry
+            .register_building(farm, TemplateOrigin::new("test".into(), None))
+            .unwrap();
+        let infantry = UnitTemplate::new(
+            SimulationID::new_abstract_id("infantry"),
+            "infantry".into(),
+            HashMap::from([(Resource::Credits, 100)]),
+            HashMap::new(),
+            SimulationPropertyStorage::new(),
+        );
+        registry
+            .register_unit(infantry, TemplateOrigin::new("test".into(), None))
+            .unwrap();
+        let infantry = registry
+            .unit(&SimulationID::new_abstract_id("infantry"))
+            .unwrap();
+
+        let capital_a = Settlement::new(SimulationID::new_map_entity_id(1), "A".into(), tile(0, 0));
+        let capital_b = Settlement::new(SimulationID::new_map_entity_id(2), "B".into(), tile(6, 0));
+        let mut nations = vec![
+            Nation::new(
+                SimulationID::new_abstract_id("a"),
+                "a".into(),
+                build_mock_leader(1, vec![build_mock_trait("aggressive")]),
+                &capital_a,
+            ),
+            Nation::new(
+                SimulationID::new_abstract_id("b"),
+                "b".into(),
+                build_mock_leader(2, vec![]),
+                &capital_b,
+            ),
+        ];
+        let [a, b] = ["a", "b"].map(SimulationID::new_abstract_id);
+        let hq = SimulationID::new_map_entity_id(10);
+        nations[0]
+            .add_headquarters(HqUnit::new(
+                hq.clone(),
+                HexMapCoordinates::Cube(tile(0, 0)),
+                None,
+                SimulationPropertyStorage::new(),
+            ))
+            .unwrap();
+        for id in 11..=13 {
+            nations[0]
+                .order_of_battle_mut()
+                .attach_unit(
+                    &hq,
+                    Unit::new(
+                        SimulationID::new_map_entity_id(id),
+                        HexMapCoordinates::Cube(tile(4, 0)),
+                        infantry,
+                        100,
+                    ),
+                )
+                .unwrap();
+        }
+        nations[0]
+            .resources_mut()
+            .replenish(Resource::Credits, 1000);
+        let mut diplomacy = Diplomacy::new();
+        let ai = NationAi::from_leader(
+            &nations[0],
+            &PersonalityModel::default(),
+            Difficulty::Normal,
+        );
+        assert!(ai.personality().aggression() > 1.0);
+
+        // at peace, b is discovered: three units against none, war is worth it
+        let mut fog = FogOfWar::new(a.clone());
+        fog.update(&nations[0], &map, &Territory::new());
+        let view = WorldView::new(&nations[0], &nations, &diplomacy, &fog, &map);
+        assert_eq!(view.foreign_settlements().len(), 1);
+        let plan = ai.decide(&view, &registry);
+        let orders: Vec<_> = plan.orders().collect();
+        assert_eq!(orders[0], &AiOrder::DeclareWar { target: b.clone() });
+        assert!(orders.contains(&&AiOrder::Construct {
+            settlement: capital_a.id().clone(),
+            position: tile(0, 0),
+            template: SimulationID::new_abstract_id("farm"),
+        }));
+        assert!(plan
+            .options()
+            .iter()
+            .all(|option| (0.0..=1.0).contains(&option.utility())));
+
+        // at war: the units march on the capital of b, the farm is no longer wanted once built
+        diplomacy.declare_war(&a, &b).unwrap();
+        map.tile_mut(&tile(0, 0))
+            .unwrap()
+            .layer_artificial_mut()
+            .add_building(Box::new(Building::new(
+                SimulationID::new_map_entity_id(20),
+                Box::leak(Box::new(BuildingTemplate::new(
+                    SimulationID::new_abstract_id("farm"),
+                    "agriculture".into(),
+                    HashMap::new(),
+                    HashMap::new(),
+                    HashMap::new(),
+                ))),
+                100,
+            )));
+        let view = WorldView::new(&nations[0], &nations, &diplomacy, &fog, &map);
+        let plan = ai.decide(&view, &registry);
+        let moves = plan
+            .orders()
+            .filter(
+                |order| matches!(order, AiOrder::Move(order) if order.destination() == &tile(6, 0)),
+            )
+            .count();
+        assert_eq!(moves, 3);
+        assert!(!plan.orders().any(|order| matches!(
+            order,
+            AiOrder::Construct { .. } | AiOrder::DeclareWar { .. }
+        )));
+
+        // planning by fronts, the movements are chosen within the same limits
+        let ai = ai.with_operations(OperationalModel::default());
+        let plan = ai.decide(&view, &registry);
+        let operations = plan.operations().unwrap();
+        assert_eq!(operations.scored_orders().len(), 3);
+        let moves = plan
+            .orders()
+            .filter(|order| matches!(order, AiOrder::Move(_)))
+            .count();
+        assert_eq!(moves, Difficulty::Normal.orders_per_turn());
+    }
+}
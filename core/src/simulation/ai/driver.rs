@@ -0,0 +1,423 @@
+//! Headless play of the computer-controlled nations.
+//!
+//! The `AiDriver` carries out the orders of an `AiPlan` through the same functions a human player would use, so that
+//! whole games can be simulated without a player in the loop.
+
+use crate::{
+    hex_map::HexMap,
+    simulation::{
+        construction::{
+            ConstructionError, ConstructionOrder, ConstructionPayment, ConstructionQueueKey,
+            ConstructionQueues,
+        },
+        diplomacy::{Diplomacy, DiplomacyError, DiplomaticEvent, Treaty, TreatyKind},
+        ids::{SimulationID, WithSimulationID},
+        infrastructure::network::InfrastructureNetwork,
+        military::{
+            domains::MilitaryBases,
+            movement::{MovementModel, MovementReport},
+            recruitment::RecruitmentError,
+        },
+        nations::Nation,
+        templates::TemplateRegistry,
+    },
+};
+
+use super::{AiOrder, AiPlan};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AiOrderError {
+    /// The nation of the plan is not among the given nations.
+    UnknownNation(SimulationID),
+    /// The settlement does not belong to the nation.
+    UnknownSettlement(SimulationID),
+    /// No building or unit template has the given id.
+    UnknownTemplate(SimulationID),
+    Construction(ConstructionError),
+    Recruitment(RecruitmentError),
+    Diplomacy(DiplomacyError),
+}
+
+/// What came of the orders of an `AiPlan`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AiReport {
+    /// Every chosen order, in the order of the plan, with its result.
+    outcomes: Vec<(AiOrder, Result<(), AiOrderError>)>,
+    diplomatic_events: Vec<DiplomaticEvent>,
+    movement: MovementReport,
+}
+
+impl AiReport {
+    pub fn outcomes(&self) -> &[(AiOrder, Result<(), AiOrderError>)] {
+        &self.outcomes
+    }
+
+    /// The orders which could not be carried out.
+    pub fn failures(&self) -> impl Iterator<Item = (&AiOrder, &AiOrderError)> {
+        self.outcomes
+            .iter()
+            .filter_map(|(order, result)| Some((order, result.as_ref().err()?)))
+    }
+
+    pub fn diplomatic_events(&self) -> &[DiplomaticEvent] {
+        &self.diplomatic_events
+    }
+
+    pub fn movement(&self) -> &MovementReport {
+        &self.movement
+    }
+}
+
+/// Carries out the plans of the computer-controlled nations.
+#[derive(Debug)]
+pub struct AiDriver<'t> {
+    templates: &'t TemplateRegistry,
+    movement: MovementModel,
+    payment: ConstructionPayment,
+    /// Number of the next `SimulationID::MapEntityID` given to the construction orders and the recruited units.
+    next_map_entity_id: u32,
+    /// Number of the next `SimulationID::EntityID` given to the treaties.
+    next_entity_id: u32,
+}
+
+impl<'t> AiDriver<'t> {
+    /// The ids must not be used by any entity yet, nor by the ones to be created by other means.
+    pub fn new(
+        templates: &'t TemplateRegistry,
+        next_map_entity_id: u32,
+        next_entity_id: u32,
+    ) -> Self {
+        Self {
+            templates,
+            movement: MovementModel::default(),
+            payment: ConstructionPayment::Upfront,
+            next_map_entity_id,
+            next_entity_id,
+        }
+    }
+
+    pub fn with_movement(mut self, movement: MovementModel) -> Self {
+        self.movement = movement;
+        self
+    }
+
+    pub fn with_payment(mut self, payment: ConstructionPayment) -> Self {
+        self.payment = payment;
+        self
+    }
+
+    pub fn next_map_entity_id(&self) -> u32 {
+        self.next_map_entity_id
+    }
+
+    pub fn next_entity_id(&self) -> u32 {
+        self.next_entity_id
+    }
+
+    fn take_map_entity_id(&mut self) -> SimulationID {
+        self.next_map_entity_id += 1;
+        SimulationID::new_map_entity_id(self.next_map_entity_id - 1)
+    }
+
+    fn take_entity_id(&mut self) -> SimulationID {
+        self.next_entity_id += 1;
+        SimulationID::new_entity_id(self.next_entity_id - 1)
+    }
+
+    /// Called every turn, for every computer-controlled nation, after `NationAi::decide`. Carry out the chosen orders
+    /// of the plan, in order, the movements last so that they see the wars declared this turn.
+    pub fn apply<'a>(
+        &mut self,
+        plan: &AiPlan,
+        nations: &mut [Nation<'a>],
+        diplomacy: &mut Diplomacy,
+        construction: &mut ConstructionQueues<'t>,
+        map: &HexMap,
+        network: &InfrastructureNetwork,
+    ) -> AiReport
+    where
+        't: 'a,
+    {
+        let mut report = AiReport::default();
+        let own = plan.nation();
+        let Some(index) = nations.iter().position(|nation| nation.id() == own) else {
+            report.outcomes = plan
+                .orders()
+                .map(|order| (order.clone(), Err(AiOrderError::UnknownNation(own.clone()))))
+                .collect();
+            return report;
+        };
+
+        let mut movements = vec![];
+        for order in plan.orders() {
+            let result = match order {
+                AiOrder::Construct {
+                    settlement,
+                    position,
+                    template,
+                } => {
+                    let nation = &mut nations[index];
+                    let owned = nation
+                        .settlements()
+                        .iter()
+                        .any(|owned| owned.id() == settlement);
+                    match self.templates.building(template) {
+                        _ if !owned => Err(AiOrderError::UnknownSettlement(settlement.clone())),
+                        Some(template) => {
+                            let order = ConstructionOrder::new(
+                                self.take_map_entity_id(),
+                                template,
+                                *position,
+                                self.payment,
+                            )
+                            .with_owner(own.clone());
+                            construction
+                                .enqueue(
+                                    map,
+                                    ConstructionQueueKey::Settlement(settlement.clone()),
+                                    order,
+                                    nation.resources_mut(),
+                                )
+                                .map_err(AiOrderError::Construction)
+                        }
+                        None => Err(AiOrderError::UnknownTemplate(template.clone())),
+                    }
+                }
+                AiOrder::Recruit {
+                    settlement,
+                    template,
+                    headquarters,
+                } => match self.templates.unit(template) {
+                    Some(template) => nations[index]
+                        .recruit(
+                            self.take_map_entity_id(),
+                            template,
+                            settlement,
+                            headquarters.clone(),
+                            map,
+                        )
+                        .map_err(AiOrderError::Recruitment),
+                    None => Err(AiOrderError::UnknownTemplate(template.clone())),
+                },
+                AiOrder::Move(movement) => {
+                    movements.push(movement.clone());
+                    Ok(())
+                }
+                AiOrder::DeclareWar { target } => diplomacy
+                    .declare_war(own, target)
+                    .map(|events| report.diplomatic_events.extend(events))
+                    .map_err(AiOrderError::Diplomacy),
+                AiOrder::ProposePeace { target } => {
+                    let treaty = Treaty::new(
+                        self.take_entity_id(),
+                        TreatyKind::Peace,
+                        own.clone(),
+                        target.clone(),
+                    );
+                    diplomacy
+                        .negotiate_peace(treaty, nations)
+                        .map(|events| report.diplomatic_events.extend(events))
+                        .map_err(AiOrderError::Diplomacy)
+                }
+            };
+            report.outcomes.push((order.clone(), result));
+        }
+
+        let enemies = diplomacy.enemy_positions(own, nations);
+        let bases = MilitaryBases::from_map(map, own);
+        report.movement = self.movement.resolve(
+            &movements,
+            nations[index].order_of_battle_mut(),
+            map,
+            network,
+            &bases,
+            &enemies,
+        );
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::{
+        hex_map::{
+            coordinates::{CubeCoords, HexMapCoordinates},
+            layers::natural::HexMapTerrain,
+            tile::HexMapTile,
+            HexMap, HexMapStorage,
+        },
+        simulation::{
+            buildings::BuildingTemplate,
+            construction::{ConstructionQueueKey, ConstructionQueues},
+            diplomacy::Diplomacy,
+            ids::{SimulationID, WithSimulationID},
+            infrastructure::network::InfrastructureNetwork,
+            military::{movement::MovementOrder, HqUnit, Unit, UnitTemplate},
+            nations::Nation,
+            properties::SimulationPropertyStorage,
+            resources::{Resource, ResourceDataStore},
+            settlements::Settlement,
+            templates::{TemplateOrigin, TemplateRegistry},
+            testing::build_mock_leader,
+        },
+    };
+
+    use super::{
+        super::{AiOrder, AiPlan, ScoredOrder},
+        AiDriver, AiOrderError,
+    };
+
+    #[test]
+    fn test_ai_driver_apply() {
+        let mut tiles = HexMapStorage::new();
+        for q in 0..=8 {
+            tiles.insert(
+                CubeCoords::from_axial_coords(q, 0),
+                HexMapTile::from_terrain(0, HexMapTerrain::Plains),
+            );
+        }
+        let map = HexMap::from_tiles(tiles);
+        let tile = CubeCoords::from_axial_coords;
+        let mut registry = TemplateRegistry::new();
+        let farm = BuildingTemplate::new(
+            SimulationID::new_abstract_id("farm"),
+            "agriculture".into(),
+            HashMap::from([(Resource::Credits, 50)]),
+            HashMap::new(),
+            HashMap::from([(Resource::Food, 10)]),
+        );
+        registry
+            .register_building(farm, TemplateOrigin::new("test".into(), None))
+            .unwrap();
+        let infantry = UnitTemplate::new(
+            SimulationID::new_abstract_id("infantry"),
+            "infantry".into(),
+            HashMap::new(),
+            HashMap::new(),
+            SimulationPropertyStorage::new(),
+        );
+
+        let capital_a = Settlement::new(SimulationID::new_map_entity_id(1), "A".into(), tile(0, 0));
+        let capital_b = Settlement::new(SimulationID::new_map_entity_id(2), "B".into(), tile(6, 0));
+        let mut nations = vec![
+            Nation::new(
+                SimulationID::new_abstract_id("a"),
+                "a".into(),
+                build_mock_leader(1, vec![]),
+                &capital_a,
+            ),
+            Nation::new(
+                SimulationID::new_abstract_id("b"),
+                "b".into(),
+                build_mock_leader(2, vec![]),
+                &capital_b,
+            ),
+        ];
+        let [a, b] = ["a", "b"].map(SimulationID::new_abstract_id);
+        let hq = SimulationID::new_map_entity_id(10);
+        nations[0]
+            .add_headquarters(HqUnit::new(
+                hq.clone(),
+                HexMapCoordinates::Cube(tile(0, 0)),
+                None,
+                SimulationPropertyStorage::new(),
+            ))
+            .unwrap();
+        nations[0]
+            .order_of_battle_mut()
+            .attach_unit(
+                &hq,
+                Unit::new(
+                    SimulationID::new_map_entity_id(11),
+                    HexMapCoordinates::Cube(tile(1, 0)),
+                    &infantry,
+                    100,
+                ),
+            )
+            .unwrap();
+        nations[0]
+            .resources_mut()
+            .replenish(Resource::Credits, 1000);
+
+        let orders = [
+            AiOrder::DeclareWar { target: b.clone() },
+            AiOrder::Construct {
+                settlement: capital_a.id().clone(),
+                position: tile(0, 0),
+                template: SimulationID::new_abstract_id("farm"),
+            },
+            AiOrder::Construct {
+                settlement: capital_b.id().clone(),
+                position: tile(6, 0),
+                template: SimulationID::new_abstract_id("farm"),
+            },
+            AiOrder::Recruit {
+                settlement: capital_a.id().clone(),
+                template: SimulationID::new_abstract_id("cavalry"),
+                headquarters: SimulationID::new_map_entity_id(10),
+            },
+            AiOrder::Move(MovementOrder::new(
+                SimulationID::new_map_entity_id(11),
+                tile(2, 0),
+            )),
+        ];
+        let mut plan = AiPlan::new(a.clone());
+        plan.options = orders
+            .iter()
+            .map(|order| ScoredOrder {
+                order: order.clone(),
+                utility: 1.0,
+                chosen: true,
+            })
+            .collect();
+
+        let mut driver = AiDriver::new(&registry, 100, 1);
+        let mut diplomacy = Diplomacy::new();
+        let mut construction = ConstructionQueues::new();
+        let report = driver.apply(
+            &plan,
+            &mut nations,
+            &mut diplomacy,
+            &mut construction,
+            &map,
+            &InfrastructureNetwork::new(),
+        );
+        assert_eq!(report.outcomes().len(), orders.len());
+        assert!(diplomacy.are_hostile(&a, &b));
+        assert!(!report.diplomatic_events().is_empty());
+        let queue = construction
+            .queue(&ConstructionQueueKey::Settlement(capital_a.id().clone()))
+            .unwrap();
+        assert_eq!(queue.orders().count(), 1);
+        assert_eq!(queue.orders().next().unwrap().owner(), Some(&a));
+        assert_eq!(nations[0].resources().quantity_of(Resource::Credits), 950);
+        let failures: Vec<_> = report.failures().map(|(_, error)| error.clone()).collect();
+        assert_eq!(
+            failures,
+            vec![
+                AiOrderError::UnknownSettlement(capital_b.id().clone()),
+                AiOrderError::UnknownTemplate(SimulationID::new_abstract_id("cavalry")),
+            ]
+        );
+        let unit_move = report
+            .movement()
+            .move_of(&SimulationID::new_map_entity_id(11))
+            .unwrap();
+        assert_eq!(unit_move.to(), &tile(2, 0));
+        assert_eq!(driver.next_map_entity_id(), 101);
+        assert_eq!(driver.next_entity_id(), 1);
+
+        // the plan of a nation which is not in the game is not carried out
+        let report = driver.apply(
+            &AiPlan::new(SimulationID::new_abstract_id("c")),
+            &mut nations,
+            &mut diplomacy,
+            &mut construction,
+            &map,
+            &InfrastructureNetwork::new(),
+        );
+        assert!(report.outcomes().is_empty());
+    }
+}
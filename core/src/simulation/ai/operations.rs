@@ -0,0 +1,714 @@
+//! Operational layer of the AI: fronts, offensives and defensive lines.
+//!
+//! The strength of the units of a nation, and of the hostile units it sees, spreads over the `HexMap` as influence
+//! maps, fading with distance. Where the influence of the nation meets the one of an enemy lies a front, one per
+//! enemy nation. Each headquarters, with its units, is assigned to the nearest front, or kept in reserve when no
+//! front is in reach. A front where the nation dominates goes on the offensive toward the hostile settlement it can
+//! best take, otherwise it holds a defensive line on its most contested tiles. Reserves and new recruits reinforce
+//! the front in the worst shape.
+
+use std::{collections::HashMap, fmt::Write};
+
+use crate::{
+    hex_map::{
+        coordinates::{CubeCoords, HexMapCoordinatesSystem},
+        HexMap,
+    },
+    simulation::{
+        ids::{SimulationID, WithSimulationID},
+        military::{
+            domains::{unit_domain, UnitDomain},
+            movement::MovementOrder,
+            order_of_battle::OrderOfBattle,
+            HqUnit,
+        },
+    },
+};
+
+use super::{personality::Personality, unit_strength, AiOrder, WorldView};
+
+/// Influence of the units of a nation, or of the hostile units it sees, over the tiles of the map.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct InfluenceMap {
+    values: HashMap<CubeCoords, f64>,
+}
+
+impl InfluenceMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Influence over a tile, `0.0` if none.
+    pub fn get(&self, position: &CubeCoords) -> f64 {
+        self.values.get(position).copied().unwrap_or(0.0)
+    }
+
+    /// Every tile under some influence, in no particular order.
+    pub fn tiles(&self) -> impl Iterator<Item = (&CubeCoords, f64)> {
+        self.values
+            .iter()
+            .map(|(position, value)| (position, *value))
+    }
+
+    /// Spread the strength of a source over the tiles within `radius`, multiplied by `decay` per tile of distance.
+    pub fn spread(
+        &mut self,
+        map: &HexMap,
+        center: CubeCoords,
+        strength: f64,
+        radius: u16,
+        decay: f64,
+    ) {
+        for tile in std::iter::once(center).chain(map.within_radius(&center, radius)) {
+            if map.contains(&tile) {
+                *self.values.entry(tile).or_insert(0.0) +=
+                    strength * decay.powf(center.distance_to(tile));
+            }
+        }
+    }
+
+    fn add(&mut self, other: &InfluenceMap) {
+        for (position, value) in other.tiles() {
+            *self.values.entry(*position).or_insert(0.0) += value;
+        }
+    }
+}
+
+/// Influence maps of a nation and of its enemies.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct InfluenceMaps {
+    friendly: InfluenceMap,
+    /// By enemy nation, in order of discovery.
+    enemies: Vec<(SimulationID, InfluenceMap)>,
+    /// All enemies combined.
+    hostile: InfluenceMap,
+}
+
+impl InfluenceMaps {
+    pub fn friendly(&self) -> &InfluenceMap {
+        &self.friendly
+    }
+
+    pub fn hostile(&self) -> &InfluenceMap {
+        &self.hostile
+    }
+
+    pub fn enemy(&self, nation: &SimulationID) -> Option<&InfluenceMap> {
+        self.enemies
+            .iter()
+            .find(|(enemy, _)| enemy == nation)
+            .map(|(_, influence)| influence)
+    }
+
+    /// Positive where the nation dominates, negative where its enemies do.
+    pub fn balance(&self, position: &CubeCoords) -> f64 {
+        self.friendly.get(position) - self.hostile.get(position)
+    }
+
+    /// High wherever there is strength, from either side.
+    pub fn tension(&self, position: &CubeCoords) -> f64 {
+        self.friendly.get(position) + self.hostile.get(position)
+    }
+
+    /// High where both sides are strong: the tiles fought over.
+    pub fn vulnerability(&self, position: &CubeCoords) -> f64 {
+        self.tension(position) - self.balance(position).abs()
+    }
+
+    /// Table of the influence over every tile, one tab-separated line per tile sorted by coordinates, for
+    /// inspection.
+    pub fn dump(&self) -> String {
+        let mut tiles: Vec<&CubeCoords> = self
+            .friendly
+            .values
+            .keys()
+            .chain(self.hostile.values.keys())
+            .collect();
+        tiles.sort();
+        tiles.dedup();
+        let mut dump = String::from("q\tr\tfriendly\thostile\tbalance\ttension\tvulnerability\n");
+        for tile in tiles {
+            writeln!(
+                dump,
+                "{}\t{}\t{:.1}\t{:.1}\t{:.1}\t{:.1}\t{:.1}",
+                tile.q(),
+                tile.r(),
+                self.friendly.get(tile),
+                self.hostile.get(tile),
+                self.balance(tile),
+                self.tension(tile),
+                self.vulnerability(tile),
+            )
+            .unwrap();
+        }
+        dump
+    }
+}
+
+/// What a front is ordered to do.
+#[derive(Clone, Debug, PartialEq)]
+pub enum FrontPosture {
+    /// March on a hostile settlement.
+    Offensive { objective: CubeCoords },
+    /// Hold the given tiles, most contested first.
+    Defensive { line: Vec<CubeCoords> },
+}
+
+/// Where the units of a nation face an enemy nation.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Front {
+    /// Must be the `SimulationID` of a `Nation`.
+    enemy: SimulationID,
+    /// Tiles where both sides have influence, sorted. Empty when the enemy is known but not in reach.
+    tiles: Vec<CubeCoords>,
+    /// Must be the `SimulationID`s of `HqUnit`s.
+    headquarters: Vec<SimulationID>,
+    /// Friendly over hostile influence on the front.
+    strength_ratio: f64,
+    posture: FrontPosture,
+}
+
+impl Front {
+    pub fn enemy(&self) -> &SimulationID {
+        &self.enemy
+    }
+
+    pub fn tiles(&self) -> &[CubeCoords] {
+        &self.tiles
+    }
+
+    pub fn headquarters(&self) -> &[SimulationID] {
+        &self.headquarters
+    }
+
+    pub fn strength_ratio(&self) -> f64 {
+        self.strength_ratio
+    }
+
+    pub fn posture(&self) -> &FrontPosture {
+        &self.posture
+    }
+
+    /// Where the units of the front, and its reinforcements, should head to.
+    pub fn rally_point(&self) -> Option<&CubeCoords> {
+        match &self.posture {
+            FrontPosture::Offensive { objective } => Some(objective),
+            FrontPosture::Defensive { line } => line.first(),
+        }
+    }
+}
+
+/// Operations of a nation for a turn.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct OperationalPlan {
+    influence: InfluenceMaps,
+    fronts: Vec<Front>,
+    /// Headquarters assigned to no front. Must be the `SimulationID`s of `HqUnit`s.
+    reserves: Vec<SimulationID>,
+    /// Movement orders of the units, with their urgency.
+    orders: Vec<(AiOrder, f64)>,
+}
+
+impl OperationalPlan {
+    pub fn influence(&self) -> &InfluenceMaps {
+        &self.influence
+    }
+
+    pub fn fronts(&self) -> &[Front] {
+        &self.fronts
+    }
+
+    pub fn reserves(&self) -> &[SimulationID] {
+        &self.reserves
+    }
+
+    pub fn orders(&self) -> impl Iterator<Item = &AiOrder> {
+        self.orders.iter().map(|(order, _)| order)
+    }
+
+    pub(super) fn scored_orders(&self) -> &[(AiOrder, f64)] {
+        &self.orders
+    }
+
+    /// The front in the worst shape, which gets the reserves and the new recruits.
+    pub fn neediest_front(&self) -> Option<&Front> {
+        self.fronts.iter().reduce(|neediest, front| {
+            if front.strength_ratio < neediest.strength_ratio {
+                front
+            } else {
+                neediest
+            }
+        })
+    }
+
+    /// Headquarters new units should join: one of the neediest front, or a reserve, with room left under its command.
+    pub fn reinforcement_headquarters(
+        &self,
+        order_of_battle: &OrderOfBattle,
+    ) -> Option<&SimulationID> {
+        self.neediest_front()
+            .into_iter()
+            .flat_map(|front| &front.headquarters)
+            .chain(&self.reserves)
+            .find(|id| {
+                order_of_battle
+                    .get(id)
+                    .is_some_and(|hq| hq.command_load() < hq.command_capacity())
+            })
+    }
+}
+
+/// Rules of the operational planning.
+#[derive(Debug)]
+pub struct OperationalModel {
+    /// Distance, in tiles, over which units spread their influence.
+    influence_radius: u16,
+    /// Multiplier of the influence per tile of distance, between `0.0` and `1.0`.
+    influence_decay: f64,
+    /// Influence both sides need over a tile for it to be part of a front.
+    front_threshold: f64,
+    /// Strength ratio a front of neutral aggression needs to go on the offensive.
+    offensive_ratio: f64,
+    /// Distance, in tiles, beyond which an headquarters is not assigned to a front.
+    assignment_range: f64,
+}
+
+impl Default for OperationalModel {
+    fn default() -> Self {
+        Self {
+            influence_radius: 5,
+            influence_decay: 0.7,
+            front_threshold: 10.0,
+            offensive_ratio: 1.5,
+            assignment_range: 15.0,
+        }
+    }
+}
+
+impl OperationalModel {
+    pub fn new(influence_radius: u16, influence_decay: f64, offensive_ratio: f64) -> Self {
+        assert!(influence_decay > 0.0 && influence_decay < 1.0);
+        assert!(offensive_ratio > 0.0);
+        Self {
+            influence_radius,
+            influence_decay,
+            offensive_ratio,
+            ..Self::default()
+        }
+    }
+
+    /// Influence of the nation and of the enemies it sees.
+    pub fn influence(&self, view: &WorldView) -> InfluenceMaps {
+        let map = view.map();
+        let mut maps = InfluenceMaps::default();
+        for unit in view.nation().order_of_battle().units() {
+            if let Some(position) = unit.position().as_cube_coords() {
+                maps.friendly.spread(
+                    map,
+                    position,
+                    unit_strength(unit),
+                    self.influence_radius,
+                    self.influence_decay,
+                );
+            }
+        }
+        for sighting in view.hostile_sightings() {
+            let index = match maps
+                .enemies
+                .iter()
+                .position(|(enemy, _)| enemy == sighting.nation())
+            {
+                Some(index) => index,
+                None => {
+                    maps.enemies
+                        .push((sighting.nation().clone(), InfluenceMap::new()));
+                    maps.enemies.len() - 1
+                }
+            };
+            maps.enemies[index].1.spread(
+                map,
+                *sighting.position(),
+                sighting.strength(),
+                self.influence_radius,
+                self.influence_decay,
+            );
+        }
+        for (_, influence) in &maps.enemies {
+            maps.hostile.add(influence);
+        }
+        maps
+    }
+
+    /// Called every turn, for every computer-controlled nation, before its orders are chosen (see
+    /// `NationAi::with_operations`). Draw the fronts, assign them the headquarters, and order the movements of their
+    /// land units.
+    pub fn plan(&self, view: &WorldView, personality: &Personality) -> OperationalPlan {
+        let nation = view.nation();
+        let own = nation.id();
+        let influence = self.influence(view);
+
+        // a front per known enemy
+        let enemies: Vec<SimulationID> = view
+            .known_nations()
+            .into_iter()
+            .filter(|other| view.diplomacy().are_hostile(own, other))
+            .collect();
+        let mut fronts: Vec<Front> = vec![];
+        for enemy in enemies {
+            let enemy_influence = influence.enemy(&enemy);
+            let mut tiles: Vec<CubeCoords> = enemy_influence
+                .into_iter()
+                .flat_map(|enemy_influence| enemy_influence.tiles())
+                .filter(|(position, value)| {
+                    *value >= self.front_threshold
+                        && influence.friendly.get(position) >= self.front_threshold
+                })
+                .map(|(position, _)| *position)
+                .collect();
+            tiles.sort();
+            let settlements: Vec<_> = view
+                .foreign_settlements()
+                .iter()
+                .filter(|settlement| settlement.nation() == &enemy)
+                .collect();
+            if tiles.is_empty() && settlements.is_empty() {
+                continue;
+            }
+            let strength_ratio = if tiles.is_empty() {
+                (view.own_strength() + 1.0) / (view.known_strength_of(&enemy) + 1.0)
+            } else {
+                let friendly: f64 = tiles.iter().map(|tile| influence.friendly.get(tile)).sum();
+                let hostile: f64 = tiles
+                    .iter()
+                    .map(|tile| enemy_influence.map_or(0.0, |map| map.get(tile)))
+                    .sum();
+                (friendly + 1.0) / (hostile + 1.0)
+            };
+
+            let objective = settlements
+                .iter()
+                .map(|settlement| {
+                    let capital = if settlement.is_capital() { 1.5 } else { 1.0 };
+                    let defense = influence.hostile.get(settlement.position());
+                    (*settlement.position(), capital / (1.0 + defense))
+                })
+                .reduce(|best, candidate| {
+                    if candidate.1 > best.1 {
+                        candidate
+                    } else {
+                        best
+                    }
+                })
+                .map(|(position, _)| position);
+            let offensive = strength_ratio * personality.aggression() >= self.offensive_ratio;
+            let posture = match objective {
+                Some(objective) if offensive => FrontPosture::Offensive { objective },
+                _ => {
+                    let mut line = tiles.clone();
+                    line.sort_by(|a, b| {
+                        influence
+                            .vulnerability(b)
+                            .total_cmp(&influence.vulnerability(a))
+                            .then(a.cmp(b))
+                    });
+                    if line.is_empty() {
+                        // nothing in reach yet: hold the settlements closest to the enemy
+                        let mut own_settlements: Vec<CubeCoords> = nation
+                            .settlements()
+                            .iter()
+                            .map(|settlement| *settlement.position())
+                            .collect();
+                        own_settlements.sort_by(|a, b| {
+                            let distance = |position: &CubeCoords| {
+                                settlements
+                                    .iter()
+                                    .map(|enemy| position.distance_to(*enemy.position()))
+                                    .fold(f64::INFINITY, f64::min)
+                            };
+                            distance(a).total_cmp(&distance(b)).then(a.cmp(b))
+                        });
+                        line = own_settlements.into_iter().take(1).collect();
+                    }
+                    FrontPosture::Defensive { line }
+                }
+            };
+            fronts.push(Front {
+                enemy,
+                tiles,
+                headquarters: vec![],
+                strength_ratio,
+                posture,
+            });
+        }
+
+        // every headquarters commanding units joins the nearest front in range
+        let mut reserves = vec![];
+        for hq in nation.order_of_battle().headquarters() {
+            if hq.attached_units().is_empty() {
+                continue;
+            }
+            let Some(position) = hq.position().as_cube_coords() else {
+                continue;
+            };
+            let nearest = fronts
+                .iter()
+                .enumerate()
+                .filter_map(|(index, front)| {
+                    let anchors = front.tiles.iter().chain(front.rally_point());
+                    let distance = anchors
+                        .map(|anchor| position.distance_to(*anchor))
+                        .fold(f64::INFINITY, f64::min);
+                    (distance <= self.assignment_range).then_some((index, distance))
+                })
+                .reduce(|nearest, candidate| {
+                    if candidate.1 < nearest.1 {
+                        candidate
+                    } else {
+                        nearest
+                    }
+                });
+            match nearest {
+                Some((index, _)) => fronts[index].headquarters.push(hq.id().clone()),
+                None => reserves.push(hq.id().clone()),
+            }
+        }
+
+        let mut plan = OperationalPlan {
+            influence,
+            fronts,
+            reserves,
+            orders: vec![],
+        };
+        let mut orders = vec![];
+        for front in &plan.fronts {
+            let urgency = match front.posture {
+                FrontPosture::Offensive { .. } => {
+                    front.strength_ratio / (1.0 + front.strength_ratio)
+                }
+                FrontPosture::Defensive { .. } => 1.0 / (1.0 + front.strength_ratio),
+            };
+            let destinations: Vec<CubeCoords> = match &front.posture {
+                FrontPosture::Offensive { objective } => vec![*objective],
+                FrontPosture::Defensive { line } => line.clone(),
+            };
+            let units = front
+                .headquarters
+                .iter()
+                .filter_map(|id| nation.order_of_battle().get(id));
+            Self::dispatch(units, &destinations, urgency, &mut orders);
+        }
+        if let Some(rally_point) = plan.neediest_front().and_then(Front::rally_point) {
+            let units = plan
+                .reserves
+                .iter()
+                .filter_map(|id| nation.order_of_battle().get(id));
+            Self::dispatch(units, &[*rally_point], 0.5, &mut orders);
+        }
+        plan.orders = orders;
+        plan
+    }
+
+    /// Send the land units of headquarters to the destinations in turn.
+    fn dispatch<'h, 'a: 'h>(
+        headquarters: impl Iterator<Item = &'h HqUnit<'a>>,
+        destinations: &[CubeCoords],
+        urgency: f64,
+        orders: &mut Vec<(AiOrder, f64)>,
+    ) {
+        if destinations.is_empty() {
+            return;
+        }
+        let units = headquarters
+            .flat_map(|hq| hq.attached_units())
+            .filter(|unit| {
+                !unit.is_destroyed()
+                    && !unit.is_routed()
+                    && unit_domain(unit.template()) == UnitDomain::Land
+            });
+        for (unit, destination) in units.zip(destinations.iter().cycle()) {
+            if unit.position().as_cube_coords() != Some(*destination) {
+                orders.push((
+                    AiOrder::Move(MovementOrder::new(unit.id().clone(), *destination)),
+                    urgency,
+                ));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, ops::RangeInclusive};
+
+    use crate::{
+        hex_map::{
+            coordinates::{CubeCoords, HexMapCoordinates},
+            layers::natural::HexMapTerrain,
+            tile::HexMapTile,
+            HexMap, HexMapStorage,
+        },
+        simulation::{
+            ai::{personality::Personality, AiOrder, WorldView},
+            diplomacy::Diplomacy,
+            fog::FogOfWar,
+            ids::{SimulationID, WithSimulationID},
+            military::{order_of_battle::OrderOfBattle, HqUnit, Unit, UnitTemplate},
+            nations::Nation,
+            properties::SimulationPropertyStorage,
+            settlements::Settlement,
+            territory::Territory,
+            testing::build_mock_leader,
+        },
+    };
+
+    use super::{FrontPosture, OperationalModel};
+
+    fn build_mock_hq<'a>(id: u32, position: CubeCoords) -> HqUnit<'a> {
+        HqUnit::new(
+            SimulationID::new_map_entity_id(id),
+            HexMapCoordinates::Cube(position),
+            None,
+            SimulationPropertyStorage::new(),
+        )
+    }
+
+    /// Add an headquarters to an order of battle, with units on its tile.
+    fn add_mock_hq<'a>(
+        order_of_battle: &mut OrderOfBattle<'a>,
+        hq: HqUnit<'a>,
+        units: RangeInclusive<u32>,
+        template: &'a UnitTemplate,
+    ) {
+        let id = hq.id().clone();
+        let position = hq.position().clone();
+        order_of_battle.add(hq, None).unwrap();
+        for unit in units {
+            order_of_battle
+                .attach_unit(
+                    &id,
+                    Unit::new(
+                        SimulationID::new_map_entity_id(unit),
+                        position.clone(),
+                        template,
+                        100,
+                    ),
+                )
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn test_operational_plan() {
+        let mut tiles = HexMapStorage::new();
+        for q in 0..=30 {
+            tiles.insert(
+                CubeCoords::from_axial_coords(q, 0),
+                HexMapTile::from_terrain(0, HexMapTerrain::Plains),
+            );
+        }
+        let map = HexMap::from_tiles(tiles);
+        let tile = CubeCoords::from_axial_coords;
+        let infantry = UnitTemplate::new(
+            SimulationID::new_abstract_id("infantry"),
+            "infantry".into(),
+            HashMap::new(),
+            HashMap::new(),
+            SimulationPropertyStorage::new(),
+        );
+        let capital_a = Settlement::new(SimulationID::new_map_entity_id(1), "A".into(), tile(0, 0));
+        let capital_b =
+            Settlement::new(SimulationID::new_map_entity_id(2), "B".into(), tile(25, 0));
+        let mut nations = vec![
+            Nation::new(
+                SimulationID::new_abstract_id("a"),
+                "a".into(),
+                build_mock_leader(1, vec![]),
+                &capital_a,
+            ),
+            Nation::new(
+                SimulationID::new_abstract_id("b"),
+                "b".into(),
+                build_mock_leader(2, vec![]),
+                &capital_b,
+            ),
+        ];
+        let [a, b] = ["a", "b"].map(SimulationID::new_abstract_id);
+        // a: two units facing b, a reserve far behind; b: one unit
+        for (nation, id, position, units) in [
+            (0, 10, tile(23, 0), 11..=12),
+            (0, 20, tile(0, 0), 21..=21),
+            (1, 30, tile(24, 0), 31..=31),
+        ] {
+            add_mock_hq(
+                nations[nation].order_of_battle_mut(),
+                build_mock_hq(id, position),
+                units,
+                &infantry,
+            );
+        }
+        let mut diplomacy = Diplomacy::new();
+        diplomacy.declare_war(&a, &b).unwrap();
+        let mut fog = FogOfWar::new(a.clone());
+        fog.update(&nations[0], &map, &Territory::new());
+        let view = WorldView::new(&nations[0], &nations, &diplomacy, &fog, &map);
+        let model = OperationalModel::default();
+
+        // twice as strong on the front: offensive on the capital of b, the reserve follows
+        let plan = model.plan(&view, &Personality::default());
+        assert_eq!(plan.fronts().len(), 1);
+        let front = &plan.fronts()[0];
+        assert_eq!(front.enemy(), &b);
+        assert_eq!(
+            front.tiles(),
+            (19..=28).map(|q| tile(q, 0)).collect::<Vec<_>>()
+        );
+        assert_eq!(front.headquarters(), &[SimulationID::new_map_entity_id(10)]);
+        assert_eq!(plan.reserves(), &[SimulationID::new_map_entity_id(20)]);
+        assert_eq!(
+            front.posture(),
+            &FrontPosture::Offensive {
+                objective: tile(25, 0)
+            }
+        );
+        let destinations: Vec<_> = plan
+            .orders()
+            .map(|order| match order {
+                AiOrder::Move(order) => *order.destination(),
+                _ => unreachable!(),
+            })
+            .collect();
+        assert_eq!(destinations, vec![tile(25, 0); 3]);
+        assert_eq!(
+            plan.reinforcement_headquarters(nations[0].order_of_battle()),
+            Some(&SimulationID::new_map_entity_id(10))
+        );
+        // once the headquarters of the front is full, the reserve gets the new units
+        let mut order_of_battle = OrderOfBattle::new();
+        add_mock_hq(
+            &mut order_of_battle,
+            build_mock_hq(10, tile(23, 0)).with_command_capacity(2),
+            11..=12,
+            &infantry,
+        );
+        add_mock_hq(
+            &mut order_of_battle,
+            build_mock_hq(20, tile(0, 0)),
+            21..=21,
+            &infantry,
+        );
+        assert_eq!(
+            plan.reinforcement_headquarters(&order_of_battle),
+            Some(&SimulationID::new_map_entity_id(20))
+        );
+        let dump = plan.influence().dump();
+        assert!(dump.starts_with("q\tr\tfriendly"));
+        assert_eq!(dump.lines().count(), 1 + 18);
+
+        // a timid nation holds the line instead, on the most contested tiles
+        let plan = model.plan(&view, &Personality::new(0.5, 1.0, 1.0, 1.0));
+        let FrontPosture::Defensive { line } = plan.fronts()[0].posture() else {
+            panic!("expected a defensive front");
+        };
+        assert_eq!(line[0], tile(24, 0));
+    }
+}
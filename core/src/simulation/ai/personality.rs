@@ -0,0 +1,173 @@
+//! Personalities and difficulty levels of the computer-controlled nations.
+//!
+//! A `Personality` weighs the utility of every kind of order (see `NationAi`). It is derived from the traits of the
+//! national `Leader`: each known trait multiplies some of its weights, so that an aggressive leader declares wars
+//! more willingly, and a cautious one keeps larger reserves.
+
+use std::collections::HashMap;
+
+use crate::simulation::{
+    ids::{SimulationID, WithSimulationID},
+    people::leaders::Leader,
+};
+
+/// Lowest value of a weight of a `Personality`.
+pub const PERSONALITY_MIN_WEIGHT: f64 = 0.1;
+/// Highest value of a weight of a `Personality`.
+pub const PERSONALITY_MAX_WEIGHT: f64 = 3.0;
+
+/// Weights of the utilities of the orders of a nation, `1.0` being neutral.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Personality {
+    /// Declaring wars and attacking.
+    aggression: f64,
+    /// Defending, seeking peace and keeping reserves.
+    caution: f64,
+    /// Constructing buildings.
+    development: f64,
+    /// Recruiting units.
+    militarism: f64,
+}
+
+impl Default for Personality {
+    fn default() -> Self {
+        Self::new(1.0, 1.0, 1.0, 1.0)
+    }
+}
+
+impl Personality {
+    pub fn new(aggression: f64, caution: f64, development: f64, militarism: f64) -> Self {
+        let clamp = |weight: f64| weight.clamp(PERSONALITY_MIN_WEIGHT, PERSONALITY_MAX_WEIGHT);
+        Self {
+            aggression: clamp(aggression),
+            caution: clamp(caution),
+            development: clamp(development),
+            militarism: clamp(militarism),
+        }
+    }
+
+    pub fn aggression(&self) -> f64 {
+        self.aggression
+    }
+
+    pub fn caution(&self) -> f64 {
+        self.caution
+    }
+
+    pub fn development(&self) -> f64 {
+        self.development
+    }
+
+    pub fn militarism(&self) -> f64 {
+        self.militarism
+    }
+
+    /// Multiply the weights by the ones of a modifier.
+    pub fn combine(&self, modifier: &Personality) -> Self {
+        Self::new(
+            self.aggression * modifier.aggression,
+            self.caution * modifier.caution,
+            self.development * modifier.development,
+            self.militarism * modifier.militarism,
+        )
+    }
+}
+
+/// How the traits of leaders shape personalities.
+#[derive(Debug)]
+pub struct PersonalityModel {
+    /// Keys must be the `SimulationID`s of `Trait`s, values multiply the weights of the personality.
+    traits: HashMap<SimulationID, Personality>,
+}
+
+impl Default for PersonalityModel {
+    fn default() -> Self {
+        Self::new()
+            .with_trait(
+                SimulationID::new_abstract_id("aggressive"),
+                Personality::new(1.5, 0.7, 1.0, 1.2),
+            )
+            .with_trait(
+                SimulationID::new_abstract_id("cautious"),
+                Personality::new(0.7, 1.5, 1.0, 1.0),
+            )
+            .with_trait(
+                SimulationID::new_abstract_id("industrious"),
+                Personality::new(1.0, 1.0, 1.5, 1.0),
+            )
+            .with_trait(
+                SimulationID::new_abstract_id("militarist"),
+                Personality::new(1.2, 1.0, 0.8, 1.5),
+            )
+            .with_trait(
+                SimulationID::new_abstract_id("pacifist"),
+                Personality::new(0.5, 1.2, 1.2, 0.7),
+            )
+    }
+}
+
+impl PersonalityModel {
+    /// Model without any known trait: every leader gets the default personality.
+    pub fn new() -> Self {
+        Self {
+            traits: HashMap::new(),
+        }
+    }
+
+    pub fn with_trait(mut self, r#trait: SimulationID, modifier: Personality) -> Self {
+        assert!(matches!(r#trait, SimulationID::Abstract(_)));
+        self.traits.insert(r#trait, modifier);
+        self
+    }
+
+    /// Personality of a leader, combining the modifiers of its known traits.
+    pub fn personality_of(&self, leader: &Leader) -> Personality {
+        leader
+            .traits()
+            .iter()
+            .filter_map(|r#trait| self.traits.get(r#trait.id()))
+            .fold(Personality::default(), |personality, modifier| {
+                personality.combine(modifier)
+            })
+    }
+}
+
+/// Skill of a computer-controlled nation. It only changes the way the nation plays, never the rules it plays by.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Difficulty {
+    Easy,
+    #[default]
+    Normal,
+    Hard,
+}
+
+impl Difficulty {
+    /// Maximum number of construction and recruitment orders issued per turn.
+    pub fn orders_per_turn(&self) -> usize {
+        match self {
+            Difficulty::Easy => 1,
+            Difficulty::Normal => 2,
+            Difficulty::Hard => 4,
+        }
+    }
+
+    /// Utility below which an option is not worth taking.
+    pub fn min_utility(&self) -> f64 {
+        match self {
+            Difficulty::Easy => 0.4,
+            Difficulty::Normal => 0.2,
+            Difficulty::Hard => 0.1,
+        }
+    }
+
+    /// Ratio of its strength over the known strength of a target a nation needs before declaring war.
+    ///
+    /// Easy nations start wars they cannot win.
+    pub fn war_strength_ratio(&self) -> f64 {
+        match self {
+            Difficulty::Easy => 0.8,
+            Difficulty::Normal => 1.3,
+            Difficulty::Hard => 1.6,
+        }
+    }
+}
@@ -1,6 +1,14 @@
-use std::collections::HashMap;
+use std::{any::Any, collections::HashMap};
 
-use crate::hex_map::layers::dynamic::HexMapTileBuilding;
+use crate::hex_map::{
+    layers::{dynamic::HexMapTileBuilding, natural::HexMapTerrain},
+    tile::HexMapTile,
+};
+
+use self::adjacency::{AdjacencyBonus, AdjacencyRule, AdjacencyTarget};
+
+pub mod adjacency;
+pub mod lifecycle;
 
 use super::{
     economy::{ConstructionCosts, MaintenanceCosts},
@@ -11,6 +19,154 @@ use super::{
 /// Resources output of a building, per turn.
 pub type BuildingProduction = HashMap<Resource, ResourceQuantity>;
 
+/// Resources consumed by a single run of a `BuildingRecipe`.
+pub type BuildingRecipeInputs = HashMap<Resource, ResourceQuantity>;
+
+/// A production recipe of a building, transforming inputs into outputs.
+///
+/// For instance, a steel mill would require metals and electricity to output steel.
+#[derive(Clone, Debug)]
+pub struct BuildingRecipe {
+    /// Consumed per run.
+    inputs: BuildingRecipeInputs,
+    /// Produced per run.
+    outputs: BuildingProduction,
+    /// Maximum number of runs per turn.
+    throughput: u16,
+    /// Workers needed to run the recipe at full throughput.
+    workforce: u32,
+}
+
+impl BuildingRecipe {
+    pub fn new(
+        inputs: BuildingRecipeInputs,
+        outputs: BuildingProduction,
+        throughput: u16,
+        workforce: u32,
+    ) -> Self {
+        Self {
+            inputs,
+            outputs,
+            throughput,
+            workforce,
+        }
+    }
+
+    pub fn inputs(&self) -> &BuildingRecipeInputs {
+        &self.inputs
+    }
+
+    pub fn outputs(&self) -> &BuildingProduction {
+        &self.outputs
+    }
+
+    pub fn throughput(&self) -> u16 {
+        self.throughput
+    }
+
+    pub fn workforce(&self) -> u32 {
+        self.workforce
+    }
+}
+
+/// Rules restricting on which `HexMapTile` a building can be built.
+#[derive(Clone, Debug, Default)]
+pub struct BuildingPlacementRules {
+    /// Terrains the building can be built on. Any land terrain if empty.
+    terrains: Vec<HexMapTerrain>,
+    /// Minimum elevation, in meters.
+    min_elevation: Option<i16>,
+    /// Maximum elevation, in meters.
+    max_elevation: Option<i16>,
+    /// Must the tile have a resource deposit (e.g. for a mine)?
+    requires_deposit: bool,
+    /// Must the building be the only one on its tile?
+    exclusive: bool,
+}
+
+impl BuildingPlacementRules {
+    pub fn new(
+        terrains: Vec<HexMapTerrain>,
+        min_elevation: Option<i16>,
+        max_elevation: Option<i16>,
+        requires_deposit: bool,
+        exclusive: bool,
+    ) -> Self {
+        Self {
+            terrains,
+            min_elevation,
+            max_elevation,
+            requires_deposit,
+            exclusive,
+        }
+    }
+
+    pub fn terrains(&self) -> &[HexMapTerrain] {
+        &self.terrains
+    }
+
+    pub fn min_elevation(&self) -> Option<i16> {
+        self.min_elevation
+    }
+
+    pub fn max_elevation(&self) -> Option<i16> {
+        self.max_elevation
+    }
+
+    pub fn requires_deposit(&self) -> bool {
+        self.requires_deposit
+    }
+
+    pub fn exclusive(&self) -> bool {
+        self.exclusive
+    }
+}
+
+/// Why a building cannot be placed on a tile.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BuildingPlacementError {
+    OutOfMap,
+    Terrain(HexMapTerrain),
+    Elevation(i16),
+    MissingDeposit,
+    /// An existing (or planned) building prevents it.
+    Occupied,
+}
+
+/// A possible upgrade of a building, from its template to another one.
+#[derive(Clone, Debug)]
+pub struct BuildingUpgradePath {
+    /// Must be the `SimulationID` of the target `BuildingTemplate`.
+    target: SimulationID,
+    cost: ConstructionCosts,
+    /// Upgrade time, in turns.
+    build_time: u16,
+}
+
+impl BuildingUpgradePath {
+    pub fn new(target: SimulationID, cost: ConstructionCosts, build_time: u16) -> Self {
+        assert!(matches!(target, SimulationID::Abstract(_)));
+        assert!(build_time > 0);
+        Self {
+            target,
+            cost,
+            build_time,
+        }
+    }
+
+    pub fn target(&self) -> &SimulationID {
+        &self.target
+    }
+
+    pub fn cost(&self) -> &ConstructionCosts {
+        &self.cost
+    }
+
+    pub fn build_time(&self) -> u16 {
+        self.build_time
+    }
+}
+
 /// Template of a building.
 #[derive(Debug)]
 pub struct BuildingTemplate {
@@ -19,16 +175,398 @@ pub struct BuildingTemplate {
     r#type: String,
     cost: ConstructionCosts,
     maintenance_costs: MaintenanceCosts,
+    /// Unconditional output, not requiring any input (e.g. extraction).
     production: BuildingProduction,
+    recipes: Vec<BuildingRecipe>,
+    /// Construction time, in turns.
+    build_time: u16,
+    max_health_points: u16,
+    placement: BuildingPlacementRules,
+    upgrades: Vec<BuildingUpgradePath>,
+    adjacency_rules: Vec<AdjacencyRule>,
+}
+
+impl BuildingTemplate {
+    pub fn new(
+        id: SimulationID,
+        r#type: String,
+        cost: ConstructionCosts,
+        maintenance_costs: MaintenanceCosts,
+        production: BuildingProduction,
+    ) -> Self {
+        assert!(matches!(id, SimulationID::Abstract(_)));
+        Self {
+            id,
+            r#type,
+            cost,
+            maintenance_costs,
+            production,
+            recipes: vec![],
+            build_time: 1,
+            max_health_points: 100,
+            placement: BuildingPlacementRules::default(),
+            upgrades: vec![],
+            adjacency_rules: vec![],
+        }
+    }
+
+    /// Register a new production recipe.
+    pub fn with_recipe(mut self, recipe: BuildingRecipe) -> Self {
+        self.recipes.push(recipe);
+        self
+    }
+
+    pub fn with_build_time(mut self, build_time: u16) -> Self {
+        assert!(build_time > 0);
+        self.build_time = build_time;
+        self
+    }
+
+    pub fn with_max_health_points(mut self, max_health_points: u16) -> Self {
+        assert!(max_health_points > 0);
+        self.max_health_points = max_health_points;
+        self
+    }
+
+    pub fn with_placement(mut self, placement: BuildingPlacementRules) -> Self {
+        self.placement = placement;
+        self
+    }
+
+    /// Register a new upgrade path.
+    pub fn with_upgrade(mut self, upgrade: BuildingUpgradePath) -> Self {
+        self.upgrades.push(upgrade);
+        self
+    }
+
+    /// Register a new adjacency rule.
+    pub fn with_adjacency_rule(mut self, rule: AdjacencyRule) -> Self {
+        self.adjacency_rules.push(rule);
+        self
+    }
+
+    pub fn r#type(&self) -> &str {
+        &self.r#type
+    }
+
+    pub fn cost(&self) -> &ConstructionCosts {
+        &self.cost
+    }
+
+    pub fn maintenance_costs(&self) -> &MaintenanceCosts {
+        &self.maintenance_costs
+    }
+
+    pub fn production(&self) -> &BuildingProduction {
+        &self.production
+    }
+
+    pub fn recipes(&self) -> &[BuildingRecipe] {
+        &self.recipes
+    }
+
+    pub fn build_time(&self) -> u16 {
+        self.build_time
+    }
+
+    pub fn max_health_points(&self) -> u16 {
+        self.max_health_points
+    }
+
+    pub fn placement(&self) -> &BuildingPlacementRules {
+        &self.placement
+    }
+
+    pub fn upgrades(&self) -> &[BuildingUpgradePath] {
+        &self.upgrades
+    }
+
+    pub fn adjacency_rules(&self) -> &[AdjacencyRule] {
+        &self.adjacency_rules
+    }
+
+    pub fn upgrade_to(&self, target: &SimulationID) -> Option<&BuildingUpgradePath> {
+        self.upgrades
+            .iter()
+            .find(|upgrade| &upgrade.target == target)
+    }
+
+    /// Check whether the building can be placed on the given tile.
+    ///
+    /// `planned` are the templates of the buildings already planned on the tile, but not yet built.
+    pub fn validate_placement(
+        &self,
+        tile: &HexMapTile,
+        planned: &[&BuildingTemplate],
+    ) -> Result<(), BuildingPlacementError> {
+        let terrain = tile.terrain();
+        let terrain_allowed = if self.placement.terrains.is_empty() {
+            !terrain.is_water()
+        } else {
+            self.placement.terrains.contains(&terrain)
+        };
+        if !terrain_allowed {
+            return Err(BuildingPlacementError::Terrain(terrain));
+        }
+
+        let elevation = tile.elevation();
+        if self
+            .placement
+            .min_elevation
+            .is_some_and(|min| elevation < min)
+            || self
+                .placement
+                .max_elevation
+                .is_some_and(|max| elevation > max)
+        {
+            return Err(BuildingPlacementError::Elevation(elevation));
+        }
+
+        if self.placement.requires_deposit && !tile.layer_natural().has_deposits() {
+            return Err(BuildingPlacementError::MissingDeposit);
+        }
+
+        let others: Vec<&BuildingTemplate> = Building::all_on_tile(tile)
+            .map(|building| building.template())
+            .chain(planned.iter().copied())
+            .collect();
+        let occupied = if self.placement.exclusive {
+            !others.is_empty()
+        } else {
+            others
+                .iter()
+                .any(|other| other.placement.exclusive || other.id == self.id)
+        };
+        if occupied {
+            return Err(BuildingPlacementError::Occupied);
+        }
+
+        Ok(())
+    }
+}
+
+impl WithSimulationID for BuildingTemplate {
+    fn id(&self) -> &SimulationID {
+        &self.id
+    }
 }
 
 /// A building somewhere on the world map. Not supposed to be moved.
+///
+/// Stored in the artificial layer of its `HexMapTile`, which only holds `'static` data: buildings on the map borrow
+/// templates living for the whole game (e.g. from a `TemplateRegistry` created at startup and leaked).
 #[derive(Debug)]
 pub struct Building<'a> {
     /// Must be `SimulationID::SimulationMapEntityID`.
     id: SimulationID,
     template: &'a BuildingTemplate,
+    /// Must be the `SimulationID` of a `Nation`, `None` if no nation owns the building.
+    owner: Option<SimulationID>,
     health_points: u16,
+    /// Cached, see `adjacency::refresh_adjacency_bonuses`.
+    adjacency_bonus: AdjacencyBonus,
+    /// Ratio, from `0.0` to `1.0`, of the electricity demand met by the power grid. Cached, see
+    /// `economy::power_grid::update_power_grids`.
+    power_supply: f64,
+    /// Electricity generated by its recipes and not consumed during the last production. Cached, see
+    /// `economy::power_grid::record_surplus_electricity`.
+    surplus_electricity: u32,
+}
+
+impl<'a> Building<'a> {
+    pub fn new(id: SimulationID, template: &'a BuildingTemplate, health_points: u16) -> Self {
+        assert!(matches!(id, SimulationID::MapEntityID(_)));
+        Self {
+            id,
+            template,
+            owner: None,
+            health_points,
+            adjacency_bonus: AdjacencyBonus::default(),
+            power_supply: 1.0,
+            surplus_electricity: 0,
+        }
+    }
+
+    pub fn with_owner(mut self, owner: SimulationID) -> Self {
+        self.owner = Some(owner);
+        self
+    }
+
+    pub fn template(&self) -> &'a BuildingTemplate {
+        self.template
+    }
+
+    pub fn owner(&self) -> Option<&SimulationID> {
+        self.owner.as_ref()
+    }
+
+    /// Change the owner of the building (e.g. captured), returning the previous one.
+    pub fn set_owner(&mut self, owner: Option<SimulationID>) -> Option<SimulationID> {
+        std::mem::replace(&mut self.owner, owner)
+    }
+
+    pub fn health_points(&self) -> u16 {
+        self.health_points
+    }
+
+    pub fn is_destroyed(&self) -> bool {
+        self.health_points == 0
+    }
+
+    /// Ratio, from `0.0` to `1.0`, of the nominal output of the building given its health.
+    pub fn efficiency(&self) -> f64 {
+        (self.health_points as f64 / self.template.max_health_points as f64).min(1.0)
+    }
+
+    pub fn adjacency_bonus(&self) -> &AdjacencyBonus {
+        &self.adjacency_bonus
+    }
+
+    pub fn set_adjacency_bonus(&mut self, adjacency_bonus: AdjacencyBonus) {
+        self.adjacency_bonus = adjacency_bonus;
+    }
+
+    pub fn power_supply(&self) -> f64 {
+        self.power_supply
+    }
+
+    pub fn set_power_supply(&mut self, power_supply: f64) {
+        self.power_supply = power_supply.clamp(0.0, 1.0);
+    }
+
+    pub fn set_surplus_electricity(&mut self, surplus_electricity: u32) {
+        self.surplus_electricity = surplus_electricity;
+    }
+
+    /// Electricity fed to the power grid per turn: its own production, given its health and its adjacency bonus,
+    /// and what its recipes generated but did not consume during the last production.
+    pub fn power_generation(&self) -> u32 {
+        self.production()
+            .get(&Resource::Electricity)
+            .copied()
+            .unwrap_or(0) as u32
+            + self.surplus_electricity
+    }
+
+    /// Electricity drawn from the power grid per turn to run all the recipes at full throughput.
+    pub fn power_demand(&self) -> u32 {
+        self.template
+            .recipes
+            .iter()
+            .map(|recipe| {
+                recipe
+                    .inputs
+                    .get(&Resource::Electricity)
+                    .copied()
+                    .unwrap_or(0) as u32
+                    * recipe.throughput as u32
+            })
+            .sum()
+    }
+
+    /// Multiplier of all the outputs of the building, given its health and its adjacency bonus.
+    pub fn output_multiplier(&self) -> f64 {
+        self.efficiency() * self.adjacency_bonus.multiplier(AdjacencyTarget::Production)
+    }
+
+    /// Actual unconditional output of the building, per turn.
+    pub fn production(&self) -> BuildingProduction {
+        scale_quantities(&self.template.production, self.output_multiplier())
+    }
+
+    /// Actual maintenance costs of the building, per turn, given its adjacency bonus.
+    pub fn maintenance_costs(&self) -> MaintenanceCosts {
+        scale_quantities(
+            &self.template.maintenance_costs,
+            self.adjacency_bonus
+                .multiplier(AdjacencyTarget::Maintenance),
+        )
+    }
+
+    /// Lose health points, down to zero. Return the health points actually lost.
+    pub fn damage(&mut self, amount: u16) -> u16 {
+        let lost = amount.min(self.health_points);
+        self.health_points -= lost;
+        lost
+    }
+
+    /// Regain health points, up to the maximum of the template. Return the health points actually regained.
+    pub fn repair(&mut self, amount: u16) -> u16 {
+        let regained = amount.min(
+            self.template
+                .max_health_points
+                .saturating_sub(self.health_points),
+        );
+        self.health_points += regained;
+        regained
+    }
+
+    /// Switch to another template, keeping the same share of health points.
+    pub fn upgrade(&mut self, template: &'a BuildingTemplate) {
+        let efficiency = self.efficiency();
+        self.health_points = (template.max_health_points as f64 * efficiency).round() as u16;
+        self.template = template;
+    }
+}
+
+impl Building<'static> {
+    /// Get back the `Building` stored in a tile.
+    pub fn from_tile_building(building: &dyn HexMapTileBuilding) -> Option<&Self> {
+        (building as &dyn Any).downcast_ref()
+    }
+
+    /// Get back the `Building` stored in a tile, mutably.
+    pub fn from_tile_building_mut(building: &mut dyn HexMapTileBuilding) -> Option<&mut Self> {
+        (building as &mut dyn Any).downcast_mut()
+    }
+
+    /// Iterate over all the buildings built on a tile.
+    pub fn all_on_tile(tile: &HexMapTile) -> impl Iterator<Item = &Self> {
+        tile.layer_artificial()
+            .buildings()
+            .filter_map(Self::from_tile_building)
+    }
+
+    /// Find a building built on a tile.
+    pub fn find_on_tile<'t>(tile: &'t HexMapTile, id: &SimulationID) -> Option<&'t Self> {
+        Self::all_on_tile(tile).find(|building| &building.id == id)
+    }
+
+    /// Find a building built on a tile, mutably.
+    pub fn find_on_tile_mut<'t>(
+        tile: &'t mut HexMapTile,
+        id: &SimulationID,
+    ) -> Option<&'t mut Self> {
+        tile.layer_artificial_mut()
+            .buildings_mut()
+            .filter_map(Self::from_tile_building_mut)
+            .find(|building| &building.id == id)
+    }
+
+    /// Remove a building from its tile.
+    pub fn remove_from_tile(tile: &mut HexMapTile, id: &SimulationID) -> Option<Box<Self>> {
+        let building = tile.layer_artificial_mut().remove_building(|building| {
+            Self::from_tile_building(building).is_some_and(|building| &building.id == id)
+        })?;
+        (building as Box<dyn Any>).downcast().ok()
+    }
+}
+
+/// Scale resource quantities by a (positive) multiplier, rounding to the nearest unit.
+pub(crate) fn scale_quantities(
+    quantities: &HashMap<Resource, ResourceQuantity>,
+    multiplier: f64,
+) -> HashMap<Resource, ResourceQuantity> {
+    quantities
+        .iter()
+        .map(|(resource, quantity)| {
+            let scaled = (*quantity as f64 * multiplier).round();
+            (
+                *resource,
+                scaled.min(ResourceQuantity::MAX as f64) as ResourceQuantity,
+            )
+        })
+        .collect()
 }
 
 impl<'a> WithSimulationID for Building<'a> {
@@ -37,4 +575,4 @@ impl<'a> WithSimulationID for Building<'a> {
     }
 }
 
-impl<'a> HexMapTileBuilding for Building<'a> {}
+impl HexMapTileBuilding for Building<'static> {}
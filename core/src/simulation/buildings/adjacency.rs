@@ -0,0 +1,325 @@
+//! Adjacency bonuses: spatial consequences of where a building is placed on the `HexMap`.
+//!
+//! Every `BuildingTemplate` can declare data-driven `AdjacencyRule`s, each one modifying the production or the
+//! maintenance of the building depending on what is found on the tiles around it (buildings, deposits, elevation,
+//! terrain or infrastructure). For instance, a farm could get +10% of production for every neighboring farm.
+
+use std::collections::HashMap;
+
+use serde::Deserialize;
+
+use crate::{
+    hex_map::{coordinates::CubeCoords, layers::natural::HexMapTerrain, tile::HexMapTile, HexMap},
+    simulation::ids::{SimulationID, WithSimulationID},
+};
+
+use super::Building;
+
+/// What is looked for on the tiles around a building.
+#[derive(Clone, Debug, PartialEq)]
+pub enum AdjacencyCondition {
+    /// A building of the given template.
+    ///
+    /// Must be the `SimulationID` of a `BuildingTemplate`.
+    Building(SimulationID),
+    /// A building of the given template type.
+    BuildingType(String),
+    /// Any resource deposit.
+    Deposit,
+    /// An elevation in the given range, in meters.
+    Elevation {
+        min: Option<i16>,
+        max: Option<i16>,
+    },
+    /// Any built infrastructure.
+    Infrastructure,
+    Terrain(HexMapTerrain),
+}
+
+impl AdjacencyCondition {
+    pub fn matches(&self, tile: &HexMapTile) -> bool {
+        match self {
+            Self::Building(template) => {
+                Building::all_on_tile(tile).any(|building| building.template().id() == template)
+            }
+            Self::BuildingType(r#type) => {
+                Building::all_on_tile(tile).any(|building| building.template().r#type() == r#type)
+            }
+            Self::Deposit => tile.layer_natural().has_deposits(),
+            Self::Elevation { min, max } => {
+                min.is_none_or(|min| tile.elevation() >= min)
+                    && max.is_none_or(|max| tile.elevation() <= max)
+            }
+            Self::Infrastructure => tile.layer_artificial().has_infrastructure(),
+            Self::Terrain(terrain) => tile.terrain() == *terrain,
+        }
+    }
+}
+
+/// What an adjacency rule modifies.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Deserialize)]
+pub enum AdjacencyTarget {
+    Production,
+    Maintenance,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AdjacencyRule {
+    /// Must be `SimulationID::SimulationAbstractID`.
+    id: SimulationID,
+    condition: AdjacencyCondition,
+    /// Radius around the building in which tiles are inspected.
+    radius: u16,
+    target: AdjacencyTarget,
+    /// Modifier applied for every matching tile, as a ratio (`0.1` for +10%, `-0.05` for -5%).
+    modifier_per_match: f64,
+    /// Maximum number of matching tiles taken into account.
+    max_matches: Option<u16>,
+}
+
+impl AdjacencyRule {
+    pub fn new(
+        id: SimulationID,
+        condition: AdjacencyCondition,
+        radius: u16,
+        target: AdjacencyTarget,
+        modifier_per_match: f64,
+        max_matches: Option<u16>,
+    ) -> Self {
+        assert!(matches!(id, SimulationID::Abstract(_)));
+        assert!(radius > 0);
+        Self {
+            id,
+            condition,
+            radius,
+            target,
+            modifier_per_match,
+            max_matches,
+        }
+    }
+
+    pub fn condition(&self) -> &AdjacencyCondition {
+        &self.condition
+    }
+
+    pub fn radius(&self) -> u16 {
+        self.radius
+    }
+
+    pub fn target(&self) -> AdjacencyTarget {
+        self.target
+    }
+
+    pub fn modifier_per_match(&self) -> f64 {
+        self.modifier_per_match
+    }
+
+    pub fn max_matches(&self) -> Option<u16> {
+        self.max_matches
+    }
+}
+
+impl WithSimulationID for AdjacencyRule {
+    fn id(&self) -> &SimulationID {
+        &self.id
+    }
+}
+
+/// The contribution of a single rule to an `AdjacencyBonus`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AdjacencyBonusEntry {
+    /// Must be the `SimulationID` of an `AdjacencyRule`.
+    rule: SimulationID,
+    target: AdjacencyTarget,
+    /// Number of matching tiles taken into account.
+    matches: u16,
+    modifier: f64,
+}
+
+impl AdjacencyBonusEntry {
+    pub fn rule(&self) -> &SimulationID {
+        &self.rule
+    }
+
+    pub fn target(&self) -> AdjacencyTarget {
+        self.target
+    }
+
+    pub fn matches(&self) -> u16 {
+        self.matches
+    }
+
+    pub fn modifier(&self) -> f64 {
+        self.modifier
+    }
+}
+
+/// Breakdown of the adjacency bonuses of a building, one entry per rule with at least a match.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AdjacencyBonus {
+    entries: Vec<AdjacencyBonusEntry>,
+}
+
+impl AdjacencyBonus {
+    /// Compute the adjacency bonus of a building placed on the given tile.
+    pub fn compute(map: &HexMap, position: &CubeCoords, building: &Building) -> Self {
+        let mut entries = vec![];
+        for rule in building.template().adjacency_rules() {
+            let mut matches = map
+                .within_radius(position, rule.radius)
+                .filter(|coords| {
+                    map.tile(coords)
+                        .is_some_and(|tile| rule.condition.matches(tile))
+                })
+                .count() as u16;
+            if let Some(max_matches) = rule.max_matches {
+                matches = matches.min(max_matches);
+            }
+            if matches > 0 {
+                entries.push(AdjacencyBonusEntry {
+                    rule: rule.id.clone(),
+                    target: rule.target,
+                    matches,
+                    modifier: rule.modifier_per_match * matches as f64,
+                });
+            }
+        }
+        Self { entries }
+    }
+
+    pub fn entries(&self) -> &[AdjacencyBonusEntry] {
+        &self.entries
+    }
+
+    /// Multiplier to apply to the given target, never negative.
+    pub fn multiplier(&self, target: AdjacencyTarget) -> f64 {
+        let modifier: f64 = self
+            .entries
+            .iter()
+            .filter(|entry| entry.target == target)
+            .map(|entry| entry.modifier)
+            .sum();
+        (1.0 + modifier).max(0.0)
+    }
+}
+
+/// Recompute the adjacency bonuses of every building on the map.
+///
+/// To be called every turn, or whenever buildings, deposits or infrastructure change on the map.
+pub fn refresh_adjacency_bonuses(map: &mut HexMap) {
+    let mut bonuses: HashMap<CubeCoords, Vec<(SimulationID, AdjacencyBonus)>> = HashMap::new();
+    for (position, tile) in map.tiles() {
+        for building in Building::all_on_tile(tile) {
+            bonuses.entry(*position).or_default().push((
+                building.id().clone(),
+                AdjacencyBonus::compute(map, position, building),
+            ));
+        }
+    }
+    for (position, buildings) in bonuses {
+        let Some(tile) = map.tile_mut(&position) else {
+            continue;
+        };
+        for (id, bonus) in buildings {
+            if let Some(building) = Building::find_on_tile_mut(tile, &id) {
+                building.set_adjacency_bonus(bonus);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::{
+        hex_map::{
+            coordinates::{CubeCoords, HexMapCoordinatesSystem},
+            tile::HexMapTile,
+            HexMap, HexMapStorage,
+        },
+        simulation::{
+            buildings::{Building, BuildingTemplate},
+            ids::SimulationID,
+            resources::Resource,
+        },
+    };
+
+    use super::{refresh_adjacency_bonuses, AdjacencyCondition, AdjacencyRule, AdjacencyTarget};
+
+    #[test]
+    fn test_adjacency_bonuses() {
+        let farm: &BuildingTemplate = Box::leak(Box::new(
+            BuildingTemplate::new(
+                SimulationID::new_abstract_id("farm"),
+                "farm".into(),
+                HashMap::new(),
+                HashMap::from([(Resource::Credits, 10)]),
+                HashMap::from([(Resource::Food, 10)]),
+            )
+            .with_adjacency_rule(AdjacencyRule::new(
+                SimulationID::new_abstract_id("farm_cluster"),
+                AdjacencyCondition::Building(SimulationID::new_abstract_id("farm")),
+                1,
+                AdjacencyTarget::Production,
+                0.1,
+                Some(2),
+            ))
+            .with_adjacency_rule(AdjacencyRule::new(
+                SimulationID::new_abstract_id("farm_highlands"),
+                AdjacencyCondition::Elevation {
+                    min: Some(500),
+                    max: None,
+                },
+                1,
+                AdjacencyTarget::Maintenance,
+                0.5,
+                None,
+            )),
+        ));
+
+        let center = CubeCoords::from_axial_coords(0, 0);
+        let mut tiles = HexMapStorage::new();
+        tiles.insert(center, HexMapTile::from_properties(0));
+        for (index, neighbor) in center.neighbors().enumerate() {
+            let elevation = if index == 0 { 800 } else { 0 };
+            let mut tile = HexMapTile::from_properties(elevation);
+            if index < 3 {
+                tile.layer_artificial_mut()
+                    .add_building(Box::new(Building::new(
+                        SimulationID::new_map_entity_id(10 + index as u32),
+                        farm,
+                        100,
+                    )));
+            }
+            tiles.insert(neighbor, tile);
+        }
+        tiles
+            .get_mut(&center)
+            .unwrap()
+            .layer_artificial_mut()
+            .add_building(Box::new(Building::new(
+                SimulationID::new_map_entity_id(1),
+                farm,
+                100,
+            )));
+        let mut map = HexMap::from_tiles(tiles);
+
+        refresh_adjacency_bonuses(&mut map);
+        let building = Building::find_on_tile(
+            map.tile(&center).unwrap(),
+            &SimulationID::new_map_entity_id(1),
+        )
+        .unwrap();
+        let bonus = building.adjacency_bonus();
+        assert_eq!(bonus.entries().len(), 2);
+        assert_eq!(bonus.entries()[0].matches(), 2);
+        assert_eq!(bonus.multiplier(AdjacencyTarget::Production), 1.2);
+        assert_eq!(bonus.multiplier(AdjacencyTarget::Maintenance), 1.5);
+        assert_eq!(building.production().get(&Resource::Food), Some(&12));
+        assert_eq!(
+            building.maintenance_costs().get(&Resource::Credits),
+            Some(&15)
+        );
+    }
+}
@@ -0,0 +1,530 @@
+//! Lifecycle of the buildings once built: damage, repair, upgrades and demolition.
+//!
+//! Repairs and upgrades take time, and are tracked as work orders progressing every turn.
+//! Damage and demolition are immediate.
+
+use crate::{
+    hex_map::{coordinates::CubeCoords, HexMap},
+    simulation::{
+        economy::ConstructionCosts,
+        ids::{SimulationID, WithSimulationID},
+        resources::{Resource, ResourceDataStorage, ResourceDataStore, ResourceQuantity},
+    },
+};
+
+use super::{Building, BuildingTemplate};
+
+/// Share of the maximum health points of a building repaired every turn.
+pub const BUILDING_REPAIR_RATE: f64 = 0.1;
+
+/// Share of the construction cost needed to fully repair a building from zero health points.
+pub const BUILDING_REPAIR_COST_RATIO: f64 = 0.5;
+
+/// Share of the construction cost recovered when demolishing a building in perfect health.
+pub const BUILDING_SALVAGE_RATIO: f64 = 0.25;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BuildingDamageSource {
+    Combat,
+    /// Natural disaster, sabotage, scripted event...
+    Event,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BuildingLifecycleEvent {
+    Damaged {
+        building: SimulationID,
+        source: BuildingDamageSource,
+        health_points: u16,
+    },
+    /// The building lost all its health points, and was removed from its tile.
+    Destroyed {
+        building: SimulationID,
+        position: CubeCoords,
+    },
+    Repaired {
+        building: SimulationID,
+        health_points: u16,
+    },
+    Upgraded {
+        building: SimulationID,
+        template: SimulationID,
+    },
+    /// A work order could not progress this turn, for lack of the given resource.
+    WorkStalled {
+        building: SimulationID,
+        resource: Resource,
+    },
+    Demolished {
+        building: SimulationID,
+        salvage: ConstructionCosts,
+    },
+    /// The work order on a building was dropped because the building is gone.
+    ///
+    /// `refund` is the cost paid upfront for an upgrade, empty for a repair. It is given back to the treasury, except
+    /// on `BuildingsLifecycle::damage`, which has none: the caller must give it back to whoever paid for the work.
+    WorkCancelled {
+        building: SimulationID,
+        refund: ConstructionCosts,
+    },
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BuildingLifecycleError {
+    UnknownBuilding,
+    NotDamaged,
+    NoUpgradePath,
+    AlreadyOrdered,
+    CannotAfford(Resource),
+}
+
+#[derive(Debug)]
+pub enum BuildingWork {
+    /// Paid every turn, until the building is back to full health.
+    Repair,
+    /// Paid upfront.
+    Upgrade {
+        /// `'static` since the upgraded `Building` is stored on the map.
+        template: &'static BuildingTemplate,
+        progress: u16,
+        paid: ConstructionCosts,
+    },
+}
+
+#[derive(Debug)]
+pub struct BuildingWorkOrder {
+    /// Must be the `SimulationID` of a `Building`.
+    building: SimulationID,
+    position: CubeCoords,
+    work: BuildingWork,
+}
+
+impl BuildingWorkOrder {
+    pub fn building(&self) -> &SimulationID {
+        &self.building
+    }
+
+    pub fn position(&self) -> &CubeCoords {
+        &self.position
+    }
+
+    pub fn work(&self) -> &BuildingWork {
+        &self.work
+    }
+
+    /// Cost paid upfront for the work, which is lost if it never completes.
+    pub fn paid(&self) -> ConstructionCosts {
+        match &self.work {
+            BuildingWork::Repair => ConstructionCosts::new(),
+            BuildingWork::Upgrade { paid, .. } => paid.clone(),
+        }
+    }
+
+    /// Give the cost paid upfront back to the treasury.
+    fn refund(&self, treasury: &mut ResourceDataStorage) -> BuildingLifecycleEvent {
+        for (resource, quantity) in self.paid() {
+            treasury.replenish(resource, quantity);
+        }
+        self.cancelled()
+    }
+
+    fn cancelled(&self) -> BuildingLifecycleEvent {
+        BuildingLifecycleEvent::WorkCancelled {
+            building: self.building.clone(),
+            refund: self.paid(),
+        }
+    }
+}
+
+/// Tracks the work orders on the buildings of the simulation, and applies the lifecycle operations.
+#[derive(Debug, Default)]
+pub struct BuildingsLifecycle {
+    orders: Vec<BuildingWorkOrder>,
+}
+
+impl BuildingsLifecycle {
+    pub fn new() -> Self {
+        Self { orders: vec![] }
+    }
+
+    pub fn orders(&self) -> &[BuildingWorkOrder] {
+        &self.orders
+    }
+
+    pub fn order_for(&self, building: &SimulationID) -> Option<&BuildingWorkOrder> {
+        self.orders.iter().find(|order| &order.building == building)
+    }
+
+    /// Damage a building. If it loses all its health points, it is destroyed and removed from its tile.
+    pub fn damage(
+        &mut self,
+        map: &mut HexMap,
+        position: &CubeCoords,
+        id: &SimulationID,
+        amount: u16,
+        source: BuildingDamageSource,
+    ) -> Result<Vec<BuildingLifecycleEvent>, BuildingLifecycleError> {
+        let tile = map
+            .tile_mut(position)
+            .ok_or(BuildingLifecycleError::UnknownBuilding)?;
+        let building =
+            Building::find_on_tile_mut(tile, id).ok_or(BuildingLifecycleError::UnknownBuilding)?;
+        building.damage(amount);
+        let mut events = vec![BuildingLifecycleEvent::Damaged {
+            building: id.clone(),
+            source,
+            health_points: building.health_points(),
+        }];
+        if building.is_destroyed() {
+            Building::remove_from_tile(tile, id);
+            events.extend(self.cancel(id).map(|order| order.cancelled()));
+            events.push(BuildingLifecycleEvent::Destroyed {
+                building: id.clone(),
+                position: *position,
+            });
+        }
+        Ok(events)
+    }
+
+    /// Demolish a building, recovering part of its construction cost depending on its health, and the cost of its
+    /// pending upgrade.
+    pub fn demolish(
+        &mut self,
+        map: &mut HexMap,
+        position: &CubeCoords,
+        id: &SimulationID,
+        treasury: &mut ResourceDataStorage,
+    ) -> Result<Vec<BuildingLifecycleEvent>, BuildingLifecycleError> {
+        let building = map
+            .tile_mut(position)
+            .and_then(|tile| Building::remove_from_tile(tile, id))
+            .ok_or(BuildingLifecycleError::UnknownBuilding)?;
+        let mut events = vec![];
+        if let Some(order) = self.cancel(id) {
+            events.push(order.refund(treasury));
+        }
+
+        let ratio = BUILDING_SALVAGE_RATIO * building.efficiency();
+        let salvage: ConstructionCosts = building
+            .template()
+            .cost()
+            .iter()
+            .map(|(resource, quantity)| (*resource, (*quantity as f64 * ratio) as ResourceQuantity))
+            .filter(|(_, quantity)| *quantity > 0)
+            .collect();
+        for (resource, quantity) in &salvage {
+            treasury.replenish(*resource, *quantity);
+        }
+        events.push(BuildingLifecycleEvent::Demolished {
+            building: id.clone(),
+            salvage,
+        });
+        Ok(events)
+    }
+
+    /// Order the repair of a damaged building.
+    pub fn order_repair(
+        &mut self,
+        map: &HexMap,
+        position: &CubeCoords,
+        id: &SimulationID,
+    ) -> Result<(), BuildingLifecycleError> {
+        let building = map
+            .tile(position)
+            .and_then(|tile| Building::find_on_tile(tile, id))
+            .ok_or(BuildingLifecycleError::UnknownBuilding)?;
+        if building.health_points() >= building.template().max_health_points() {
+            return Err(BuildingLifecycleError::NotDamaged);
+        }
+        if self.order_for(id).is_some() {
+            return Err(BuildingLifecycleError::AlreadyOrdered);
+        }
+        self.orders.push(BuildingWorkOrder {
+            building: id.clone(),
+            position: *position,
+            work: BuildingWork::Repair,
+        });
+        Ok(())
+    }
+
+    /// Order the upgrade of a building to the given template, paying for it right away.
+    pub fn order_upgrade(
+        &mut self,
+        map: &HexMap,
+        position: &CubeCoords,
+        id: &SimulationID,
+        template: &'static BuildingTemplate,
+        treasury: &mut ResourceDataStorage,
+    ) -> Result<(), BuildingLifecycleError> {
+        let building = map
+            .tile(position)
+            .and_then(|tile| Building::find_on_tile(tile, id))
+            .ok_or(BuildingLifecycleError::UnknownBuilding)?;
+        let upgrade = building
+            .template()
+            .upgrade_to(template.id())
+            .ok_or(BuildingLifecycleError::NoUpgradePath)?;
+        if self.order_for(id).is_some() {
+            return Err(BuildingLifecycleError::AlreadyOrdered);
+        }
+        treasury
+            .consume_all(upgrade.cost())
+            .map_err(BuildingLifecycleError::CannotAfford)?;
+        self.orders.push(BuildingWorkOrder {
+            building: id.clone(),
+            position: *position,
+            work: BuildingWork::Upgrade {
+                template,
+                progress: 0,
+                paid: upgrade.cost().clone(),
+            },
+        });
+        Ok(())
+    }
+
+    /// Cancel the work order on a building. Nothing is refunded, see `BuildingWorkOrder::paid`.
+    pub fn cancel(&mut self, id: &SimulationID) -> Option<BuildingWorkOrder> {
+        let index = self.orders.iter().position(|order| &order.building == id)?;
+        Some(self.orders.remove(index))
+    }
+
+    /// Called every turn. Progress all the work orders.
+    pub fn update(
+        &mut self,
+        map: &mut HexMap,
+        treasury: &mut ResourceDataStorage,
+    ) -> Vec<BuildingLifecycleEvent> {
+        let mut events = vec![];
+        self.orders.retain_mut(|order| {
+            let Some(building) = map
+                .tile_mut(&order.position)
+                .and_then(|tile| Building::find_on_tile_mut(tile, &order.building))
+            else {
+                events.push(order.refund(treasury));
+                return false;
+            };
+            match &mut order.work {
+                BuildingWork::Repair => {
+                    let max_health_points = building.template().max_health_points();
+                    let missing = max_health_points.saturating_sub(building.health_points());
+                    let amount = ((max_health_points as f64 * BUILDING_REPAIR_RATE).ceil() as u16)
+                        .min(missing);
+                    let ratio =
+                        BUILDING_REPAIR_COST_RATIO * amount as f64 / max_health_points as f64;
+                    let cost: ConstructionCosts = building
+                        .template()
+                        .cost()
+                        .iter()
+                        .map(|(resource, quantity)| {
+                            (
+                                *resource,
+                                (*quantity as f64 * ratio).ceil() as ResourceQuantity,
+                            )
+                        })
+                        .collect();
+                    if let Err(resource) = treasury.consume_all(&cost) {
+                        events.push(BuildingLifecycleEvent::WorkStalled {
+                            building: order.building.clone(),
+                            resource,
+                        });
+                        return true;
+                    }
+                    building.repair(amount);
+                    events.push(BuildingLifecycleEvent::Repaired {
+                        building: order.building.clone(),
+                        health_points: building.health_points(),
+                    });
+                    building.health_points() < max_health_points
+                }
+                BuildingWork::Upgrade {
+                    template, progress, ..
+                } => {
+                    *progress += 1;
+                    let build_time = building
+                        .template()
+                        .upgrade_to(template.id())
+                        .map_or(1, |upgrade| upgrade.build_time());
+                    if *progress < build_time {
+                        return true;
+                    }
+                    building.upgrade(template);
+                    events.push(BuildingLifecycleEvent::Upgraded {
+                        building: order.building.clone(),
+                        template: template.id().clone(),
+                    });
+                    false
+                }
+            }
+        });
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::{
+        hex_map::{coordinates::CubeCoords, tile::HexMapTile, HexMap, HexMapStorage},
+        simulation::{
+            buildings::{Building, BuildingTemplate, BuildingUpgradePath},
+            ids::SimulationID,
+            resources::{Resource, ResourceDataStorage, ResourceDataStore},
+        },
+    };
+
+    use super::{BuildingDamageSource, BuildingLifecycleEvent, BuildingsLifecycle};
+
+    fn build_mock_templates() -> (&'static BuildingTemplate, &'static BuildingTemplate) {
+        let workshop = BuildingTemplate::new(
+            SimulationID::new_abstract_id("workshop"),
+            "workshop".into(),
+            HashMap::from([(Resource::Metals, 100)]),
+            HashMap::new(),
+            HashMap::new(),
+        )
+        .with_upgrade(BuildingUpgradePath::new(
+            SimulationID::new_abstract_id("factory"),
+            HashMap::from([(Resource::Metals, 50)]),
+            2,
+        ));
+        let factory = BuildingTemplate::new(
+            SimulationID::new_abstract_id("factory"),
+            "factory".into(),
+            HashMap::from([(Resource::Metals, 150)]),
+            HashMap::new(),
+            HashMap::new(),
+        )
+        .with_max_health_points(200);
+        (Box::leak(Box::new(workshop)), Box::leak(Box::new(factory)))
+    }
+
+    fn build_mock_map(template: &'static BuildingTemplate, position: CubeCoords) -> HexMap {
+        let mut tile = HexMapTile::from_properties(0);
+        tile.layer_artificial_mut()
+            .add_building(Box::new(Building::new(
+                SimulationID::new_map_entity_id(1),
+                template,
+                100,
+            )));
+        let mut tiles = HexMapStorage::new();
+        tiles.insert(position, tile);
+        HexMap::from_tiles(tiles)
+    }
+
+    #[test]
+    fn test_building_damage_and_repair() {
+        let (workshop, factory) = build_mock_templates();
+        let position = CubeCoords::from_axial_coords(0, 0);
+        let id = SimulationID::new_map_entity_id(1);
+        let mut map = build_mock_map(workshop, position);
+        let mut lifecycle = BuildingsLifecycle::new();
+        let mut treasury = ResourceDataStorage::new();
+
+        lifecycle
+            .damage(&mut map, &position, &id, 15, BuildingDamageSource::Combat)
+            .unwrap();
+        let building = Building::find_on_tile(map.tile(&position).unwrap(), &id).unwrap();
+        assert_eq!(building.efficiency(), 0.85);
+
+        lifecycle.order_repair(&map, &position, &id).unwrap();
+        assert_eq!(
+            lifecycle.update(&mut map, &mut treasury),
+            vec![BuildingLifecycleEvent::WorkStalled {
+                building: id.clone(),
+                resource: Resource::Metals,
+            }]
+        );
+        treasury.replenish(Resource::Metals, 100);
+        lifecycle.update(&mut map, &mut treasury);
+        lifecycle.update(&mut map, &mut treasury);
+        assert!(lifecycle.orders().is_empty());
+        assert_eq!(treasury.quantity_of(Resource::Metals), 92);
+        let building = Building::find_on_tile(map.tile(&position).unwrap(), &id).unwrap();
+        assert_eq!(building.health_points(), 100);
+
+        // the paid upgrade is reported for the caller to refund
+        lifecycle
+            .order_upgrade(&map, &position, &id, factory, &mut treasury)
+            .unwrap();
+        let events = lifecycle
+            .damage(&mut map, &position, &id, 500, BuildingDamageSource::Event)
+            .unwrap();
+        assert_eq!(
+            events[1..],
+            [
+                BuildingLifecycleEvent::WorkCancelled {
+                    building: id.clone(),
+                    refund: HashMap::from([(Resource::Metals, 50)]),
+                },
+                BuildingLifecycleEvent::Destroyed {
+                    building: id,
+                    position,
+                }
+            ]
+        );
+        assert!(lifecycle.orders().is_empty());
+        assert!(!map
+            .tile(&position)
+            .unwrap()
+            .layer_artificial()
+            .has_buildings());
+    }
+
+    #[test]
+    fn test_building_upgrade_and_demolition() {
+        let (workshop, factory) = build_mock_templates();
+        let position = CubeCoords::from_axial_coords(0, 0);
+        let id = SimulationID::new_map_entity_id(1);
+        let mut map = build_mock_map(workshop, position);
+        let mut lifecycle = BuildingsLifecycle::new();
+        let mut treasury = ResourceDataStorage::new();
+        treasury.replenish(Resource::Metals, 50);
+
+        lifecycle
+            .order_upgrade(&map, &position, &id, factory, &mut treasury)
+            .unwrap();
+        assert!(lifecycle.update(&mut map, &mut treasury).is_empty());
+        assert_eq!(
+            lifecycle.update(&mut map, &mut treasury),
+            vec![BuildingLifecycleEvent::Upgraded {
+                building: id.clone(),
+                template: SimulationID::new_abstract_id("factory"),
+            }]
+        );
+        let building = Building::find_on_tile(map.tile(&position).unwrap(), &id).unwrap();
+        assert_eq!(building.health_points(), 200);
+
+        let events = lifecycle
+            .demolish(&mut map, &position, &id, &mut treasury)
+            .unwrap();
+        assert_eq!(
+            events,
+            vec![BuildingLifecycleEvent::Demolished {
+                building: id,
+                salvage: HashMap::from([(Resource::Metals, 37)]),
+            }]
+        );
+        assert_eq!(treasury.quantity_of(Resource::Metals), 37);
+
+        // demolishing a building being upgraded refunds the upgrade
+        let id = SimulationID::new_map_entity_id(1);
+        let mut map = build_mock_map(workshop, position);
+        treasury.replenish(Resource::Metals, 13);
+        lifecycle
+            .order_upgrade(&map, &position, &id, factory, &mut treasury)
+            .unwrap();
+        let events = lifecycle
+            .demolish(&mut map, &position, &id, &mut treasury)
+            .unwrap();
+        assert_eq!(
+            events[0],
+            BuildingLifecycleEvent::WorkCancelled {
+                building: id,
+                refund: HashMap::from([(Resource::Metals, 50)]),
+            }
+        );
+        assert!(lifecycle.orders().is_empty());
+        assert_eq!(treasury.quantity_of(Resource::Metals), 50 + 25);
+    }
+}
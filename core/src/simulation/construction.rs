@@ -0,0 +1,552 @@
+//! Construction of buildings on the `HexMap`.
+//!
+//! Construction orders are queued either for a settlement (the settlement builds one thing at a time) or for a single
+//! tile (e.g. for remote facilities). Only the order at the head of a queue progresses each turn, and once its build
+//! time is elapsed, the resulting `Building` is attached to the artificial layer of its tile.
+//!
+//! Orders borrow their templates for as long as they are queued. Only progressing them needs templates outliving
+//! the `HexMap`, which stores the completed buildings.
+
+use std::collections::VecDeque;
+
+use crate::hex_map::{coordinates::CubeCoords, HexMap};
+
+use super::{
+    buildings::{Building, BuildingPlacementError, BuildingTemplate},
+    economy::ConstructionCosts,
+    ids::{SimulationID, WithSimulationID},
+    resources::{Resource, ResourceDataStorage, ResourceDataStore, ResourceQuantity},
+};
+
+/// Share of the already paid costs given back when cancelling a construction order.
+pub const CONSTRUCTION_CANCELLATION_REFUND_RATIO: f64 = 0.5;
+
+/// How the construction costs of an order are paid.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ConstructionPayment {
+    /// Everything is paid when the order is queued.
+    Upfront,
+    /// An equal share is paid every turn of construction, which stalls if it cannot be paid.
+    PerTurn,
+}
+
+/// Who a construction queue belongs to.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ConstructionQueueKey {
+    /// Must be the `SimulationID` of a `Settlement`.
+    Settlement(SimulationID),
+    Tile(CubeCoords),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ConstructionError {
+    Placement(BuildingPlacementError),
+    CannotAfford(Resource),
+    DuplicateOrder,
+    UnknownOrder,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ConstructionEvent {
+    /// The order could not progress this turn, for lack of the given resource.
+    Stalled {
+        order: SimulationID,
+        resource: Resource,
+    },
+    /// The building was built and attached to its tile.
+    Completed {
+        building: SimulationID,
+        position: CubeCoords,
+    },
+    /// The tile of the order is no longer on the map: the order was dropped and everything paid refunded.
+    Cancelled {
+        order: SimulationID,
+        refund: ConstructionCosts,
+    },
+}
+
+#[derive(Debug)]
+pub struct ConstructionOrder<'a> {
+    /// Must be `SimulationID::MapEntityID`. Becomes the ID of the completed `Building`.
+    id: SimulationID,
+    template: &'a BuildingTemplate,
+    position: CubeCoords,
+    /// Must be the `SimulationID` of a `Nation`, which will own the completed `Building`.
+    owner: Option<SimulationID>,
+    payment: ConstructionPayment,
+    /// Number of turns of construction done.
+    progress: u16,
+    paid: ConstructionCosts,
+}
+
+impl<'a> ConstructionOrder<'a> {
+    pub fn new(
+        id: SimulationID,
+        template: &'a BuildingTemplate,
+        position: CubeCoords,
+        payment: ConstructionPayment,
+    ) -> Self {
+        assert!(matches!(id, SimulationID::MapEntityID(_)));
+        Self {
+            id,
+            template,
+            position,
+            owner: None,
+            payment,
+            progress: 0,
+            paid: ConstructionCosts::new(),
+        }
+    }
+
+    pub fn with_owner(mut self, owner: SimulationID) -> Self {
+        assert!(matches!(owner, SimulationID::Abstract(_)));
+        self.owner = Some(owner);
+        self
+    }
+
+    pub fn template(&self) -> &'a BuildingTemplate {
+        self.template
+    }
+
+    pub fn position(&self) -> &CubeCoords {
+        &self.position
+    }
+
+    pub fn owner(&self) -> Option<&SimulationID> {
+        self.owner.as_ref()
+    }
+
+    pub fn payment(&self) -> ConstructionPayment {
+        self.payment
+    }
+
+    pub fn progress(&self) -> u16 {
+        self.progress
+    }
+
+    pub fn remaining_turns(&self) -> u16 {
+        self.template.build_time() - self.progress
+    }
+
+    pub fn paid(&self) -> &ConstructionCosts {
+        &self.paid
+    }
+
+    /// What is due for the next turn of construction. The last turn pays whatever remains.
+    fn next_installment(&self) -> ConstructionCosts {
+        if self.payment == ConstructionPayment::Upfront {
+            return ConstructionCosts::new();
+        }
+        let build_time = self.template.build_time();
+        self.template
+            .cost()
+            .iter()
+            .map(|(resource, total)| {
+                let already_paid = self.paid.get(resource).copied().unwrap_or(0);
+                let installment = if self.progress + 1 == build_time {
+                    total - already_paid
+                } else {
+                    total.div_ceil(build_time).min(total - already_paid)
+                };
+                (*resource, installment)
+            })
+            .collect()
+    }
+}
+
+impl<'a> WithSimulationID for ConstructionOrder<'a> {
+    fn id(&self) -> &SimulationID {
+        &self.id
+    }
+}
+
+#[derive(Debug)]
+pub struct ConstructionQueue<'a> {
+    key: ConstructionQueueKey,
+    orders: VecDeque<ConstructionOrder<'a>>,
+}
+
+impl<'a> ConstructionQueue<'a> {
+    pub fn key(&self) -> &ConstructionQueueKey {
+        &self.key
+    }
+
+    pub fn orders(&self) -> impl Iterator<Item = &ConstructionOrder<'a>> {
+        self.orders.iter()
+    }
+}
+
+/// All the construction queues in the simulation.
+#[derive(Debug, Default)]
+pub struct ConstructionQueues<'a> {
+    /// Kept in creation order, so that resources are spent in a deterministic order.
+    queues: Vec<ConstructionQueue<'a>>,
+}
+
+impl<'a> ConstructionQueues<'a> {
+    pub fn new() -> Self {
+        Self { queues: vec![] }
+    }
+
+    pub fn queue(&self, key: &ConstructionQueueKey) -> Option<&ConstructionQueue<'a>> {
+        self.queues.iter().find(|queue| &queue.key == key)
+    }
+
+    pub fn order(&self, id: &SimulationID) -> Option<&ConstructionOrder<'a>> {
+        self.orders().find(|order| order.id() == id)
+    }
+
+    pub fn orders(&self) -> impl Iterator<Item = &ConstructionOrder<'a>> {
+        self.queues.iter().flat_map(|queue| queue.orders.iter())
+    }
+
+    /// Iterate over the orders planned on the given tile, whatever their queue.
+    pub fn orders_at<'s>(
+        &'s self,
+        position: &'s CubeCoords,
+    ) -> impl Iterator<Item = &'s ConstructionOrder<'a>> + 's {
+        self.orders()
+            .filter(move |order| &order.position == position)
+    }
+
+    /// Validate and queue a new construction order, paying for it right away if `ConstructionPayment::Upfront`.
+    pub fn enqueue(
+        &mut self,
+        map: &HexMap,
+        key: ConstructionQueueKey,
+        mut order: ConstructionOrder<'a>,
+        treasury: &mut ResourceDataStorage,
+    ) -> Result<(), ConstructionError> {
+        if self.order(&order.id).is_some() {
+            return Err(ConstructionError::DuplicateOrder);
+        }
+        let tile = map
+            .tile(&order.position)
+            .ok_or(ConstructionError::Placement(
+                BuildingPlacementError::OutOfMap,
+            ))?;
+        let planned: Vec<&BuildingTemplate> = self
+            .orders_at(&order.position)
+            .map(|order| order.template())
+            .collect();
+        order
+            .template
+            .validate_placement(tile, &planned)
+            .map_err(ConstructionError::Placement)?;
+
+        if order.payment == ConstructionPayment::Upfront {
+            treasury
+                .consume_all(order.template.cost())
+                .map_err(ConstructionError::CannotAfford)?;
+            order.paid = order.template.cost().clone();
+        }
+
+        match self.queues.iter_mut().find(|queue| queue.key == key) {
+            Some(queue) => queue.orders.push_back(order),
+            None => self.queues.push(ConstructionQueue {
+                key,
+                orders: VecDeque::from([order]),
+            }),
+        }
+        Ok(())
+    }
+
+    /// Cancel an order, refunding part of what was already paid for it.
+    pub fn cancel(
+        &mut self,
+        id: &SimulationID,
+        treasury: &mut ResourceDataStorage,
+    ) -> Result<ConstructionCosts, ConstructionError> {
+        let (queue_index, order_index) = self
+            .queues
+            .iter()
+            .enumerate()
+            .find_map(|(queue_index, queue)| {
+                queue
+                    .orders
+                    .iter()
+                    .position(|order| order.id() == id)
+                    .map(|order_index| (queue_index, order_index))
+            })
+            .ok_or(ConstructionError::UnknownOrder)?;
+        let order = self.queues[queue_index]
+            .orders
+            .remove(order_index)
+            .expect("order index must be valid");
+
+        let refund: ConstructionCosts = order
+            .paid
+            .iter()
+            .map(|(resource, quantity)| {
+                let refunded = *quantity as f64 * CONSTRUCTION_CANCELLATION_REFUND_RATIO;
+                (*resource, refunded as ResourceQuantity)
+            })
+            .filter(|(_, quantity)| *quantity > 0)
+            .collect();
+        for (resource, quantity) in &refund {
+            treasury.replenish(*resource, *quantity);
+        }
+        Ok(refund)
+    }
+}
+
+impl ConstructionQueues<'static> {
+    /// Called every turn. Progress the order at the head of every queue, attaching completed buildings to the map.
+    ///
+    /// An order whose tile is no longer on the map is cancelled, and everything paid for it refunded.
+    pub fn update(
+        &mut self,
+        map: &mut HexMap,
+        treasury: &mut ResourceDataStorage,
+    ) -> Vec<ConstructionEvent> {
+        let mut events = vec![];
+        for queue in &mut self.queues {
+            let Some(order) = queue.orders.front_mut() else {
+                continue;
+            };
+            if map.tile(&order.position).is_none() {
+                let order = queue.orders.pop_front().expect("queue head must exist");
+                for (resource, quantity) in &order.paid {
+                    treasury.replenish(*resource, *quantity);
+                }
+                events.push(ConstructionEvent::Cancelled {
+                    order: order.id,
+                    refund: order.paid,
+                });
+                continue;
+            }
+
+            let installment = order.next_installment();
+            if let Err(resource) = treasury.consume_all(&installment) {
+                events.push(ConstructionEvent::Stalled {
+                    order: order.id.clone(),
+                    resource,
+                });
+                continue;
+            }
+            for (resource, quantity) in installment {
+                *order.paid.entry(resource).or_insert(0) += quantity;
+            }
+            order.progress += 1;
+
+            if order.progress >= order.template.build_time() {
+                let order = queue.orders.pop_front().expect("queue head must exist");
+                let health_points = order.template.max_health_points();
+                let tile = map
+                    .tile_mut(&order.position)
+                    .expect("tile of the order must be on the map");
+                let mut building = Building::new(order.id.clone(), order.template, health_points);
+                building.set_owner(order.owner);
+                tile.layer_artificial_mut().add_building(Box::new(building));
+                events.push(ConstructionEvent::Completed {
+                    building: order.id,
+                    position: order.position,
+                });
+            }
+        }
+        self.queues.retain(|queue| !queue.orders.is_empty());
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::{
+        hex_map::{
+            coordinates::CubeCoords, layers::natural::HexMapTerrain, tile::HexMapTile, HexMap,
+            HexMapStorage,
+        },
+        simulation::{
+            buildings::{
+                Building, BuildingPlacementError, BuildingPlacementRules, BuildingTemplate,
+            },
+            ids::SimulationID,
+            resources::{Resource, ResourceDataStorage, ResourceDataStore},
+        },
+    };
+
+    use super::{
+        ConstructionError, ConstructionEvent, ConstructionOrder, ConstructionPayment,
+        ConstructionQueueKey, ConstructionQueues,
+    };
+
+    fn build_mock_map() -> HexMap {
+        let mut tiles = HexMapStorage::new();
+        tiles.insert(
+            CubeCoords::from_axial_coords(0, 0),
+            HexMapTile::from_properties(10),
+        );
+        tiles.insert(
+            CubeCoords::from_axial_coords(1, 0),
+            HexMapTile::from_terrain(-20, HexMapTerrain::Sea),
+        );
+        HexMap::from_tiles(tiles)
+    }
+
+    fn build_mock_template() -> BuildingTemplate {
+        BuildingTemplate::new(
+            SimulationID::new_abstract_id("factory"),
+            "factory".into(),
+            HashMap::from([(Resource::Metals, 30)]),
+            HashMap::new(),
+            HashMap::new(),
+        )
+        .with_build_time(3)
+        .with_placement(BuildingPlacementRules::new(vec![], None, None, false, true))
+    }
+
+    #[test]
+    fn test_construction_placement() {
+        let map = build_mock_map();
+        let template = &build_mock_template();
+        let mut treasury = ResourceDataStorage::new();
+        let mut queues = ConstructionQueues::new();
+        let key = ConstructionQueueKey::Tile(CubeCoords::from_axial_coords(0, 0));
+
+        assert_eq!(
+            queues.enqueue(
+                &map,
+                key.clone(),
+                ConstructionOrder::new(
+                    SimulationID::new_map_entity_id(1),
+                    template,
+                    CubeCoords::from_axial_coords(1, 0),
+                    ConstructionPayment::PerTurn,
+                ),
+                &mut treasury,
+            ),
+            Err(ConstructionError::Placement(
+                BuildingPlacementError::Terrain(HexMapTerrain::Sea)
+            ))
+        );
+        assert_eq!(
+            queues.enqueue(
+                &map,
+                key.clone(),
+                ConstructionOrder::new(
+                    SimulationID::new_map_entity_id(1),
+                    template,
+                    CubeCoords::from_axial_coords(0, 0),
+                    ConstructionPayment::Upfront,
+                ),
+                &mut treasury,
+            ),
+            Err(ConstructionError::CannotAfford(Resource::Metals))
+        );
+        treasury.replenish(Resource::Metals, 30);
+        assert!(queues
+            .enqueue(
+                &map,
+                key.clone(),
+                ConstructionOrder::new(
+                    SimulationID::new_map_entity_id(1),
+                    template,
+                    CubeCoords::from_axial_coords(0, 0),
+                    ConstructionPayment::Upfront,
+                ),
+                &mut treasury,
+            )
+            .is_ok());
+        assert_eq!(treasury.quantity_of(Resource::Metals), 0);
+        // exclusive building already planned on the tile
+        assert_eq!(
+            queues.enqueue(
+                &map,
+                key,
+                ConstructionOrder::new(
+                    SimulationID::new_map_entity_id(2),
+                    template,
+                    CubeCoords::from_axial_coords(0, 0),
+                    ConstructionPayment::PerTurn,
+                ),
+                &mut treasury,
+            ),
+            Err(ConstructionError::Placement(
+                BuildingPlacementError::Occupied
+            ))
+        );
+
+        let refund = queues
+            .cancel(&SimulationID::new_map_entity_id(1), &mut treasury)
+            .unwrap();
+        assert_eq!(refund.get(&Resource::Metals), Some(&15));
+        assert_eq!(treasury.quantity_of(Resource::Metals), 15);
+    }
+
+    #[test]
+    fn test_construction_progression() {
+        let mut map = build_mock_map();
+        let template: &'static BuildingTemplate = Box::leak(Box::new(build_mock_template()));
+        let position = CubeCoords::from_axial_coords(0, 0);
+        let mut treasury = ResourceDataStorage::new();
+        treasury.replenish(Resource::Metals, 15);
+        let mut queues = ConstructionQueues::new();
+        queues
+            .enqueue(
+                &map,
+                ConstructionQueueKey::Settlement(SimulationID::new_map_entity_id(100)),
+                ConstructionOrder::new(
+                    SimulationID::new_map_entity_id(1),
+                    template,
+                    position,
+                    ConstructionPayment::PerTurn,
+                ),
+                &mut treasury,
+            )
+            .unwrap();
+
+        assert!(queues.update(&mut map, &mut treasury).is_empty());
+        assert_eq!(treasury.quantity_of(Resource::Metals), 5);
+        assert_eq!(
+            queues.update(&mut map, &mut treasury),
+            vec![ConstructionEvent::Stalled {
+                order: SimulationID::new_map_entity_id(1),
+                resource: Resource::Metals,
+            }]
+        );
+        treasury.replenish(Resource::Metals, 15);
+        assert!(queues.update(&mut map, &mut treasury).is_empty());
+        assert_eq!(
+            queues.update(&mut map, &mut treasury),
+            vec![ConstructionEvent::Completed {
+                building: SimulationID::new_map_entity_id(1),
+                position,
+            }]
+        );
+        assert_eq!(treasury.quantity_of(Resource::Metals), 0);
+        assert!(queues.orders().next().is_none());
+        let tile = map.tile(&position).unwrap();
+        assert_eq!(Building::all_on_tile(tile).count(), 1);
+
+        // the tile of an order paid upfront is gone: everything paid is given back
+        let mut tiles = HexMapStorage::new();
+        let elsewhere = CubeCoords::from_axial_coords(2, 0);
+        tiles.insert(elsewhere, HexMapTile::from_properties(10));
+        treasury.replenish(Resource::Metals, 30);
+        queues
+            .enqueue(
+                &HexMap::from_tiles(tiles),
+                ConstructionQueueKey::Tile(elsewhere),
+                ConstructionOrder::new(
+                    SimulationID::new_map_entity_id(2),
+                    template,
+                    elsewhere,
+                    ConstructionPayment::Upfront,
+                ),
+                &mut treasury,
+            )
+            .unwrap();
+        assert_eq!(treasury.quantity_of(Resource::Metals), 0);
+        assert_eq!(
+            queues.update(&mut map, &mut treasury),
+            vec![ConstructionEvent::Cancelled {
+                order: SimulationID::new_map_entity_id(2),
+                refund: HashMap::from([(Resource::Metals, 30)]),
+            }]
+        );
+        assert_eq!(treasury.quantity_of(Resource::Metals), 30);
+        assert!(queues.orders().next().is_none());
+    }
+}
@@ -0,0 +1,1031 @@
+//! Diplomacy between `Nation`s.
+//!
+//! Every pair of nations has a relation: a score of how much they like each other, and a diplomatic state (peace,
+//! war, alliance, non-aggression pact or vassalage). States other than peace and war are set by treaties, which
+//! can have terms (reparations, tributes, ceded settlements) and a limited duration. Peace treaties last
+//! `PEACE_TREATY_DURATION` turns unless given another duration.
+//!
+//! Declaring war on a nation requires breaking the treaties signed with it first, and costs relations unless the
+//! aggressor holds a casus belli against its target. The allies and vassals of the target, and the vassals of the
+//! aggressor, are called to arms. Wars end with a peace treaty, which the other side accepts depending on its
+//! relation with the proposer, the war weariness and the terms of the treaty.
+//!
+//! The other subsystems rely on `Diplomacy::are_hostile` to decide who fights whom: combats require
+//! `Belligerents` and the movement of units `EnemyPositions`, which can only be built for nations at war.
+
+use std::collections::HashSet;
+
+use crate::hex_map::coordinates::CubeCoords;
+
+use super::{
+    ids::{SimulationID, WithSimulationID},
+    nations::Nation,
+    resources::{Resource, ResourceDataStore, ResourceQuantity},
+};
+
+/// Lowest relation score, of sworn enemies.
+pub const RELATION_MIN: i16 = -100;
+
+/// Highest relation score, of the best friends.
+pub const RELATION_MAX: i16 = 100;
+
+/// Relation scores move back toward `0` by this amount every turn.
+pub const RELATION_DRIFT: i16 = 1;
+
+/// Relation lost by an aggressor with its target, and half of it with all the other nations it has relations
+/// with, when declaring a war without casus belli.
+pub const UNJUSTIFIED_WAR_PENALTY: i16 = 40;
+
+/// Relation lost by a nation with the other party of a treaty it breaks.
+pub const BROKEN_TREATY_PENALTY: i16 = 30;
+
+/// Willingness to accept peace gained every turn of war.
+pub const WAR_WEARINESS_PER_TURN: i16 = 5;
+
+/// Number of turns a casus belli can be used for.
+pub const CASUS_BELLI_DURATION: u16 = 20;
+
+/// Number of turns a peace treaty lasts by default, before war can be declared again without breaking it.
+pub const PEACE_TREATY_DURATION: u16 = 30;
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum DiplomaticState {
+    #[default]
+    Peace,
+    War,
+    Alliance,
+    NonAggressionPact,
+    /// One of the parties is the vassal of the other.
+    Vassalage {
+        /// Must be the `SimulationID` of a `Nation`.
+        overlord: SimulationID,
+    },
+}
+
+/// Relation between two nations.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Relation {
+    /// Must be the `SimulationID`s of `Nation`s.
+    parties: [SimulationID; 2],
+    /// From `RELATION_MIN` to `RELATION_MAX`.
+    score: i16,
+    state: DiplomaticState,
+    /// Number of turns since the state last changed.
+    turns_in_state: u16,
+}
+
+impl Relation {
+    pub fn parties(&self) -> &[SimulationID; 2] {
+        &self.parties
+    }
+
+    pub fn score(&self) -> i16 {
+        self.score
+    }
+
+    pub fn state(&self) -> &DiplomaticState {
+        &self.state
+    }
+
+    pub fn turns_in_state(&self) -> u16 {
+        self.turns_in_state
+    }
+
+    pub fn involves(&self, nation: &SimulationID) -> bool {
+        self.parties.contains(nation)
+    }
+
+    /// The party of the relation which is not the given nation.
+    pub fn other(&self, nation: &SimulationID) -> &SimulationID {
+        if &self.parties[0] == nation {
+            &self.parties[1]
+        } else {
+            &self.parties[0]
+        }
+    }
+
+    fn is_between(&self, a: &SimulationID, b: &SimulationID) -> bool {
+        a != b && self.involves(a) && self.involves(b)
+    }
+
+    fn set_state(&mut self, state: DiplomaticState) {
+        self.state = state;
+        self.turns_in_state = 0;
+    }
+
+    fn change_score(&mut self, delta: i16) {
+        self.score = self
+            .score
+            .saturating_add(delta)
+            .clamp(RELATION_MIN, RELATION_MAX);
+    }
+}
+
+/// A term of a treaty.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TreatyTerm {
+    /// `Resource::Credits` paid once, when the treaty is signed, to the other party.
+    Reparations {
+        /// Must be the `SimulationID` of a `Nation`.
+        payer: SimulationID,
+        amount: ResourceQuantity,
+    },
+    /// `Resource::Credits` paid every turn to the other party, as long as the treaty lasts.
+    Tribute {
+        /// Must be the `SimulationID` of a `Nation`.
+        payer: SimulationID,
+        amount: ResourceQuantity,
+    },
+    /// Settlement handed over when the treaty is signed.
+    CedeSettlement {
+        /// Must be the `SimulationID` of a `Settlement`.
+        settlement: SimulationID,
+        /// Must be the `SimulationID` of a `Nation`.
+        to: SimulationID,
+    },
+}
+
+impl TreatyTerm {
+    /// How much the term costs to a nation, in relation score: positive if it loses from it, negative if it
+    /// gains from it.
+    pub fn cost_for(&self, nation: &SimulationID) -> i16 {
+        let (loser, cost) = match self {
+            Self::Reparations { payer, amount } => (
+                payer == nation,
+                i16::try_from(*amount / 10).unwrap_or(i16::MAX),
+            ),
+            Self::Tribute { payer, amount } => {
+                (payer == nation, i16::try_from(*amount).unwrap_or(i16::MAX))
+            }
+            Self::CedeSettlement { to, .. } => (to != nation, 25),
+        };
+        if loser {
+            cost
+        } else {
+            -cost
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TreatyKind {
+    /// Ends a war.
+    Peace,
+    Alliance,
+    NonAggressionPact,
+    Vassalage {
+        /// Must be the `SimulationID` of a `Nation`.
+        overlord: SimulationID,
+    },
+}
+
+impl TreatyKind {
+    /// Diplomatic state between the parties while the treaty lasts.
+    pub fn state(&self) -> DiplomaticState {
+        match self {
+            Self::Peace => DiplomaticState::Peace,
+            Self::Alliance => DiplomaticState::Alliance,
+            Self::NonAggressionPact => DiplomaticState::NonAggressionPact,
+            Self::Vassalage { overlord } => DiplomaticState::Vassalage {
+                overlord: overlord.clone(),
+            },
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Treaty {
+    /// Must be `SimulationID::EntityID`.
+    id: SimulationID,
+    kind: TreatyKind,
+    /// Must be the `SimulationID`s of `Nation`s. The first one proposes the treaty.
+    parties: [SimulationID; 2],
+    terms: Vec<TreatyTerm>,
+    /// Number of turns the treaty lasts, `None` if it lasts until broken. Peace treaties always expire.
+    duration: Option<u16>,
+    elapsed: u16,
+}
+
+impl Treaty {
+    pub fn new(
+        id: SimulationID,
+        kind: TreatyKind,
+        proposer: SimulationID,
+        recipient: SimulationID,
+    ) -> Self {
+        assert!(matches!(id, SimulationID::EntityID(_)));
+        assert!(proposer != recipient);
+        if let TreatyKind::Vassalage { overlord } = &kind {
+            assert!(overlord == &proposer || overlord == &recipient);
+        }
+        let duration = (kind == TreatyKind::Peace).then_some(PEACE_TREATY_DURATION);
+        Self {
+            id,
+            kind,
+            parties: [proposer, recipient],
+            terms: vec![],
+            duration,
+            elapsed: 0,
+        }
+    }
+
+    pub fn with_term(mut self, term: TreatyTerm) -> Self {
+        self.terms.push(term);
+        self
+    }
+
+    pub fn with_duration(mut self, duration: u16) -> Self {
+        assert!(duration > 0);
+        self.duration = Some(duration);
+        self
+    }
+
+    pub fn kind(&self) -> &TreatyKind {
+        &self.kind
+    }
+
+    pub fn parties(&self) -> &[SimulationID; 2] {
+        &self.parties
+    }
+
+    pub fn terms(&self) -> &[TreatyTerm] {
+        &self.terms
+    }
+
+    pub fn duration(&self) -> Option<u16> {
+        self.duration
+    }
+
+    pub fn elapsed(&self) -> u16 {
+        self.elapsed
+    }
+
+    pub fn involves(&self, nation: &SimulationID) -> bool {
+        self.parties.contains(nation)
+    }
+
+    fn other(&self, nation: &SimulationID) -> &SimulationID {
+        if &self.parties[0] == nation {
+            &self.parties[1]
+        } else {
+            &self.parties[0]
+        }
+    }
+}
+
+impl WithSimulationID for Treaty {
+    fn id(&self) -> &SimulationID {
+        &self.id
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CasusBelliReason {
+    /// Claim on a settlement of the target.
+    Claim(SimulationID),
+    /// The target broke a treaty with the holder.
+    BrokenTreaty(SimulationID),
+    Insult,
+}
+
+/// Justification for a nation to declare war on another one.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CasusBelli {
+    /// Must be the `SimulationID` of a `Nation`.
+    holder: SimulationID,
+    /// Must be the `SimulationID` of a `Nation`.
+    target: SimulationID,
+    reason: CasusBelliReason,
+    /// Number of turns left to use it.
+    expires_in: u16,
+}
+
+impl CasusBelli {
+    pub fn holder(&self) -> &SimulationID {
+        &self.holder
+    }
+
+    pub fn target(&self) -> &SimulationID {
+        &self.target
+    }
+
+    pub fn reason(&self) -> &CasusBelliReason {
+        &self.reason
+    }
+
+    pub fn expires_in(&self) -> u16 {
+        self.expires_in
+    }
+}
+
+/// Two nations at war, the only ones which can fight each other (see `Diplomacy::belligerents`).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Belligerents {
+    /// Must be the `SimulationID` of a `Nation`.
+    attacker: SimulationID,
+    /// Must be the `SimulationID` of a `Nation`.
+    defender: SimulationID,
+}
+
+impl Belligerents {
+    pub fn attacker(&self) -> &SimulationID {
+        &self.attacker
+    }
+
+    pub fn defender(&self) -> &SimulationID {
+        &self.defender
+    }
+}
+
+/// Tiles occupied by the units of the nations at war with a nation (see `Diplomacy::enemy_positions`).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct EnemyPositions {
+    tiles: HashSet<CubeCoords>,
+}
+
+impl EnemyPositions {
+    /// No enemy anywhere.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn tiles(&self) -> &HashSet<CubeCoords> {
+        &self.tiles
+    }
+
+    pub fn contains(&self, tile: &CubeCoords) -> bool {
+        self.tiles.contains(tile)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DiplomacyError {
+    SameNation,
+    AlreadyAtWar,
+    NotAtWar,
+    /// A treaty between the nations must be broken first.
+    BoundByTreaty(SimulationID),
+    /// Vassals cannot declare war on their own.
+    Vassal,
+    UnknownTreaty(SimulationID),
+    DuplicateTreaty(SimulationID),
+    /// The recipient of the proposal turned it down.
+    Rejected,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DiplomaticEvent {
+    WarDeclared {
+        aggressor: SimulationID,
+        target: SimulationID,
+        justified: bool,
+    },
+    /// A nation called to arms by an ally or its overlord.
+    JoinedWar {
+        nation: SimulationID,
+        against: SimulationID,
+    },
+    TreatySigned(SimulationID),
+    TreatyBroken {
+        treaty: SimulationID,
+        breaker: SimulationID,
+    },
+    TreatyExpired(SimulationID),
+    TributePaid {
+        treaty: SimulationID,
+        payer: SimulationID,
+        amount: ResourceQuantity,
+    },
+    CasusBelliGained {
+        holder: SimulationID,
+        target: SimulationID,
+    },
+    CasusBelliExpired {
+        holder: SimulationID,
+        target: SimulationID,
+    },
+}
+
+/// All the diplomatic relations, treaties and casus belli in the simulation.
+#[derive(Debug, Default)]
+pub struct Diplomacy {
+    relations: Vec<Relation>,
+    treaties: Vec<Treaty>,
+    casus_belli: Vec<CasusBelli>,
+}
+
+impl Diplomacy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn relations(&self) -> &[Relation] {
+        &self.relations
+    }
+
+    pub fn relation(&self, a: &SimulationID, b: &SimulationID) -> Option<&Relation> {
+        self.relations
+            .iter()
+            .find(|relation| relation.is_between(a, b))
+    }
+
+    /// Diplomatic state between two nations, at peace if they never interacted.
+    pub fn state(&self, a: &SimulationID, b: &SimulationID) -> DiplomaticState {
+        self.relation(a, b)
+            .map_or(DiplomaticState::Peace, |relation| relation.state.clone())
+    }
+
+    /// Relation score between two nations, `0` if they never interacted.
+    pub fn score(&self, a: &SimulationID, b: &SimulationID) -> i16 {
+        self.relation(a, b).map_or(0, |relation| relation.score)
+    }
+
+    pub fn change_score(&mut self, a: &SimulationID, b: &SimulationID, delta: i16) {
+        self.relation_mut(a, b).change_score(delta);
+    }
+
+    pub fn are_hostile(&self, a: &SimulationID, b: &SimulationID) -> bool {
+        self.state(a, b) == DiplomaticState::War
+    }
+
+    pub fn are_allied(&self, a: &SimulationID, b: &SimulationID) -> bool {
+        self.state(a, b) == DiplomaticState::Alliance
+    }
+
+    pub fn overlord_of(&self, vassal: &SimulationID) -> Option<&SimulationID> {
+        self.relations
+            .iter()
+            .filter(|relation| relation.involves(vassal))
+            .find_map(|relation| match &relation.state {
+                DiplomaticState::Vassalage { overlord } if overlord != vassal => Some(overlord),
+                _ => None,
+            })
+    }
+
+    /// Nations at war with a nation.
+    pub fn enemies_of<'s>(
+        &'s self,
+        nation: &'s SimulationID,
+    ) -> impl Iterator<Item = &'s SimulationID> + 's {
+        self.relations
+            .iter()
+            .filter(move |relation| {
+                relation.involves(nation) && relation.state == DiplomaticState::War
+            })
+            .map(move |relation| relation.other(nation))
+    }
+
+    /// Nations allowed to fight each other, as expected by `CombatModel::resolve`.
+    pub fn belligerents(
+        &self,
+        attacker: &SimulationID,
+        defender: &SimulationID,
+    ) -> Result<Belligerents, DiplomacyError> {
+        if !self.are_hostile(attacker, defender) {
+            return Err(DiplomacyError::NotAtWar);
+        }
+        Ok(Belligerents {
+            attacker: attacker.clone(),
+            defender: defender.clone(),
+        })
+    }
+
+    /// Tiles occupied by the units of the nations at war with a nation, as expected by
+    /// `MovementModel::resolve`.
+    pub fn enemy_positions(&self, nation: &SimulationID, nations: &[Nation]) -> EnemyPositions {
+        let tiles = nations
+            .iter()
+            .filter(|other| self.are_hostile(nation, other.id()))
+            .flat_map(|other| other.order_of_battle().units())
+            .filter(|unit| !unit.is_destroyed())
+            .filter_map(|unit| unit.position().as_cube_coords())
+            .collect();
+        EnemyPositions { tiles }
+    }
+
+    pub fn treaties(&self) -> &[Treaty] {
+        &self.treaties
+    }
+
+    pub fn treaty(&self, id: &SimulationID) -> Option<&Treaty> {
+        self.treaties.iter().find(|treaty| treaty.id() == id)
+    }
+
+    pub fn treaties_between<'s>(
+        &'s self,
+        a: &'s SimulationID,
+        b: &'s SimulationID,
+    ) -> impl Iterator<Item = &'s Treaty> + 's {
+        self.treaties
+            .iter()
+            .filter(move |treaty| treaty.involves(a) && treaty.involves(b))
+    }
+
+    pub fn casus_belli(&self) -> &[CasusBelli] {
+        &self.casus_belli
+    }
+
+    pub fn has_casus_belli(&self, holder: &SimulationID, target: &SimulationID) -> bool {
+        self.casus_belli
+            .iter()
+            .any(|casus_belli| &casus_belli.holder == holder && &casus_belli.target == target)
+    }
+
+    pub fn add_casus_belli(
+        &mut self,
+        holder: SimulationID,
+        target: SimulationID,
+        reason: CasusBelliReason,
+    ) -> DiplomaticEvent {
+        assert!(holder != target);
+        self.casus_belli.push(CasusBelli {
+            holder: holder.clone(),
+            target: target.clone(),
+            reason,
+            expires_in: CASUS_BELLI_DURATION,
+        });
+        DiplomaticEvent::CasusBelliGained { holder, target }
+    }
+
+    /// Declare war on a nation, calling the allies and vassals of both sides to arms.
+    ///
+    /// A casus belli held by the aggressor against its target is used up, otherwise the aggressor loses relations
+    /// with everyone.
+    pub fn declare_war(
+        &mut self,
+        aggressor: &SimulationID,
+        target: &SimulationID,
+    ) -> Result<Vec<DiplomaticEvent>, DiplomacyError> {
+        if aggressor == target {
+            return Err(DiplomacyError::SameNation);
+        }
+        if self.are_hostile(aggressor, target) {
+            return Err(DiplomacyError::AlreadyAtWar);
+        }
+        if self.overlord_of(aggressor).is_some() {
+            return Err(DiplomacyError::Vassal);
+        }
+        if let Some(treaty) = self.treaties_between(aggressor, target).next() {
+            return Err(DiplomacyError::BoundByTreaty(treaty.id().clone()));
+        }
+
+        let justified = match self.casus_belli.iter().position(|casus_belli| {
+            &casus_belli.holder == aggressor && &casus_belli.target == target
+        }) {
+            Some(index) => {
+                self.casus_belli.remove(index);
+                true
+            }
+            None => false,
+        };
+        if !justified {
+            for relation in &mut self.relations {
+                if relation.involves(aggressor) && !relation.involves(target) {
+                    relation.change_score(-UNJUSTIFIED_WAR_PENALTY / 2);
+                }
+            }
+            self.change_score(aggressor, target, -UNJUSTIFIED_WAR_PENALTY);
+        }
+        self.relation_mut(aggressor, target)
+            .set_state(DiplomaticState::War);
+        let mut events = vec![DiplomaticEvent::WarDeclared {
+            aggressor: aggressor.clone(),
+            target: target.clone(),
+            justified,
+        }];
+
+        // allies and vassals of the target, vassals of the aggressor
+        let mut called = vec![];
+        for relation in &self.relations {
+            let called_by = if relation.involves(target) {
+                target
+            } else if relation.involves(aggressor) {
+                aggressor
+            } else {
+                continue;
+            };
+            let nation = relation.other(called_by);
+            let answers = match &relation.state {
+                DiplomaticState::Alliance => called_by == target,
+                DiplomaticState::Vassalage { overlord } => overlord == called_by,
+                _ => false,
+            };
+            if answers && nation != aggressor && nation != target {
+                let enemy = if called_by == target {
+                    aggressor
+                } else {
+                    target
+                };
+                called.push((nation.clone(), enemy.clone()));
+            }
+        }
+        for (nation, enemy) in called {
+            if self.are_hostile(&nation, &enemy)
+                || self.treaties_between(&nation, &enemy).next().is_some()
+            {
+                continue;
+            }
+            self.relation_mut(&nation, &enemy)
+                .set_state(DiplomaticState::War);
+            events.push(DiplomaticEvent::JoinedWar {
+                nation,
+                against: enemy,
+            });
+        }
+        Ok(events)
+    }
+
+    /// How willing the recipient of a peace treaty is to accept it. It accepts if it is not negative.
+    pub fn peace_acceptance(&self, treaty: &Treaty) -> i16 {
+        let [proposer, recipient] = &treaty.parties;
+        let weariness = self.relation(proposer, recipient).map_or(0, |relation| {
+            i16::try_from(relation.turns_in_state)
+                .unwrap_or(i16::MAX)
+                .saturating_mul(WAR_WEARINESS_PER_TURN)
+        });
+        let cost = treaty
+            .terms
+            .iter()
+            .map(|term| term.cost_for(recipient))
+            .fold(0i16, i16::saturating_add);
+        self.score(proposer, recipient)
+            .saturating_add(weariness)
+            .saturating_sub(cost)
+    }
+
+    /// Propose a peace treaty to end a war, which is signed if the recipient accepts it (see `peace_acceptance`).
+    pub fn negotiate_peace(
+        &mut self,
+        treaty: Treaty,
+        nations: &mut [Nation],
+    ) -> Result<Vec<DiplomaticEvent>, DiplomacyError> {
+        assert!(treaty.kind == TreatyKind::Peace);
+        let [proposer, recipient] = &treaty.parties;
+        if !self.are_hostile(proposer, recipient) {
+            return Err(DiplomacyError::NotAtWar);
+        }
+        if self.peace_acceptance(&treaty) < 0 {
+            return Err(DiplomacyError::Rejected);
+        }
+        self.sign(treaty, nations)
+    }
+
+    /// Sign a treaty both parties agreed to, applying its one-time terms and setting their diplomatic state.
+    ///
+    /// Only peace treaties can be signed between nations at war. A new treaty replaces the ones already signed by
+    /// the parties.
+    pub fn sign(
+        &mut self,
+        treaty: Treaty,
+        nations: &mut [Nation],
+    ) -> Result<Vec<DiplomaticEvent>, DiplomacyError> {
+        let [a, b] = treaty.parties.clone();
+        if self.treaty(treaty.id()).is_some() {
+            return Err(DiplomacyError::DuplicateTreaty(treaty.id().clone()));
+        }
+        match (self.are_hostile(&a, &b), &treaty.kind) {
+            (false, TreatyKind::Peace) => return Err(DiplomacyError::NotAtWar),
+            (true, TreatyKind::Peace) => {}
+            (true, _) => return Err(DiplomacyError::AlreadyAtWar),
+            (false, _) => {}
+        }
+
+        for term in &treaty.terms {
+            match term {
+                TreatyTerm::Reparations { payer, amount } => {
+                    Self::pay(nations, payer, treaty.other(payer), *amount);
+                }
+                TreatyTerm::Tribute { .. } => {}
+                TreatyTerm::CedeSettlement { settlement, to } => {
+                    let from = treaty.other(to);
+                    let ceded = nations
+                        .iter_mut()
+                        .find(|nation| nation.id() == from)
+                        .and_then(|nation| nation.cede_settlement(settlement));
+                    if let (Some(ceded), Some(to)) =
+                        (ceded, nations.iter_mut().find(|nation| nation.id() == to))
+                    {
+                        to.annex_settlement(ceded);
+                    }
+                }
+            }
+        }
+
+        self.treaties
+            .retain(|signed| !(signed.involves(&a) && signed.involves(&b)));
+        self.relation_mut(&a, &b).set_state(treaty.kind.state());
+        let event = DiplomaticEvent::TreatySigned(treaty.id().clone());
+        self.treaties.push(treaty);
+        Ok(vec![event])
+    }
+
+    /// Break a treaty. The diplomatic state of the parties falls back to peace, and the other party gains a casus
+    /// belli against the breaker.
+    pub fn break_treaty(
+        &mut self,
+        id: &SimulationID,
+        breaker: &SimulationID,
+    ) -> Result<Vec<DiplomaticEvent>, DiplomacyError> {
+        let index = self
+            .treaties
+            .iter()
+            .position(|treaty| treaty.id() == id && treaty.involves(breaker))
+            .ok_or_else(|| DiplomacyError::UnknownTreaty(id.clone()))?;
+        let treaty = self.treaties.remove(index);
+        let victim = treaty.other(breaker).clone();
+        let relation = self.relation_mut(breaker, &victim);
+        relation.change_score(-BROKEN_TREATY_PENALTY);
+        relation.set_state(DiplomaticState::Peace);
+        Ok(vec![
+            DiplomaticEvent::TreatyBroken {
+                treaty: id.clone(),
+                breaker: breaker.clone(),
+            },
+            self.add_casus_belli(
+                victim,
+                breaker.clone(),
+                CasusBelliReason::BrokenTreaty(id.clone()),
+            ),
+        ])
+    }
+
+    /// Called every turn. Pay the tributes, expire the treaties and casus belli which ran out, and let the
+    /// relation scores drift back toward neutrality.
+    pub fn update(&mut self, nations: &mut [Nation]) -> Vec<DiplomaticEvent> {
+        let mut events = vec![];
+        for relation in &mut self.relations {
+            relation.turns_in_state = relation.turns_in_state.saturating_add(1);
+            relation.change_score(-relation.score.signum() * RELATION_DRIFT);
+        }
+
+        let mut expired = vec![];
+        for treaty in &mut self.treaties {
+            for term in &treaty.terms {
+                if let TreatyTerm::Tribute { payer, amount } = term {
+                    let paid = Self::pay(nations, payer, treaty.other(payer), *amount);
+                    events.push(DiplomaticEvent::TributePaid {
+                        treaty: treaty.id.clone(),
+                        payer: payer.clone(),
+                        amount: paid,
+                    });
+                }
+            }
+            treaty.elapsed += 1;
+            if treaty
+                .duration
+                .is_some_and(|duration| treaty.elapsed >= duration)
+            {
+                expired.push(treaty.id.clone());
+            }
+        }
+        for id in expired {
+            let index = self
+                .treaties
+                .iter()
+                .position(|treaty| treaty.id == id)
+                .expect("expired treaty must exist");
+            let treaty = self.treaties.remove(index);
+            let [a, b] = &treaty.parties;
+            let relation = self.relation_mut(a, b);
+            if relation.state == treaty.kind.state() {
+                relation.set_state(DiplomaticState::Peace);
+            }
+            events.push(DiplomaticEvent::TreatyExpired(id));
+        }
+
+        self.casus_belli.retain_mut(|casus_belli| {
+            casus_belli.expires_in = casus_belli.expires_in.saturating_sub(1);
+            if casus_belli.expires_in == 0 {
+                events.push(DiplomaticEvent::CasusBelliExpired {
+                    holder: casus_belli.holder.clone(),
+                    target: casus_belli.target.clone(),
+                });
+                return false;
+            }
+            true
+        });
+        events
+    }
+
+    fn relation_mut(&mut self, a: &SimulationID, b: &SimulationID) -> &mut Relation {
+        assert!(a != b);
+        let index = match self
+            .relations
+            .iter()
+            .position(|relation| relation.is_between(a, b))
+        {
+            Some(index) => index,
+            None => {
+                self.relations.push(Relation {
+                    parties: [a.clone(), b.clone()],
+                    score: 0,
+                    state: DiplomaticState::Peace,
+                    turns_in_state: 0,
+                });
+                self.relations.len() - 1
+            }
+        };
+        &mut self.relations[index]
+    }
+
+    /// Transfer credits between nations, as much as the payer can afford. Return the amount paid.
+    fn pay(
+        nations: &mut [Nation],
+        payer: &SimulationID,
+        payee: &SimulationID,
+        amount: ResourceQuantity,
+    ) -> ResourceQuantity {
+        let Some(payer) = nations.iter_mut().find(|nation| nation.id() == payer) else {
+            return 0;
+        };
+        let paid = amount.min(payer.resources().quantity_of(Resource::Credits));
+        payer.resources_mut().consume(Resource::Credits, paid);
+        if let Some(payee) = nations.iter_mut().find(|nation| nation.id() == payee) {
+            payee.resources_mut().replenish(Resource::Credits, paid);
+        }
+        paid
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::{
+        hex_map::coordinates::{CubeCoords, HexMapCoordinates},
+        simulation::{
+            ids::{SimulationID, WithSimulationID},
+            military::{HqUnit, Unit, UnitTemplate},
+            nations::Nation,
+            properties::SimulationPropertyStorage,
+            resources::{Resource, ResourceDataStore},
+            settlements::Settlement,
+            testing::build_mock_leader,
+        },
+    };
+
+    use super::{
+        CasusBelliReason, Diplomacy, DiplomacyError, DiplomaticEvent, DiplomaticState, Treaty,
+        TreatyKind, TreatyTerm, PEACE_TREATY_DURATION,
+    };
+
+    #[test]
+    fn test_diplomacy_war_and_peace() {
+        let settlements: Vec<Settlement> = (0..4)
+            .map(|index| {
+                Settlement::new(
+                    SimulationID::new_map_entity_id(index),
+                    format!("Settlement {index}"),
+                    CubeCoords::from_axial_coords(index as i16 * 3, 0),
+                )
+            })
+            .collect();
+        let template = UnitTemplate::new(
+            SimulationID::new_abstract_id("infantry"),
+            "infantry".into(),
+            HashMap::new(),
+            HashMap::new(),
+            SimulationPropertyStorage::new(),
+        );
+        let mut nations: Vec<Nation> = ["a", "b", "c"]
+            .into_iter()
+            .zip(&settlements)
+            .enumerate()
+            .map(|(index, (id, capital))| {
+                let mut nation = Nation::new(
+                    SimulationID::new_abstract_id(id),
+                    id.into(),
+                    build_mock_leader(index as u32, vec![]),
+                    capital,
+                );
+                let position = HexMapCoordinates::Cube(*capital.position());
+                let hq = SimulationID::new_map_entity_id(10 * index as u32 + 10);
+                nation
+                    .add_headquarters(HqUnit::new(
+                        hq.clone(),
+                        position.clone(),
+                        None,
+                        SimulationPropertyStorage::new(),
+                    ))
+                    .unwrap();
+                nation
+                    .order_of_battle_mut()
+                    .attach_unit(
+                        &hq,
+                        Unit::new(
+                            SimulationID::new_map_entity_id(10 * index as u32 + 11),
+                            position,
+                            &template,
+                            100,
+                        ),
+                    )
+                    .unwrap();
+                nation
+            })
+            .collect();
+        nations[1].annex_settlement(&settlements[3]);
+        nations[1].resources_mut().replenish(Resource::Credits, 100);
+        let [a, b, c] = ["a", "b", "c"].map(SimulationID::new_abstract_id);
+        let treaty_id = SimulationID::new_entity_id;
+
+        // b is allied with c, and bound to a by a pact it breaks, giving a a casus belli
+        let mut diplomacy = Diplomacy::new();
+        let alliance = Treaty::new(treaty_id(1), TreatyKind::Alliance, b.clone(), c.clone());
+        diplomacy.sign(alliance, &mut nations).unwrap();
+        let pact = Treaty::new(
+            treaty_id(2),
+            TreatyKind::NonAggressionPact,
+            a.clone(),
+            b.clone(),
+        )
+        .with_duration(10);
+        diplomacy.sign(pact, &mut nations).unwrap();
+        assert_eq!(
+            diplomacy.declare_war(&a, &b),
+            Err(DiplomacyError::BoundByTreaty(treaty_id(2)))
+        );
+        diplomacy.break_treaty(&treaty_id(2), &b).unwrap();
+        assert_eq!(diplomacy.score(&a, &b), -30);
+        assert!(diplomacy.has_casus_belli(&a, &b));
+
+        // a justified war, which c joins
+        let events = diplomacy.declare_war(&a, &b).unwrap();
+        assert_eq!(
+            events,
+            vec![
+                DiplomaticEvent::WarDeclared {
+                    aggressor: a.clone(),
+                    target: b.clone(),
+                    justified: true,
+                },
+                DiplomaticEvent::JoinedWar {
+                    nation: c.clone(),
+                    against: a.clone(),
+                },
+            ]
+        );
+        assert!(diplomacy.are_hostile(&a, &b) && diplomacy.are_hostile(&c, &a));
+        assert!(!diplomacy.are_hostile(&b, &c));
+        assert_eq!(diplomacy.score(&a, &b), -30);
+        assert_eq!(
+            diplomacy.enemy_positions(&a, &nations).tiles(),
+            &[1, 2].map(|index| *settlements[index].position()).into()
+        );
+        assert_eq!(diplomacy.belligerents(&a, &b).unwrap().defender(), &b);
+        assert_eq!(
+            diplomacy.belligerents(&b, &c),
+            Err(DiplomacyError::NotAtWar)
+        );
+        let tribute = TreatyTerm::Tribute {
+            payer: b.clone(),
+            amount: 40_000,
+        };
+        assert_eq!(tribute.cost_for(&b), i16::MAX);
+        assert_eq!(tribute.cost_for(&a), -i16::MAX);
+
+        // b refuses to give up a settlement at first, then gives in after a few turns of war
+        let peace = || {
+            Treaty::new(treaty_id(3), TreatyKind::Peace, a.clone(), b.clone())
+                .with_term(TreatyTerm::CedeSettlement {
+                    settlement: settlements[3].id().clone(),
+                    to: a.clone(),
+                })
+                .with_term(TreatyTerm::Reparations {
+                    payer: b.clone(),
+                    amount: 50,
+                })
+        };
+        assert_eq!(
+            diplomacy.negotiate_peace(peace(), &mut nations),
+            Err(DiplomacyError::Rejected)
+        );
+        for _ in 0..12 {
+            diplomacy.update(&mut nations);
+        }
+        diplomacy.negotiate_peace(peace(), &mut nations).unwrap();
+        assert_eq!(diplomacy.state(&a, &b), DiplomaticState::Peace);
+        assert!(diplomacy.are_hostile(&c, &a));
+        assert_eq!(nations[0].settlements().len(), 2);
+        assert_eq!(nations[0].resources().quantity_of(Resource::Credits), 50);
+        assert_eq!(
+            diplomacy.declare_war(&b, &a),
+            Err(DiplomacyError::BoundByTreaty(treaty_id(3)))
+        );
+
+        // casus belli run out
+        diplomacy.add_casus_belli(c.clone(), b.clone(), CasusBelliReason::Insult);
+        let expired = (0..20)
+            .flat_map(|_| diplomacy.update(&mut nations))
+            .filter(|event| matches!(event, DiplomaticEvent::CasusBelliExpired { .. }))
+            .count();
+        assert_eq!(expired, 1);
+        assert!(!diplomacy.has_casus_belli(&c, &b));
+
+        // so does the peace treaty, after which war can be declared again
+        let events: Vec<DiplomaticEvent> = (20..PEACE_TREATY_DURATION)
+            .flat_map(|_| diplomacy.update(&mut nations))
+            .collect();
+        assert!(events.contains(&DiplomaticEvent::TreatyExpired(treaty_id(3))));
+        assert!(diplomacy.declare_war(&b, &a).is_ok());
+    }
+}
@@ -1,5 +1,8 @@
 use std::collections::HashMap;
 
+pub mod budget;
+pub mod power_grid;
+
 use super::resources::{Resource, ResourceQuantity};
 
 /// Construction cost.
@@ -0,0 +1,380 @@
+//! National budget: taxation, spending and debt.
+//!
+//! The treasury of a nation is its stockpile of `Resource::Credits`. When the spending of a turn cannot be covered
+//! by the treasury, the nation borrows the difference, and has to pay interest on its debt every turn until repaid.
+
+use std::collections::HashMap;
+
+use crate::simulation::{
+    buildings::Building,
+    economy::MaintenanceCosts,
+    infrastructure::Infrastructure,
+    nations::Nation,
+    resources::{Resource, ResourceDataStorage, ResourceDataStore, ResourceQuantity},
+};
+
+/// Scalar for budget computations.
+///
+/// Signed since a balance can be negative, and wider than `ResourceQuantity` since it aggregates a whole nation.
+pub type BudgetAmount = i64;
+
+/// Where the income of a nation comes from.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum BudgetIncomeCategory {
+    /// Levied on every `PopulationGroup`, proportionally to its size.
+    PopulationTax,
+    /// Levied on every `Settlement`, regardless of its size.
+    SettlementTax,
+}
+
+/// Where the spending of a nation goes.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum BudgetSpendingCategory {
+    MilitaryUpkeep,
+    LeaderUpkeep,
+    InfrastructureMaintenance,
+    BuildingMaintenance,
+    DebtInterest,
+    /// Recruitment and reinforcement of units, paid when ordered.
+    Recruitment,
+}
+
+/// Tax policy of a nation.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TaxPolicy {
+    /// Credits levied per turn for every thousand inhabitants.
+    population_tax_rate: f64,
+    /// Flat credits levied per turn on every settlement.
+    settlement_tax: BudgetAmount,
+}
+
+impl TaxPolicy {
+    pub fn new(population_tax_rate: f64, settlement_tax: BudgetAmount) -> Self {
+        assert!(population_tax_rate >= 0.0);
+        assert!(settlement_tax >= 0);
+        Self {
+            population_tax_rate,
+            settlement_tax,
+        }
+    }
+
+    pub fn population_tax_rate(&self) -> f64 {
+        self.population_tax_rate
+    }
+
+    pub fn settlement_tax(&self) -> BudgetAmount {
+        self.settlement_tax
+    }
+}
+
+impl Default for TaxPolicy {
+    fn default() -> Self {
+        Self::new(1.0, 5)
+    }
+}
+
+/// Breakdown of the income and spending of a nation for a turn.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BudgetReport {
+    income: HashMap<BudgetIncomeCategory, BudgetAmount>,
+    spending: HashMap<BudgetSpendingCategory, BudgetAmount>,
+    /// Credits of the surplus the treasury could not hold, lost once the report is applied.
+    overflow: BudgetAmount,
+    /// Part of the spending already paid from the treasury during the turn.
+    prepaid: BudgetAmount,
+}
+
+impl BudgetReport {
+    pub fn income(&self, category: BudgetIncomeCategory) -> BudgetAmount {
+        self.income.get(&category).copied().unwrap_or(0)
+    }
+
+    pub fn spending(&self, category: BudgetSpendingCategory) -> BudgetAmount {
+        self.spending.get(&category).copied().unwrap_or(0)
+    }
+
+    pub fn total_income(&self) -> BudgetAmount {
+        self.income.values().sum()
+    }
+
+    pub fn total_spending(&self) -> BudgetAmount {
+        self.spending.values().sum()
+    }
+
+    /// Income minus spending: negative if the nation spends more than it earns.
+    pub fn balance(&self) -> BudgetAmount {
+        self.total_income() - self.total_spending()
+    }
+
+    pub fn overflow(&self) -> BudgetAmount {
+        self.overflow
+    }
+
+    pub fn prepaid(&self) -> BudgetAmount {
+        self.prepaid
+    }
+
+    fn add_income(&mut self, category: BudgetIncomeCategory, amount: BudgetAmount) {
+        *self.income.entry(category).or_insert(0) += amount;
+    }
+
+    fn add_spending(&mut self, category: BudgetSpendingCategory, amount: BudgetAmount) {
+        *self.spending.entry(category).or_insert(0) += amount;
+    }
+}
+
+/// Budget of a nation.
+#[derive(Debug)]
+pub struct NationalBudget {
+    tax_policy: TaxPolicy,
+    /// Always positive or zero.
+    debt: BudgetAmount,
+    /// Interest rate applied on the debt every turn (`0.01` for 1%).
+    interest_rate: f64,
+    /// Spending paid from the treasury since the last report was applied.
+    expenses: HashMap<BudgetSpendingCategory, BudgetAmount>,
+}
+
+impl Default for NationalBudget {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl NationalBudget {
+    pub fn new() -> Self {
+        Self {
+            tax_policy: TaxPolicy::default(),
+            debt: 0,
+            interest_rate: 0.02,
+            expenses: HashMap::new(),
+        }
+    }
+
+    pub fn tax_policy(&self) -> &TaxPolicy {
+        &self.tax_policy
+    }
+
+    pub fn set_tax_policy(&mut self, tax_policy: TaxPolicy) {
+        self.tax_policy = tax_policy;
+    }
+
+    pub fn debt(&self) -> BudgetAmount {
+        self.debt
+    }
+
+    pub fn interest_rate(&self) -> f64 {
+        self.interest_rate
+    }
+
+    pub fn set_interest_rate(&mut self, interest_rate: f64) {
+        assert!(interest_rate >= 0.0);
+        self.interest_rate = interest_rate;
+    }
+
+    /// Record spending already paid from the treasury (e.g. a recruitment), to be reported with the next turn.
+    pub fn record_expense(&mut self, category: BudgetSpendingCategory, amount: BudgetAmount) {
+        *self.expenses.entry(category).or_insert(0) += amount;
+    }
+
+    /// Compute the income and spending of the next turn, without applying them.
+    ///
+    /// `infrastructure` and `buildings` are the ones maintained by the nation.
+    pub fn project(
+        &self,
+        nation: &Nation,
+        infrastructure: &[&Infrastructure],
+        buildings: &[&Building],
+    ) -> BudgetReport {
+        let mut report = BudgetReport::default();
+
+        for settlement in nation.settlements() {
+            report.add_income(
+                BudgetIncomeCategory::PopulationTax,
+                (settlement.inhabitants() as f64 / 1000.0 * self.tax_policy.population_tax_rate)
+                    as BudgetAmount,
+            );
+            report.add_income(
+                BudgetIncomeCategory::SettlementTax,
+                self.tax_policy.settlement_tax,
+            );
+        }
+
+        let units = nation
+            .headquarters()
+            .iter()
+            .flat_map(|hq| hq.attached_units())
+            .map(|unit| unit.template());
+        let recruits = nation
+            .recruitment()
+            .orders()
+            .iter()
+            .map(|order| order.template());
+        for template in units.chain(recruits) {
+            report.add_spending(
+                BudgetSpendingCategory::MilitaryUpkeep,
+                credits_of(template.upkeep()),
+            );
+        }
+
+        let leaders = std::iter::once(nation.leader())
+            .chain(nation.headquarters().iter().filter_map(|hq| hq.leader()))
+            .chain(nation.settlements().iter().filter_map(|s| s.leader()));
+        for leader in leaders {
+            report.add_spending(
+                BudgetSpendingCategory::LeaderUpkeep,
+                credits_of(leader.upkeep()),
+            );
+        }
+
+        for infrastructure in infrastructure {
+            report.add_spending(
+                BudgetSpendingCategory::InfrastructureMaintenance,
+                credits_of(infrastructure.maintenance_costs()),
+            );
+        }
+
+        for building in buildings {
+            report.add_spending(
+                BudgetSpendingCategory::BuildingMaintenance,
+                credits_of(&building.maintenance_costs()),
+            );
+        }
+
+        report.add_spending(
+            BudgetSpendingCategory::DebtInterest,
+            (self.debt as f64 * self.interest_rate).ceil() as BudgetAmount,
+        );
+
+        for (category, amount) in &self.expenses {
+            report.add_spending(*category, *amount);
+            report.prepaid += amount;
+        }
+
+        report
+    }
+
+    /// Apply the balance of a turn to the treasury.
+    ///
+    /// A surplus repays the debt first, and the rest is stored in the treasury up to the maximum quantity it can
+    /// hold: the excess is recorded as the overflow of the report. A deficit is paid from the treasury, then
+    /// borrowed. Spending already paid is not paid again.
+    pub fn apply(&mut self, report: &mut BudgetReport, treasury: &mut ResourceDataStorage) {
+        let balance = report.balance() + report.prepaid;
+        self.expenses.clear();
+        report.overflow = 0;
+        if balance >= 0 {
+            let repaid = balance.min(self.debt);
+            self.debt -= repaid;
+            let room =
+                (ResourceQuantity::MAX - treasury.quantity_of(Resource::Credits)) as BudgetAmount;
+            let stored = (balance - repaid).min(room);
+            treasury.replenish(Resource::Credits, to_quantity(stored));
+            report.overflow = balance - repaid - stored;
+        } else {
+            let deficit = -balance;
+            let paid = deficit.min(treasury.quantity_of(Resource::Credits) as BudgetAmount);
+            if paid > 0 {
+                treasury.consume(Resource::Credits, to_quantity(paid));
+            }
+            self.debt += deficit - paid;
+        }
+    }
+}
+
+fn credits_of(costs: &MaintenanceCosts) -> BudgetAmount {
+    costs.get(&Resource::Credits).copied().unwrap_or(0) as BudgetAmount
+}
+
+/// `amount` must fit in a `ResourceQuantity`.
+fn to_quantity(amount: BudgetAmount) -> ResourceQuantity {
+    ResourceQuantity::try_from(amount).expect("amount fitting in a resource quantity")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::{
+        hex_map::coordinates::CubeCoords,
+        simulation::{
+            ids::SimulationID,
+            infrastructure::Infrastructure,
+            nations::Nation,
+            people::{
+                leaders::{IndividualIDCard, IndividualName, Leader},
+                population::PopulationGroup,
+            },
+            resources::{Resource, ResourceDataStore},
+            settlements::Settlement,
+        },
+    };
+
+    use super::{BudgetIncomeCategory, BudgetSpendingCategory, TaxPolicy};
+
+    #[test]
+    fn test_national_budget_update() {
+        let mut capital = Settlement::new(
+            SimulationID::new_map_entity_id(1),
+            "Capital".into(),
+            CubeCoords::from_axial_coords(0, 0),
+        );
+        capital.add_population_group(PopulationGroup::new(
+            SimulationID::new_entity_id(10),
+            20_000,
+            HashMap::new(),
+        ));
+        let leader = Leader::new(
+            IndividualIDCard::new(
+                SimulationID::new_entity_id(1),
+                IndividualName::HumanLike("Jane".into(), "Doe".into()),
+            ),
+            vec![],
+            HashMap::from([(Resource::Credits, 30)]),
+        );
+        let mut nation = Nation::new(
+            SimulationID::new_abstract_id("nation"),
+            "Nation".into(),
+            leader,
+            &capital,
+        );
+        nation.budget_mut().set_tax_policy(TaxPolicy::new(2.0, 10));
+        nation.budget_mut().set_interest_rate(0.1);
+        nation.resources_mut().replenish(Resource::Credits, 5);
+        let road = Infrastructure::new(
+            SimulationID::new_map_entity_id(2),
+            "road".into(),
+            HashMap::from([(Resource::Credits, 20)]),
+        );
+
+        // income: 20 * 2 + 10 = 50 ; spending: 30 + 20 = 50
+        let report = nation.update_budget(&[&road], &[]);
+        assert_eq!(report.income(BudgetIncomeCategory::PopulationTax), 40);
+        assert_eq!(report.spending(BudgetSpendingCategory::LeaderUpkeep), 30);
+        assert_eq!(report.balance(), 0);
+        assert_eq!(nation.budget().debt(), 0);
+
+        // deficit of 40: the whole treasury of 5, then 35 borrowed
+        let report = nation.update_budget(&[&road, &road, &road], &[]);
+        assert_eq!(report.balance(), -40);
+        assert_eq!(nation.resources().quantity_of(Resource::Credits), 0);
+        assert_eq!(nation.budget().debt(), 35);
+
+        // interest is now due
+        let projected = nation.budget().project(&nation, &[], &[]);
+        assert_eq!(projected.spending(BudgetSpendingCategory::DebtInterest), 4);
+        nation.update_budget(&[], &[]);
+        assert_eq!(nation.budget().debt(), 19);
+
+        // the surplus beyond what the treasury can hold is reported, not silently dropped
+        nation
+            .budget_mut()
+            .set_tax_policy(TaxPolicy::new(4000.0, 0));
+        let report = nation.update_budget(&[], &[]);
+        // income: 20 * 4000 ; spending: 30 + 2 of interest
+        assert_eq!(report.balance(), 80_000 - 32);
+        assert_eq!(nation.budget().debt(), 0);
+        assert_eq!(nation.resources().quantity_of(Resource::Credits), u16::MAX);
+        assert_eq!(report.overflow(), 80_000 - 32 - 19 - u16::MAX as i64);
+    }
+}
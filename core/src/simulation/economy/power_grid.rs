@@ -0,0 +1,382 @@
+//! Power grids: electricity cannot be stockpiled, so it is balanced every turn between the buildings connected by
+//! power lines.
+//!
+//! Every connected component of the power-line network is a grid. A tile with buildings but no power line is a
+//! grid of its own, which only links the buildings on it. Generators produce electricity passively or through the
+//! recipe runs of the last production they did not consume (see `Building::power_generation`), and consumers need it
+//! to run their recipes (see `Building::power_demand`).
+//! When a grid cannot meet its demand, all its consumers are browned out proportionally.
+
+use std::collections::HashMap;
+
+use crate::{
+    hex_map::{coordinates::CubeCoords, HexMap},
+    simulation::{
+        buildings::Building,
+        ids::{SimulationID, WithSimulationID},
+        infrastructure::{network::InfrastructureNetwork, InfrastructureKind},
+        production::BuildingProductionReport,
+    },
+};
+
+/// Balance of a power grid for a turn.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PowerGridStatus {
+    /// Sorted tiles of the grid.
+    tiles: Vec<CubeCoords>,
+    generators: Vec<SimulationID>,
+    consumers: Vec<SimulationID>,
+    generation: u32,
+    demand: u32,
+}
+
+impl PowerGridStatus {
+    pub fn tiles(&self) -> &[CubeCoords] {
+        &self.tiles
+    }
+
+    pub fn generators(&self) -> &[SimulationID] {
+        &self.generators
+    }
+
+    pub fn consumers(&self) -> &[SimulationID] {
+        &self.consumers
+    }
+
+    pub fn generation(&self) -> u32 {
+        self.generation
+    }
+
+    pub fn demand(&self) -> u32 {
+        self.demand
+    }
+
+    /// Ratio, from `0.0` to `1.0`, of the demand met by the generation.
+    pub fn supply_ratio(&self) -> f64 {
+        if self.demand == 0 {
+            1.0
+        } else {
+            (self.generation as f64 / self.demand as f64).min(1.0)
+        }
+    }
+
+    /// Is the demand only partially met?
+    pub fn is_brownout(&self) -> bool {
+        let ratio = self.supply_ratio();
+        ratio > 0.0 && ratio < 1.0
+    }
+
+    /// Electricity produced but not consumed.
+    pub fn surplus(&self) -> u32 {
+        self.generation.saturating_sub(self.demand)
+    }
+}
+
+/// Balance of all the power grids of a map for a turn.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PowerGridReport {
+    grids: Vec<PowerGridStatus>,
+}
+
+impl PowerGridReport {
+    /// Grids with at least a generator or a consumer, sorted by their first tile.
+    pub fn grids(&self) -> &[PowerGridStatus] {
+        &self.grids
+    }
+
+    /// The grid a tile belongs to.
+    pub fn grid_of(&self, position: &CubeCoords) -> Option<&PowerGridStatus> {
+        self.grids
+            .iter()
+            .find(|grid| grid.tiles.binary_search(position).is_ok())
+    }
+
+    /// The grids which cannot fully meet their demand.
+    pub fn brownouts(&self) -> impl Iterator<Item = &PowerGridStatus> {
+        self.grids.iter().filter(|grid| grid.is_brownout())
+    }
+
+    /// The consumers receiving no electricity at all.
+    pub fn unpowered_buildings(&self) -> Vec<&SimulationID> {
+        self.grids
+            .iter()
+            .filter(|grid| grid.supply_ratio() == 0.0)
+            .flat_map(|grid| grid.consumers.iter())
+            .collect()
+    }
+}
+
+/// Called every turn. Balance the power grids of a map, and update the power supply of every consumer.
+///
+/// Must be called before the production of the turn is resolved.
+pub fn update_power_grids(map: &mut HexMap, network: &InfrastructureNetwork) -> PowerGridReport {
+    let mut grids: Vec<PowerGridStatus> = network
+        .components(InfrastructureKind::PowerLine)
+        .into_iter()
+        .map(|tiles| PowerGridStatus {
+            tiles,
+            generators: vec![],
+            consumers: vec![],
+            generation: 0,
+            demand: 0,
+        })
+        .collect();
+    let mut grid_of_tile: HashMap<CubeCoords, usize> = HashMap::new();
+    for (index, grid) in grids.iter().enumerate() {
+        for position in &grid.tiles {
+            grid_of_tile.insert(*position, index);
+        }
+    }
+
+    let mut positions: Vec<CubeCoords> = map.tiles().map(|(position, _)| *position).collect();
+    positions.sort();
+    for position in &positions {
+        let Some(tile) = map.tile(position) else {
+            continue;
+        };
+        for building in Building::all_on_tile(tile) {
+            let (generation, demand) = (building.power_generation(), building.power_demand());
+            if generation == 0 && demand == 0 {
+                continue;
+            }
+            let index = *grid_of_tile.entry(*position).or_insert_with(|| {
+                grids.push(PowerGridStatus {
+                    tiles: vec![*position],
+                    generators: vec![],
+                    consumers: vec![],
+                    generation: 0,
+                    demand: 0,
+                });
+                grids.len() - 1
+            });
+            let grid = &mut grids[index];
+            if generation > 0 {
+                grid.generators.push(building.id().clone());
+                grid.generation += generation;
+            }
+            if demand > 0 {
+                grid.consumers.push(building.id().clone());
+                grid.demand += demand;
+            }
+        }
+    }
+
+    for position in &positions {
+        let Some(&index) = grid_of_tile.get(position) else {
+            continue;
+        };
+        let supply_ratio = grids[index].supply_ratio();
+        let Some(tile) = map.tile_mut(position) else {
+            continue;
+        };
+        for building in tile
+            .layer_artificial_mut()
+            .buildings_mut()
+            .filter_map(Building::from_tile_building_mut)
+        {
+            if building.power_demand() > 0 {
+                building.set_power_supply(supply_ratio);
+            }
+        }
+    }
+
+    grids.retain(|grid| !grid.generators.is_empty() || !grid.consumers.is_empty());
+    grids.sort_by(|a, b| a.tiles[0].cmp(&b.tiles[0]));
+    PowerGridReport { grids }
+}
+
+/// Called every turn, after the production is resolved. Record the electricity the recipes of every building
+/// generated but did not consume, to be fed to its power grid on the next turn.
+pub fn record_surplus_electricity(map: &mut HexMap, reports: &[BuildingProductionReport]) {
+    let positions: Vec<CubeCoords> = map.tiles().map(|(position, _)| *position).collect();
+    for position in positions {
+        let Some(tile) = map.tile_mut(&position) else {
+            continue;
+        };
+        for building in tile
+            .layer_artificial_mut()
+            .buildings_mut()
+            .filter_map(Building::from_tile_building_mut)
+        {
+            if let Some(report) = reports
+                .iter()
+                .find(|report| report.building() == building.id())
+            {
+                building.set_surplus_electricity(report.surplus_electricity());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::{
+        hex_map::{coordinates::CubeCoords, tile::HexMapTile, HexMap, HexMapStorage},
+        simulation::{
+            buildings::{Building, BuildingRecipe, BuildingTemplate},
+            ids::SimulationID,
+            infrastructure::{
+                network::InfrastructureNetwork, Infrastructure, InfrastructureKind,
+                InfrastructureSegment,
+            },
+            production::resolve_production,
+            resources::{Resource, ResourceDataStorage, ResourceDataStore},
+        },
+    };
+
+    use super::{record_surplus_electricity, update_power_grids};
+
+    /// Power plant at `q = 0` linked to two factories at `q = 1` and `q = 2`, an isolated factory at `q = 4`, and a
+    /// factory sharing its tile with an oil-fired generator at `q = 6`.
+    fn build_mock_map() -> HexMap {
+        let power_plant: &BuildingTemplate = Box::leak(Box::new(BuildingTemplate::new(
+            SimulationID::new_abstract_id("power_plant"),
+            "power_plant".into(),
+            HashMap::new(),
+            HashMap::from([(Resource::Oil, 2)]),
+            HashMap::from([(Resource::Electricity, 30)]),
+        )));
+        let factory: &BuildingTemplate = Box::leak(Box::new(
+            BuildingTemplate::new(
+                SimulationID::new_abstract_id("factory"),
+                "factory".into(),
+                HashMap::new(),
+                HashMap::new(),
+                HashMap::new(),
+            )
+            .with_recipe(BuildingRecipe::new(
+                HashMap::from([(Resource::Electricity, 10), (Resource::Metals, 1)]),
+                HashMap::from([(Resource::Credits, 5)]),
+                2,
+                0,
+            )),
+        ));
+
+        let mut tiles = HexMapStorage::new();
+        for q in 0..=4 {
+            let position = CubeCoords::from_axial_coords(q, 0);
+            let mut tile = HexMapTile::from_properties(0);
+            let template = if q == 0 { power_plant } else { factory };
+            if q != 3 {
+                tile.layer_artificial_mut()
+                    .add_building(Box::new(Building::new(
+                        SimulationID::new_map_entity_id(q as u32 + 1),
+                        template,
+                        100,
+                    )));
+            }
+            if q < 2 {
+                tile.layer_artificial_mut().add_infrastructure(Box::new(
+                    Infrastructure::new(
+                        SimulationID::new_map_entity_id(q as u32 + 10),
+                        "power_line".into(),
+                        HashMap::new(),
+                    )
+                    .with_segment(InfrastructureSegment::new(
+                        InfrastructureKind::PowerLine,
+                        position,
+                        CubeCoords::from_axial_coords(q + 1, 0),
+                        100,
+                    )),
+                ));
+            }
+            tiles.insert(position, tile);
+        }
+
+        let generator: &BuildingTemplate = Box::leak(Box::new(
+            BuildingTemplate::new(
+                SimulationID::new_abstract_id("generator"),
+                "generator".into(),
+                HashMap::new(),
+                HashMap::new(),
+                HashMap::new(),
+            )
+            .with_recipe(BuildingRecipe::new(
+                HashMap::from([(Resource::Oil, 1)]),
+                HashMap::from([(Resource::Electricity, 10)]),
+                2,
+                0,
+            )),
+        ));
+        let mut tile = HexMapTile::from_properties(0);
+        tile.layer_artificial_mut()
+            .add_building(Box::new(Building::new(
+                SimulationID::new_map_entity_id(6),
+                generator,
+                100,
+            )));
+        tile.layer_artificial_mut()
+            .add_building(Box::new(Building::new(
+                SimulationID::new_map_entity_id(7),
+                factory,
+                100,
+            )));
+        tiles.insert(CubeCoords::from_axial_coords(6, 0), tile);
+        HexMap::from_tiles(tiles)
+    }
+
+    #[test]
+    fn test_power_grids_update() {
+        let mut map = build_mock_map();
+        let network = InfrastructureNetwork::from_map(&map);
+        let report = update_power_grids(&mut map, &network);
+
+        // 30 generated for 40 demanded on the main grid
+        assert_eq!(report.grids().len(), 3);
+        let grid = report
+            .grid_of(&CubeCoords::from_axial_coords(1, 0))
+            .unwrap();
+        assert_eq!(grid.generation(), 30);
+        assert_eq!(grid.demand(), 40);
+        assert_eq!(grid.consumers().len(), 2);
+        assert_eq!(report.brownouts().count(), 1);
+        assert_eq!(
+            report.unpowered_buildings(),
+            vec![
+                &SimulationID::new_map_entity_id(5),
+                &SimulationID::new_map_entity_id(7)
+            ]
+        );
+
+        let factory = Building::find_on_tile(
+            map.tile(&CubeCoords::from_axial_coords(2, 0)).unwrap(),
+            &SimulationID::new_map_entity_id(3),
+        )
+        .unwrap();
+        assert_eq!(factory.power_supply(), 0.75);
+        let isolated = Building::find_on_tile(
+            map.tile(&CubeCoords::from_axial_coords(4, 0)).unwrap(),
+            &SimulationID::new_map_entity_id(5),
+        )
+        .unwrap();
+        assert_eq!(isolated.power_supply(), 0.0);
+
+        // without oil, the generator next to the factory produces nothing
+        let generator_tile = CubeCoords::from_axial_coords(6, 0);
+        let grid = report.grid_of(&generator_tile).unwrap();
+        assert_eq!(grid.generation(), 0);
+        assert_eq!(grid.supply_ratio(), 0.0);
+
+        // once it had oil, the electricity its recipe generated powers the factory on the next turn
+        let mut stockpile = ResourceDataStorage::new();
+        stockpile.replenish(Resource::Oil, 10);
+        let reports = {
+            let buildings: Vec<&Building> =
+                Building::all_on_tile(map.tile(&generator_tile).unwrap()).collect();
+            resolve_production(&buildings[..1], &mut stockpile, 0)
+        };
+        record_surplus_electricity(&mut map, &reports);
+        let report = update_power_grids(&mut map, &network);
+        let grid = report.grid_of(&generator_tile).unwrap();
+        assert_eq!(grid.generation(), 20);
+        assert_eq!(grid.supply_ratio(), 1.0);
+        let factory = Building::find_on_tile(
+            map.tile(&generator_tile).unwrap(),
+            &SimulationID::new_map_entity_id(7),
+        )
+        .unwrap();
+        assert_eq!(factory.power_supply(), 1.0);
+    }
+}
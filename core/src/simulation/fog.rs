@@ -1 +1,149 @@
-//! TODO: fog of war, 1 per Nation
+//! Fog of war, 1 per `Nation`.
+//!
+//! A nation sees the tiles of its territory, and the tiles around its settlements, headquarters and units. Tiles
+//! seen once stay explored: their terrain is known, but not what stands on them now.
+
+use std::collections::HashSet;
+
+use crate::hex_map::{coordinates::CubeCoords, HexMap};
+
+use super::{
+    ids::{SimulationID, WithSimulationID},
+    military::{combat::unit_attribute, Unit},
+    nations::Nation,
+    territory::{territory_of, Territory},
+};
+
+/// Attribute of an `UnitTemplate` (`SimulationPropertyValue::Integer`), the radius of the tiles a unit sees.
+pub const SIGHT_PROPERTY: &str = "sight";
+/// Sight of units without the `SIGHT_PROPERTY` attribute, and of headquarters.
+pub const DEFAULT_UNIT_SIGHT: u16 = 2;
+/// Radius of the tiles seen around a settlement.
+pub const SETTLEMENT_SIGHT: u16 = 3;
+
+pub fn unit_sight(unit: &Unit) -> u16 {
+    unit_attribute(unit.template(), SIGHT_PROPERTY, DEFAULT_UNIT_SIGHT as i32)
+        .clamp(0, u16::MAX as i32) as u16
+}
+
+/// What a nation knows of the map.
+#[derive(Debug)]
+pub struct FogOfWar {
+    /// Must be the `SimulationID` of a `Nation`.
+    nation: SimulationID,
+    /// Seen this turn.
+    visible: HashSet<CubeCoords>,
+    /// Seen at least once, visible tiles included.
+    explored: HashSet<CubeCoords>,
+}
+
+impl FogOfWar {
+    pub fn new(nation: SimulationID) -> Self {
+        assert!(matches!(nation, SimulationID::Abstract(_)));
+        Self {
+            nation,
+            visible: HashSet::new(),
+            explored: HashSet::new(),
+        }
+    }
+
+    pub fn nation(&self) -> &SimulationID {
+        &self.nation
+    }
+
+    pub fn visible(&self) -> &HashSet<CubeCoords> {
+        &self.visible
+    }
+
+    pub fn is_visible(&self, position: &CubeCoords) -> bool {
+        self.visible.contains(position)
+    }
+
+    pub fn is_explored(&self, position: &CubeCoords) -> bool {
+        self.explored.contains(position)
+    }
+
+    /// Called every turn, once the units moved and the borders were redrawn. Recompute the tiles seen by the nation.
+    pub fn update(&mut self, nation: &Nation, map: &HexMap, territory: &Territory) {
+        assert!(&self.nation == nation.id());
+        let mut eyes: Vec<(CubeCoords, u16)> = nation
+            .settlements()
+            .iter()
+            .map(|settlement| (*settlement.position(), SETTLEMENT_SIGHT))
+            .collect();
+        for hq in nation.order_of_battle().headquarters() {
+            if let Some(position) = hq.position().as_cube_coords() {
+                eyes.push((position, DEFAULT_UNIT_SIGHT));
+            }
+            for unit in hq.attached_units() {
+                if let Some(position) = unit.position().as_cube_coords() {
+                    eyes.push((position, unit_sight(unit)));
+                }
+            }
+        }
+
+        self.visible = territory_of(territory, &self.nation).into_iter().collect();
+        for (center, sight) in eyes {
+            if map.contains(&center) {
+                self.visible.insert(center);
+            }
+            self.visible.extend(map.within_radius(&center, sight));
+        }
+        self.explored.extend(self.visible.iter().copied());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        hex_map::{
+            coordinates::CubeCoords, layers::natural::HexMapTerrain, tile::HexMapTile, HexMap,
+            HexMapStorage,
+        },
+        simulation::{
+            ids::{SimulationID, WithSimulationID},
+            nations::Nation,
+            settlements::Settlement,
+            territory::Territory,
+            testing::build_mock_leader,
+        },
+    };
+
+    use super::FogOfWar;
+
+    #[test]
+    fn test_fog_of_war_update() {
+        let mut tiles = HexMapStorage::new();
+        for q in 0..=9 {
+            tiles.insert(
+                CubeCoords::from_axial_coords(q, 0),
+                HexMapTile::from_terrain(0, HexMapTerrain::Plains),
+            );
+        }
+        let map = HexMap::from_tiles(tiles);
+        let tile = CubeCoords::from_axial_coords;
+        let capital = Settlement::new(SimulationID::new_map_entity_id(1), "A".into(), tile(0, 0));
+        let outpost = Settlement::new(SimulationID::new_map_entity_id(2), "B".into(), tile(9, 0));
+        let mut nation = Nation::new(
+            SimulationID::new_abstract_id("a"),
+            "a".into(),
+            build_mock_leader(1, vec![]),
+            &capital,
+        );
+        let mut fog = FogOfWar::new(SimulationID::new_abstract_id("a"));
+        let territory = Territory::new();
+
+        fog.update(&nation, &map, &territory);
+        assert_eq!(fog.visible().len(), 4);
+        assert!(fog.is_visible(&tile(3, 0)) && !fog.is_visible(&tile(4, 0)));
+
+        // the outpost is seen, then lost: its surroundings stay explored
+        nation.annex_settlement(&outpost);
+        fog.update(&nation, &map, &territory);
+        assert_eq!(fog.visible().len(), 8);
+        nation.cede_settlement(outpost.id());
+        fog.update(&nation, &map, &territory);
+        assert!(!fog.is_visible(&tile(9, 0)));
+        assert!(fog.is_explored(&tile(9, 0)) && !fog.is_explored(&tile(5, 0)));
+    }
+}
@@ -1,17 +1,135 @@
-use crate::hex_map::layers::dynamic::HexMapTileInfrastructure;
+use std::any::Any;
+
+use crate::hex_map::{
+    coordinates::{CubeCoords, HexMapCoordinatesSystem},
+    layers::dynamic::HexMapTileInfrastructure,
+    tile::HexMapTile,
+};
 
 use super::{
     economy::MaintenanceCosts,
     ids::{SimulationID, WithSimulationID},
 };
 
+pub mod network;
+
+/// The networks an infrastructure segment can be part of.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum InfrastructureKind {
+    Road,
+    Rail,
+    Pipeline,
+    PowerLine,
+}
+
+impl InfrastructureKind {
+    /// Whether goods and units travel along the network.
+    pub fn is_transport(&self) -> bool {
+        matches!(self, Self::Road | Self::Rail)
+    }
+}
+
+/// A section of a network linking two neighboring tiles, in both directions.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InfrastructureSegment {
+    kind: InfrastructureKind,
+    from: CubeCoords,
+    to: CubeCoords,
+    /// Maximum quantity carried per turn (goods, fuel, electricity... depending on the kind).
+    capacity: u32,
+}
+
+impl InfrastructureSegment {
+    pub fn new(kind: InfrastructureKind, from: CubeCoords, to: CubeCoords, capacity: u32) -> Self {
+        assert!(from.neighbors().any(|neighbor| neighbor == to));
+        Self {
+            kind,
+            from,
+            to,
+            capacity,
+        }
+    }
+
+    pub fn kind(&self) -> InfrastructureKind {
+        self.kind
+    }
+
+    pub fn from(&self) -> &CubeCoords {
+        &self.from
+    }
+
+    pub fn to(&self) -> &CubeCoords {
+        &self.to
+    }
+
+    pub fn capacity(&self) -> u32 {
+        self.capacity
+    }
+}
+
 /// Infrastructure somewhere on the world map. Not supposed to be moved.
+///
+/// Network infrastructure (roads, rails...) is attached to the tile its segment starts from.
 #[derive(Debug)]
 pub struct Infrastructure {
     /// Must be `SimulationID::SimulationMapEntityID`.
     id: SimulationID,
     r#type: String,
     maintenance_costs: MaintenanceCosts,
+    segment: Option<InfrastructureSegment>,
+}
+
+impl Infrastructure {
+    pub fn new(id: SimulationID, r#type: String, maintenance_costs: MaintenanceCosts) -> Self {
+        assert!(matches!(id, SimulationID::MapEntityID(_)));
+        Self {
+            id,
+            r#type,
+            maintenance_costs,
+            segment: None,
+        }
+    }
+
+    /// Make the infrastructure part of a network.
+    pub fn with_segment(mut self, segment: InfrastructureSegment) -> Self {
+        self.segment = Some(segment);
+        self
+    }
+
+    pub fn r#type(&self) -> &str {
+        &self.r#type
+    }
+
+    pub fn maintenance_costs(&self) -> &MaintenanceCosts {
+        &self.maintenance_costs
+    }
+
+    pub fn segment(&self) -> Option<&InfrastructureSegment> {
+        self.segment.as_ref()
+    }
+
+    /// Get back the `Infrastructure` stored in a tile.
+    pub fn from_tile_infrastructure(
+        infrastructure: &dyn HexMapTileInfrastructure,
+    ) -> Option<&Self> {
+        (infrastructure as &dyn Any).downcast_ref()
+    }
+
+    /// Iterate over all the infrastructure built on a tile.
+    pub fn all_on_tile(tile: &HexMapTile) -> impl Iterator<Item = &Self> {
+        tile.layer_artificial()
+            .infrastructure()
+            .filter_map(Self::from_tile_infrastructure)
+    }
+
+    /// Whether a road or rail segment starts from a tile.
+    pub fn has_transport_on_tile(tile: &HexMapTile) -> bool {
+        Self::all_on_tile(tile).any(|infrastructure| {
+            infrastructure
+                .segment()
+                .is_some_and(|segment| segment.kind().is_transport())
+        })
+    }
 }
 
 impl WithSimulationID for Infrastructure {
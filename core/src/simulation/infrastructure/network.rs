@@ -0,0 +1,420 @@
+//! Infrastructure networks: graphs of the segments built between neighboring tiles, one graph per kind.
+//!
+//! A road network is only made of roads, a power grid only of power lines, and so on. Tiles are the nodes of the
+//! graph, segments its (undirected) edges.
+
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap, HashSet},
+};
+
+use crate::{
+    hex_map::{coordinates::CubeCoords, HexMap},
+    simulation::ids::{SimulationID, WithSimulationID},
+};
+
+use super::{Infrastructure, InfrastructureKind, InfrastructureSegment};
+
+/// A segment of a network, as seen from one of its ends.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InfrastructureEdge {
+    /// Must be the `SimulationID` of an `Infrastructure`.
+    infrastructure: SimulationID,
+    to: CubeCoords,
+    capacity: u32,
+}
+
+impl InfrastructureEdge {
+    pub fn infrastructure(&self) -> &SimulationID {
+        &self.infrastructure
+    }
+
+    pub fn to(&self) -> &CubeCoords {
+        &self.to
+    }
+
+    pub fn capacity(&self) -> u32 {
+        self.capacity
+    }
+}
+
+/// A path found in an infrastructure network.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InfrastructureNetworkPath {
+    /// Ordered tiles from start to goal, both included.
+    tiles: Vec<CubeCoords>,
+    /// Infrastructure used between every pair of consecutive tiles.
+    segments: Vec<SimulationID>,
+    /// Capacity of the smallest segment of the path.
+    capacity: u32,
+}
+
+impl InfrastructureNetworkPath {
+    pub fn tiles(&self) -> &[CubeCoords] {
+        &self.tiles
+    }
+
+    pub fn segments(&self) -> &[SimulationID] {
+        &self.segments
+    }
+
+    pub fn capacity(&self) -> u32 {
+        self.capacity
+    }
+
+    /// Number of segments travelled.
+    pub fn len(&self) -> usize {
+        self.segments.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.segments.is_empty()
+    }
+}
+
+/// Set of tiles linked together by a network.
+pub type InfrastructureNetworkComponent = Vec<CubeCoords>;
+
+/// The infrastructure networks of a map.
+#[derive(Debug, Default)]
+pub struct InfrastructureNetwork {
+    edges: HashMap<InfrastructureKind, HashMap<CubeCoords, Vec<InfrastructureEdge>>>,
+}
+
+impl InfrastructureNetwork {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Build the networks from all the infrastructure built on a map.
+    pub fn from_map(map: &HexMap) -> Self {
+        let mut network = Self::new();
+        let mut positions: Vec<&CubeCoords> = map.tiles().map(|(position, _)| position).collect();
+        positions.sort();
+        for position in positions {
+            for infrastructure in map
+                .tile(position)
+                .into_iter()
+                .flat_map(Infrastructure::all_on_tile)
+            {
+                network.add(infrastructure);
+            }
+        }
+        network
+    }
+
+    /// Add an infrastructure to the networks, if it has a segment.
+    pub fn add(&mut self, infrastructure: &Infrastructure) {
+        if let Some(segment) = infrastructure.segment() {
+            self.add_segment(infrastructure.id().clone(), segment);
+        }
+    }
+
+    pub fn add_segment(&mut self, infrastructure: SimulationID, segment: &InfrastructureSegment) {
+        let graph = self.edges.entry(segment.kind()).or_default();
+        for (from, to) in [
+            (segment.from(), segment.to()),
+            (segment.to(), segment.from()),
+        ] {
+            graph.entry(*from).or_default().push(InfrastructureEdge {
+                infrastructure: infrastructure.clone(),
+                to: *to,
+                capacity: segment.capacity(),
+            });
+        }
+    }
+
+    /// Remove the segment of an infrastructure (e.g. when destroyed). Return true if it was part of a network.
+    pub fn remove(&mut self, infrastructure: &SimulationID) -> bool {
+        let mut removed = false;
+        for graph in self.edges.values_mut() {
+            for edges in graph.values_mut() {
+                let count = edges.len();
+                edges.retain(|edge| &edge.infrastructure != infrastructure);
+                removed |= edges.len() != count;
+            }
+            graph.retain(|_, edges| !edges.is_empty());
+        }
+        removed
+    }
+
+    /// Segments of the given kind leaving a tile.
+    pub fn edges_from(
+        &self,
+        kind: InfrastructureKind,
+        position: &CubeCoords,
+    ) -> &[InfrastructureEdge] {
+        self.edges
+            .get(&kind)
+            .and_then(|graph| graph.get(position))
+            .map_or(&[], |edges| edges.as_slice())
+    }
+
+    /// Is the tile part of a network of the given kind?
+    pub fn contains(&self, kind: InfrastructureKind, position: &CubeCoords) -> bool {
+        !self.edges_from(kind, position).is_empty()
+    }
+
+    /// Is there a direct segment of the given kind between two tiles?
+    pub fn connects(&self, kind: InfrastructureKind, from: &CubeCoords, to: &CubeCoords) -> bool {
+        self.edges_from(kind, from)
+            .iter()
+            .any(|edge| &edge.to == to)
+    }
+
+    /// Total capacity of the segments of the given kind directly linking two tiles, `0` if not linked.
+    pub fn capacity_between(
+        &self,
+        kind: InfrastructureKind,
+        from: &CubeCoords,
+        to: &CubeCoords,
+    ) -> u32 {
+        self.edges_from(kind, from)
+            .iter()
+            .filter(|edge| &edge.to == to)
+            .map(|edge| edge.capacity)
+            .sum()
+    }
+
+    /// Split the network of the given kind into its connected components.
+    ///
+    /// Tiles are sorted within every component, and components are sorted by their first tile.
+    pub fn components(&self, kind: InfrastructureKind) -> Vec<InfrastructureNetworkComponent> {
+        let Some(graph) = self.edges.get(&kind) else {
+            return vec![];
+        };
+        let mut positions: Vec<&CubeCoords> = graph.keys().collect();
+        positions.sort();
+
+        let mut visited: HashSet<CubeCoords> = HashSet::new();
+        let mut components = vec![];
+        for start in positions {
+            if !visited.insert(*start) {
+                continue;
+            }
+            let mut component = vec![*start];
+            let mut stack = vec![*start];
+            while let Some(current) = stack.pop() {
+                for edge in self.edges_from(kind, &current) {
+                    if visited.insert(edge.to) {
+                        component.push(edge.to);
+                        stack.push(edge.to);
+                    }
+                }
+            }
+            component.sort();
+            components.push(component);
+        }
+        components
+    }
+
+    /// Are two tiles linked by the network of the given kind?
+    pub fn are_connected(
+        &self,
+        kind: InfrastructureKind,
+        from: &CubeCoords,
+        to: &CubeCoords,
+    ) -> bool {
+        self.shortest_path(kind, *from, *to, 0).is_some()
+    }
+
+    /// Find the path with the fewest segments between two tiles, only using segments of the given kind with at
+    /// least `min_capacity`.
+    pub fn shortest_path(
+        &self,
+        kind: InfrastructureKind,
+        start: CubeCoords,
+        goal: CubeCoords,
+        min_capacity: u32,
+    ) -> Option<InfrastructureNetworkPath> {
+        if !self.contains(kind, &start) || !self.contains(kind, &goal) {
+            return None;
+        }
+
+        let mut frontier = BinaryHeap::new();
+        let mut came_from: HashMap<CubeCoords, (CubeCoords, &InfrastructureEdge)> = HashMap::new();
+        let mut cost_so_far: HashMap<CubeCoords, u32> = HashMap::new();
+        frontier.push(Reverse((0, start)));
+        cost_so_far.insert(start, 0);
+
+        while let Some(Reverse((cost, current))) = frontier.pop() {
+            if current == goal {
+                break;
+            }
+            if cost > cost_so_far[&current] {
+                continue;
+            }
+            for edge in self.edges_from(kind, &current) {
+                if edge.capacity < min_capacity {
+                    continue;
+                }
+                let new_cost = cost + 1;
+                if cost_so_far
+                    .get(&edge.to)
+                    .is_none_or(|&known| new_cost < known)
+                {
+                    cost_so_far.insert(edge.to, new_cost);
+                    came_from.insert(edge.to, (current, edge));
+                    frontier.push(Reverse((new_cost, edge.to)));
+                }
+            }
+        }
+
+        if !cost_so_far.contains_key(&goal) {
+            return None;
+        }
+        let mut tiles = vec![goal];
+        let mut segments = vec![];
+        let mut capacity = u32::MAX;
+        let mut current = goal;
+        while current != start {
+            let (previous, edge) = came_from[&current];
+            tiles.push(previous);
+            segments.push(edge.infrastructure.clone());
+            capacity = capacity.min(edge.capacity);
+            current = previous;
+        }
+        tiles.reverse();
+        segments.reverse();
+        Some(InfrastructureNetworkPath {
+            tiles,
+            segments,
+            capacity: if capacity == u32::MAX { 0 } else { capacity },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::{
+        hex_map::{coordinates::CubeCoords, tile::HexMapTile, HexMap, HexMapStorage},
+        simulation::{
+            ids::SimulationID,
+            infrastructure::{Infrastructure, InfrastructureKind, InfrastructureSegment},
+        },
+    };
+
+    use super::InfrastructureNetwork;
+
+    fn build_mock_infrastructure(
+        id: u32,
+        kind: InfrastructureKind,
+        from: CubeCoords,
+        to: CubeCoords,
+        capacity: u32,
+    ) -> Infrastructure {
+        Infrastructure::new(
+            SimulationID::new_map_entity_id(id),
+            "segment".into(),
+            HashMap::new(),
+        )
+        .with_segment(InfrastructureSegment::new(kind, from, to, capacity))
+    }
+
+    /// Road along `r = 0` from `q = 0` to `q = 4` (with a low-capacity segment between 1 and 2, bypassed through
+    /// `r = -1`), and an isolated power line between `q = 3` and `q = 4`.
+    fn build_mock_map() -> HexMap {
+        let segments = [
+            (InfrastructureKind::Road, (0, 0), (1, 0), 10),
+            (InfrastructureKind::Road, (1, 0), (2, 0), 2),
+            (InfrastructureKind::Road, (2, 0), (3, 0), 10),
+            (InfrastructureKind::Road, (3, 0), (4, 0), 10),
+            (InfrastructureKind::Road, (1, 0), (2, -1), 10),
+            (InfrastructureKind::Road, (2, -1), (3, -1), 10),
+            (InfrastructureKind::Road, (3, -1), (3, 0), 10),
+            (InfrastructureKind::PowerLine, (3, 0), (4, 0), 100),
+        ];
+        let mut tiles = HexMapStorage::new();
+        for q in 0..=4 {
+            for r in -1..=0 {
+                tiles.insert(
+                    CubeCoords::from_axial_coords(q, r),
+                    HexMapTile::from_properties(0),
+                );
+            }
+        }
+        for (index, (kind, (from_q, from_r), (to_q, to_r), capacity)) in
+            segments.into_iter().enumerate()
+        {
+            let from = CubeCoords::from_axial_coords(from_q, from_r);
+            let to = CubeCoords::from_axial_coords(to_q, to_r);
+            tiles
+                .get_mut(&from)
+                .unwrap()
+                .layer_artificial_mut()
+                .add_infrastructure(Box::new(build_mock_infrastructure(
+                    index as u32 + 1,
+                    kind,
+                    from,
+                    to,
+                    capacity,
+                )));
+        }
+        HexMap::from_tiles(tiles)
+    }
+
+    #[test]
+    fn test_infrastructure_network_components() {
+        let mut network = InfrastructureNetwork::from_map(&build_mock_map());
+        assert_eq!(network.components(InfrastructureKind::Road).len(), 1);
+        assert_eq!(network.components(InfrastructureKind::Road)[0].len(), 7);
+        assert_eq!(network.components(InfrastructureKind::PowerLine).len(), 1);
+        assert!(network.components(InfrastructureKind::Rail).is_empty());
+        assert!(!network.are_connected(
+            InfrastructureKind::PowerLine,
+            &CubeCoords::from_axial_coords(0, 0),
+            &CubeCoords::from_axial_coords(4, 0),
+        ));
+
+        // cutting the road between 3 and 4 isolates the end of the road
+        assert!(network.remove(&SimulationID::new_map_entity_id(4)));
+        let components = network.components(InfrastructureKind::Road);
+        assert_eq!(components.len(), 1);
+        assert!(!network.contains(
+            InfrastructureKind::Road,
+            &CubeCoords::from_axial_coords(4, 0)
+        ));
+        assert!(network.contains(
+            InfrastructureKind::PowerLine,
+            &CubeCoords::from_axial_coords(4, 0)
+        ));
+    }
+
+    #[test]
+    fn test_infrastructure_network_shortest_path() {
+        let network = InfrastructureNetwork::from_map(&build_mock_map());
+        let start = CubeCoords::from_axial_coords(0, 0);
+        let goal = CubeCoords::from_axial_coords(4, 0);
+
+        let path = network
+            .shortest_path(InfrastructureKind::Road, start, goal, 0)
+            .unwrap();
+        assert_eq!(path.len(), 4);
+        assert_eq!(path.capacity(), 2);
+        assert_eq!(
+            network.capacity_between(
+                InfrastructureKind::Road,
+                &CubeCoords::from_axial_coords(2, 0),
+                &CubeCoords::from_axial_coords(1, 0),
+            ),
+            2
+        );
+
+        // heavy convoys take the bypass
+        let path = network
+            .shortest_path(InfrastructureKind::Road, start, goal, 5)
+            .unwrap();
+        assert_eq!(path.len(), 5);
+        assert_eq!(path.capacity(), 10);
+        assert_eq!(path.tiles()[2], CubeCoords::from_axial_coords(2, -1));
+
+        assert!(network
+            .shortest_path(InfrastructureKind::Road, start, goal, 50)
+            .is_none());
+        assert!(network
+            .shortest_path(InfrastructureKind::Rail, start, goal, 0)
+            .is_none());
+    }
+}
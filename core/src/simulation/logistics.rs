@@ -1,18 +1,130 @@
-use crate::hex_map::layers::dynamic::HexMapTileSupplyNode;
+//! Logistics: supply of the military units of a nation.
+//!
+//! Every turn, the `SupplyNode`s of a nation are refilled from its national stockpile. Supply flows from its
+//! settlements to the nodes linked to them by a road or rail network, then from node to node, so that a chain of
+//! nodes carries supply further than any single network. Every link of a network carries a limited quantity per turn
+//! (its capacity), shared by all the nodes refilled through it, closest first. Then, every unit draws its supply
+//! needs from the nodes in range, closest first. The range of a node grows with its level, and is shortened by rough
+//! terrain unless travelling along roads or rails.
+
+use std::{any::Any, collections::HashMap};
+
+use crate::hex_map::{
+    coordinates::CubeCoords,
+    layers::{dynamic::HexMapTileSupplyNode, natural::HexMapTerrain},
+    pathfinding::{reachable_tiles, HexMapPathCost},
+    tile::HexMapTile,
+    HexMap,
+};
 
 use super::{
     ids::{SimulationID, WithSimulationID},
-    resources::ResourceDataStorage,
+    infrastructure::{
+        network::{InfrastructureNetwork, InfrastructureNetworkPath},
+        Infrastructure, InfrastructureKind,
+    },
+    military::Unit,
+    resources::{Resource, ResourceDataStorage, ResourceDataStore, ResourceQuantity},
+    settlements::Settlement,
 };
 
+/// Resources distributed by the supply network.
+pub const SUPPLY_RESOURCES: [Resource; 3] = [Resource::Food, Resource::Oil, Resource::Ammunition];
+
+/// Supply range, as a path cost, for every level of a `SupplyNode`.
+pub const SUPPLY_NODE_RANGE_PER_LEVEL: HexMapPathCost = 3;
+
+/// Maximum stock of every supply resource, for every level of a `SupplyNode`.
+pub const SUPPLY_NODE_STORAGE_PER_LEVEL: ResourceQuantity = 100;
+
+/// Cost for supply to enter a tile, `None` if it cannot.
+pub fn supply_entering_cost(tile: &HexMapTile) -> Option<HexMapPathCost> {
+    if Infrastructure::has_transport_on_tile(tile) {
+        return Some(1);
+    }
+    match tile.terrain() {
+        HexMapTerrain::Sea | HexMapTerrain::Lake => None,
+        HexMapTerrain::Plains => Some(1),
+        HexMapTerrain::Desert | HexMapTerrain::Forest | HexMapTerrain::Hills => Some(2),
+        HexMapTerrain::Marsh | HexMapTerrain::Mountains => Some(3),
+    }
+}
+
 #[derive(Debug)]
 pub struct SupplyNode {
     /// Must be `SimulationID::SimulationMapEntityID`.
     id: SimulationID,
+    /// Must be the `SimulationID` of a `Nation`.
+    owner: SimulationID,
     level: u8,
     resources: ResourceDataStorage,
 }
 
+impl SupplyNode {
+    pub fn new(id: SimulationID, owner: SimulationID, level: u8) -> Self {
+        assert!(matches!(id, SimulationID::MapEntityID(_)));
+        assert!(level > 0);
+        Self {
+            id,
+            owner,
+            level,
+            resources: ResourceDataStorage::new(),
+        }
+    }
+
+    pub fn owner(&self) -> &SimulationID {
+        &self.owner
+    }
+
+    pub fn level(&self) -> u8 {
+        self.level
+    }
+
+    pub fn resources(&self) -> &ResourceDataStorage {
+        &self.resources
+    }
+
+    pub fn resources_mut(&mut self) -> &mut ResourceDataStorage {
+        &mut self.resources
+    }
+
+    /// Maximum path cost from the node to a supplied unit.
+    pub fn range(&self) -> HexMapPathCost {
+        self.level as HexMapPathCost * SUPPLY_NODE_RANGE_PER_LEVEL
+    }
+
+    /// Maximum stock of every supply resource.
+    pub fn storage(&self) -> ResourceQuantity {
+        (self.level as ResourceQuantity).saturating_mul(SUPPLY_NODE_STORAGE_PER_LEVEL)
+    }
+
+    /// Get back the `SupplyNode` stored in a tile.
+    pub fn from_tile_supply_node(supply_node: &dyn HexMapTileSupplyNode) -> Option<&Self> {
+        (supply_node as &dyn Any).downcast_ref()
+    }
+
+    /// Get back the `SupplyNode` stored in a tile, mutably.
+    pub fn from_tile_supply_node_mut(
+        supply_node: &mut dyn HexMapTileSupplyNode,
+    ) -> Option<&mut Self> {
+        (supply_node as &mut dyn Any).downcast_mut()
+    }
+
+    /// The supply node built on a tile, if any.
+    pub fn on_tile(tile: &HexMapTile) -> Option<&Self> {
+        tile.layer_artificial()
+            .supply_node()
+            .and_then(Self::from_tile_supply_node)
+    }
+
+    /// The supply node built on a tile, if any, mutably.
+    pub fn on_tile_mut(tile: &mut HexMapTile) -> Option<&mut Self> {
+        tile.layer_artificial_mut()
+            .supply_node_mut()
+            .and_then(Self::from_tile_supply_node_mut)
+    }
+}
+
 impl WithSimulationID for SupplyNode {
     fn id(&self) -> &SimulationID {
         &self.id
@@ -20,3 +132,543 @@ impl WithSimulationID for SupplyNode {
 }
 
 impl HexMapTileSupplyNode for SupplyNode {}
+
+/// Resources moved from the national stockpile to a supply node.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SupplyNodeRefill {
+    /// Must be the `SimulationID` of a `SupplyNode`.
+    node: SimulationID,
+    resources: HashMap<Resource, ResourceQuantity>,
+}
+
+impl SupplyNodeRefill {
+    pub fn node(&self) -> &SimulationID {
+        &self.node
+    }
+
+    pub fn resources(&self) -> &HashMap<Resource, ResourceQuantity> {
+        &self.resources
+    }
+}
+
+/// Resources received by a unit from a supply node.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SupplyDelivery {
+    /// Must be the `SimulationID` of a `Unit`.
+    unit: SimulationID,
+    /// Must be the `SimulationID` of a `SupplyNode`.
+    node: SimulationID,
+    resource: Resource,
+    quantity: ResourceQuantity,
+}
+
+impl SupplyDelivery {
+    pub fn unit(&self) -> &SimulationID {
+        &self.unit
+    }
+
+    pub fn node(&self) -> &SimulationID {
+        &self.node
+    }
+
+    pub fn resource(&self) -> Resource {
+        self.resource
+    }
+
+    pub fn quantity(&self) -> ResourceQuantity {
+        self.quantity
+    }
+}
+
+/// A unit which did not receive all of its supply needs.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OutOfSupplyUnit {
+    /// Must be the `SimulationID` of a `Unit`.
+    unit: SimulationID,
+    /// Is the unit out of range of every supply node?
+    out_of_range: bool,
+    missing: HashMap<Resource, ResourceQuantity>,
+    /// Consecutive turns out of supply, this one included.
+    turns: u16,
+}
+
+impl OutOfSupplyUnit {
+    pub fn unit(&self) -> &SimulationID {
+        &self.unit
+    }
+
+    pub fn is_out_of_range(&self) -> bool {
+        self.out_of_range
+    }
+
+    pub fn missing(&self) -> &HashMap<Resource, ResourceQuantity> {
+        &self.missing
+    }
+
+    pub fn turns(&self) -> u16 {
+        self.turns
+    }
+}
+
+/// Outcome of the supply of a nation for a turn.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SupplyReport {
+    refills: Vec<SupplyNodeRefill>,
+    deliveries: Vec<SupplyDelivery>,
+    out_of_supply: Vec<OutOfSupplyUnit>,
+}
+
+impl SupplyReport {
+    pub fn refills(&self) -> &[SupplyNodeRefill] {
+        &self.refills
+    }
+
+    pub fn deliveries(&self) -> &[SupplyDelivery] {
+        &self.deliveries
+    }
+
+    pub fn out_of_supply(&self) -> &[OutOfSupplyUnit] {
+        &self.out_of_supply
+    }
+
+    pub fn is_out_of_supply(&self, unit: &SimulationID) -> bool {
+        self.out_of_supply.iter().any(|entry| &entry.unit == unit)
+    }
+}
+
+/// Called every turn. Refill the supply nodes of a nation from its stockpile, then supply its units.
+///
+/// `owner` is the `SimulationID` of the nation, and `network` the infrastructure networks of `map`.
+pub fn update_supply(
+    owner: &SimulationID,
+    settlements: &[&Settlement],
+    stockpile: &mut ResourceDataStorage,
+    units: &mut [&mut Unit],
+    map: &mut HexMap,
+    network: &InfrastructureNetwork,
+) -> SupplyReport {
+    let mut report = SupplyReport::default();
+    let mut nodes: Vec<CubeCoords> = map
+        .tiles()
+        .filter(|(_, tile)| SupplyNode::on_tile(tile).is_some_and(|node| &node.owner == owner))
+        .map(|(position, _)| *position)
+        .collect();
+    nodes.sort();
+
+    let mut carried: HashMap<SupplyLink, u32> = HashMap::new();
+    for (index, route) in supply_routes(&nodes, settlements, network) {
+        let Some(node) = map
+            .tile_mut(&nodes[index])
+            .and_then(SupplyNode::on_tile_mut)
+        else {
+            continue;
+        };
+        let mut remaining = route
+            .iter()
+            .map(|link| {
+                let capacity = network.capacity_between(link.0, &link.1, &link.2);
+                capacity.saturating_sub(carried.get(link).copied().unwrap_or(0))
+            })
+            .min()
+            .unwrap_or(u32::MAX);
+        let throughput = remaining;
+        let mut resources = HashMap::new();
+        for resource in SUPPLY_RESOURCES {
+            let missing = node
+                .storage()
+                .saturating_sub(node.resources.quantity_of(resource));
+            let quantity = missing
+                .min(stockpile.quantity_of(resource))
+                .min(remaining.min(ResourceQuantity::MAX as u32) as ResourceQuantity);
+            if quantity == 0 {
+                continue;
+            }
+            stockpile.consume(resource, quantity);
+            node.resources.replenish(resource, quantity);
+            remaining -= quantity as u32;
+            resources.insert(resource, quantity);
+        }
+        for link in route {
+            *carried.entry(link).or_default() += throughput - remaining;
+        }
+        if !resources.is_empty() {
+            report.refills.push(SupplyNodeRefill {
+                node: node.id.clone(),
+                resources,
+            });
+        }
+    }
+
+    let ranges: Vec<HashMap<CubeCoords, HexMapPathCost>> = nodes
+        .iter()
+        .map(|position| {
+            let range = map
+                .tile(position)
+                .and_then(SupplyNode::on_tile)
+                .map_or(0, |node| node.range());
+            reachable_tiles(map, *position, range, |_, tile| supply_entering_cost(tile))
+        })
+        .collect();
+
+    for unit in units.iter_mut() {
+        let position = unit.position().as_cube_coords();
+        let mut in_range: Vec<(HexMapPathCost, usize)> = ranges
+            .iter()
+            .enumerate()
+            .filter_map(|(index, range)| Some((*range.get(position.as_ref()?)?, index)))
+            .collect();
+        in_range.sort();
+
+        let mut needs: Vec<(Resource, ResourceQuantity)> = unit
+            .template()
+            .supply_needs()
+            .iter()
+            .filter(|(_, quantity)| **quantity > 0)
+            .map(|(resource, quantity)| (*resource, *quantity))
+            .collect();
+        needs.sort();
+
+        let mut missing = HashMap::new();
+        for (resource, mut needed) in needs {
+            for (_, index) in &in_range {
+                let Some(node) = map
+                    .tile_mut(&nodes[*index])
+                    .and_then(SupplyNode::on_tile_mut)
+                else {
+                    continue;
+                };
+                let quantity = needed.min(node.resources.quantity_of(resource));
+                if quantity == 0 {
+                    continue;
+                }
+                node.resources.consume(resource, quantity);
+                needed -= quantity;
+                report.deliveries.push(SupplyDelivery {
+                    unit: unit.id().clone(),
+                    node: node.id.clone(),
+                    resource,
+                    quantity,
+                });
+                if needed == 0 {
+                    break;
+                }
+            }
+            if needed > 0 {
+                missing.insert(resource, needed);
+            }
+        }
+
+        unit.set_supplied(missing.is_empty());
+        if !missing.is_empty() {
+            report.out_of_supply.push(OutOfSupplyUnit {
+                unit: unit.id().clone(),
+                out_of_range: in_range.is_empty(),
+                missing,
+                turns: unit.out_of_supply_turns(),
+            });
+        }
+    }
+
+    report
+}
+
+/// A link between two neighboring tiles of a network, its tiles sorted.
+type SupplyLink = (InfrastructureKind, CubeCoords, CubeCoords);
+
+/// Links travelled by supply from the settlements to every reachable node, in refill order: nodes linked to a
+/// settlement first, then the nodes linked to them, and so on, closest first within every step.
+///
+/// A node built on a settlement needs no link.
+fn supply_routes(
+    nodes: &[CubeCoords],
+    settlements: &[&Settlement],
+    network: &InfrastructureNetwork,
+) -> Vec<(usize, Vec<SupplyLink>)> {
+    let mut routes: Vec<Option<Vec<SupplyLink>>> = nodes
+        .iter()
+        .map(|position| {
+            if settlements
+                .iter()
+                .any(|settlement| settlement.position() == position)
+            {
+                return Some(vec![]);
+            }
+            settlements
+                .iter()
+                .filter_map(|settlement| best_link(*settlement.position(), *position, network))
+                .max_by_key(|(_, path)| path.capacity())
+                .map(|(kind, path)| links_of(kind, &path))
+        })
+        .collect();
+    let mut order: Vec<usize> = (0..nodes.len())
+        .filter(|index| routes[*index].is_some())
+        .collect();
+    order.sort_by_key(|index| routes[*index].as_ref().map_or(0, Vec::len));
+
+    let mut step = 0;
+    while step < order.len() {
+        let reached = order.len();
+        let mut relayed = vec![];
+        for index in 0..nodes.len() {
+            if routes[index].is_some() {
+                continue;
+            }
+            let upstream = order[step..reached]
+                .iter()
+                .filter_map(|&from| {
+                    best_link(nodes[from], nodes[index], network).map(|link| (from, link))
+                })
+                .max_by_key(|(_, (_, path))| path.capacity());
+            if let Some((from, (kind, path))) = upstream {
+                let mut route = routes[from].clone().unwrap_or_default();
+                route.extend(links_of(kind, &path));
+                relayed.push((index, route));
+            }
+        }
+        relayed.sort_by_key(|(_, route)| route.len());
+        for (index, route) in relayed {
+            routes[index] = Some(route);
+            order.push(index);
+        }
+        step = reached;
+    }
+
+    order
+        .into_iter()
+        .filter_map(|index| Some((index, routes[index].take()?)))
+        .collect()
+}
+
+/// The road or rail path with the highest capacity between two tiles, if they are linked.
+fn best_link(
+    start: CubeCoords,
+    goal: CubeCoords,
+    network: &InfrastructureNetwork,
+) -> Option<(InfrastructureKind, InfrastructureNetworkPath)> {
+    [InfrastructureKind::Road, InfrastructureKind::Rail]
+        .into_iter()
+        .filter_map(|kind| Some((kind, network.shortest_path(kind, start, goal, 0)?)))
+        .max_by_key(|(_, path)| path.capacity())
+}
+
+fn links_of(kind: InfrastructureKind, path: &InfrastructureNetworkPath) -> Vec<SupplyLink> {
+    path.tiles()
+        .windows(2)
+        .map(|pair| (kind, pair[0].min(pair[1]), pair[0].max(pair[1])))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::{
+        hex_map::{
+            coordinates::{CubeCoords, HexMapCoordinates},
+            layers::natural::HexMapTerrain,
+            tile::HexMapTile,
+            HexMap, HexMapStorage,
+        },
+        simulation::{
+            ids::SimulationID,
+            infrastructure::{
+                network::InfrastructureNetwork, Infrastructure, InfrastructureKind,
+                InfrastructureSegment,
+            },
+            military::{Unit, UnitTemplate},
+            properties::SimulationPropertyStorage,
+            resources::{Resource, ResourceDataStorage, ResourceDataStore},
+            settlements::Settlement,
+        },
+    };
+
+    use super::{update_supply, SupplyNode};
+
+    /// Plains along `r = 0` from `q = 0` to `q = 8` (mountains from `q = 6`), with a settlement at `q = 0` linked
+    /// by road to a supply node at `q = 2`.
+    fn build_mock_map() -> HexMap {
+        let mut tiles = HexMapStorage::new();
+        for q in 0..=8 {
+            let terrain = if q >= 6 {
+                HexMapTerrain::Mountains
+            } else {
+                HexMapTerrain::Plains
+            };
+            let mut tile = HexMapTile::from_terrain(0, terrain);
+            if q < 2 {
+                tile.layer_artificial_mut().add_infrastructure(Box::new(
+                    Infrastructure::new(
+                        SimulationID::new_map_entity_id(10 + q as u32),
+                        "road".into(),
+                        HashMap::new(),
+                    )
+                    .with_segment(InfrastructureSegment::new(
+                        InfrastructureKind::Road,
+                        CubeCoords::from_axial_coords(q, 0),
+                        CubeCoords::from_axial_coords(q + 1, 0),
+                        50,
+                    )),
+                ));
+            }
+            if q == 2 {
+                tile.layer_artificial_mut()
+                    .set_supply_node(Box::new(SupplyNode::new(
+                        SimulationID::new_map_entity_id(1),
+                        SimulationID::new_abstract_id("nation"),
+                        1,
+                    )));
+            }
+            tiles.insert(CubeCoords::from_axial_coords(q, 0), tile);
+        }
+        HexMap::from_tiles(tiles)
+    }
+
+    #[test]
+    fn test_supply_update() {
+        let mut map = build_mock_map();
+        let network = InfrastructureNetwork::from_map(&map);
+        let capital = Settlement::new(
+            SimulationID::new_map_entity_id(2),
+            "Capital".into(),
+            CubeCoords::from_axial_coords(0, 0),
+        );
+        let mut stockpile = ResourceDataStorage::new();
+        stockpile.replenish(Resource::Food, 30);
+        stockpile.replenish(Resource::Oil, 500);
+
+        let infantry = UnitTemplate::new(
+            SimulationID::new_abstract_id("infantry"),
+            "infantry".into(),
+            HashMap::new(),
+            HashMap::new(),
+            SimulationPropertyStorage::new(),
+        )
+        .with_supply_needs(HashMap::from([(Resource::Food, 10), (Resource::Oil, 5)]));
+        let mut units: Vec<Unit> = [4, 5, 7]
+            .into_iter()
+            .enumerate()
+            .map(|(index, q)| {
+                Unit::new(
+                    SimulationID::new_map_entity_id(20 + index as u32),
+                    HexMapCoordinates::Cube(CubeCoords::from_axial_coords(q, 0)),
+                    &infantry,
+                    100,
+                )
+            })
+            .collect();
+        let mut units: Vec<&mut Unit> = units.iter_mut().collect();
+
+        // refill capped by the road capacity: 30 food and 20 oil
+        let report = update_supply(
+            &SimulationID::new_abstract_id("nation"),
+            &[&capital],
+            &mut stockpile,
+            &mut units,
+            &mut map,
+            &network,
+        );
+        assert_eq!(
+            report.refills()[0].resources(),
+            &HashMap::from([(Resource::Food, 30), (Resource::Oil, 20)])
+        );
+        assert_eq!(stockpile.quantity_of(Resource::Oil), 480);
+
+        // the first two units are supplied, the last one is beyond the mountains
+        assert_eq!(report.deliveries().len(), 4);
+        assert_eq!(report.out_of_supply().len(), 1);
+        let out_of_supply = &report.out_of_supply()[0];
+        assert_eq!(out_of_supply.unit(), &SimulationID::new_map_entity_id(22));
+        assert!(out_of_supply.is_out_of_range());
+        assert_eq!(out_of_supply.turns(), 1);
+        assert!(units[2].is_out_of_supply());
+        assert!(!units[0].is_out_of_supply());
+
+        // food runs short at the node on the next turn
+        let report = update_supply(
+            &SimulationID::new_abstract_id("nation"),
+            &[&capital],
+            &mut stockpile,
+            &mut units,
+            &mut map,
+            &network,
+        );
+        assert!(!report.is_out_of_supply(&SimulationID::new_map_entity_id(20)));
+        assert!(report.is_out_of_supply(&SimulationID::new_map_entity_id(21)));
+        assert_eq!(
+            report.out_of_supply()[0].missing(),
+            &HashMap::from([(Resource::Food, 10)])
+        );
+        assert_eq!(units[2].out_of_supply_turns(), 2);
+    }
+
+    #[test]
+    fn test_supply_chain() {
+        // roads from the capital at `q = 0` to nodes at `q = 2` and `q = 4`, then a rail to a node at `q = 6`
+        let mut tiles = HexMapStorage::new();
+        for q in 0..=6 {
+            let mut tile = HexMapTile::from_terrain(0, HexMapTerrain::Plains);
+            if q < 6 {
+                let (kind, capacity) = match q {
+                    0 | 1 => (InfrastructureKind::Road, 220),
+                    2 | 3 => (InfrastructureKind::Road, 250),
+                    _ => (InfrastructureKind::Rail, 30),
+                };
+                tile.layer_artificial_mut().add_infrastructure(Box::new(
+                    Infrastructure::new(
+                        SimulationID::new_map_entity_id(10 + q as u32),
+                        "link".into(),
+                        HashMap::new(),
+                    )
+                    .with_segment(InfrastructureSegment::new(
+                        kind,
+                        CubeCoords::from_axial_coords(q, 0),
+                        CubeCoords::from_axial_coords(q + 1, 0),
+                        capacity,
+                    )),
+                ));
+            }
+            if q > 0 && q % 2 == 0 {
+                tile.layer_artificial_mut()
+                    .set_supply_node(Box::new(SupplyNode::new(
+                        SimulationID::new_map_entity_id(q as u32 / 2),
+                        SimulationID::new_abstract_id("nation"),
+                        1,
+                    )));
+            }
+            tiles.insert(CubeCoords::from_axial_coords(q, 0), tile);
+        }
+        let mut map = HexMap::from_tiles(tiles);
+        let network = InfrastructureNetwork::from_map(&map);
+        let capital = Settlement::new(
+            SimulationID::new_map_entity_id(4),
+            "Capital".into(),
+            CubeCoords::from_axial_coords(0, 0),
+        );
+        let mut stockpile = ResourceDataStorage::new();
+        stockpile.replenish(Resource::Food, 500);
+
+        // the first road is shared by the three nodes: only 20 food are left for the last one
+        let report = update_supply(
+            &SimulationID::new_abstract_id("nation"),
+            &[&capital],
+            &mut stockpile,
+            &mut [],
+            &mut map,
+            &network,
+        );
+        let refills: Vec<_> = report
+            .refills()
+            .iter()
+            .map(|refill| (refill.node().clone(), refill.resources()[&Resource::Food]))
+            .collect();
+        assert_eq!(
+            refills,
+            vec![
+                (SimulationID::new_map_entity_id(1), 100),
+                (SimulationID::new_map_entity_id(2), 100),
+                (SimulationID::new_map_entity_id(3), 20),
+            ]
+        );
+        assert_eq!(stockpile.quantity_of(Resource::Food), 280);
+    }
+}
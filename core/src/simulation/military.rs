@@ -1,3 +1,5 @@
+use std::collections::HashMap;
+
 use crate::hex_map::coordinates::HexMapCoordinates;
 
 use super::{
@@ -5,8 +7,56 @@ use super::{
     ids::{SimulationID, WithSimulationID},
     people::leaders::Leader,
     properties::SimulationPropertyStorage,
+    resources::{Resource, ResourceQuantity},
 };
 
+pub mod attrition;
+pub mod combat;
+pub mod command;
+pub mod domains;
+pub mod morale;
+pub mod movement;
+pub mod order_of_battle;
+pub mod recruitment;
+pub mod siege;
+
+/// Maximum morale of a unit.
+pub const UNIT_MAX_MORALE: u16 = 100;
+
+/// Level of experience of a unit, from the lowest to the highest.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Veterancy {
+    #[default]
+    Green,
+    Regular,
+    Veteran,
+    Elite,
+}
+
+impl Veterancy {
+    pub fn from_experience(experience: u32) -> Self {
+        match experience {
+            0..=99 => Self::Green,
+            100..=299 => Self::Regular,
+            300..=599 => Self::Veteran,
+            _ => Self::Elite,
+        }
+    }
+
+    /// Multiplier of the attack and defense of a unit.
+    pub fn combat_multiplier(&self) -> f64 {
+        match self {
+            Self::Green => 1.0,
+            Self::Regular => 1.1,
+            Self::Veteran => 1.2,
+            Self::Elite => 1.35,
+        }
+    }
+}
+
+/// Resources a unit must receive from the supply network every turn (fuel, food, ammunition...).
+pub type UnitSupplyNeeds = HashMap<Resource, ResourceQuantity>;
+
 /// Template of a military unit.
 #[derive(Debug)]
 pub struct UnitTemplate {
@@ -16,6 +66,58 @@ pub struct UnitTemplate {
     cost: ConstructionCosts,
     upkeep: MaintenanceCosts,
     attributes: SimulationPropertyStorage,
+    supply_needs: UnitSupplyNeeds,
+}
+
+impl UnitTemplate {
+    pub fn new(
+        id: SimulationID,
+        r#type: String,
+        cost: ConstructionCosts,
+        upkeep: MaintenanceCosts,
+        attributes: SimulationPropertyStorage,
+    ) -> Self {
+        assert!(matches!(id, SimulationID::Abstract(_)));
+        Self {
+            id,
+            r#type,
+            cost,
+            upkeep,
+            attributes,
+            supply_needs: UnitSupplyNeeds::new(),
+        }
+    }
+
+    pub fn with_supply_needs(mut self, supply_needs: UnitSupplyNeeds) -> Self {
+        self.supply_needs = supply_needs;
+        self
+    }
+
+    pub fn r#type(&self) -> &str {
+        &self.r#type
+    }
+
+    pub fn cost(&self) -> &ConstructionCosts {
+        &self.cost
+    }
+
+    pub fn upkeep(&self) -> &MaintenanceCosts {
+        &self.upkeep
+    }
+
+    pub fn attributes(&self) -> &SimulationPropertyStorage {
+        &self.attributes
+    }
+
+    pub fn supply_needs(&self) -> &UnitSupplyNeeds {
+        &self.supply_needs
+    }
+}
+
+impl WithSimulationID for UnitTemplate {
+    fn id(&self) -> &SimulationID {
+        &self.id
+    }
 }
 
 /// A military unit.
@@ -26,6 +128,160 @@ pub struct Unit<'a> {
     position: HexMapCoordinates,
     template: &'a UnitTemplate,
     health_points: u16,
+    /// From `0` to `UNIT_MAX_MORALE`.
+    morale: u16,
+    /// Has its morale broken? Routed units cannot attack until they rally.
+    routed: bool,
+    experience: u32,
+    /// Ratio, from `0.0` to `1.0`, of the nominal combat strength of the unit.
+    combat_effectiveness: f64,
+    /// Consecutive turns without receiving all of its supply needs.
+    out_of_supply_turns: u16,
+    /// Ratio added to the combat strength of the unit by its chain of command (negative when out of command).
+    command_modifier: f64,
+}
+
+impl<'a> Unit<'a> {
+    pub fn new(
+        id: SimulationID,
+        position: HexMapCoordinates,
+        template: &'a UnitTemplate,
+        health_points: u16,
+    ) -> Self {
+        assert!(matches!(id, SimulationID::MapEntityID(_)));
+        Self {
+            id,
+            position,
+            template,
+            health_points,
+            morale: UNIT_MAX_MORALE,
+            routed: false,
+            experience: 0,
+            combat_effectiveness: 1.0,
+            out_of_supply_turns: 0,
+            command_modifier: 0.0,
+        }
+    }
+
+    pub fn position(&self) -> &HexMapCoordinates {
+        &self.position
+    }
+
+    /// Relocate the unit. See `movement` for moves following the rules of the map.
+    pub fn move_to(&mut self, position: HexMapCoordinates) {
+        self.position = position;
+    }
+
+    pub fn template(&self) -> &'a UnitTemplate {
+        self.template
+    }
+
+    pub fn health_points(&self) -> u16 {
+        self.health_points
+    }
+
+    pub fn is_destroyed(&self) -> bool {
+        self.health_points == 0
+    }
+
+    pub fn morale(&self) -> u16 {
+        self.morale
+    }
+
+    pub fn is_routed(&self) -> bool {
+        self.routed
+    }
+
+    pub fn set_routed(&mut self, routed: bool) {
+        self.routed = routed;
+    }
+
+    pub fn experience(&self) -> u32 {
+        self.experience
+    }
+
+    pub fn veterancy(&self) -> Veterancy {
+        Veterancy::from_experience(self.experience)
+    }
+
+    /// Gain experience. Return the new veterancy of the unit if promoted.
+    pub fn gain_experience(&mut self, amount: u32) -> Option<Veterancy> {
+        let previous = self.veterancy();
+        self.experience = self.experience.saturating_add(amount);
+        Some(self.veterancy()).filter(|veterancy| *veterancy > previous)
+    }
+
+    /// Receive fresh troops, up to the full strength of its template (see `recruitment::unit_full_health_points`).
+    /// Experience is diluted in proportion of the health points received. Return the health points received.
+    pub fn reinforce(&mut self, health_points: u16) -> u16 {
+        let received = health_points.min(
+            recruitment::unit_full_health_points(self.template).saturating_sub(self.health_points),
+        );
+        if received > 0 {
+            let total = self.health_points as u64 + received as u64;
+            self.experience = (self.experience as u64 * self.health_points as u64 / total) as u32;
+            self.health_points += received;
+        }
+        received
+    }
+
+    pub fn combat_effectiveness(&self) -> f64 {
+        self.combat_effectiveness
+    }
+
+    /// Lose health points, down to zero. Return the health points actually lost.
+    pub fn damage(&mut self, amount: u16) -> u16 {
+        let lost = amount.min(self.health_points);
+        self.health_points -= lost;
+        lost
+    }
+
+    /// Lose morale, down to zero. Return the morale actually lost.
+    pub fn lower_morale(&mut self, amount: u16) -> u16 {
+        let lost = amount.min(self.morale);
+        self.morale -= lost;
+        lost
+    }
+
+    /// Regain morale, up to `UNIT_MAX_MORALE`. Return the morale actually regained.
+    pub fn raise_morale(&mut self, amount: u16) -> u16 {
+        let regained = amount.min(UNIT_MAX_MORALE.saturating_sub(self.morale));
+        self.morale += regained;
+        regained
+    }
+
+    /// Change the combat effectiveness by a (possibly negative) delta, within `0.0` and `1.0`. Return the actual
+    /// change.
+    pub fn change_combat_effectiveness(&mut self, delta: f64) -> f64 {
+        let previous = self.combat_effectiveness;
+        self.combat_effectiveness = (previous + delta).clamp(0.0, 1.0);
+        self.combat_effectiveness - previous
+    }
+
+    pub fn out_of_supply_turns(&self) -> u16 {
+        self.out_of_supply_turns
+    }
+
+    pub fn is_out_of_supply(&self) -> bool {
+        self.out_of_supply_turns > 0
+    }
+
+    /// Record whether the unit received all of its supply needs this turn.
+    pub fn set_supplied(&mut self, supplied: bool) {
+        self.out_of_supply_turns = if supplied {
+            0
+        } else {
+            self.out_of_supply_turns.saturating_add(1)
+        };
+    }
+
+    pub fn command_modifier(&self) -> f64 {
+        self.command_modifier
+    }
+
+    pub fn set_command_modifier(&mut self, command_modifier: f64) {
+        self.command_modifier = command_modifier;
+    }
 }
 
 impl<'a> WithSimulationID for Unit<'a> {
@@ -34,19 +290,133 @@ impl<'a> WithSimulationID for Unit<'a> {
     }
 }
 
+/// Level of an headquarters in the chain of command, from the lowest to the highest.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Echelon {
+    #[default]
+    Division,
+    Corps,
+    Army,
+    ArmyGroup,
+}
+
+impl Echelon {
+    /// Default maximum number of direct subordinates (headquarters and units) of an headquarters of this echelon.
+    pub fn command_capacity(&self) -> usize {
+        match self {
+            Echelon::Division => 12,
+            Echelon::Corps => 5,
+            Echelon::Army => 5,
+            Echelon::ArmyGroup => 6,
+        }
+    }
+
+    /// Default command range, in tiles, of an headquarters of this echelon.
+    pub fn command_range(&self) -> u32 {
+        match self {
+            Echelon::Division => 3,
+            Echelon::Corps => 5,
+            Echelon::Army => 8,
+            Echelon::ArmyGroup => 12,
+        }
+    }
+}
+
 /// A HeadQuarters military unit.
+///
+/// Its links to other headquarters are maintained by the `OrderOfBattle` it belongs to.
 #[derive(Debug)]
 pub struct HqUnit<'a> {
     /// Must be `SimulationID::SimulationMapEntityID`.
     id: SimulationID,
     position: HexMapCoordinates,
     leader: Option<Leader>,
-    superior: Option<Box<HqUnit<'a>>>,
-    subordinates: Option<Vec<Box<HqUnit<'a>>>>,
+    echelon: Echelon,
+    /// Overrides the default capacity of the echelon.
+    command_capacity: Option<usize>,
+    /// Must be the `SimulationID` of an `HqUnit`.
+    superior: Option<SimulationID>,
+    /// Must be `SimulationID`s of `HqUnit`s.
+    subordinates: Vec<SimulationID>,
     attributes: SimulationPropertyStorage,
     attached_units: Vec<Unit<'a>>,
 }
 
+impl<'a> HqUnit<'a> {
+    pub fn new(
+        id: SimulationID,
+        position: HexMapCoordinates,
+        leader: Option<Leader>,
+        attributes: SimulationPropertyStorage,
+    ) -> Self {
+        assert!(matches!(id, SimulationID::MapEntityID(_)));
+        Self {
+            id,
+            position,
+            leader,
+            echelon: Echelon::default(),
+            command_capacity: None,
+            superior: None,
+            subordinates: vec![],
+            attributes,
+            attached_units: vec![],
+        }
+    }
+
+    pub fn with_echelon(mut self, echelon: Echelon) -> Self {
+        self.echelon = echelon;
+        self
+    }
+
+    pub fn with_command_capacity(mut self, command_capacity: usize) -> Self {
+        self.command_capacity = Some(command_capacity);
+        self
+    }
+
+    pub fn position(&self) -> &HexMapCoordinates {
+        &self.position
+    }
+
+    pub fn leader(&self) -> Option<&Leader> {
+        self.leader.as_ref()
+    }
+
+    pub fn echelon(&self) -> Echelon {
+        self.echelon
+    }
+
+    /// Maximum number of direct subordinates, headquarters and units alike.
+    pub fn command_capacity(&self) -> usize {
+        self.command_capacity
+            .unwrap_or_else(|| self.echelon.command_capacity())
+    }
+
+    /// Number of direct subordinates, headquarters and units alike.
+    pub fn command_load(&self) -> usize {
+        self.subordinates.len() + self.attached_units.len()
+    }
+
+    pub fn superior(&self) -> Option<&SimulationID> {
+        self.superior.as_ref()
+    }
+
+    pub fn subordinates(&self) -> &[SimulationID] {
+        &self.subordinates
+    }
+
+    pub fn attributes(&self) -> &SimulationPropertyStorage {
+        &self.attributes
+    }
+
+    pub fn attached_units(&self) -> &[Unit<'a>] {
+        &self.attached_units
+    }
+
+    pub fn attached_units_mut(&mut self) -> &mut [Unit<'a>] {
+        &mut self.attached_units
+    }
+}
+
 impl<'a> WithSimulationID for HqUnit<'a> {
     fn id(&self) -> &SimulationID {
         &self.id
@@ -0,0 +1,376 @@
+//! Attrition: the toll taken every turn on the units which did not receive their supply.
+//!
+//! Units out of supply lose health, morale and combat effectiveness, more so on rough terrain, in bad weather and
+//! the longer they stay out of supply. The headquarters a unit is attached to can mitigate these losses, through
+//! its `ATTRITION_MITIGATION_PROPERTY` attribute and the traits of its leader (e.g. an excellent logistician).
+
+use std::collections::HashMap;
+
+use crate::{
+    hex_map::{layers::natural::HexMapTerrain, HexMap},
+    simulation::{
+        ids::{SimulationID, WithSimulationID},
+        nations::Nation,
+        properties::SimulationPropertyValue,
+        weather::WeatherMap,
+    },
+};
+
+use super::{HqUnit, Unit};
+
+/// Attribute of an `HqUnit`, as a ratio (`SimulationPropertyValue::Float`), mitigating the attrition of its units.
+pub const ATTRITION_MITIGATION_PROPERTY: &str = "attrition_mitigation";
+
+/// Whatever the headquarters, units always suffer at least a quarter of their attrition.
+pub const ATTRITION_MAX_MITIGATION: f64 = 0.75;
+
+/// Multiplier of the attrition suffered on a terrain.
+pub fn terrain_attrition_multiplier(terrain: HexMapTerrain) -> f64 {
+    match terrain {
+        HexMapTerrain::Plains | HexMapTerrain::Sea | HexMapTerrain::Lake => 1.0,
+        HexMapTerrain::Forest | HexMapTerrain::Hills => 1.25,
+        HexMapTerrain::Desert | HexMapTerrain::Marsh => 1.5,
+        HexMapTerrain::Mountains => 2.0,
+    }
+}
+
+/// Losses of a unit to attrition for a turn.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AttritionLoss {
+    /// Must be the `SimulationID` of a `Unit`.
+    unit: SimulationID,
+    health_points: u16,
+    morale: u16,
+    combat_effectiveness: f64,
+    /// Ratio of the losses avoided thanks to the headquarters of the unit.
+    mitigation: f64,
+}
+
+impl AttritionLoss {
+    pub fn unit(&self) -> &SimulationID {
+        &self.unit
+    }
+
+    pub fn health_points(&self) -> u16 {
+        self.health_points
+    }
+
+    pub fn morale(&self) -> u16 {
+        self.morale
+    }
+
+    pub fn combat_effectiveness(&self) -> f64 {
+        self.combat_effectiveness
+    }
+
+    pub fn mitigation(&self) -> f64 {
+        self.mitigation
+    }
+}
+
+/// Attrition suffered by the units of a nation for a turn.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AttritionReport {
+    losses: Vec<AttritionLoss>,
+    /// Units whose health fell to zero.
+    destroyed: Vec<SimulationID>,
+}
+
+impl AttritionReport {
+    pub fn losses(&self) -> &[AttritionLoss] {
+        &self.losses
+    }
+
+    pub fn destroyed(&self) -> &[SimulationID] {
+        &self.destroyed
+    }
+
+    pub fn loss_of(&self, unit: &SimulationID) -> Option<&AttritionLoss> {
+        self.losses.iter().find(|loss| &loss.unit == unit)
+    }
+
+    pub fn total_health_points(&self) -> u32 {
+        self.losses
+            .iter()
+            .map(|loss| loss.health_points as u32)
+            .sum()
+    }
+}
+
+/// Parameters of the attrition suffered by units out of supply.
+#[derive(Debug)]
+pub struct AttritionModel {
+    /// Health points lost per turn, before multipliers.
+    health_loss: u16,
+    /// Morale lost per turn, before multipliers.
+    morale_loss: u16,
+    /// Combat effectiveness lost per turn, before multipliers.
+    effectiveness_loss: f64,
+    /// Combat effectiveness regained per turn by supplied units.
+    effectiveness_recovery: f64,
+    /// Additional multiplier for every consecutive turn out of supply after the first one.
+    escalation_per_turn: f64,
+    /// Maximum escalation multiplier.
+    max_escalation: f64,
+    /// Mitigation ratio granted by the leader traits of an headquarters.
+    ///
+    /// Keys must be the `SimulationID`s of `Trait`s.
+    trait_mitigations: HashMap<SimulationID, f64>,
+}
+
+impl Default for AttritionModel {
+    fn default() -> Self {
+        Self {
+            health_loss: 5,
+            morale_loss: 5,
+            effectiveness_loss: 0.1,
+            effectiveness_recovery: 0.1,
+            escalation_per_turn: 0.25,
+            max_escalation: 2.0,
+            trait_mitigations: HashMap::new(),
+        }
+    }
+}
+
+impl AttritionModel {
+    pub fn new(health_loss: u16, morale_loss: u16, effectiveness_loss: f64) -> Self {
+        assert!(effectiveness_loss >= 0.0);
+        Self {
+            health_loss,
+            morale_loss,
+            effectiveness_loss,
+            ..Self::default()
+        }
+    }
+
+    pub fn with_trait_mitigation(mut self, r#trait: SimulationID, mitigation: f64) -> Self {
+        assert!(matches!(r#trait, SimulationID::Abstract(_)));
+        self.trait_mitigations.insert(r#trait, mitigation);
+        self
+    }
+
+    /// Ratio of the attrition avoided by the units attached to an headquarters.
+    pub fn mitigation(&self, hq: &HqUnit) -> f64 {
+        let from_attributes = match hq.attributes().get_from_id(&SimulationID::new_property_id(
+            ATTRITION_MITIGATION_PROPERTY.into(),
+        )) {
+            Some(SimulationPropertyValue::Float(mitigation)) => *mitigation,
+            _ => 0.0,
+        };
+        let from_traits: f64 = hq
+            .leader()
+            .into_iter()
+            .flat_map(|leader| leader.traits())
+            .filter_map(|r#trait| self.trait_mitigations.get(r#trait.id()))
+            .sum();
+        (from_attributes + from_traits).clamp(0.0, ATTRITION_MAX_MITIGATION)
+    }
+
+    /// Multiplier of the attrition of a unit, given where it stands and since when it is out of supply.
+    pub fn multiplier(&self, unit: &Unit, map: &HexMap, weather: &WeatherMap) -> f64 {
+        let Some(position) = unit.position().as_cube_coords() else {
+            return 1.0;
+        };
+        let terrain = map
+            .tile(&position)
+            .map_or(1.0, |tile| terrain_attrition_multiplier(tile.terrain()));
+        let escalation = (1.0
+            + self.escalation_per_turn * unit.out_of_supply_turns().saturating_sub(1) as f64)
+            .min(self.max_escalation);
+        terrain * weather.weather_at(&position).attrition_multiplier() * escalation
+    }
+
+    /// Apply a turn of attrition to a unit: losses if out of supply, recovery otherwise.
+    pub fn apply(
+        &self,
+        unit: &mut Unit,
+        multiplier: f64,
+        mitigation: f64,
+    ) -> Option<AttritionLoss> {
+        if !unit.is_out_of_supply() {
+            unit.change_combat_effectiveness(self.effectiveness_recovery);
+            return None;
+        }
+        let factor = multiplier * (1.0 - mitigation);
+        Some(AttritionLoss {
+            unit: unit.id().clone(),
+            health_points: unit.damage((self.health_loss as f64 * factor).round() as u16),
+            morale: unit.lower_morale((self.morale_loss as f64 * factor).round() as u16),
+            combat_effectiveness: -unit
+                .change_combat_effectiveness(-self.effectiveness_loss * factor),
+            mitigation,
+        })
+    }
+
+    /// Called every turn, after supply. Apply attrition to all the units of a nation.
+    ///
+    /// Destroyed units are taken away from their headquarters.
+    pub fn update(
+        &self,
+        nation: &mut Nation,
+        map: &HexMap,
+        weather: &WeatherMap,
+    ) -> AttritionReport {
+        let mut report = AttritionReport::default();
+        for hq in nation.headquarters_mut() {
+            let mitigation = self.mitigation(hq);
+            for unit in hq.attached_units_mut() {
+                let multiplier = self.multiplier(unit, map, weather);
+                let Some(loss) = self.apply(unit, multiplier, mitigation) else {
+                    continue;
+                };
+                if loss.health_points > 0 && unit.is_destroyed() {
+                    report.destroyed.push(unit.id().clone());
+                }
+                report.losses.push(loss);
+            }
+        }
+        nation.order_of_battle_mut().remove_destroyed_units();
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::{
+        hex_map::{
+            coordinates::{CubeCoords, HexMapCoordinates},
+            layers::natural::HexMapTerrain,
+            tile::HexMapTile,
+            HexMap, HexMapStorage,
+        },
+        simulation::{
+            ids::SimulationID,
+            military::{HqUnit, Unit, UnitTemplate, UNIT_MAX_MORALE},
+            nations::Nation,
+            people::leaders::{Trait, TraitAbility},
+            properties::{SimulationPropertyStorage, SimulationPropertyValue},
+            settlements::Settlement,
+            testing::build_mock_leader,
+            weather::{Weather, WeatherMap},
+        },
+    };
+
+    use super::{AttritionModel, ATTRITION_MITIGATION_PROPERTY};
+
+    #[test]
+    fn test_attrition_update() {
+        let mut tiles = HexMapStorage::new();
+        tiles.insert(
+            CubeCoords::from_axial_coords(0, 0),
+            HexMapTile::from_terrain(0, HexMapTerrain::Plains),
+        );
+        tiles.insert(
+            CubeCoords::from_axial_coords(1, 0),
+            HexMapTile::from_terrain(2000, HexMapTerrain::Mountains),
+        );
+        let map = HexMap::from_tiles(tiles);
+        let mut weather = WeatherMap::new(Weather::Clear);
+        weather.set_local(CubeCoords::from_axial_coords(1, 0), Weather::Snow);
+
+        let template = UnitTemplate::new(
+            SimulationID::new_abstract_id("infantry"),
+            "infantry".into(),
+            HashMap::new(),
+            HashMap::new(),
+            SimulationPropertyStorage::new(),
+        );
+        let capital = Settlement::new(
+            SimulationID::new_map_entity_id(1),
+            "Capital".into(),
+            CubeCoords::from_axial_coords(0, 0),
+        );
+        let mut nation = Nation::new(
+            SimulationID::new_abstract_id("nation"),
+            "Nation".into(),
+            build_mock_leader(1, vec![]),
+            &capital,
+        );
+
+        // one HQ on the plains with a logistician and supply-aware staff, one HQ in the snowy mountains
+        let hq = HqUnit::new(
+            SimulationID::new_map_entity_id(10),
+            HexMapCoordinates::Cube(CubeCoords::from_axial_coords(0, 0)),
+            Some(build_mock_leader(
+                2,
+                vec![Trait::Ability(TraitAbility::new(
+                    SimulationID::new_abstract_id("logistician"),
+                ))],
+            )),
+            SimulationPropertyStorage::new().register_new(
+                SimulationID::new_property_id(ATTRITION_MITIGATION_PROPERTY.into()),
+                SimulationPropertyValue::Float(0.25),
+            ),
+        );
+        nation.add_headquarters(hq).unwrap();
+        for (index, supplied) in [false, true].into_iter().enumerate() {
+            let mut unit = Unit::new(
+                SimulationID::new_map_entity_id(20 + index as u32),
+                HexMapCoordinates::Cube(CubeCoords::from_axial_coords(0, 0)),
+                &template,
+                100,
+            );
+            unit.set_supplied(supplied);
+            nation
+                .order_of_battle_mut()
+                .attach_unit(&SimulationID::new_map_entity_id(10), unit)
+                .unwrap();
+        }
+        nation
+            .add_headquarters(HqUnit::new(
+                SimulationID::new_map_entity_id(11),
+                HexMapCoordinates::Cube(CubeCoords::from_axial_coords(1, 0)),
+                None,
+                SimulationPropertyStorage::new(),
+            ))
+            .unwrap();
+        let mut unit = Unit::new(
+            SimulationID::new_map_entity_id(30),
+            HexMapCoordinates::Cube(CubeCoords::from_axial_coords(1, 0)),
+            &template,
+            5,
+        );
+        unit.set_supplied(false);
+        unit.set_supplied(false);
+        nation
+            .order_of_battle_mut()
+            .attach_unit(&SimulationID::new_map_entity_id(11), unit)
+            .unwrap();
+
+        let model = AttritionModel::new(10, 20, 0.2)
+            .with_trait_mitigation(SimulationID::new_abstract_id("logistician"), 0.25);
+        let report = model.update(&mut nation, &map, &weather);
+        assert_eq!(report.losses().len(), 2);
+
+        // halved by the headquarters
+        let loss = report
+            .loss_of(&SimulationID::new_map_entity_id(20))
+            .unwrap();
+        assert_eq!(loss.mitigation(), 0.5);
+        assert_eq!(loss.health_points(), 5);
+        assert_eq!(loss.morale(), 10);
+        assert!((loss.combat_effectiveness() - 0.1).abs() < 1e-9);
+        assert!(report
+            .loss_of(&SimulationID::new_map_entity_id(21))
+            .is_none());
+
+        // mountains (x2), snow (x1.75) and a second turn out of supply (x1.25)
+        let loss = report
+            .loss_of(&SimulationID::new_map_entity_id(30))
+            .unwrap();
+        assert_eq!(loss.health_points(), 5);
+        assert_eq!(loss.morale(), 88);
+        assert!((loss.combat_effectiveness() - 0.875).abs() < 1e-9);
+        assert_eq!(report.destroyed(), &[SimulationID::new_map_entity_id(30)]);
+        assert!(nation.headquarters()[1].attached_units().is_empty());
+        assert!(nation
+            .order_of_battle()
+            .unit(&SimulationID::new_map_entity_id(30))
+            .is_none());
+        let unit = &nation.headquarters()[0].attached_units()[0];
+        assert_eq!(unit.morale(), UNIT_MAX_MORALE - 10);
+        assert!((unit.combat_effectiveness() - 0.9).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,849 @@
+//! Combat between military units.
+//!
+//! Attackers assault the defenders of a tile over a few rounds, as long as their nations are at war (see
+//! `Belligerents`). Each round, every attacker in range strikes a random
+//! defender, then every defender strikes back at a random attacker within its own range. Units only strike the units
+//! they can engage given their domains (see `domains::engagement_strength`). The damage of a strike depends on the
+//! attack, defense, anti-air or bombardment of the striking unit (see `UnitTemplate` attributes), its effectiveness,
+//! morale, supply and command modifier, a random roll, and the armor of the target. Defenders are further protected
+//! by their terrain and by higher ground.
+//!
+//! After the last round, shaken defenders retreat to the neighboring tiles away from the attackers, without exceeding
+//! their stacking limit, or surrender if there is none left. The
+//! units which fought gain experience, and the survivors of the winning side gain morale. Every step is recorded in
+//! the combat log, so that the fight can be replayed.
+
+use std::collections::HashMap;
+
+use crate::{
+    hex_map::{
+        coordinates::{CubeCoords, HexMapCoordinates, HexMapCoordinatesSystem},
+        layers::natural::HexMapTerrain,
+        HexMap,
+    },
+    prng::CoreRandom,
+    simulation::{
+        diplomacy::Belligerents,
+        ids::{SimulationID, WithSimulationID},
+        properties::SimulationPropertyValue,
+    },
+};
+
+use super::{
+    domains::{engagement_strength, unit_domain},
+    Unit, UnitTemplate, Veterancy, UNIT_MAX_MORALE,
+};
+
+/// Attribute of an `UnitTemplate` (`SimulationPropertyValue::Integer`), its strength when attacking.
+pub const ATTACK_PROPERTY: &str = "attack";
+
+/// Attribute of an `UnitTemplate` (`SimulationPropertyValue::Integer`), its strength when defending.
+pub const DEFENSE_PROPERTY: &str = "defense";
+
+/// Attribute of an `UnitTemplate` (`SimulationPropertyValue::Integer`), reducing the damage it takes.
+pub const ARMOR_PROPERTY: &str = "armor";
+
+/// Attribute of an `UnitTemplate` (`SimulationPropertyValue::Integer`), the distance in tiles it can strike at.
+pub const RANGE_PROPERTY: &str = "range";
+
+/// Integer attribute of an `UnitTemplate`, or a default value.
+pub fn unit_attribute(template: &UnitTemplate, name: &str, default: i32) -> i32 {
+    match template
+        .attributes()
+        .get_from_id(&SimulationID::new_property_id(name.into()))
+    {
+        Some(SimulationPropertyValue::Integer(value)) => *value,
+        _ => default,
+    }
+}
+
+/// Multiplier of the defense of units on a terrain.
+pub fn terrain_defense_multiplier(terrain: HexMapTerrain) -> f64 {
+    match terrain {
+        HexMapTerrain::Plains
+        | HexMapTerrain::Desert
+        | HexMapTerrain::Sea
+        | HexMapTerrain::Lake => 1.0,
+        HexMapTerrain::Marsh => 1.1,
+        HexMapTerrain::Forest | HexMapTerrain::Hills => 1.25,
+        HexMapTerrain::Mountains => 1.5,
+    }
+}
+
+/// Multiplier of the defense of units holding higher ground than their attackers: +10% per 100 elevation, up to
+/// +30%.
+pub fn elevation_defense_multiplier(attacker_elevation: i16, defender_elevation: i16) -> f64 {
+    let advantage = (defender_elevation as f64 - attacker_elevation as f64) / 1000.0;
+    1.0 + advantage.clamp(0.0, 0.3)
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CombatExclusion {
+    /// The target tile is beyond the range of the unit.
+    OutOfRange,
+    /// The unit is already destroyed.
+    Destroyed,
+    /// The morale of the unit is broken.
+    Routed,
+    /// None of the defenders can be struck by the unit, given their domains.
+    CannotEngage,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CombatError {
+    /// The unit is not on a `CubeCoords` position, which combats do not support.
+    UnsupportedCoordinates(SimulationID),
+    /// There is no defender to attack.
+    NoDefenders,
+    /// The defender does not stand on the same tile as the first one.
+    ScatteredDefenders(SimulationID),
+}
+
+/// A step of a combat.
+#[derive(Clone, Debug, PartialEq)]
+pub enum CombatLogEntry {
+    /// An attacker which did not take part in the combat.
+    Excluded {
+        unit: SimulationID,
+        reason: CombatExclusion,
+    },
+    Round(u8),
+    Strike {
+        unit: SimulationID,
+        target: SimulationID,
+        /// Random multiplier of the strength of the strike.
+        roll: f64,
+        damage: u16,
+    },
+    Destroyed(SimulationID),
+    Retreated {
+        unit: SimulationID,
+        to: CubeCoords,
+    },
+    /// A defender which had nowhere to retreat to.
+    Captured(SimulationID),
+    /// A unit which reached a new veterancy level thanks to the experience of the combat.
+    Promoted {
+        unit: SimulationID,
+        veterancy: Veterancy,
+    },
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CombatOutcome {
+    /// No defender is left on the tile.
+    AttackerWon,
+    DefenderHeld,
+    /// Neither an engaged attacker nor a defender is left standing, so no side is rewarded.
+    MutualDestruction,
+}
+
+/// Result of a combat, with the log of everything which happened.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CombatReport {
+    belligerents: Belligerents,
+    /// Tile of the defenders.
+    position: CubeCoords,
+    log: Vec<CombatLogEntry>,
+    outcome: CombatOutcome,
+}
+
+impl CombatReport {
+    pub fn belligerents(&self) -> &Belligerents {
+        &self.belligerents
+    }
+
+    pub fn position(&self) -> &CubeCoords {
+        &self.position
+    }
+
+    pub fn log(&self) -> &[CombatLogEntry] {
+        &self.log
+    }
+
+    pub fn outcome(&self) -> CombatOutcome {
+        self.outcome
+    }
+
+    /// Health points lost by a unit during the combat.
+    pub fn damage_taken(&self, unit: &SimulationID) -> u32 {
+        self.log
+            .iter()
+            .map(|entry| match entry {
+                CombatLogEntry::Strike { target, damage, .. } if target == unit => *damage as u32,
+                _ => 0,
+            })
+            .sum()
+    }
+
+    /// Health points inflicted by a unit during the combat.
+    pub fn damage_dealt(&self, unit: &SimulationID) -> u32 {
+        self.log
+            .iter()
+            .map(|entry| match entry {
+                CombatLogEntry::Strike {
+                    unit: striker,
+                    damage,
+                    ..
+                } if striker == unit => *damage as u32,
+                _ => 0,
+            })
+            .sum()
+    }
+
+    pub fn destroyed(&self) -> impl Iterator<Item = &SimulationID> {
+        self.log.iter().filter_map(|entry| match entry {
+            CombatLogEntry::Destroyed(unit) => Some(unit),
+            _ => None,
+        })
+    }
+
+    /// Units which surrendered. They must be removed from the order of battle of their nation.
+    pub fn captured(&self) -> impl Iterator<Item = &SimulationID> {
+        self.log.iter().filter_map(|entry| match entry {
+            CombatLogEntry::Captured(unit) => Some(unit),
+            _ => None,
+        })
+    }
+
+    pub fn retreated(&self) -> impl Iterator<Item = (&SimulationID, &CubeCoords)> {
+        self.log.iter().filter_map(|entry| match entry {
+            CombatLogEntry::Retreated { unit, to } => Some((unit, to)),
+            _ => None,
+        })
+    }
+}
+
+/// Rules of the combats between units.
+#[derive(Debug)]
+pub struct CombatModel {
+    rounds: u8,
+    /// Width of the random roll of a strike: from `1 - spread / 2` to `1 + spread / 2`.
+    roll_spread: f64,
+    /// Armor halving the damage taken.
+    armor_scale: f64,
+    /// Morale lost per health point lost.
+    morale_per_damage: f64,
+    /// Defenders below this morale retreat at the end of the combat.
+    retreat_morale: u16,
+    /// Ratio of the strength lost by units out of supply.
+    out_of_supply_penalty: f64,
+    /// Ratio of the strength lost by routed units.
+    routed_penalty: f64,
+    /// Experience gained per health point of damage dealt or taken.
+    experience_per_damage: f64,
+    /// Morale gained by the surviving units of the winning side.
+    victory_morale: u16,
+    /// Maximum number of units on the tile a defender retreats to.
+    stacking_limit: usize,
+}
+
+impl Default for CombatModel {
+    fn default() -> Self {
+        Self {
+            rounds: 3,
+            roll_spread: 0.5,
+            armor_scale: 10.0,
+            morale_per_damage: 0.5,
+            retreat_morale: 25,
+            out_of_supply_penalty: 0.25,
+            routed_penalty: 0.5,
+            experience_per_damage: 1.0,
+            victory_morale: 10,
+            stacking_limit: 3,
+        }
+    }
+}
+
+impl CombatModel {
+    pub fn new(rounds: u8, retreat_morale: u16) -> Self {
+        assert!(rounds > 0);
+        Self {
+            rounds,
+            retreat_morale,
+            ..Self::default()
+        }
+    }
+
+    pub fn with_stacking_limit(mut self, stacking_limit: usize) -> Self {
+        assert!(stacking_limit > 0);
+        self.stacking_limit = stacking_limit;
+        self
+    }
+
+    /// Strength of a unit given its base attack or defense.
+    pub fn strength(&self, unit: &Unit, base: i32) -> f64 {
+        let morale = 0.5 + unit.morale() as f64 / (2.0 * UNIT_MAX_MORALE as f64);
+        let supply = if unit.is_out_of_supply() {
+            1.0 - self.out_of_supply_penalty
+        } else {
+            1.0
+        };
+        let routed = if unit.is_routed() {
+            1.0 - self.routed_penalty
+        } else {
+            1.0
+        };
+        base.max(0) as f64
+            * unit.veterancy().combat_multiplier()
+            * unit.combat_effectiveness()
+            * (1.0 + unit.command_modifier()).max(0.0)
+            * morale
+            * supply
+            * routed
+    }
+
+    /// Ratio of the damage a unit lets through its armor.
+    pub fn armor_factor(&self, unit: &Unit) -> f64 {
+        let armor = unit_attribute(unit.template(), ARMOR_PROPERTY, 0).max(0) as f64;
+        self.armor_scale / (self.armor_scale + armor)
+    }
+
+    /// Resolve the assault of the defenders of a tile by the attackers.
+    ///
+    /// The attackers must be units of the attacker of `belligerents`, and the defenders units of its defender, all
+    /// standing on the same tile. Surrendered defenders are left in place and must be removed by the caller (see
+    /// `CombatReport::captured`).
+    ///
+    /// `occupants` is the number of units already standing on the tiles around the defenders, for their retreat.
+    pub fn resolve<R: CoreRandom>(
+        &self,
+        belligerents: &Belligerents,
+        attackers: &mut [&mut Unit],
+        defenders: &mut [&mut Unit],
+        map: &HexMap,
+        occupants: &HashMap<CubeCoords, usize>,
+        rng: &mut R,
+    ) -> Result<CombatReport, CombatError> {
+        let Some(target) = defenders.first().map(|unit| unit.position().clone()) else {
+            return Err(CombatError::NoDefenders);
+        };
+        if let Some(defender) = defenders.iter().find(|unit| unit.position() != &target) {
+            return Err(CombatError::ScatteredDefenders(defender.id().clone()));
+        }
+        let position = target
+            .as_cube_coords()
+            .ok_or_else(|| CombatError::UnsupportedCoordinates(defenders[0].id().clone()))?;
+        if let Some(attacker) = attackers
+            .iter()
+            .find(|unit| unit.position().as_cube_coords().is_none())
+        {
+            return Err(CombatError::UnsupportedCoordinates(attacker.id().clone()));
+        }
+        let mut log = vec![];
+
+        // defense multiplier of the tile against every attacker
+        let defender_tile = map.tile(&position);
+        let mut engaged: Vec<(usize, f64, f64)> = vec![];
+        for (index, attacker) in attackers.iter().enumerate() {
+            let distance = attacker.position().distance_from(&target);
+            if attacker.is_destroyed() {
+                log.push(CombatLogEntry::Excluded {
+                    unit: attacker.id().clone(),
+                    reason: CombatExclusion::Destroyed,
+                });
+            } else if attacker.is_routed() {
+                log.push(CombatLogEntry::Excluded {
+                    unit: attacker.id().clone(),
+                    reason: CombatExclusion::Routed,
+                });
+            } else if distance > unit_attribute(attacker.template(), RANGE_PROPERTY, 1) as f64 {
+                log.push(CombatLogEntry::Excluded {
+                    unit: attacker.id().clone(),
+                    reason: CombatExclusion::OutOfRange,
+                });
+            } else if defenders
+                .iter()
+                .all(|defender| engagement_strength(attacker, defender, true).is_none())
+            {
+                log.push(CombatLogEntry::Excluded {
+                    unit: attacker.id().clone(),
+                    reason: CombatExclusion::CannotEngage,
+                });
+            } else {
+                let defense = defender_tile.map_or(1.0, |tile| {
+                    let attacker_elevation = attacker
+                        .position()
+                        .as_cube_coords()
+                        .and_then(|position| map.tile(&position))
+                        .map_or(tile.elevation(), |attacker_tile| attacker_tile.elevation());
+                    terrain_defense_multiplier(tile.terrain())
+                        * elevation_defense_multiplier(attacker_elevation, tile.elevation())
+                });
+                engaged.push((index, distance, defense));
+            }
+        }
+
+        for round in 1..=self.rounds {
+            let alive_defenders = |defenders: &[&mut Unit]| -> Vec<usize> {
+                (0..defenders.len())
+                    .filter(|index| !defenders[*index].is_destroyed())
+                    .collect()
+            };
+            if engaged.is_empty() || alive_defenders(defenders).is_empty() {
+                break;
+            }
+            log.push(CombatLogEntry::Round(round));
+
+            for (attacker, _, defense) in &engaged {
+                if attackers[*attacker].is_destroyed() {
+                    continue;
+                }
+                let targets: Vec<(usize, i32)> = alive_defenders(defenders)
+                    .into_iter()
+                    .filter_map(|defender| {
+                        engagement_strength(attackers[*attacker], defenders[defender], true)
+                            .map(|base| (defender, base))
+                    })
+                    .collect();
+                if targets.is_empty() {
+                    continue;
+                }
+                let (defender, base) =
+                    targets[rng.random_maxed_value_u32(targets.len() as u32) as usize];
+                let strength = self.strength(attackers[*attacker], base) / defense;
+                self.strike(
+                    attackers[*attacker],
+                    defenders[defender],
+                    strength,
+                    rng,
+                    &mut log,
+                );
+            }
+
+            for defender in alive_defenders(defenders) {
+                let range =
+                    unit_attribute(defenders[defender].template(), RANGE_PROPERTY, 1) as f64;
+                let targets: Vec<(usize, i32)> = engaged
+                    .iter()
+                    .filter(|(attacker, distance, _)| {
+                        *distance <= range && !attackers[*attacker].is_destroyed()
+                    })
+                    .filter_map(|(attacker, _, _)| {
+                        engagement_strength(defenders[defender], attackers[*attacker], false)
+                            .map(|base| (*attacker, base))
+                    })
+                    .collect();
+                if targets.is_empty() {
+                    continue;
+                }
+                let (attacker, base) =
+                    targets[rng.random_maxed_value_u32(targets.len() as u32) as usize];
+                let strength = self.strength(defenders[defender], base);
+                self.strike(
+                    defenders[defender],
+                    attackers[attacker],
+                    strength,
+                    rng,
+                    &mut log,
+                );
+            }
+        }
+
+        // shaken defenders fall back away from the attackers
+        let attacker_positions: Vec<CubeCoords> = attackers
+            .iter()
+            .filter_map(|attacker| attacker.position().as_cube_coords())
+            .collect();
+        let mut retreat_tiles: Vec<CubeCoords> = map
+            .neighbors_of(&position)
+            .filter(|neighbor| !attacker_positions.contains(neighbor))
+            .collect();
+        let distance_from_attackers = |tile: &CubeCoords| {
+            attacker_positions
+                .iter()
+                .map(|attacker| attacker.distance_to(*tile) as i64)
+                .min()
+                .unwrap_or(0)
+        };
+        retreat_tiles.sort_by(|a, b| {
+            distance_from_attackers(b)
+                .cmp(&distance_from_attackers(a))
+                .then(a.cmp(b))
+        });
+        let attackers_left = engaged
+            .iter()
+            .any(|(attacker, _, _)| !attackers[*attacker].is_destroyed());
+        let mut retreated: HashMap<CubeCoords, usize> = HashMap::new();
+        let mut defenders_left = false;
+        for defender in defenders.iter_mut() {
+            if defender.is_destroyed() {
+                continue;
+            }
+            if !attackers_left || defender.morale() >= self.retreat_morale {
+                defenders_left = true;
+                continue;
+            }
+            let domain = unit_domain(defender.template());
+            let retreat_tile = retreat_tiles.iter().find(|tile| {
+                let stacked = occupants.get(tile).copied().unwrap_or(0)
+                    + retreated.get(tile).copied().unwrap_or(0);
+                stacked < self.stacking_limit
+                    && map
+                        .tile(tile)
+                        .and_then(|tile| domain.terrain_movement_cost(tile.terrain()))
+                        .is_some()
+            });
+            match retreat_tile {
+                Some(to) => {
+                    *retreated.entry(*to).or_default() += 1;
+                    defender.move_to(HexMapCoordinates::Cube(*to));
+                    log.push(CombatLogEntry::Retreated {
+                        unit: defender.id().clone(),
+                        to: *to,
+                    });
+                }
+                None => log.push(CombatLogEntry::Captured(defender.id().clone())),
+            }
+        }
+
+        let outcome = match (attackers_left, defenders_left) {
+            (false, false) => CombatOutcome::MutualDestruction,
+            (true, false) => CombatOutcome::AttackerWon,
+            _ => CombatOutcome::DefenderHeld,
+        };
+
+        // experience for everyone who fought, morale for the winners
+        let mut report = CombatReport {
+            belligerents: belligerents.clone(),
+            position,
+            log,
+            outcome,
+        };
+        let mut promotions = vec![];
+        for (attacker, _, _) in &engaged {
+            let won = outcome == CombatOutcome::AttackerWon;
+            self.reward(attackers[*attacker], won, &report, &mut promotions);
+        }
+        for defender in defenders.iter_mut() {
+            let won = outcome == CombatOutcome::DefenderHeld;
+            self.reward(defender, won, &report, &mut promotions);
+        }
+        report.log.extend(promotions);
+        Ok(report)
+    }
+
+    /// Award experience to a unit for the damage it dealt and took, and morale if its side won.
+    fn reward(
+        &self,
+        unit: &mut Unit,
+        won: bool,
+        report: &CombatReport,
+        promotions: &mut Vec<CombatLogEntry>,
+    ) {
+        if unit.is_destroyed() {
+            return;
+        }
+        if won {
+            unit.raise_morale(self.victory_morale);
+        }
+        let damage = report.damage_dealt(unit.id()) + report.damage_taken(unit.id());
+        let experience = (damage as f64 * self.experience_per_damage).round() as u32;
+        if let Some(veterancy) = unit.gain_experience(experience) {
+            promotions.push(CombatLogEntry::Promoted {
+                unit: unit.id().clone(),
+                veterancy,
+            });
+        }
+    }
+
+    fn strike<R: CoreRandom>(
+        &self,
+        unit: &Unit,
+        target: &mut Unit,
+        strength: f64,
+        rng: &mut R,
+        log: &mut Vec<CombatLogEntry>,
+    ) {
+        let roll = 1.0 - self.roll_spread / 2.0 + self.roll_spread * rng.random_ratio();
+        let damage = (strength * roll * self.armor_factor(target)).round() as u16;
+        let damage = target.damage(damage);
+        target.lower_morale((damage as f64 * self.morale_per_damage).round() as u16);
+        log.push(CombatLogEntry::Strike {
+            unit: unit.id().clone(),
+            target: target.id().clone(),
+            roll,
+            damage,
+        });
+        if target.is_destroyed() {
+            log.push(CombatLogEntry::Destroyed(target.id().clone()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::{
+        hex_map::{
+            coordinates::{CubeCoords, HexMapCoordinates},
+            layers::natural::HexMapTerrain,
+            tile::HexMapTile,
+            HexMap, HexMapStorage,
+        },
+        prng::TestingHarnessRandomGenerator,
+        simulation::{
+            diplomacy::{Belligerents, Diplomacy},
+            ids::SimulationID,
+            military::{Unit, UnitTemplate, UNIT_MAX_MORALE},
+            properties::{SimulationPropertyStorage, SimulationPropertyValue},
+        },
+    };
+
+    use super::{
+        CombatError, CombatExclusion, CombatLogEntry, CombatModel, CombatOutcome, ARMOR_PROPERTY,
+        ATTACK_PROPERTY, DEFENSE_PROPERTY,
+    };
+
+    fn build_mock_template(id: &str, attack: i32, defense: i32, armor: i32) -> UnitTemplate {
+        let attributes = [
+            (ATTACK_PROPERTY, attack),
+            (DEFENSE_PROPERTY, defense),
+            (ARMOR_PROPERTY, armor),
+        ]
+        .into_iter()
+        .fold(
+            SimulationPropertyStorage::new(),
+            |attributes, (name, value)| {
+                attributes.register_new(
+                    SimulationID::new_property_id(name.into()),
+                    SimulationPropertyValue::Integer(value),
+                )
+            },
+        );
+        UnitTemplate::new(
+            SimulationID::new_abstract_id(id),
+            id.into(),
+            HashMap::new(),
+            HashMap::new(),
+            attributes,
+        )
+    }
+
+    fn build_mock_belligerents() -> Belligerents {
+        let [attacker, defender] = ["attacker", "defender"].map(SimulationID::new_abstract_id);
+        let mut diplomacy = Diplomacy::new();
+        diplomacy.declare_war(&attacker, &defender).unwrap();
+        diplomacy.belligerents(&attacker, &defender).unwrap()
+    }
+
+    fn build_mock_unit(id: u32, q: i16, template: &UnitTemplate) -> Unit<'_> {
+        Unit::new(
+            SimulationID::new_map_entity_id(id),
+            HexMapCoordinates::Cube(CubeCoords::from_axial_coords(q, 0)),
+            template,
+            100,
+        )
+    }
+
+    #[test]
+    fn test_combat_resolution() {
+        // defender on hills, higher than the attacker
+        let mut tiles = HexMapStorage::new();
+        for (q, r, elevation, terrain) in [
+            (-1, 0, 0, HexMapTerrain::Plains),
+            (0, 0, 0, HexMapTerrain::Plains),
+            (1, 0, 500, HexMapTerrain::Hills),
+            (2, 0, 0, HexMapTerrain::Plains),
+            (2, -1, 0, HexMapTerrain::Sea),
+        ] {
+            tiles.insert(
+                CubeCoords::from_axial_coords(q, r),
+                HexMapTile::from_terrain(elevation, terrain),
+            );
+        }
+        let map = HexMap::from_tiles(tiles);
+        let tank = build_mock_template("tank", 20, 5, 0);
+        let infantry = build_mock_template("infantry", 5, 10, 10);
+        // every roll is exactly 1.0
+        let mut rng = TestingHarnessRandomGenerator::new(vec![500]);
+
+        let mut attacker = build_mock_unit(10, 0, &tank);
+        let mut too_far = build_mock_unit(11, -1, &tank);
+        let mut defender = build_mock_unit(20, 1, &infantry);
+        let model = CombatModel::new(3, 95);
+        let belligerents = build_mock_belligerents();
+        let report = model
+            .resolve(
+                &belligerents,
+                &mut [&mut attacker, &mut too_far],
+                &mut [&mut defender],
+                &map,
+                &HashMap::new(),
+                &mut rng,
+            )
+            .unwrap();
+
+        assert_eq!(
+            report.log()[0],
+            CombatLogEntry::Excluded {
+                unit: SimulationID::new_map_entity_id(11),
+                reason: CombatExclusion::OutOfRange
+            }
+        );
+        assert_eq!(
+            report
+                .log()
+                .iter()
+                .filter(|entry| matches!(entry, CombatLogEntry::Round(_)))
+                .count(),
+            3
+        );
+        // 20 attack, halved by armor, against 1.25 (hills) x 1.3 (elevation)
+        assert_eq!(
+            report.damage_taken(&SimulationID::new_map_entity_id(20)),
+            18
+        );
+        assert_eq!(
+            report.damage_taken(&SimulationID::new_map_entity_id(10)),
+            30
+        );
+        assert_eq!(report.damage_taken(&SimulationID::new_map_entity_id(11)), 0);
+        assert_eq!(attacker.health_points(), 70);
+        assert_eq!(defender.morale(), 91);
+        assert_eq!(attacker.experience(), 48);
+        assert_eq!(too_far.experience(), 0);
+
+        // shaken, the defender falls back away from the attackers
+        assert_eq!(report.outcome(), CombatOutcome::AttackerWon);
+        let retreated: Vec<_> = report.retreated().collect();
+        assert_eq!(
+            retreated,
+            vec![(
+                &SimulationID::new_map_entity_id(20),
+                &CubeCoords::from_axial_coords(2, 0)
+            )]
+        );
+        assert_eq!(
+            defender.position(),
+            &HexMapCoordinates::Cube(CubeCoords::from_axial_coords(2, 0))
+        );
+
+        // cornered, it surrenders
+        let mut attacker = build_mock_unit(12, 1, &tank);
+        let report = model
+            .resolve(
+                &belligerents,
+                &mut [&mut attacker, &mut too_far],
+                &mut [&mut defender],
+                &map,
+                &HashMap::new(),
+                &mut rng,
+            )
+            .unwrap();
+        assert_eq!(report.outcome(), CombatOutcome::AttackerWon);
+        assert_eq!(
+            report.captured().collect::<Vec<_>>(),
+            vec![&SimulationID::new_map_entity_id(20)]
+        );
+    }
+
+    #[test]
+    fn test_combat_retreat() {
+        let tile = CubeCoords::from_axial_coords;
+        let mut tiles = HexMapStorage::new();
+        for (q, r) in [(0, 0), (1, 0), (2, 0), (2, -1), (1, 1)] {
+            tiles.insert(
+                tile(q, r),
+                HexMapTile::from_terrain(0, HexMapTerrain::Plains),
+            );
+        }
+        let map = HexMap::from_tiles(tiles);
+        let tank = build_mock_template("tank", 20, 5, 0);
+        let infantry = build_mock_template("infantry", 5, 10, 10);
+        let mut rng = TestingHarnessRandomGenerator::new(vec![500]);
+        let model = CombatModel::new(1, UNIT_MAX_MORALE + 1).with_stacking_limit(1);
+        let belligerents = build_mock_belligerents();
+
+        // (2, 0) is already full: two defenders spread over the other tiles, the last one surrenders
+        let mut attacker = build_mock_unit(10, 0, &tank);
+        let mut defenders: Vec<Unit> = (20..23)
+            .map(|id| build_mock_unit(id, 1, &infantry))
+            .collect();
+        let mut defenders: Vec<&mut Unit> = defenders.iter_mut().collect();
+        let report = model
+            .resolve(
+                &belligerents,
+                &mut [&mut attacker],
+                &mut defenders,
+                &map,
+                &HashMap::from([(tile(2, 0), 1)]),
+                &mut rng,
+            )
+            .unwrap();
+        let mut destinations: Vec<CubeCoords> = report.retreated().map(|(_, to)| *to).collect();
+        destinations.sort();
+        let mut expected = vec![tile(2, -1), tile(1, 1)];
+        expected.sort();
+        assert_eq!(destinations, expected);
+        assert_eq!(report.captured().count(), 1);
+
+        // combats are only fought on cube coordinates
+        let mut lost = Unit::new(
+            SimulationID::new_map_entity_id(11),
+            HexMapCoordinates::Offset(),
+            &tank,
+            100,
+        );
+        let mut defender = build_mock_unit(24, 1, &infantry);
+        assert_eq!(
+            model.resolve(
+                &belligerents,
+                &mut [&mut lost],
+                &mut [&mut defender],
+                &map,
+                &HashMap::new(),
+                &mut rng,
+            ),
+            Err(CombatError::UnsupportedCoordinates(
+                SimulationID::new_map_entity_id(11)
+            ))
+        );
+
+        // defenders must all stand on one tile
+        let mut attacker = build_mock_unit(10, 0, &tank);
+        let mut elsewhere = build_mock_unit(25, 2, &infantry);
+        assert_eq!(
+            model.resolve(
+                &belligerents,
+                &mut [&mut attacker],
+                &mut [],
+                &map,
+                &HashMap::new(),
+                &mut rng,
+            ),
+            Err(CombatError::NoDefenders)
+        );
+        assert_eq!(
+            model.resolve(
+                &belligerents,
+                &mut [&mut attacker],
+                &mut [&mut defender, &mut elsewhere],
+                &map,
+                &HashMap::new(),
+                &mut rng,
+            ),
+            Err(CombatError::ScatteredDefenders(
+                SimulationID::new_map_entity_id(25)
+            ))
+        );
+
+        // nobody left standing on either side: nobody wins
+        let mut routed = build_mock_unit(10, 0, &tank);
+        routed.set_routed(true);
+        let mut wiped_out = build_mock_unit(24, 1, &infantry);
+        wiped_out.damage(100);
+        let report = model
+            .resolve(
+                &belligerents,
+                &mut [&mut routed],
+                &mut [&mut wiped_out],
+                &map,
+                &HashMap::new(),
+                &mut rng,
+            )
+            .unwrap();
+        assert_eq!(report.outcome(), CombatOutcome::MutualDestruction);
+    }
+}
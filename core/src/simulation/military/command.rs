@@ -0,0 +1,389 @@
+//! Command: the effects of headquarters on the units they command.
+//!
+//! Every headquarters has a command range, in tiles. Units within the range of their headquarters benefit from its
+//! bonuses, derived from its attributes and the traits of its leader, plus a share of the bonuses of the
+//! headquarters above it, as long as each link of the chain of command is itself within range. Units outside the
+//! range of their headquarters suffer a coordination penalty instead.
+
+use std::collections::HashMap;
+
+use crate::{
+    hex_map::coordinates::{HexMapCoordinates, HexMapCoordinatesSystem},
+    simulation::{
+        ids::{SimulationID, WithSimulationID},
+        properties::SimulationPropertyValue,
+    },
+};
+
+use super::{order_of_battle::OrderOfBattle, HqUnit};
+
+/// Attribute of an `HqUnit` (`SimulationPropertyValue::Integer`), overriding the command range of its echelon.
+pub const COMMAND_RANGE_PROPERTY: &str = "command_range";
+
+/// Attribute of an `HqUnit` (`SimulationPropertyValue::Float`), the combat bonus ratio granted to its units.
+pub const COMMAND_COMBAT_BONUS_PROPERTY: &str = "command_combat_bonus";
+
+/// Attribute of an `HqUnit` (`SimulationPropertyValue::Integer`), the morale granted to its units every turn.
+pub const COMMAND_MORALE_BONUS_PROPERTY: &str = "command_morale_bonus";
+
+/// Effects of the chain of command on a unit.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct CommandModifiers {
+    /// Ratio added to the combat strength.
+    combat: f64,
+    /// Morale gained (or lost, if negative) every turn.
+    morale: i32,
+}
+
+impl CommandModifiers {
+    pub fn new(combat: f64, morale: i32) -> Self {
+        Self { combat, morale }
+    }
+
+    pub fn combat(&self) -> f64 {
+        self.combat
+    }
+
+    pub fn morale(&self) -> i32 {
+        self.morale
+    }
+
+    /// Add a share of other modifiers to these ones.
+    fn add_scaled(&mut self, other: &Self, ratio: f64) {
+        self.combat += other.combat * ratio;
+        self.morale += (other.morale as f64 * ratio).round() as i32;
+    }
+}
+
+/// Command situation of a unit for a turn.
+#[derive(Clone, Debug, PartialEq)]
+pub struct UnitCommandStatus {
+    /// Must be the `SimulationID` of a `Unit`.
+    unit: SimulationID,
+    /// Must be the `SimulationID` of an `HqUnit`.
+    headquarters: SimulationID,
+    /// Distance, in tiles, from the headquarters. Infinite if either is not on a `CubeCoords` position.
+    distance: f64,
+    in_range: bool,
+    modifiers: CommandModifiers,
+}
+
+impl UnitCommandStatus {
+    pub fn unit(&self) -> &SimulationID {
+        &self.unit
+    }
+
+    pub fn headquarters(&self) -> &SimulationID {
+        &self.headquarters
+    }
+
+    pub fn distance(&self) -> f64 {
+        self.distance
+    }
+
+    pub fn is_in_range(&self) -> bool {
+        self.in_range
+    }
+
+    pub fn modifiers(&self) -> &CommandModifiers {
+        &self.modifiers
+    }
+}
+
+/// Command situation of all the units of an order of battle for a turn.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CommandReport {
+    units: Vec<UnitCommandStatus>,
+}
+
+impl CommandReport {
+    pub fn units(&self) -> &[UnitCommandStatus] {
+        &self.units
+    }
+
+    pub fn status_of(&self, unit: &SimulationID) -> Option<&UnitCommandStatus> {
+        self.units.iter().find(|status| &status.unit == unit)
+    }
+
+    /// The units outside the command range of their headquarters.
+    pub fn out_of_command(&self) -> impl Iterator<Item = &UnitCommandStatus> {
+        self.units.iter().filter(|status| !status.in_range)
+    }
+}
+
+/// Parameters of the effects of headquarters on their units.
+#[derive(Debug)]
+pub struct CommandModel {
+    /// Applied, instead of any bonus, to units outside the range of their headquarters.
+    out_of_command: CommandModifiers,
+    /// Share of the modifiers of an headquarters passed down to each of its subordinate headquarters.
+    propagation: f64,
+    /// Modifiers granted by the leader traits of an headquarters.
+    ///
+    /// Keys must be the `SimulationID`s of `Trait`s.
+    trait_modifiers: HashMap<SimulationID, CommandModifiers>,
+}
+
+impl Default for CommandModel {
+    fn default() -> Self {
+        Self {
+            out_of_command: CommandModifiers::new(-0.2, -5),
+            propagation: 0.5,
+            trait_modifiers: HashMap::new(),
+        }
+    }
+}
+
+impl CommandModel {
+    pub fn new(out_of_command: CommandModifiers, propagation: f64) -> Self {
+        assert!((0.0..=1.0).contains(&propagation));
+        Self {
+            out_of_command,
+            propagation,
+            ..Self::default()
+        }
+    }
+
+    pub fn with_trait_modifiers(
+        mut self,
+        r#trait: SimulationID,
+        modifiers: CommandModifiers,
+    ) -> Self {
+        assert!(matches!(r#trait, SimulationID::Abstract(_)));
+        self.trait_modifiers.insert(r#trait, modifiers);
+        self
+    }
+
+    /// Command range of an headquarters, in tiles.
+    pub fn range(&self, hq: &HqUnit) -> f64 {
+        match hq.attributes().get_from_id(&SimulationID::new_property_id(
+            COMMAND_RANGE_PROPERTY.into(),
+        )) {
+            Some(SimulationPropertyValue::Integer(range)) => (*range).max(0) as f64,
+            _ => hq.echelon().command_range() as f64,
+        }
+    }
+
+    /// Modifiers granted by an headquarters on its own, from its attributes and the traits of its leader.
+    pub fn own_modifiers(&self, hq: &HqUnit) -> CommandModifiers {
+        let attribute = |name: &str| {
+            hq.attributes()
+                .get_from_id(&SimulationID::new_property_id(name.into()))
+        };
+        let mut modifiers = CommandModifiers::default();
+        if let Some(SimulationPropertyValue::Float(combat)) =
+            attribute(COMMAND_COMBAT_BONUS_PROPERTY)
+        {
+            modifiers.combat += combat;
+        }
+        if let Some(SimulationPropertyValue::Integer(morale)) =
+            attribute(COMMAND_MORALE_BONUS_PROPERTY)
+        {
+            modifiers.morale += morale;
+        }
+        for r#trait in hq.leader().into_iter().flat_map(|leader| leader.traits()) {
+            if let Some(bonus) = self.trait_modifiers.get(r#trait.id()) {
+                modifiers.add_scaled(bonus, 1.0);
+            }
+        }
+        modifiers
+    }
+
+    /// Modifiers granted by every headquarters to the units within its range, including the share passed down by
+    /// its superiors.
+    pub fn effective_modifiers(
+        &self,
+        order_of_battle: &OrderOfBattle,
+    ) -> HashMap<SimulationID, CommandModifiers> {
+        let mut effective: HashMap<SimulationID, CommandModifiers> = HashMap::new();
+        // pre-order: superiors are always resolved before their subordinates
+        for (_, hq) in order_of_battle.traverse_all() {
+            let mut modifiers = self.own_modifiers(hq);
+            let superior = hq
+                .superior()
+                .and_then(|superior| order_of_battle.get(superior));
+            if let Some(superior) = superior {
+                if distance(hq.position(), superior.position()) <= self.range(superior) {
+                    modifiers.add_scaled(&effective[superior.id()], self.propagation);
+                }
+            }
+            effective.insert(hq.id().clone(), modifiers);
+        }
+        effective
+    }
+
+    /// Called every turn. Update the command modifiers and the morale of all the units of an order of battle.
+    pub fn update(&self, order_of_battle: &mut OrderOfBattle) -> CommandReport {
+        let effective = self.effective_modifiers(order_of_battle);
+        let mut report = CommandReport::default();
+        for hq in order_of_battle.headquarters_mut() {
+            let range = self.range(hq);
+            let position = hq.position().clone();
+            let id = hq.id().clone();
+            for unit in hq.attached_units_mut() {
+                let distance = distance(unit.position(), &position);
+                let in_range = distance <= range;
+                let modifiers = if in_range {
+                    effective[&id]
+                } else {
+                    self.out_of_command
+                };
+                unit.set_command_modifier(modifiers.combat);
+                let morale = modifiers.morale.clamp(-(u16::MAX as i32), u16::MAX as i32);
+                if morale >= 0 {
+                    unit.raise_morale(morale as u16);
+                } else {
+                    unit.lower_morale(morale.unsigned_abs() as u16);
+                }
+                report.units.push(UnitCommandStatus {
+                    unit: unit.id().clone(),
+                    headquarters: id.clone(),
+                    distance,
+                    in_range,
+                    modifiers,
+                });
+            }
+        }
+        report
+    }
+}
+
+/// Distance, in tiles, between two positions. Infinite (i.e. out of any range) unless both are `CubeCoords`.
+fn distance(from: &HexMapCoordinates, to: &HexMapCoordinates) -> f64 {
+    match (from.as_cube_coords(), to.as_cube_coords()) {
+        (Some(from), Some(to)) => from.distance_to(to),
+        _ => f64::INFINITY,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::{
+        hex_map::coordinates::{CubeCoords, HexMapCoordinates},
+        simulation::{
+            ids::SimulationID,
+            military::{order_of_battle::OrderOfBattle, Echelon, HqUnit, Unit, UnitTemplate},
+            people::leaders::{IndividualIDCard, IndividualName, Leader, Trait, TraitPersonality},
+            properties::{SimulationPropertyStorage, SimulationPropertyValue},
+        },
+    };
+
+    use super::{
+        CommandModel, CommandModifiers, COMMAND_COMBAT_BONUS_PROPERTY,
+        COMMAND_MORALE_BONUS_PROPERTY,
+    };
+
+    fn position(q: i16) -> HexMapCoordinates {
+        HexMapCoordinates::Cube(CubeCoords::from_axial_coords(q, 0))
+    }
+
+    fn build_mock_attributes(combat: f64, morale: i32) -> SimulationPropertyStorage {
+        SimulationPropertyStorage::new()
+            .register_new(
+                SimulationID::new_property_id(COMMAND_COMBAT_BONUS_PROPERTY.into()),
+                SimulationPropertyValue::Float(combat),
+            )
+            .register_new(
+                SimulationID::new_property_id(COMMAND_MORALE_BONUS_PROPERTY.into()),
+                SimulationPropertyValue::Integer(morale),
+            )
+    }
+
+    #[test]
+    fn test_command_update() {
+        let template = UnitTemplate::new(
+            SimulationID::new_abstract_id("infantry"),
+            "infantry".into(),
+            HashMap::new(),
+            HashMap::new(),
+            SimulationPropertyStorage::new(),
+        );
+        let inspiring = SimulationID::new_abstract_id("inspiring");
+        let leader = Leader::new(
+            IndividualIDCard::new(
+                SimulationID::new_entity_id(1),
+                IndividualName::HumanLike("John".into(), "Doe".into()),
+            ),
+            vec![Trait::TraitPersonality(TraitPersonality::new(
+                inspiring.clone(),
+            ))],
+            HashMap::new(),
+        );
+
+        // army at q = 0 (range 8) > division at q = 2 (range 3) > units at q = 3, q = 10 and on offset coordinates
+        let mut oob = OrderOfBattle::new();
+        let army = SimulationID::new_map_entity_id(1);
+        let division = SimulationID::new_map_entity_id(2);
+        oob.add(
+            HqUnit::new(
+                army.clone(),
+                position(0),
+                Some(leader),
+                build_mock_attributes(0.1, 0),
+            )
+            .with_echelon(Echelon::Army),
+            None,
+        )
+        .unwrap();
+        oob.add(
+            HqUnit::new(
+                division.clone(),
+                position(2),
+                None,
+                build_mock_attributes(0.1, 2),
+            ),
+            Some(&army),
+        )
+        .unwrap();
+        for (id, q) in [(10, 3), (11, 10)] {
+            oob.attach_unit(
+                &division,
+                Unit::new(
+                    SimulationID::new_map_entity_id(id),
+                    position(q),
+                    &template,
+                    100,
+                ),
+            )
+            .unwrap();
+        }
+        oob.attach_unit(
+            &division,
+            Unit::new(
+                SimulationID::new_map_entity_id(12),
+                HexMapCoordinates::Offset(),
+                &template,
+                100,
+            ),
+        )
+        .unwrap();
+
+        let model =
+            CommandModel::default().with_trait_modifiers(inspiring, CommandModifiers::new(0.1, 2));
+        let report = model.update(&mut oob);
+
+        // own bonuses, plus half of the army's
+        let status = report
+            .status_of(&SimulationID::new_map_entity_id(10))
+            .unwrap();
+        assert!(status.is_in_range());
+        assert!((status.modifiers().combat() - 0.2).abs() < 1e-9);
+        assert_eq!(status.modifiers().morale(), 3);
+
+        let status = report
+            .status_of(&SimulationID::new_map_entity_id(11))
+            .unwrap();
+        assert_eq!(status.distance(), 8.0);
+        // the distance to positions which are not cube coordinates cannot be measured
+        let status = report
+            .status_of(&SimulationID::new_map_entity_id(12))
+            .unwrap();
+        assert_eq!(status.distance(), f64::INFINITY);
+        assert_eq!(report.out_of_command().count(), 2);
+        let unit = oob.unit(&SimulationID::new_map_entity_id(11)).unwrap();
+        assert_eq!(unit.command_modifier(), -0.2);
+        assert_eq!(unit.morale(), 95);
+    }
+}
@@ -0,0 +1,482 @@
+//! Domains of military units: land, sea and air.
+//!
+//! The domain of a unit (see `DOMAIN_PROPERTY`) decides where it can go and whom it can fight. Land units follow
+//! the terrain. Naval units sail on water and dock at the ports of their template (see `BASE_PROPERTY`). Air units
+//! fly over any terrain at a flat cost, but never farther than their operational range from one of their airbases.
+//!
+//! Engagements between domains use their own template attributes: air units can only be hit by air units and
+//! anti-air (see `ANTI_AIR_PROPERTY`), and naval and land units can only fire at each other across the shore with
+//! their bombardment strength (see `BOMBARDMENT_PROPERTY`).
+
+use std::collections::HashMap;
+
+use crate::{
+    hex_map::{
+        coordinates::{CubeCoords, HexMapCoordinatesSystem},
+        layers::natural::HexMapTerrain,
+        pathfinding::HexMapPathCost,
+        HexMap,
+    },
+    simulation::{
+        buildings::Building,
+        ids::{SimulationID, WithSimulationID},
+        properties::SimulationPropertyValue,
+    },
+};
+
+use super::{
+    combat::{unit_attribute, ATTACK_PROPERTY, DEFENSE_PROPERTY},
+    movement::terrain_movement_cost,
+    Unit, UnitTemplate,
+};
+
+/// Attribute of an `UnitTemplate` (`SimulationPropertyValue::Text`): `land`, `sea` or `air`. Units are land units
+/// by default.
+pub const DOMAIN_PROPERTY: &str = "domain";
+
+/// Attribute of an `UnitTemplate` (`SimulationPropertyValue::Text`), the `SimulationID` of the `BuildingTemplate`
+/// its units operate from. Defaults to `DEFAULT_PORT` for naval units and `DEFAULT_AIRBASE` for air units.
+pub const BASE_PROPERTY: &str = "base";
+
+/// Attribute of an `UnitTemplate` (`SimulationPropertyValue::Integer`), the distance in tiles air units can fly
+/// from their bases.
+pub const OPERATIONAL_RANGE_PROPERTY: &str = "operational_range";
+
+/// Attribute of an `UnitTemplate` (`SimulationPropertyValue::Integer`), its strength against air units.
+pub const ANTI_AIR_PROPERTY: &str = "anti_air";
+
+/// Attribute of an `UnitTemplate` (`SimulationPropertyValue::Integer`), its strength against units across the
+/// shore: land units for naval units, naval units for land units.
+pub const BOMBARDMENT_PROPERTY: &str = "bombardment";
+
+/// Template of the buildings naval units operate from, when their template does not define `BASE_PROPERTY`.
+pub const DEFAULT_PORT: &str = "port";
+
+/// Template of the buildings air units operate from, when their template does not define `BASE_PROPERTY`.
+pub const DEFAULT_AIRBASE: &str = "airbase";
+
+/// Operational range of the air units whose template does not define `OPERATIONAL_RANGE_PROPERTY`.
+pub const DEFAULT_OPERATIONAL_RANGE: u32 = 8;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum UnitDomain {
+    #[default]
+    Land,
+    Sea,
+    Air,
+}
+
+impl UnitDomain {
+    /// Cost for units of the domain of entering a tile of the given terrain, `None` if they cannot enter it.
+    pub fn terrain_movement_cost(&self, terrain: HexMapTerrain) -> Option<HexMapPathCost> {
+        match self {
+            Self::Land => terrain_movement_cost(terrain),
+            Self::Sea if terrain.is_water() => Some(2),
+            Self::Sea => None,
+            Self::Air => Some(2),
+        }
+    }
+}
+
+/// Domain of the units of a template.
+pub fn unit_domain(template: &UnitTemplate) -> UnitDomain {
+    match template
+        .attributes()
+        .get_from_id(&SimulationID::new_property_id(DOMAIN_PROPERTY.into()))
+    {
+        Some(SimulationPropertyValue::Text(domain)) => match domain.as_str() {
+            "sea" => UnitDomain::Sea,
+            "air" => UnitDomain::Air,
+            _ => UnitDomain::Land,
+        },
+        _ => UnitDomain::Land,
+    }
+}
+
+/// Template of the buildings the units of a template operate from, if any.
+pub fn unit_base(template: &UnitTemplate) -> Option<SimulationID> {
+    match template
+        .attributes()
+        .get_from_id(&SimulationID::new_property_id(BASE_PROPERTY.into()))
+    {
+        Some(SimulationPropertyValue::Text(base)) => Some(SimulationID::new_abstract_id(base)),
+        _ => match unit_domain(template) {
+            UnitDomain::Land => None,
+            UnitDomain::Sea => Some(SimulationID::new_abstract_id(DEFAULT_PORT)),
+            UnitDomain::Air => Some(SimulationID::new_abstract_id(DEFAULT_AIRBASE)),
+        },
+    }
+}
+
+/// Distance in tiles the air units of a template can fly from their bases.
+pub fn operational_range(template: &UnitTemplate) -> u32 {
+    match template
+        .attributes()
+        .get_from_id(&SimulationID::new_property_id(
+            OPERATIONAL_RANGE_PROPERTY.into(),
+        )) {
+        Some(SimulationPropertyValue::Integer(range)) => (*range).max(0) as u32,
+        _ => DEFAULT_OPERATIONAL_RANGE,
+    }
+}
+
+/// Base strength of a unit striking a target, depending on their domains, or `None` if it cannot strike it.
+///
+/// `attacking` tells whether the unit strikes with its attack or its defense when both share a domain.
+pub fn engagement_strength(unit: &Unit, target: &Unit, attacking: bool) -> Option<i32> {
+    let own_strength = || {
+        if attacking {
+            unit_attribute(unit.template(), ATTACK_PROPERTY, 10)
+        } else {
+            unit_attribute(unit.template(), DEFENSE_PROPERTY, 10)
+        }
+    };
+    let special_strength =
+        |name: &str| Some(unit_attribute(unit.template(), name, 0)).filter(|value| *value > 0);
+    match (unit_domain(unit.template()), unit_domain(target.template())) {
+        (UnitDomain::Air, _) => Some(own_strength()),
+        (_, UnitDomain::Air) => special_strength(ANTI_AIR_PROPERTY),
+        (UnitDomain::Sea, UnitDomain::Land) | (UnitDomain::Land, UnitDomain::Sea) => {
+            special_strength(BOMBARDMENT_PROPERTY)
+        }
+        _ => Some(own_strength()),
+    }
+}
+
+/// Positions of the buildings units operate from, by template of building.
+#[derive(Clone, Debug, Default)]
+pub struct MilitaryBases {
+    bases: HashMap<SimulationID, Vec<CubeCoords>>,
+}
+
+impl MilitaryBases {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Every building of the map owned by a nation which is not destroyed.
+    pub fn from_map(map: &HexMap, owner: &SimulationID) -> Self {
+        let mut bases = Self::new();
+        for (position, tile) in map.tiles() {
+            for building in Building::all_on_tile(tile) {
+                if building.owner() == Some(owner) && !building.is_destroyed() {
+                    bases.add(building.template().id().clone(), *position);
+                }
+            }
+        }
+        bases
+    }
+
+    pub fn add(&mut self, template: SimulationID, position: CubeCoords) {
+        assert!(matches!(template, SimulationID::Abstract(_)));
+        let positions = self.bases.entry(template).or_default();
+        if !positions.contains(&position) {
+            positions.push(position);
+        }
+    }
+
+    /// Positions of the buildings of a template.
+    pub fn of(&self, template: &SimulationID) -> &[CubeCoords] {
+        self.bases.get(template).map_or(&[], |positions| positions)
+    }
+
+    /// Does a tile hold a base of the units of a template?
+    pub fn is_base_of(&self, template: &UnitTemplate, position: &CubeCoords) -> bool {
+        unit_base(template).is_some_and(|base| self.of(&base).contains(position))
+    }
+
+    /// Can the units of a template operate on a tile? Air units must stay within their operational range of one of
+    /// their bases, the others are not restricted.
+    pub fn within_operational_range(&self, template: &UnitTemplate, position: &CubeCoords) -> bool {
+        if unit_domain(template) != UnitDomain::Air {
+            return true;
+        }
+        let range = operational_range(template);
+        unit_base(template).is_some_and(|base| {
+            self.of(&base)
+                .iter()
+                .any(|base| base.distance_to(*position) <= range as f64)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::{
+        hex_map::{
+            coordinates::{CubeCoords, HexMapCoordinates},
+            layers::natural::HexMapTerrain,
+            tile::HexMapTile,
+            HexMap, HexMapStorage,
+        },
+        prng::TestingHarnessRandomGenerator,
+        simulation::{
+            buildings::{Building, BuildingTemplate},
+            construction::{
+                ConstructionOrder, ConstructionPayment, ConstructionQueueKey, ConstructionQueues,
+            },
+            diplomacy::{Diplomacy, EnemyPositions},
+            ids::SimulationID,
+            infrastructure::network::InfrastructureNetwork,
+            military::{
+                combat::{CombatExclusion, CombatLogEntry, CombatModel, ATTACK_PROPERTY},
+                movement::{MovementModel, MovementOrder, MovementOutcome},
+                order_of_battle::OrderOfBattle,
+                HqUnit, Unit, UnitTemplate,
+            },
+            properties::{SimulationPropertyStorage, SimulationPropertyValue},
+            resources::ResourceDataStorage,
+        },
+    };
+
+    use super::{
+        unit_domain, MilitaryBases, UnitDomain, ANTI_AIR_PROPERTY, DOMAIN_PROPERTY,
+        OPERATIONAL_RANGE_PROPERTY,
+    };
+
+    fn build_mock_template(
+        id: &str,
+        attributes: Vec<(&str, SimulationPropertyValue)>,
+    ) -> UnitTemplate {
+        let attributes = attributes.into_iter().fold(
+            SimulationPropertyStorage::new(),
+            |attributes, (name, value)| {
+                attributes.register_new(SimulationID::new_property_id(name.into()), value)
+            },
+        );
+        UnitTemplate::new(
+            SimulationID::new_abstract_id(id),
+            id.into(),
+            HashMap::new(),
+            HashMap::new(),
+            attributes,
+        )
+    }
+
+    fn build_mock_unit(id: u32, (q, r): (i16, i16), template: &UnitTemplate) -> Unit<'_> {
+        Unit::new(
+            SimulationID::new_map_entity_id(id),
+            HexMapCoordinates::Cube(CubeCoords::from_axial_coords(q, r)),
+            template,
+            100,
+        )
+    }
+
+    /// Land from `q = 0` to `q = 3` and sea from `q = 4` to `q = 7`, on a single row, with a port at `(3, 0)`
+    /// and an airbase at `(0, 0)` owned by `nation`, and an airbase at `(3, 0)` owned by `enemy`.
+    fn build_mock_map() -> HexMap {
+        let mut tiles = HexMapStorage::new();
+        for q in 0..=7 {
+            let terrain = if q < 4 {
+                HexMapTerrain::Plains
+            } else {
+                HexMapTerrain::Sea
+            };
+            tiles.insert(
+                CubeCoords::from_axial_coords(q, 0),
+                HexMapTile::from_terrain(0, terrain),
+            );
+        }
+        let mut map = HexMap::from_tiles(tiles);
+        for (id, position, template, owner) in [
+            (2, (3, 0), "port", "nation"),
+            (3, (0, 0), "airbase", "nation"),
+            (4, (3, 0), "airbase", "enemy"),
+        ] {
+            let template: &BuildingTemplate = Box::leak(Box::new(BuildingTemplate::new(
+                SimulationID::new_abstract_id(template),
+                template.into(),
+                HashMap::new(),
+                HashMap::new(),
+                HashMap::new(),
+            )));
+            map.tile_mut(&CubeCoords::from_axial_coords(position.0, position.1))
+                .unwrap()
+                .layer_artificial_mut()
+                .add_building(Box::new(
+                    Building::new(SimulationID::new_map_entity_id(id), template, 100)
+                        .with_owner(SimulationID::new_abstract_id(owner)),
+                ));
+        }
+        map
+    }
+
+    #[test]
+    fn test_military_bases_built() {
+        let mut map = build_mock_map();
+        let nation = SimulationID::new_abstract_id("nation");
+        let port: &'static BuildingTemplate = Box::leak(Box::new(BuildingTemplate::new(
+            SimulationID::new_abstract_id("port"),
+            "port".into(),
+            HashMap::new(),
+            HashMap::new(),
+            HashMap::new(),
+        )));
+        let position = CubeCoords::from_axial_coords(2, 0);
+        let mut queues = ConstructionQueues::new();
+        let mut treasury = ResourceDataStorage::new();
+        queues
+            .enqueue(
+                &map,
+                ConstructionQueueKey::Tile(position),
+                ConstructionOrder::new(
+                    SimulationID::new_map_entity_id(10),
+                    port,
+                    position,
+                    ConstructionPayment::Upfront,
+                )
+                .with_owner(nation.clone()),
+                &mut treasury,
+            )
+            .unwrap();
+        while queues.orders().next().is_some() {
+            queues.update(&mut map, &mut treasury);
+        }
+
+        let destroyer = build_mock_template(
+            "destroyer",
+            vec![(DOMAIN_PROPERTY, SimulationPropertyValue::Text("sea".into()))],
+        );
+        assert!(MilitaryBases::from_map(&map, &nation).is_base_of(&destroyer, &position));
+        assert!(
+            !MilitaryBases::from_map(&map, &SimulationID::new_abstract_id("enemy"))
+                .is_base_of(&destroyer, &position)
+        );
+    }
+
+    #[test]
+    fn test_unit_domains() {
+        let map = build_mock_map();
+        let network = InfrastructureNetwork::new();
+        let bases = MilitaryBases::from_map(&map, &SimulationID::new_abstract_id("nation"));
+        let text = |value: &str| SimulationPropertyValue::Text(value.into());
+        let infantry = build_mock_template("infantry", vec![]);
+        let destroyer = build_mock_template("destroyer", vec![(DOMAIN_PROPERTY, text("sea"))]);
+        let fighter = build_mock_template(
+            "fighter",
+            vec![
+                (DOMAIN_PROPERTY, text("air")),
+                (
+                    OPERATIONAL_RANGE_PROPERTY,
+                    SimulationPropertyValue::Integer(4),
+                ),
+                (ATTACK_PROPERTY, SimulationPropertyValue::Integer(20)),
+            ],
+        );
+        let flak = build_mock_template(
+            "flak",
+            vec![(ANTI_AIR_PROPERTY, SimulationPropertyValue::Integer(10))],
+        );
+        assert_eq!(unit_domain(&infantry), UnitDomain::Land);
+        assert_eq!(unit_domain(&destroyer), UnitDomain::Sea);
+        assert!(bases.is_base_of(&destroyer, &CubeCoords::from_axial_coords(3, 0)));
+        assert!(!bases.is_base_of(&fighter, &CubeCoords::from_axial_coords(3, 0)));
+        assert!(
+            MilitaryBases::from_map(&map, &SimulationID::new_abstract_id("enemy"))
+                .is_base_of(&fighter, &CubeCoords::from_axial_coords(3, 0))
+        );
+
+        // ships sail from the port but cannot land, planes fly over the sea up to their range
+        let tile = CubeCoords::from_axial_coords;
+        let id = SimulationID::new_map_entity_id;
+        let mut oob = OrderOfBattle::new();
+        oob.add(
+            HqUnit::new(
+                id(1),
+                HexMapCoordinates::Cube(tile(0, 0)),
+                None,
+                SimulationPropertyStorage::new(),
+            ),
+            None,
+        )
+        .unwrap();
+        for unit in [
+            build_mock_unit(10, (3, 0), &destroyer),
+            build_mock_unit(11, (4, 0), &destroyer),
+            build_mock_unit(12, (0, 0), &fighter),
+        ] {
+            oob.attach_unit(&id(1), unit).unwrap();
+        }
+        let orders = [
+            MovementOrder::new(id(10), tile(6, 0)),
+            MovementOrder::new(id(11), tile(2, 0)),
+            MovementOrder::new(id(12), tile(7, 0)),
+        ];
+        let report = MovementModel::default().resolve(
+            &orders,
+            &mut oob,
+            &map,
+            &network,
+            &bases,
+            &EnemyPositions::new(),
+        );
+        let unit_move = report.move_of(&id(10)).unwrap();
+        assert_eq!(unit_move.outcome(), MovementOutcome::Arrived);
+        assert_eq!(unit_move.movement_points_spent(), 6);
+        assert_eq!(
+            report.move_of(&id(11)).unwrap().outcome(),
+            MovementOutcome::NoPath
+        );
+        assert_eq!(
+            report.move_of(&id(12)).unwrap().outcome(),
+            MovementOutcome::NoPath
+        );
+        let orders = [MovementOrder::new(id(12), tile(4, 0))];
+        let report = MovementModel::default().resolve(
+            &orders,
+            &mut oob,
+            &map,
+            &network,
+            &bases,
+            &EnemyPositions::new(),
+        );
+        let unit_move = report.move_of(&id(12)).unwrap();
+        assert_eq!(unit_move.outcome(), MovementOutcome::OutOfMovementPoints);
+        assert_eq!(unit_move.to(), &tile(3, 0));
+
+        // an air strike: the infantry cannot fire back, the anti-air can
+        let model = CombatModel::default();
+        let mut diplomacy = Diplomacy::new();
+        let [attacker, defender] = ["attacker", "defender"].map(SimulationID::new_abstract_id);
+        diplomacy.declare_war(&attacker, &defender).unwrap();
+        let belligerents = diplomacy.belligerents(&attacker, &defender).unwrap();
+        let mut rng = TestingHarnessRandomGenerator::new(vec![0, 500]);
+        let mut plane = build_mock_unit(20, (2, 0), &fighter);
+        let mut infantry = build_mock_unit(21, (3, 0), &infantry);
+        let mut ship = build_mock_unit(22, (4, 0), &destroyer);
+        let report = model
+            .resolve(
+                &belligerents,
+                &mut [&mut plane, &mut ship],
+                &mut [&mut infantry],
+                &map,
+                &HashMap::new(),
+                &mut rng,
+            )
+            .unwrap();
+        assert_eq!(
+            report.log()[0],
+            CombatLogEntry::Excluded {
+                unit: id(22),
+                reason: CombatExclusion::CannotEngage
+            }
+        );
+        assert!(report.damage_taken(&id(21)) > 0);
+        assert_eq!(report.damage_taken(&id(20)), 0);
+
+        let mut flak = build_mock_unit(23, (3, 0), &flak);
+        let report = model
+            .resolve(
+                &belligerents,
+                &mut [&mut plane],
+                &mut [&mut flak],
+                &map,
+                &HashMap::new(),
+                &mut rng,
+            )
+            .unwrap();
+        assert!(report.damage_taken(&id(20)) > 0);
+    }
+}
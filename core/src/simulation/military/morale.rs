@@ -0,0 +1,150 @@
+//! Morale: the will of units to fight, recovering over time and breaking under pressure.
+//!
+//! Morale is lowered by casualties and raised by victories (see `combat::CombatModel`), lowered by supply shortages
+//! (see `attrition::AttritionModel`) and changed every turn by the headquarters and leader traits of the chain of
+//! command (see `command::CommandModel`). This module handles the recovery of supplied units, and the routing and
+//! rallying of units whose morale broke.
+
+use crate::simulation::ids::{SimulationID, WithSimulationID};
+
+use super::order_of_battle::OrderOfBattle;
+
+/// Units whose morale broke or recovered during a turn.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MoraleReport {
+    routed: Vec<SimulationID>,
+    rallied: Vec<SimulationID>,
+}
+
+impl MoraleReport {
+    pub fn routed(&self) -> &[SimulationID] {
+        &self.routed
+    }
+
+    pub fn rallied(&self) -> &[SimulationID] {
+        &self.rallied
+    }
+}
+
+/// Rules of the recovery and breaking of morale.
+#[derive(Debug)]
+pub struct MoraleModel {
+    /// Morale regained every turn by supplied units.
+    recovery: u16,
+    /// Units rout when their morale falls below this threshold.
+    rout_threshold: u16,
+    /// Routed units rally when their morale gets back to this threshold.
+    rally_threshold: u16,
+}
+
+impl Default for MoraleModel {
+    fn default() -> Self {
+        Self {
+            recovery: 5,
+            rout_threshold: 20,
+            rally_threshold: 50,
+        }
+    }
+}
+
+impl MoraleModel {
+    pub fn new(recovery: u16, rout_threshold: u16, rally_threshold: u16) -> Self {
+        assert!(rout_threshold <= rally_threshold);
+        Self {
+            recovery,
+            rout_threshold,
+            rally_threshold,
+        }
+    }
+
+    /// Called every turn, after combat and attrition. Recover the morale of supplied units, then rout or rally
+    /// units depending on their morale.
+    pub fn update(&self, order_of_battle: &mut OrderOfBattle) -> MoraleReport {
+        let mut report = MoraleReport::default();
+        for hq in order_of_battle.headquarters_mut() {
+            for unit in hq.attached_units_mut() {
+                if !unit.is_out_of_supply() {
+                    unit.raise_morale(self.recovery);
+                }
+                if !unit.is_routed() && unit.morale() < self.rout_threshold {
+                    unit.set_routed(true);
+                    report.routed.push(unit.id().clone());
+                } else if unit.is_routed() && unit.morale() >= self.rally_threshold {
+                    unit.set_routed(false);
+                    report.rallied.push(unit.id().clone());
+                }
+            }
+        }
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::{
+        hex_map::coordinates::{CubeCoords, HexMapCoordinates},
+        simulation::{
+            ids::SimulationID,
+            military::{order_of_battle::OrderOfBattle, HqUnit, Unit, UnitTemplate, Veterancy},
+            properties::SimulationPropertyStorage,
+        },
+    };
+
+    use super::MoraleModel;
+
+    #[test]
+    fn test_morale_and_veterancy() {
+        let template = UnitTemplate::new(
+            SimulationID::new_abstract_id("infantry"),
+            "infantry".into(),
+            HashMap::new(),
+            HashMap::new(),
+            SimulationPropertyStorage::new(),
+        );
+        let id = SimulationID::new_map_entity_id;
+        let position = HexMapCoordinates::Cube(CubeCoords::from_axial_coords(0, 0));
+
+        // promotion, then dilution by reinforcements
+        let mut unit = Unit::new(id(10), position.clone(), &template, 50);
+        assert_eq!(unit.gain_experience(99), None);
+        assert_eq!(unit.gain_experience(221), Some(Veterancy::Veteran));
+        assert_eq!(unit.reinforce(30), 30);
+        assert_eq!(unit.experience(), 200);
+        assert_eq!(unit.veterancy(), Veterancy::Regular);
+        // up to the full strength of the template
+        assert_eq!(unit.reinforce(30), 20);
+        assert_eq!(unit.health_points(), 100);
+        assert_eq!(unit.reinforce(30), 0);
+
+        // broken, recovering and out of supply units
+        let mut broken = Unit::new(id(11), position.clone(), &template, 100);
+        broken.lower_morale(90);
+        let mut recovering = Unit::new(id(12), position.clone(), &template, 100);
+        recovering.lower_morale(52);
+        recovering.set_routed(true);
+        let mut starving = Unit::new(id(13), position.clone(), &template, 100);
+        starving.lower_morale(85);
+        starving.set_routed(true);
+        starving.set_supplied(false);
+
+        let mut oob = OrderOfBattle::new();
+        oob.add(
+            HqUnit::new(id(1), position, None, SimulationPropertyStorage::new()),
+            None,
+        )
+        .unwrap();
+        for unit in [unit, broken, recovering, starving] {
+            oob.attach_unit(&id(1), unit).unwrap();
+        }
+
+        let report = MoraleModel::default().update(&mut oob);
+        assert_eq!(report.routed(), &[id(11)]);
+        assert_eq!(report.rallied(), &[id(12)]);
+        assert!(oob.unit(&id(11)).unwrap().is_routed());
+        assert_eq!(oob.unit(&id(11)).unwrap().morale(), 15);
+        assert!(oob.unit(&id(13)).unwrap().is_routed());
+        assert_eq!(oob.unit(&id(13)).unwrap().morale(), 15);
+    }
+}
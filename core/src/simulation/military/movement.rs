@@ -0,0 +1,797 @@
+//! Movement of military units on the map.
+//!
+//! Every turn, units spend movement points (see `MOVEMENT_POINTS_PROPERTY`) to follow the path to the destination
+//! of their `MovementOrder`. Entering a tile costs more on rough terrain and less along a road or a railway. Tiles
+//! next to enemy units are their zone of control, which stops or slows the units entering it, and tiles held by
+//! enemy units cannot be entered. Only the units of nations at war are enemies (see `EnemyPositions`). No more than
+//! a given number of units can stand on the same tile.
+//!
+//! Only land units follow the terrain and the infrastructure: naval and air units move according to their domain
+//! (see `domains`), and air units ignore zones of control.
+//!
+//! Orders are resolved either sequentially, each unit completing its move before the next one starts, or
+//! simultaneously, all units moving one tile at a time. In the latter case, units trying to enter the same tile
+//! collide, and only the first ordered ones get in.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use crate::{
+    hex_map::{
+        coordinates::{CubeCoords, HexMapCoordinates, HexMapCoordinatesSystem},
+        layers::natural::HexMapTerrain,
+        pathfinding::{find_path_by_steps, HexMapPathCost},
+        tile::HexMapTile,
+        HexMap,
+    },
+    simulation::{
+        diplomacy::EnemyPositions,
+        ids::{SimulationID, WithSimulationID},
+        infrastructure::{network::InfrastructureNetwork, InfrastructureKind},
+        properties::SimulationPropertyValue,
+    },
+};
+
+use super::{
+    domains::{unit_domain, MilitaryBases, UnitDomain},
+    order_of_battle::OrderOfBattle,
+    Unit, UnitTemplate,
+};
+
+/// Attribute of an `UnitTemplate` (`SimulationPropertyValue::Integer`), its movement points per turn.
+pub const MOVEMENT_POINTS_PROPERTY: &str = "movement_points";
+
+/// Movement points of the units whose template does not define `MOVEMENT_POINTS_PROPERTY`.
+pub const DEFAULT_MOVEMENT_POINTS: HexMapPathCost = 6;
+
+/// Cost of moving along a road or a railway, whatever the terrain.
+pub const INFRASTRUCTURE_MOVEMENT_COST: HexMapPathCost = 1;
+
+/// Cost of entering a tile of the given terrain, `None` if land units cannot enter it.
+pub fn terrain_movement_cost(terrain: HexMapTerrain) -> Option<HexMapPathCost> {
+    match terrain {
+        HexMapTerrain::Sea | HexMapTerrain::Lake => None,
+        HexMapTerrain::Plains => Some(2),
+        HexMapTerrain::Desert | HexMapTerrain::Forest | HexMapTerrain::Hills => Some(4),
+        HexMapTerrain::Marsh | HexMapTerrain::Mountains => Some(6),
+    }
+}
+
+/// Movement points of the units of a template, per turn.
+pub fn movement_points(template: &UnitTemplate) -> HexMapPathCost {
+    match template
+        .attributes()
+        .get_from_id(&SimulationID::new_property_id(
+            MOVEMENT_POINTS_PROPERTY.into(),
+        )) {
+        Some(SimulationPropertyValue::Integer(points)) => (*points).max(0) as HexMapPathCost,
+        _ => DEFAULT_MOVEMENT_POINTS,
+    }
+}
+
+/// Cost for the units of a template of entering a tile off the infrastructure, `None` if they cannot enter it.
+///
+/// Naval units enter the tiles holding their ports, and air units the tiles within their operational range.
+pub fn tile_movement_cost(
+    template: &UnitTemplate,
+    map: &HexMap,
+    bases: &MilitaryBases,
+    position: &CubeCoords,
+) -> Option<HexMapPathCost> {
+    let tile = map.tile(position)?;
+    let domain = unit_domain(template);
+    if !bases.within_operational_range(template, position) {
+        return None;
+    }
+    if domain == UnitDomain::Sea && bases.is_base_of(template, position) {
+        return Some(INFRASTRUCTURE_MOVEMENT_COST);
+    }
+    domain.terrain_movement_cost(tile.terrain())
+}
+
+/// Cost for the units of a template of moving between two neighboring tiles, `None` if the destination cannot be
+/// entered.
+pub fn step_cost(
+    template: &UnitTemplate,
+    map: &HexMap,
+    network: &InfrastructureNetwork,
+    bases: &MilitaryBases,
+    from: &CubeCoords,
+    to: &CubeCoords,
+) -> Option<HexMapPathCost> {
+    if unit_domain(template) == UnitDomain::Land
+        && map.contains(to)
+        && [InfrastructureKind::Road, InfrastructureKind::Rail]
+            .into_iter()
+            .any(|kind| network.connects(kind, from, to))
+    {
+        return Some(INFRASTRUCTURE_MOVEMENT_COST);
+    }
+    tile_movement_cost(template, map, bases, to)
+}
+
+/// Tiles controlled by enemy units: the tiles they stand on and their neighbors.
+pub fn zone_of_control(enemies: &EnemyPositions) -> HashSet<CubeCoords> {
+    enemies
+        .tiles()
+        .iter()
+        .flat_map(|position| std::iter::once(*position).chain(position.neighbors()))
+        .collect()
+}
+
+/// Effect of an enemy zone of control on the units entering it.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ZoneOfControlEffect {
+    /// The unit must stop.
+    Stop,
+    /// Entering the tile costs additional movement points.
+    Slow(HexMapPathCost),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MovementResolution {
+    /// Each order is carried out completely before the next one.
+    Sequential,
+    /// All units move one tile at a time, in lockstep.
+    Simultaneous,
+}
+
+/// Order for a unit to move to a destination, over as many turns as needed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MovementOrder {
+    /// Must be the `SimulationID` of a `Unit`.
+    unit: SimulationID,
+    destination: CubeCoords,
+}
+
+impl MovementOrder {
+    pub fn new(unit: SimulationID, destination: CubeCoords) -> Self {
+        assert!(matches!(unit, SimulationID::MapEntityID(_)));
+        Self { unit, destination }
+    }
+
+    pub fn unit(&self) -> &SimulationID {
+        &self.unit
+    }
+
+    pub fn destination(&self) -> &CubeCoords {
+        &self.destination
+    }
+}
+
+/// Why a unit could not enter the next tile of its path.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MovementBlock {
+    /// The tile is occupied by an enemy unit.
+    Enemy,
+    /// The tile already holds as many units as allowed.
+    Stacking,
+    /// Other units entered the tile at the same time.
+    Collision,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MovementOutcome {
+    Arrived,
+    /// Not enough movement points left to enter the next tile. The move goes on next turn.
+    OutOfMovementPoints,
+    StoppedByZoneOfControl,
+    Blocked {
+        tile: CubeCoords,
+        reason: MovementBlock,
+    },
+    /// The destination cannot be reached.
+    NoPath,
+}
+
+/// Move of a unit for a turn.
+#[derive(Clone, Debug, PartialEq)]
+pub struct UnitMove {
+    /// Must be the `SimulationID` of a `Unit`.
+    unit: SimulationID,
+    /// Tiles the unit went through, from its starting one to its final one.
+    tiles: Vec<CubeCoords>,
+    movement_points_spent: HexMapPathCost,
+    outcome: MovementOutcome,
+}
+
+impl UnitMove {
+    pub fn unit(&self) -> &SimulationID {
+        &self.unit
+    }
+
+    pub fn tiles(&self) -> &[CubeCoords] {
+        &self.tiles
+    }
+
+    pub fn from(&self) -> &CubeCoords {
+        &self.tiles[0]
+    }
+
+    pub fn to(&self) -> &CubeCoords {
+        &self.tiles[self.tiles.len() - 1]
+    }
+
+    pub fn movement_points_spent(&self) -> HexMapPathCost {
+        self.movement_points_spent
+    }
+
+    pub fn outcome(&self) -> MovementOutcome {
+        self.outcome
+    }
+}
+
+/// Moves of the units of an order of battle for a turn, in the order of their `MovementOrder`s.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MovementReport {
+    moves: Vec<UnitMove>,
+}
+
+impl MovementReport {
+    pub fn moves(&self) -> &[UnitMove] {
+        &self.moves
+    }
+
+    pub fn move_of(&self, unit: &SimulationID) -> Option<&UnitMove> {
+        self.moves.iter().find(|unit_move| &unit_move.unit == unit)
+    }
+}
+
+/// A unit being moved during the resolution of the orders.
+struct Mover<'t> {
+    template: &'t UnitTemplate,
+    path: Vec<CubeCoords>,
+    movement_points: HexMapPathCost,
+    unit_move: UnitMove,
+    done: bool,
+}
+
+impl Mover<'_> {
+    fn position(&self) -> &CubeCoords {
+        self.unit_move.to()
+    }
+
+    fn stop(&mut self, outcome: MovementOutcome) {
+        self.unit_move.outcome = outcome;
+        self.done = true;
+    }
+
+    /// Is the tile in an enemy zone of control, for the unit? Air units fly over them.
+    fn is_in_zone_of_control(&self, tile: &CubeCoords, context: &MovementContext) -> bool {
+        unit_domain(self.template) != UnitDomain::Air && context.zone_of_control.contains(tile)
+    }
+}
+
+/// Static situation of the map during the resolution of the orders.
+struct MovementContext<'c> {
+    map: &'c HexMap,
+    network: &'c InfrastructureNetwork,
+    bases: &'c MilitaryBases,
+    enemies: &'c EnemyPositions,
+    zone_of_control: HashSet<CubeCoords>,
+}
+
+/// Rules of the movement of the units.
+#[derive(Debug)]
+pub struct MovementModel {
+    zone_of_control: ZoneOfControlEffect,
+    /// Maximum number of units of a nation on a tile.
+    stacking_limit: usize,
+    resolution: MovementResolution,
+}
+
+impl Default for MovementModel {
+    fn default() -> Self {
+        Self {
+            zone_of_control: ZoneOfControlEffect::Stop,
+            stacking_limit: 3,
+            resolution: MovementResolution::Sequential,
+        }
+    }
+}
+
+impl MovementModel {
+    pub fn new(
+        zone_of_control: ZoneOfControlEffect,
+        stacking_limit: usize,
+        resolution: MovementResolution,
+    ) -> Self {
+        assert!(stacking_limit > 0);
+        Self {
+            zone_of_control,
+            stacking_limit,
+            resolution,
+        }
+    }
+
+    /// Cheapest path for a unit to its destination, avoiding enemy units, `None` if there is none or if the unit
+    /// is not on a `CubeCoords` position.
+    ///
+    /// Steps are costed as when the unit moves (see `step_cost`).
+    pub fn plan(
+        &self,
+        unit: &Unit,
+        map: &HexMap,
+        network: &InfrastructureNetwork,
+        bases: &MilitaryBases,
+        destination: CubeCoords,
+        enemies: &EnemyPositions,
+    ) -> Option<Vec<CubeCoords>> {
+        let start = unit.position().as_cube_coords()?;
+        let template = unit.template();
+        let cost = |from: &CubeCoords, to: &CubeCoords, _: &HexMapTile| {
+            if enemies.contains(to) {
+                return None;
+            }
+            step_cost(template, map, network, bases, from, to)
+        };
+        find_path_by_steps(map, start, destination, cost).map(|path| path.tiles().to_vec())
+    }
+
+    /// Called every turn. Move the units of an order of battle according to their orders.
+    ///
+    /// `bases` are the ports and airbases naval and air units can operate from (see `MilitaryBases::from_map`), and
+    /// `enemies` the tiles occupied by the units of the nations at war with the one moving. Orders for unknown
+    /// units, or units not on a `CubeCoords` position, are ignored.
+    pub fn resolve(
+        &self,
+        orders: &[MovementOrder],
+        order_of_battle: &mut OrderOfBattle,
+        map: &HexMap,
+        network: &InfrastructureNetwork,
+        bases: &MilitaryBases,
+        enemies: &EnemyPositions,
+    ) -> MovementReport {
+        let context = MovementContext {
+            map,
+            network,
+            bases,
+            enemies,
+            zone_of_control: zone_of_control(enemies),
+        };
+        let mut occupancy: HashMap<CubeCoords, usize> = HashMap::new();
+        for unit in order_of_battle.units() {
+            if let Some(position) = unit.position().as_cube_coords() {
+                *occupancy.entry(position).or_default() += 1;
+            }
+        }
+
+        let mut movers: Vec<Mover> = orders
+            .iter()
+            .filter_map(|order| {
+                let unit = order_of_battle.unit(&order.unit)?;
+                self.start_move(unit, order, &context)
+            })
+            .collect();
+        match self.resolution {
+            MovementResolution::Sequential => {
+                for mover in &mut movers {
+                    while !mover.done {
+                        match self.check_step(mover, &context) {
+                            Ok((next, cost)) => {
+                                if occupancy.get(&next).copied().unwrap_or(0) >= self.stacking_limit
+                                {
+                                    mover.stop(MovementOutcome::Blocked {
+                                        tile: next,
+                                        reason: MovementBlock::Stacking,
+                                    });
+                                } else {
+                                    self.step(mover, next, cost, &mut occupancy, &context);
+                                }
+                            }
+                            Err(outcome) => mover.stop(outcome),
+                        }
+                    }
+                }
+            }
+            MovementResolution::Simultaneous => {
+                while movers.iter().any(|mover| !mover.done) {
+                    // requests per tile, in order of the orders
+                    let mut requests: BTreeMap<CubeCoords, Vec<(usize, HexMapPathCost)>> =
+                        BTreeMap::new();
+                    for (index, mover) in movers.iter_mut().enumerate() {
+                        if mover.done {
+                            continue;
+                        }
+                        match self.check_step(mover, &context) {
+                            Ok((next, cost)) => {
+                                requests.entry(next).or_default().push((index, cost))
+                            }
+                            Err(outcome) => mover.stop(outcome),
+                        }
+                    }
+                    for (next, requests) in requests {
+                        let room = self
+                            .stacking_limit
+                            .saturating_sub(occupancy.get(&next).copied().unwrap_or(0));
+                        for (rank, (index, cost)) in requests.iter().enumerate() {
+                            let mover = &mut movers[*index];
+                            if rank < room {
+                                self.step(mover, next, *cost, &mut occupancy, &context);
+                            } else {
+                                mover.stop(MovementOutcome::Blocked {
+                                    tile: next,
+                                    reason: if room == 0 {
+                                        MovementBlock::Stacking
+                                    } else {
+                                        MovementBlock::Collision
+                                    },
+                                });
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        for mover in &movers {
+            if let Some(unit) = order_of_battle.unit_mut(&mover.unit_move.unit) {
+                unit.move_to(HexMapCoordinates::Cube(*mover.position()));
+            }
+        }
+        MovementReport {
+            moves: movers.into_iter().map(|mover| mover.unit_move).collect(),
+        }
+    }
+
+    fn start_move<'t>(
+        &self,
+        unit: &Unit<'t>,
+        order: &MovementOrder,
+        context: &MovementContext,
+    ) -> Option<Mover<'t>> {
+        let start = unit.position().as_cube_coords()?;
+        let mut mover = Mover {
+            template: unit.template(),
+            path: vec![],
+            movement_points: movement_points(unit.template()),
+            unit_move: UnitMove {
+                unit: unit.id().clone(),
+                tiles: vec![start],
+                movement_points_spent: 0,
+                outcome: MovementOutcome::Arrived,
+            },
+            done: start == order.destination,
+        };
+        if !mover.done {
+            match self.plan(
+                unit,
+                context.map,
+                context.network,
+                context.bases,
+                order.destination,
+                context.enemies,
+            ) {
+                Some(path) => mover.path = path,
+                None => mover.stop(MovementOutcome::NoPath),
+            }
+        }
+        Some(mover)
+    }
+
+    /// Next tile of a mover and the cost of entering it, or why it must stop before.
+    fn check_step(
+        &self,
+        mover: &Mover,
+        context: &MovementContext,
+    ) -> Result<(CubeCoords, HexMapPathCost), MovementOutcome> {
+        let position = *mover.position();
+        let next = mover.path[mover.unit_move.tiles.len()];
+        if context.enemies.contains(&next) {
+            return Err(MovementOutcome::Blocked {
+                tile: next,
+                reason: MovementBlock::Enemy,
+            });
+        }
+        let mut cost = step_cost(
+            mover.template,
+            context.map,
+            context.network,
+            context.bases,
+            &position,
+            &next,
+        )
+        .ok_or(MovementOutcome::NoPath)?;
+        if let ZoneOfControlEffect::Slow(extra) = self.zone_of_control {
+            if mover.is_in_zone_of_control(&next, context) {
+                cost += extra;
+            }
+        }
+        if mover.unit_move.movement_points_spent + cost > mover.movement_points {
+            return Err(MovementOutcome::OutOfMovementPoints);
+        }
+        Ok((next, cost))
+    }
+
+    fn step(
+        &self,
+        mover: &mut Mover,
+        next: CubeCoords,
+        cost: HexMapPathCost,
+        occupancy: &mut HashMap<CubeCoords, usize>,
+        context: &MovementContext,
+    ) {
+        if let Some(count) = occupancy.get_mut(mover.position()) {
+            *count = count.saturating_sub(1);
+        }
+        *occupancy.entry(next).or_default() += 1;
+        mover.unit_move.tiles.push(next);
+        mover.unit_move.movement_points_spent += cost;
+
+        if next == mover.path[mover.path.len() - 1] {
+            mover.stop(MovementOutcome::Arrived);
+        } else if self.zone_of_control == ZoneOfControlEffect::Stop
+            && mover.is_in_zone_of_control(&next, context)
+        {
+            mover.stop(MovementOutcome::StoppedByZoneOfControl);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{HashMap, HashSet};
+
+    use crate::{
+        hex_map::{
+            coordinates::{CubeCoords, HexMapCoordinates},
+            layers::natural::HexMapTerrain,
+            tile::HexMapTile,
+            HexMap, HexMapStorage,
+        },
+        simulation::{
+            diplomacy::{Diplomacy, EnemyPositions},
+            ids::{SimulationID, WithSimulationID},
+            infrastructure::{
+                network::InfrastructureNetwork, InfrastructureKind, InfrastructureSegment,
+            },
+            military::{
+                domains::MilitaryBases, order_of_battle::OrderOfBattle, HqUnit, Unit, UnitTemplate,
+            },
+            nations::Nation,
+            properties::SimulationPropertyStorage,
+            settlements::Settlement,
+            testing::build_mock_leader,
+        },
+    };
+
+    use super::{
+        MovementBlock, MovementModel, MovementOrder, MovementOutcome, MovementResolution,
+        ZoneOfControlEffect,
+    };
+
+    /// Plains from `q = 0` to `q = 7` and `r = -1` to `r = 1`, with a road from `(0, 0)` to `(2, 0)`.
+    fn build_mock_map() -> (HexMap, InfrastructureNetwork) {
+        let mut tiles = HexMapStorage::new();
+        for q in 0..=7 {
+            for r in -1..=1 {
+                tiles.insert(
+                    CubeCoords::from_axial_coords(q, r),
+                    HexMapTile::from_terrain(0, HexMapTerrain::Plains),
+                );
+            }
+        }
+        let mut network = InfrastructureNetwork::new();
+        for q in 0..2 {
+            network.add_segment(
+                SimulationID::new_map_entity_id(100 + q as u32),
+                &InfrastructureSegment::new(
+                    InfrastructureKind::Road,
+                    CubeCoords::from_axial_coords(q, 0),
+                    CubeCoords::from_axial_coords(q + 1, 0),
+                    10,
+                ),
+            );
+        }
+        (HexMap::from_tiles(tiles), network)
+    }
+
+    fn build_mock_order_of_battle<'a>(
+        template: &'a UnitTemplate,
+        units: &[(u32, (i16, i16))],
+    ) -> OrderOfBattle<'a> {
+        let mut oob = OrderOfBattle::new();
+        let hq = SimulationID::new_map_entity_id(1);
+        oob.add(
+            HqUnit::new(
+                hq.clone(),
+                HexMapCoordinates::Cube(CubeCoords::from_axial_coords(0, 0)),
+                None,
+                SimulationPropertyStorage::new(),
+            ),
+            None,
+        )
+        .unwrap();
+        for (id, (q, r)) in units {
+            oob.attach_unit(
+                &hq,
+                Unit::new(
+                    SimulationID::new_map_entity_id(*id),
+                    HexMapCoordinates::Cube(CubeCoords::from_axial_coords(*q, *r)),
+                    template,
+                    100,
+                ),
+            )
+            .unwrap();
+        }
+        oob
+    }
+
+    /// A nation with a single unit.
+    fn build_mock_nation<'a>(
+        capital: &'a Settlement,
+        template: &'a UnitTemplate,
+        (id, (q, r)): (u32, (i16, i16)),
+    ) -> Nation<'a> {
+        let mut nation = Nation::new(
+            SimulationID::new_abstract_id(capital.name()),
+            capital.name().into(),
+            build_mock_leader(id, vec![]),
+            capital,
+        );
+        let hq = SimulationID::new_map_entity_id(id - 1);
+        nation
+            .add_headquarters(HqUnit::new(
+                hq.clone(),
+                HexMapCoordinates::Cube(*capital.position()),
+                None,
+                SimulationPropertyStorage::new(),
+            ))
+            .unwrap();
+        nation
+            .order_of_battle_mut()
+            .attach_unit(
+                &hq,
+                Unit::new(
+                    SimulationID::new_map_entity_id(id),
+                    HexMapCoordinates::Cube(CubeCoords::from_axial_coords(q, r)),
+                    template,
+                    100,
+                ),
+            )
+            .unwrap();
+        nation
+    }
+
+    #[test]
+    fn test_movement_resolution() {
+        let (map, network) = build_mock_map();
+        let template = UnitTemplate::new(
+            SimulationID::new_abstract_id("infantry"),
+            "infantry".into(),
+            HashMap::new(),
+            HashMap::new(),
+            SimulationPropertyStorage::new(),
+        );
+        let id = SimulationID::new_map_entity_id;
+        let tile = CubeCoords::from_axial_coords;
+
+        // an enemy unit at (6, 0), and a unit of a neutral nation on the road, which does not block it
+        let capitals =
+            ["enemy", "neutral"].map(|name| Settlement::new(id(2), name.into(), tile(7, 0)));
+        let nations = [
+            build_mock_nation(&capitals[0], &template, (21, (6, 0))),
+            build_mock_nation(&capitals[1], &template, (31, (1, 0))),
+        ];
+        let nation = SimulationID::new_abstract_id("nation");
+        let mut diplomacy = Diplomacy::new();
+        diplomacy.declare_war(&nation, nations[0].id()).unwrap();
+        let enemies = diplomacy.enemy_positions(&nation, &nations);
+        assert_eq!(enemies.tiles(), &HashSet::from([tile(6, 0)]));
+
+        // sequential: 1 + 1 along the road, then 2 + 2 on the plains
+        let mut oob =
+            build_mock_order_of_battle(&template, &[(10, (0, 0)), (11, (0, 0)), (12, (0, 0))]);
+        let model =
+            MovementModel::new(ZoneOfControlEffect::Stop, 2, MovementResolution::Sequential);
+        let orders = [
+            MovementOrder::new(id(10), tile(4, 0)),
+            MovementOrder::new(id(11), tile(5, 0)),
+            MovementOrder::new(id(12), tile(4, 0)),
+        ];
+        let report = model.resolve(
+            &orders,
+            &mut oob,
+            &map,
+            &network,
+            &MilitaryBases::new(),
+            &enemies,
+        );
+        let unit_move = report.move_of(&id(10)).unwrap();
+        assert_eq!(unit_move.outcome(), MovementOutcome::Arrived);
+        assert_eq!(unit_move.movement_points_spent(), 6);
+        let unit_move = report.move_of(&id(11)).unwrap();
+        assert_eq!(unit_move.outcome(), MovementOutcome::OutOfMovementPoints);
+        assert_eq!(unit_move.to(), &tile(4, 0));
+        let unit_move = report.move_of(&id(12)).unwrap();
+        assert_eq!(
+            unit_move.outcome(),
+            MovementOutcome::Blocked {
+                tile: tile(4, 0),
+                reason: MovementBlock::Stacking
+            }
+        );
+        assert_eq!(
+            oob.unit(&id(12)).unwrap().position(),
+            &HexMapCoordinates::Cube(tile(3, 0))
+        );
+
+        // simultaneous: two units colliding, and one stopped by the zone of control of the enemy
+        let mut oob =
+            build_mock_order_of_battle(&template, &[(10, (2, 0)), (11, (3, 1)), (12, (4, 1))]);
+        let model = MovementModel::new(
+            ZoneOfControlEffect::Stop,
+            1,
+            MovementResolution::Simultaneous,
+        );
+        let orders = [
+            MovementOrder::new(id(10), tile(3, 0)),
+            MovementOrder::new(id(11), tile(3, 0)),
+            MovementOrder::new(id(12), tile(6, 1)),
+        ];
+        let report = model.resolve(
+            &orders,
+            &mut oob,
+            &map,
+            &network,
+            &MilitaryBases::new(),
+            &enemies,
+        );
+        assert_eq!(
+            report.move_of(&id(10)).unwrap().outcome(),
+            MovementOutcome::Arrived
+        );
+        assert_eq!(
+            report.move_of(&id(11)).unwrap().outcome(),
+            MovementOutcome::Blocked {
+                tile: tile(3, 0),
+                reason: MovementBlock::Collision
+            }
+        );
+        let unit_move = report.move_of(&id(12)).unwrap();
+        assert_eq!(unit_move.outcome(), MovementOutcome::StoppedByZoneOfControl);
+        assert_eq!(unit_move.tiles(), &[tile(4, 1), tile(5, 1)]);
+    }
+
+    #[test]
+    fn test_movement_plan() {
+        let id = SimulationID::new_map_entity_id;
+        let tile = CubeCoords::from_axial_coords;
+        // mountains at (1, 0), with a road leaving them towards (1, 1)
+        let mut tiles = HexMapStorage::new();
+        for q in 0..=2 {
+            for r in -1..=1 {
+                let terrain = if (q, r) == (1, 0) {
+                    HexMapTerrain::Mountains
+                } else {
+                    HexMapTerrain::Plains
+                };
+                tiles.insert(tile(q, r), HexMapTile::from_terrain(0, terrain));
+            }
+        }
+        let map = HexMap::from_tiles(tiles);
+        let mut network = InfrastructureNetwork::new();
+        network.add_segment(
+            id(100),
+            &InfrastructureSegment::new(InfrastructureKind::Road, tile(1, 0), tile(1, 1), 10),
+        );
+        let template = UnitTemplate::new(
+            SimulationID::new_abstract_id("infantry"),
+            "infantry".into(),
+            HashMap::new(),
+            HashMap::new(),
+            SimulationPropertyStorage::new(),
+        );
+        let unit = Unit::new(id(10), HexMapCoordinates::Cube(tile(0, 0)), &template, 100);
+
+        // the road does not lead there: going around the mountains is cheaper
+        let path = MovementModel::default().plan(
+            &unit,
+            &map,
+            &network,
+            &MilitaryBases::new(),
+            tile(2, -1),
+            &EnemyPositions::new(),
+        );
+        assert_eq!(path, Some(vec![tile(0, 0), tile(1, -1), tile(2, -1)]));
+    }
+}
@@ -0,0 +1,542 @@
+//! Order of battle: the tree of headquarters of a nation, and the units attached to them.
+//!
+//! The headquarters are owned by the `OrderOfBattle`, and link to each other by `SimulationID`. Every operation
+//! keeps the tree consistent: no cycle, superiors of a strictly higher `Echelon` than their subordinates, and no
+//! headquarters commanding more than its `HqUnit::command_capacity`.
+
+use crate::simulation::ids::SimulationID;
+
+use super::{HqUnit, Unit};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum OrderOfBattleError {
+    UnknownHeadquarters(SimulationID),
+    UnknownUnit(SimulationID),
+    /// An headquarters or a unit with this `SimulationID` is already in the order of battle.
+    DuplicateID(SimulationID),
+    /// The headquarters would end up among its own subordinates.
+    Cycle,
+    /// The superior does not have a higher `Echelon` than its subordinate.
+    EchelonMismatch,
+    /// The headquarters cannot command more subordinates.
+    CapacityExceeded(SimulationID),
+}
+
+/// Aggregated strength of an headquarters and everything under its command.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct OrderOfBattleStrength {
+    headquarters: usize,
+    units: usize,
+    health_points: u32,
+    /// Sum of the health points of the units weighted by their combat effectiveness.
+    combat_strength: f64,
+    total_morale: u32,
+}
+
+impl OrderOfBattleStrength {
+    pub fn headquarters(&self) -> usize {
+        self.headquarters
+    }
+
+    pub fn units(&self) -> usize {
+        self.units
+    }
+
+    pub fn health_points(&self) -> u32 {
+        self.health_points
+    }
+
+    pub fn combat_strength(&self) -> f64 {
+        self.combat_strength
+    }
+
+    pub fn average_morale(&self) -> f64 {
+        if self.units == 0 {
+            0.0
+        } else {
+            self.total_morale as f64 / self.units as f64
+        }
+    }
+
+    fn add_unit(&mut self, unit: &Unit) {
+        self.units += 1;
+        self.health_points += unit.health_points() as u32;
+        self.combat_strength += unit.health_points() as f64 * unit.combat_effectiveness();
+        self.total_morale += unit.morale() as u32;
+    }
+}
+
+/// Depth-first, pre-order traversal of (parts of) an order of battle.
+///
+/// Yields every headquarters with its depth below the starting ones, subordinates in the order they were attached.
+pub struct OrderOfBattleTraversal<'o, 'a> {
+    order_of_battle: &'o OrderOfBattle<'a>,
+    stack: Vec<(usize, &'o SimulationID)>,
+}
+
+impl<'o, 'a> Iterator for OrderOfBattleTraversal<'o, 'a> {
+    type Item = (usize, &'o HqUnit<'a>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (depth, id) = self.stack.pop()?;
+        let hq = self.order_of_battle.get(id)?;
+        self.stack
+            .extend(hq.subordinates.iter().rev().map(|id| (depth + 1, id)));
+        Some((depth, hq))
+    }
+}
+
+/// The chain of command of a nation.
+#[derive(Debug, Default)]
+pub struct OrderOfBattle<'a> {
+    headquarters: Vec<HqUnit<'a>>,
+}
+
+impl<'a> OrderOfBattle<'a> {
+    pub fn new() -> Self {
+        Self {
+            headquarters: vec![],
+        }
+    }
+
+    /// All the headquarters, in the order they were added.
+    pub fn headquarters(&self) -> &[HqUnit<'a>] {
+        &self.headquarters
+    }
+
+    /// All the headquarters, in the order they were added.
+    ///
+    /// The links between headquarters, and the units attached to them, can only be changed through the
+    /// `OrderOfBattle`.
+    pub fn headquarters_mut(&mut self) -> &mut [HqUnit<'a>] {
+        &mut self.headquarters
+    }
+
+    pub fn get(&self, id: &SimulationID) -> Option<&HqUnit<'a>> {
+        self.headquarters.iter().find(|hq| &hq.id == id)
+    }
+
+    pub fn get_mut(&mut self, id: &SimulationID) -> Option<&mut HqUnit<'a>> {
+        self.headquarters.iter_mut().find(|hq| &hq.id == id)
+    }
+
+    /// The headquarters a unit is attached to.
+    pub fn headquarters_of_unit(&self, unit: &SimulationID) -> Option<&HqUnit<'a>> {
+        self.headquarters.iter().find(|hq| {
+            hq.attached_units
+                .iter()
+                .any(|attached| &attached.id == unit)
+        })
+    }
+
+    pub fn unit(&self, id: &SimulationID) -> Option<&Unit<'a>> {
+        self.units().find(|unit| &unit.id == id)
+    }
+
+    pub fn unit_mut(&mut self, id: &SimulationID) -> Option<&mut Unit<'a>> {
+        self.headquarters
+            .iter_mut()
+            .flat_map(|hq| hq.attached_units.iter_mut())
+            .find(|unit| &unit.id == id)
+    }
+
+    /// All the units, grouped by headquarters.
+    pub fn units(&self) -> impl Iterator<Item = &Unit<'a>> {
+        self.headquarters
+            .iter()
+            .flat_map(|hq| hq.attached_units.iter())
+    }
+
+    /// The headquarters without superior, in the order they were added.
+    pub fn roots(&self) -> impl Iterator<Item = &HqUnit<'a>> {
+        self.headquarters.iter().filter(|hq| hq.superior.is_none())
+    }
+
+    /// The direct subordinate headquarters of an headquarters.
+    pub fn subordinates_of<'o>(
+        &'o self,
+        id: &SimulationID,
+    ) -> impl Iterator<Item = &'o HqUnit<'a>> + 'o {
+        self.get(id)
+            .into_iter()
+            .flat_map(|hq| hq.subordinates.iter())
+            .filter_map(|id| self.get(id))
+    }
+
+    /// The superiors of an headquarters, from its direct superior up to the top of the chain of command.
+    pub fn chain_of_command<'o>(
+        &'o self,
+        id: &SimulationID,
+    ) -> impl Iterator<Item = &'o HqUnit<'a>> + 'o {
+        let mut current = self.get(id);
+        std::iter::from_fn(move || {
+            current = current
+                .and_then(|hq| hq.superior.as_ref())
+                .and_then(|superior| self.get(superior));
+            current
+        })
+    }
+
+    /// An headquarters and all the headquarters under its command.
+    pub fn traverse<'o>(&'o self, id: &'o SimulationID) -> OrderOfBattleTraversal<'o, 'a> {
+        OrderOfBattleTraversal {
+            order_of_battle: self,
+            stack: vec![(0, id)],
+        }
+    }
+
+    /// The whole order of battle, tree after tree.
+    pub fn traverse_all(&self) -> OrderOfBattleTraversal<'_, 'a> {
+        let mut stack: Vec<(usize, &SimulationID)> = self.roots().map(|hq| (0, &hq.id)).collect();
+        stack.reverse();
+        OrderOfBattleTraversal {
+            order_of_battle: self,
+            stack,
+        }
+    }
+
+    /// All the units under the command of an headquarters, directly or not.
+    pub fn units_under<'o>(&'o self, id: &'o SimulationID) -> impl Iterator<Item = &'o Unit<'a>> {
+        self.traverse(id)
+            .flat_map(|(_, hq)| hq.attached_units.iter())
+    }
+
+    /// Aggregated strength of an headquarters and everything under its command.
+    pub fn strength(&self, id: &SimulationID) -> Option<OrderOfBattleStrength> {
+        self.get(id)?;
+        let mut strength = OrderOfBattleStrength::default();
+        for (_, hq) in self.traverse(id) {
+            strength.headquarters += 1;
+            hq.attached_units
+                .iter()
+                .for_each(|unit| strength.add_unit(unit));
+        }
+        Some(strength)
+    }
+
+    /// Is `id` under the command of `superior`, directly or not?
+    pub fn is_under_command_of(&self, id: &SimulationID, superior: &SimulationID) -> bool {
+        self.chain_of_command(id).any(|hq| &hq.id == superior)
+    }
+
+    /// Add an headquarters, with the units already attached to it, under a superior or at the top of the chain of
+    /// command.
+    pub fn add(
+        &mut self,
+        mut hq: HqUnit<'a>,
+        superior: Option<&SimulationID>,
+    ) -> Result<(), OrderOfBattleError> {
+        if self.get(&hq.id).is_some() || self.unit(&hq.id).is_some() {
+            return Err(OrderOfBattleError::DuplicateID(hq.id));
+        }
+        for (index, unit) in hq.attached_units.iter().enumerate() {
+            if self.get(&unit.id).is_some()
+                || self.unit(&unit.id).is_some()
+                || hq.attached_units[..index]
+                    .iter()
+                    .any(|other| other.id == unit.id)
+            {
+                return Err(OrderOfBattleError::DuplicateID(unit.id.clone()));
+            }
+        }
+        if hq.command_load() > hq.command_capacity() {
+            return Err(OrderOfBattleError::CapacityExceeded(hq.id));
+        }
+        hq.superior = None;
+        hq.subordinates.clear();
+        if let Some(superior) = superior {
+            self.check_can_command(superior, &hq)?;
+        }
+
+        let id = hq.id.clone();
+        self.headquarters.push(hq);
+        if let Some(superior) = superior {
+            self.link(&id, superior);
+        }
+        Ok(())
+    }
+
+    /// Remove an headquarters, with its attached units. Its subordinate headquarters end up at the top of the chain
+    /// of command.
+    pub fn remove(&mut self, id: &SimulationID) -> Result<HqUnit<'a>, OrderOfBattleError> {
+        let index = self
+            .headquarters
+            .iter()
+            .position(|hq| &hq.id == id)
+            .ok_or_else(|| OrderOfBattleError::UnknownHeadquarters(id.clone()))?;
+        self.unlink(id);
+        let mut hq = self.headquarters.remove(index);
+        for subordinate in hq.subordinates.drain(..) {
+            if let Some(subordinate) = self.get_mut(&subordinate) {
+                subordinate.superior = None;
+            }
+        }
+        Ok(hq)
+    }
+
+    /// Put an headquarters under the command of another one, transferring it from its previous superior if any.
+    pub fn attach(
+        &mut self,
+        id: &SimulationID,
+        superior: &SimulationID,
+    ) -> Result<(), OrderOfBattleError> {
+        let hq = self
+            .get(id)
+            .ok_or_else(|| OrderOfBattleError::UnknownHeadquarters(id.clone()))?;
+        if hq.superior.as_ref() == Some(superior) {
+            return Ok(());
+        }
+        if id == superior || self.is_under_command_of(superior, id) {
+            return Err(OrderOfBattleError::Cycle);
+        }
+        self.check_can_command(superior, hq)?;
+        self.unlink(id);
+        self.link(id, superior);
+        Ok(())
+    }
+
+    /// Take an headquarters away from its superior, to the top of the chain of command.
+    pub fn detach(&mut self, id: &SimulationID) -> Result<(), OrderOfBattleError> {
+        if self.get(id).is_none() {
+            return Err(OrderOfBattleError::UnknownHeadquarters(id.clone()));
+        }
+        self.unlink(id);
+        Ok(())
+    }
+
+    /// Attach a new unit to an headquarters.
+    pub fn attach_unit(
+        &mut self,
+        hq: &SimulationID,
+        unit: Unit<'a>,
+    ) -> Result<(), OrderOfBattleError> {
+        if self.get(&unit.id).is_some() || self.unit(&unit.id).is_some() {
+            return Err(OrderOfBattleError::DuplicateID(unit.id));
+        }
+        let hq = self
+            .get_mut(hq)
+            .ok_or_else(|| OrderOfBattleError::UnknownHeadquarters(hq.clone()))?;
+        if hq.command_load() >= hq.command_capacity() {
+            return Err(OrderOfBattleError::CapacityExceeded(hq.id.clone()));
+        }
+        hq.attached_units.push(unit);
+        Ok(())
+    }
+
+    /// Take a unit away from the order of battle.
+    pub fn detach_unit(&mut self, unit: &SimulationID) -> Result<Unit<'a>, OrderOfBattleError> {
+        self.headquarters
+            .iter_mut()
+            .find_map(|hq| {
+                let index = hq
+                    .attached_units
+                    .iter()
+                    .position(|attached| &attached.id == unit)?;
+                Some(hq.attached_units.remove(index))
+            })
+            .ok_or_else(|| OrderOfBattleError::UnknownUnit(unit.clone()))
+    }
+
+    /// Take the destroyed units away from their headquarters.
+    pub fn remove_destroyed_units(&mut self) -> Vec<Unit<'a>> {
+        let mut destroyed = vec![];
+        for hq in &mut self.headquarters {
+            let (lost, remaining) = hq
+                .attached_units
+                .drain(..)
+                .partition(|unit: &Unit| unit.is_destroyed());
+            hq.attached_units = remaining;
+            destroyed.extend(lost);
+        }
+        destroyed
+    }
+
+    /// Move a unit to another headquarters.
+    pub fn transfer_unit(
+        &mut self,
+        unit: &SimulationID,
+        to: &SimulationID,
+    ) -> Result<(), OrderOfBattleError> {
+        let from = self
+            .headquarters_of_unit(unit)
+            .ok_or_else(|| OrderOfBattleError::UnknownUnit(unit.clone()))?;
+        if &from.id == to {
+            return Ok(());
+        }
+        let destination = self
+            .get(to)
+            .ok_or_else(|| OrderOfBattleError::UnknownHeadquarters(to.clone()))?;
+        if destination.command_load() >= destination.command_capacity() {
+            return Err(OrderOfBattleError::CapacityExceeded(to.clone()));
+        }
+        let unit = self.detach_unit(unit)?;
+        self.attach_unit(to, unit)
+    }
+
+    fn check_can_command(
+        &self,
+        superior: &SimulationID,
+        subordinate: &HqUnit,
+    ) -> Result<(), OrderOfBattleError> {
+        let superior = self
+            .get(superior)
+            .ok_or_else(|| OrderOfBattleError::UnknownHeadquarters(superior.clone()))?;
+        if superior.echelon <= subordinate.echelon {
+            return Err(OrderOfBattleError::EchelonMismatch);
+        }
+        if superior.command_load() >= superior.command_capacity() {
+            return Err(OrderOfBattleError::CapacityExceeded(superior.id.clone()));
+        }
+        Ok(())
+    }
+
+    fn link(&mut self, id: &SimulationID, superior: &SimulationID) {
+        if let Some(hq) = self.get_mut(superior) {
+            hq.subordinates.push(id.clone());
+        }
+        if let Some(hq) = self.get_mut(id) {
+            hq.superior = Some(superior.clone());
+        }
+    }
+
+    fn unlink(&mut self, id: &SimulationID) {
+        let Some(superior) = self.get_mut(id).and_then(|hq| hq.superior.take()) else {
+            return;
+        };
+        if let Some(hq) = self.get_mut(&superior) {
+            hq.subordinates.retain(|subordinate| subordinate != id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::{
+        hex_map::coordinates::{CubeCoords, HexMapCoordinates},
+        simulation::{
+            ids::{SimulationID, WithSimulationID},
+            military::{Echelon, HqUnit, Unit, UnitTemplate},
+            properties::SimulationPropertyStorage,
+        },
+    };
+
+    use super::{OrderOfBattle, OrderOfBattleError};
+
+    fn build_mock_hq<'a>(id: u32, echelon: Echelon) -> HqUnit<'a> {
+        HqUnit::new(
+            SimulationID::new_map_entity_id(id),
+            HexMapCoordinates::Cube(CubeCoords::from_axial_coords(0, 0)),
+            None,
+            SimulationPropertyStorage::new(),
+        )
+        .with_echelon(echelon)
+    }
+
+    fn build_mock_unit(id: u32, template: &UnitTemplate, health_points: u16) -> Unit<'_> {
+        Unit::new(
+            SimulationID::new_map_entity_id(id),
+            HexMapCoordinates::Cube(CubeCoords::from_axial_coords(0, 0)),
+            template,
+            health_points,
+        )
+    }
+
+    #[test]
+    fn test_order_of_battle_operations() {
+        let template = UnitTemplate::new(
+            SimulationID::new_abstract_id("infantry"),
+            "infantry".into(),
+            HashMap::new(),
+            HashMap::new(),
+            SimulationPropertyStorage::new(),
+        );
+        let id = SimulationID::new_map_entity_id;
+        let mut oob = OrderOfBattle::new();
+
+        // army 1 > corps 2 > divisions 3 and 4, corps 5 at the top
+        oob.add(build_mock_hq(1, Echelon::Army), None).unwrap();
+        oob.add(build_mock_hq(2, Echelon::Corps), Some(&id(1)))
+            .unwrap();
+        oob.add(build_mock_hq(3, Echelon::Division), Some(&id(2)))
+            .unwrap();
+        oob.add(
+            build_mock_hq(4, Echelon::Division).with_command_capacity(2),
+            Some(&id(2)),
+        )
+        .unwrap();
+        oob.add(build_mock_hq(5, Echelon::Corps), None).unwrap();
+        assert_eq!(
+            oob.add(build_mock_hq(3, Echelon::Division), None),
+            Err(OrderOfBattleError::DuplicateID(id(3)))
+        );
+        assert_eq!(
+            oob.add(build_mock_hq(6, Echelon::Corps), Some(&id(3))),
+            Err(OrderOfBattleError::EchelonMismatch)
+        );
+
+        oob.attach_unit(&id(3), build_mock_unit(10, &template, 100))
+            .unwrap();
+        oob.attach_unit(&id(4), build_mock_unit(11, &template, 50))
+            .unwrap();
+        oob.attach_unit(&id(4), build_mock_unit(12, &template, 50))
+            .unwrap();
+        assert_eq!(
+            oob.attach_unit(&id(4), build_mock_unit(13, &template, 50)),
+            Err(OrderOfBattleError::CapacityExceeded(id(4)))
+        );
+        assert_eq!(
+            oob.attach_unit(&id(3), build_mock_unit(12, &template, 50)),
+            Err(OrderOfBattleError::DuplicateID(id(12)))
+        );
+
+        // traversal and aggregates
+        let traversal: Vec<(usize, &SimulationID)> = oob
+            .traverse_all()
+            .map(|(depth, hq)| (depth, hq.id()))
+            .collect();
+        assert_eq!(
+            traversal,
+            vec![
+                (0, &id(1)),
+                (1, &id(2)),
+                (2, &id(3)),
+                (2, &id(4)),
+                (0, &id(5))
+            ]
+        );
+        let chain: Vec<&SimulationID> = oob.chain_of_command(&id(4)).map(|hq| hq.id()).collect();
+        assert_eq!(chain, vec![&id(2), &id(1)]);
+        let strength = oob.strength(&id(1)).unwrap();
+        assert_eq!(strength.headquarters(), 4);
+        assert_eq!(strength.units(), 3);
+        assert_eq!(strength.health_points(), 200);
+        assert_eq!(oob.units_under(&id(4)).count(), 2);
+
+        // no cycle, transfer of an headquarters and of a unit
+        assert_eq!(oob.attach(&id(1), &id(3)), Err(OrderOfBattleError::Cycle));
+        oob.attach(&id(4), &id(5)).unwrap();
+        assert_eq!(oob.get(&id(2)).unwrap().subordinates(), &[id(3)]);
+        assert_eq!(oob.get(&id(4)).unwrap().superior(), Some(&id(5)));
+        assert_eq!(oob.strength(&id(1)).unwrap().units(), 1);
+        assert_eq!(
+            oob.transfer_unit(&id(10), &id(4)),
+            Err(OrderOfBattleError::CapacityExceeded(id(4)))
+        );
+        oob.transfer_unit(&id(11), &id(3)).unwrap();
+        assert_eq!(oob.headquarters_of_unit(&id(11)).unwrap().id(), &id(3));
+
+        // removing an headquarters frees its subordinates
+        let corps = oob.remove(&id(2)).unwrap();
+        assert!(corps.subordinates().is_empty());
+        assert_eq!(oob.get(&id(3)).unwrap().superior(), None);
+        assert!(oob.get(&id(1)).unwrap().subordinates().is_empty());
+        assert_eq!(oob.roots().count(), 3);
+        assert_eq!(oob.detach_unit(&id(10)).unwrap().health_points(), 100);
+        assert_eq!(
+            oob.detach_unit(&id(10)).unwrap_err(),
+            OrderOfBattleError::UnknownUnit(id(10))
+        );
+    }
+}
@@ -0,0 +1,492 @@
+//! Recruitment, reinforcement and disbanding of military units.
+//!
+//! Units are recruited at settlements: the order drafts its manpower from the population groups of the settlement
+//! and pays the cost of the `UnitTemplate` from the national treasury when queued, then the unit joins its
+//! headquarters once trained. Damaged units are reinforced the same way, in proportion of the health points they
+//! lack, and disbanded units give part of their manpower back.
+
+use crate::{
+    hex_map::{
+        coordinates::{CubeCoords, HexMapCoordinates},
+        HexMap,
+    },
+    simulation::{
+        buildings::Building,
+        economy::ConstructionCosts,
+        ids::{SimulationID, WithSimulationID},
+        properties::SimulationPropertyValue,
+        resources::{Resource, ResourceDataStorage, ResourceDataStore, ResourceQuantity},
+        settlements::Settlement,
+    },
+};
+
+use super::{
+    combat::unit_attribute,
+    order_of_battle::{OrderOfBattle, OrderOfBattleError},
+    Unit, UnitTemplate,
+};
+
+/// Attribute of an `UnitTemplate` (`SimulationPropertyValue::Integer`), the people needed for a unit.
+pub const MANPOWER_PROPERTY: &str = "manpower";
+
+/// Attribute of an `UnitTemplate` (`SimulationPropertyValue::Integer`), the health points of a unit at full
+/// strength.
+pub const FULL_HEALTH_POINTS_PROPERTY: &str = "health_points";
+
+/// Attribute of an `UnitTemplate` (`SimulationPropertyValue::Integer`), the turns needed to train a unit.
+pub const TRAINING_TIME_PROPERTY: &str = "training_time";
+
+/// Attribute of an `UnitTemplate` (`SimulationPropertyValue::Text`), the ID of the `BuildingTemplate` a settlement
+/// needs to recruit the unit.
+pub const REQUIRED_BUILDING_PROPERTY: &str = "required_building";
+
+/// Share of the manpower of a disbanded unit going back to the population.
+pub const DISBANDING_MANPOWER_RETURN_RATIO: f64 = 0.5;
+
+/// People needed for a unit of the template at full strength.
+pub fn unit_manpower(template: &UnitTemplate) -> u32 {
+    unit_attribute(template, MANPOWER_PROPERTY, 1000).max(0) as u32
+}
+
+pub fn unit_full_health_points(template: &UnitTemplate) -> u16 {
+    unit_attribute(template, FULL_HEALTH_POINTS_PROPERTY, 100).clamp(1, u16::MAX as i32) as u16
+}
+
+pub fn unit_training_time(template: &UnitTemplate) -> u16 {
+    unit_attribute(template, TRAINING_TIME_PROPERTY, 2).clamp(0, u16::MAX as i32) as u16
+}
+
+pub fn unit_required_building(template: &UnitTemplate) -> Option<SimulationID> {
+    match template
+        .attributes()
+        .get_from_id(&SimulationID::new_property_id(
+            REQUIRED_BUILDING_PROPERTY.into(),
+        )) {
+        Some(SimulationPropertyValue::Text(id)) => Some(SimulationID::new_abstract_id(id)),
+        _ => None,
+    }
+}
+
+/// Share, rounded up, of the manpower and cost of a template for the given health points.
+fn share_of(total: u32, health_points: u16, full_health_points: u16) -> u32 {
+    (total as u64 * health_points as u64).div_ceil(full_health_points as u64) as u32
+}
+
+fn costs_share(
+    costs: &ConstructionCosts,
+    health_points: u16,
+    full_health_points: u16,
+) -> ConstructionCosts {
+    costs
+        .iter()
+        .map(|(resource, quantity)| {
+            let quantity = share_of(*quantity as u32, health_points, full_health_points);
+            (*resource, quantity as ResourceQuantity)
+        })
+        .collect()
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RecruitmentError {
+    /// The settlement does not belong to the nation.
+    UnknownSettlement(SimulationID),
+    /// The settlement lacks the building of the given template.
+    MissingBuilding(SimulationID),
+    NotEnoughManpower {
+        available: u32,
+        needed: u32,
+    },
+    CannotAfford(Resource),
+    DuplicateOrder,
+    AtFullStrength,
+    OrderOfBattle(OrderOfBattleError),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RecruitmentEvent {
+    /// The unit was trained and attached to its headquarters.
+    Completed {
+        unit: SimulationID,
+        headquarters: SimulationID,
+    },
+    /// The unit is trained but could not join its headquarters. Retried every turn.
+    Delayed {
+        unit: SimulationID,
+        error: OrderOfBattleError,
+    },
+}
+
+#[derive(Debug)]
+pub struct RecruitmentOrder<'a> {
+    /// Must be `SimulationID::MapEntityID`. Becomes the ID of the recruited `Unit`.
+    id: SimulationID,
+    template: &'a UnitTemplate,
+    /// Must be the `SimulationID` of a `Settlement`.
+    settlement: SimulationID,
+    position: CubeCoords,
+    /// Must be the `SimulationID` of an `HqUnit`.
+    headquarters: SimulationID,
+    /// Number of turns of training done.
+    progress: u16,
+}
+
+impl<'a> RecruitmentOrder<'a> {
+    pub fn id(&self) -> &SimulationID {
+        &self.id
+    }
+
+    pub fn template(&self) -> &'a UnitTemplate {
+        self.template
+    }
+
+    pub fn settlement(&self) -> &SimulationID {
+        &self.settlement
+    }
+
+    pub fn position(&self) -> &CubeCoords {
+        &self.position
+    }
+
+    pub fn headquarters(&self) -> &SimulationID {
+        &self.headquarters
+    }
+
+    pub fn remaining_turns(&self) -> u16 {
+        unit_training_time(self.template).saturating_sub(self.progress)
+    }
+}
+
+/// Units in training for a nation.
+#[derive(Debug, Default)]
+pub struct RecruitmentQueue<'a> {
+    orders: Vec<RecruitmentOrder<'a>>,
+}
+
+impl<'a> RecruitmentQueue<'a> {
+    pub fn new() -> Self {
+        Self { orders: vec![] }
+    }
+
+    pub fn orders(&self) -> &[RecruitmentOrder<'a>] {
+        &self.orders
+    }
+
+    /// Order the recruitment of a unit at a settlement, drafting its manpower and paying for it right away.
+    pub fn enqueue(
+        &mut self,
+        id: SimulationID,
+        template: &'a UnitTemplate,
+        settlement: &Settlement,
+        headquarters: SimulationID,
+        map: &HexMap,
+        treasury: &mut ResourceDataStorage,
+    ) -> Result<(), RecruitmentError> {
+        assert!(matches!(id, SimulationID::MapEntityID(_)));
+        if self.orders.iter().any(|order| order.id == id) {
+            return Err(RecruitmentError::DuplicateOrder);
+        }
+        if let Some(required) = unit_required_building(template) {
+            let built = map.tile(settlement.position()).is_some_and(|tile| {
+                Building::all_on_tile(tile).any(|building| building.template().id() == &required)
+            });
+            if !built {
+                return Err(RecruitmentError::MissingBuilding(required));
+            }
+        }
+        let needed = unit_manpower(template);
+        let available = settlement.available_manpower();
+        if available < needed {
+            return Err(RecruitmentError::NotEnoughManpower { available, needed });
+        }
+        treasury
+            .consume_all(template.cost())
+            .map_err(RecruitmentError::CannotAfford)?;
+        settlement.draft_manpower(needed);
+
+        self.orders.push(RecruitmentOrder {
+            id,
+            template,
+            settlement: settlement.id().clone(),
+            position: *settlement.position(),
+            headquarters,
+            progress: 0,
+        });
+        Ok(())
+    }
+
+    /// Called every turn. Train the queued units, and attach the trained ones to their headquarters.
+    pub fn update(&mut self, order_of_battle: &mut OrderOfBattle<'a>) -> Vec<RecruitmentEvent> {
+        let mut events = vec![];
+        let mut remaining = vec![];
+        for mut order in self.orders.drain(..) {
+            order.progress = order.progress.saturating_add(1);
+            if order.remaining_turns() > 0 {
+                remaining.push(order);
+                continue;
+            }
+            let unit = Unit::new(
+                order.id.clone(),
+                HexMapCoordinates::Cube(order.position),
+                order.template,
+                unit_full_health_points(order.template),
+            );
+            match order_of_battle.attach_unit(&order.headquarters, unit) {
+                Ok(()) => events.push(RecruitmentEvent::Completed {
+                    unit: order.id.clone(),
+                    headquarters: order.headquarters.clone(),
+                }),
+                Err(error) => {
+                    events.push(RecruitmentEvent::Delayed {
+                        unit: order.id.clone(),
+                        error,
+                    });
+                    remaining.push(order);
+                }
+            }
+        }
+        self.orders = remaining;
+        events
+    }
+}
+
+/// Bring a damaged unit back to full strength, or as close as the manpower of the settlement allows. Return the
+/// health points regained.
+pub fn reinforce(
+    unit: &mut Unit,
+    settlement: &Settlement,
+    treasury: &mut ResourceDataStorage,
+) -> Result<u16, RecruitmentError> {
+    let template = unit.template();
+    let full_health_points = unit_full_health_points(template);
+    let missing = full_health_points.saturating_sub(unit.health_points());
+    if missing == 0 {
+        return Err(RecruitmentError::AtFullStrength);
+    }
+    let manpower = unit_manpower(template);
+    let available = settlement.available_manpower();
+    let health_points = if manpower == 0 {
+        missing
+    } else {
+        let affordable = available as u64 * full_health_points as u64 / manpower as u64;
+        missing.min(affordable.min(u16::MAX as u64) as u16)
+    };
+    if health_points == 0 {
+        return Err(RecruitmentError::NotEnoughManpower {
+            available,
+            needed: share_of(manpower, 1, full_health_points),
+        });
+    }
+    treasury
+        .consume_all(&costs_share(
+            template.cost(),
+            health_points,
+            full_health_points,
+        ))
+        .map_err(RecruitmentError::CannotAfford)?;
+    settlement.draft_manpower(share_of(manpower, health_points, full_health_points));
+    Ok(unit.reinforce(health_points))
+}
+
+/// Disband a unit, giving part of its remaining manpower back to a settlement. Return the manpower given back.
+pub fn disband(
+    order_of_battle: &mut OrderOfBattle,
+    unit: &SimulationID,
+    settlement: &Settlement,
+) -> Result<u32, RecruitmentError> {
+    let unit = order_of_battle
+        .detach_unit(unit)
+        .map_err(RecruitmentError::OrderOfBattle)?;
+    let template = unit.template();
+    let full_health_points = unit_full_health_points(template);
+    let health_points = unit.health_points().min(full_health_points);
+    let manpower = (unit_manpower(template) as f64 * health_points as f64
+        / full_health_points as f64
+        * DISBANDING_MANPOWER_RETURN_RATIO) as u32;
+    settlement.return_manpower(manpower);
+    Ok(manpower)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::{
+        hex_map::{
+            coordinates::{CubeCoords, HexMapCoordinates},
+            tile::HexMapTile,
+            HexMap, HexMapStorage,
+        },
+        simulation::{
+            buildings::{Building, BuildingTemplate},
+            economy::budget::BudgetSpendingCategory,
+            ids::{SimulationID, WithSimulationID},
+            military::{HqUnit, UnitTemplate},
+            nations::Nation,
+            people::population::PopulationGroup,
+            properties::{SimulationPropertyStorage, SimulationPropertyValue},
+            resources::{Resource, ResourceDataStore},
+            settlements::Settlement,
+            testing::build_mock_leader,
+        },
+    };
+
+    use super::{
+        RecruitmentError, RecruitmentEvent, MANPOWER_PROPERTY, REQUIRED_BUILDING_PROPERTY,
+        TRAINING_TIME_PROPERTY,
+    };
+
+    #[test]
+    fn test_recruitment_lifecycle() {
+        let barracks: &BuildingTemplate = Box::leak(Box::new(BuildingTemplate::new(
+            SimulationID::new_abstract_id("barracks"),
+            "barracks".into(),
+            HashMap::new(),
+            HashMap::new(),
+            HashMap::new(),
+        )));
+        let template = UnitTemplate::new(
+            SimulationID::new_abstract_id("infantry"),
+            "infantry".into(),
+            HashMap::from([(Resource::Credits, 100)]),
+            HashMap::from([(Resource::Credits, 5)]),
+            SimulationPropertyStorage::new()
+                .register_new(
+                    SimulationID::new_property_id(MANPOWER_PROPERTY.into()),
+                    SimulationPropertyValue::Integer(1000),
+                )
+                .register_new(
+                    SimulationID::new_property_id(TRAINING_TIME_PROPERTY.into()),
+                    SimulationPropertyValue::Integer(2),
+                )
+                .register_new(
+                    SimulationID::new_property_id(REQUIRED_BUILDING_PROPERTY.into()),
+                    SimulationPropertyValue::Text("barracks".into()),
+                ),
+        );
+        let position = CubeCoords::from_axial_coords(0, 0);
+        let mut tiles = HexMapStorage::new();
+        tiles.insert(position, HexMapTile::from_properties(0));
+        let mut map = HexMap::from_tiles(tiles);
+
+        let mut settlement =
+            Settlement::new(SimulationID::new_map_entity_id(1), "Town".into(), position);
+        settlement.add_population_group(PopulationGroup::new(
+            SimulationID::new_entity_id(1),
+            12000,
+            HashMap::new(),
+        ));
+        let mut nation = Nation::new(
+            SimulationID::new_abstract_id("nation"),
+            "Nation".into(),
+            build_mock_leader(2, vec![]),
+            &settlement,
+        );
+        nation.resources_mut().replenish(Resource::Credits, 150);
+        let hq = SimulationID::new_map_entity_id(2);
+        nation
+            .add_headquarters(HqUnit::new(
+                hq.clone(),
+                HexMapCoordinates::Cube(position),
+                None,
+                SimulationPropertyStorage::new(),
+            ))
+            .unwrap();
+
+        // requirements: settlement of the nation, building, then manpower and resources
+        let unit = SimulationID::new_map_entity_id(10);
+        assert_eq!(
+            nation.recruit(
+                unit.clone(),
+                &template,
+                &SimulationID::new_map_entity_id(4),
+                hq.clone(),
+                &map
+            ),
+            Err(RecruitmentError::UnknownSettlement(
+                SimulationID::new_map_entity_id(4)
+            ))
+        );
+        assert_eq!(
+            nation.recruit(unit.clone(), &template, settlement.id(), hq.clone(), &map),
+            Err(RecruitmentError::MissingBuilding(
+                SimulationID::new_abstract_id("barracks")
+            ))
+        );
+        map.tile_mut(&position)
+            .unwrap()
+            .layer_artificial_mut()
+            .add_building(Box::new(Building::new(
+                SimulationID::new_map_entity_id(3),
+                barracks,
+                100,
+            )));
+        nation
+            .recruit(unit.clone(), &template, settlement.id(), hq.clone(), &map)
+            .unwrap();
+        assert_eq!(settlement.inhabitants(), 11000);
+        assert_eq!(nation.resources().quantity_of(Resource::Credits), 50);
+        assert_eq!(
+            nation.recruit(
+                SimulationID::new_map_entity_id(11),
+                &template,
+                settlement.id(),
+                hq.clone(),
+                &map
+            ),
+            Err(RecruitmentError::CannotAfford(Resource::Credits))
+        );
+
+        // training
+        assert!(nation.update_recruitment().is_empty());
+        assert_eq!(
+            nation.update_recruitment(),
+            vec![RecruitmentEvent::Completed {
+                unit: unit.clone(),
+                headquarters: hq.clone()
+            }]
+        );
+        assert!(nation.recruitment().orders().is_empty());
+        assert_eq!(
+            nation
+                .order_of_battle()
+                .unit(&unit)
+                .unwrap()
+                .health_points(),
+            100
+        );
+
+        // reinforcement, in proportion of the health points lost
+        nation
+            .order_of_battle_mut()
+            .unit_mut(&unit)
+            .unwrap()
+            .damage(40);
+        assert_eq!(nation.reinforce(&unit, settlement.id()), Ok(40));
+        assert_eq!(settlement.inhabitants(), 10600);
+        assert_eq!(nation.resources().quantity_of(Resource::Credits), 10);
+        assert_eq!(
+            nation.reinforce(&unit, settlement.id()),
+            Err(RecruitmentError::AtFullStrength)
+        );
+
+        // costs are reported as spending of the turn, but not paid twice
+        let report = nation.update_budget(&[], &[]);
+        assert_eq!(report.spending(BudgetSpendingCategory::Recruitment), 140);
+        assert_eq!(
+            nation.resources().quantity_of(Resource::Credits) as i64,
+            10 + report.balance() + 140
+        );
+        assert_eq!(
+            nation
+                .budget()
+                .project(&nation, &[], &[])
+                .spending(BudgetSpendingCategory::Recruitment),
+            0
+        );
+
+        // disbanding gives half of the manpower back
+        assert_eq!(nation.disband(&unit, settlement.id()), Ok(500));
+        assert_eq!(settlement.inhabitants(), 11100);
+        assert!(nation.order_of_battle().unit(&unit).is_none());
+    }
+}
@@ -0,0 +1,488 @@
+//! Sieges and capture of settlements.
+//!
+//! Units of a nation at war with the owner of a settlement (see `Diplomacy::are_hostile`) standing on its tile or
+//! next to it besiege it, and the sieges are lifted when the war ends. A settlement left without a garrison falls
+//! as soon as hostile units occupy its tile. Otherwise, the siege progresses every turn depending on the strength
+//! of the besiegers against the one of the garrison, which is increased by the fortifications of the settlement
+//! and lowered when it runs out of supply. When the siege completes, the settlement changes hands: its population
+//! loses loyalty, its buildings are damaged, and if it was the capital of its former owner, the nation moves its
+//! capital and its units lose morale.
+
+use std::collections::HashMap;
+
+use crate::{
+    hex_map::{
+        coordinates::{CubeCoords, HexMapCoordinatesSystem},
+        HexMap,
+    },
+    simulation::{
+        buildings::Building,
+        diplomacy::Diplomacy,
+        ids::{SimulationID, WithSimulationID},
+        nations::Nation,
+        settlements::Settlement,
+    },
+};
+
+use super::{
+    domains::{unit_domain, UnitDomain},
+    Unit,
+};
+
+/// Siege of a settlement by a hostile nation.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Siege {
+    /// Must be the `SimulationID` of a `Settlement`.
+    settlement: SimulationID,
+    /// Must be the `SimulationID` of a `Nation`.
+    besieger: SimulationID,
+    /// From `0.0` to `1.0`, when the settlement falls.
+    progress: f64,
+    /// Number of turns the siege lasted.
+    turns: u16,
+}
+
+impl Siege {
+    pub fn settlement(&self) -> &SimulationID {
+        &self.settlement
+    }
+
+    pub fn besieger(&self) -> &SimulationID {
+        &self.besieger
+    }
+
+    pub fn progress(&self) -> f64 {
+        self.progress
+    }
+
+    pub fn turns(&self) -> u16 {
+        self.turns
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum SiegeEvent {
+    Started {
+        settlement: SimulationID,
+        besieger: SimulationID,
+    },
+    /// No besieging unit is left around the settlement, or the nations are no longer at war.
+    Lifted {
+        settlement: SimulationID,
+        besieger: SimulationID,
+    },
+    /// The settlement changed hands. Its effects on the settlement itself must be applied with
+    /// `SiegeModel::occupy` by its new owner.
+    Captured {
+        settlement: SimulationID,
+        besieger: SimulationID,
+        /// Whether the settlement was the capital of its former owner.
+        capital: bool,
+    },
+}
+
+/// Rules of the sieges and the capture of settlements.
+#[derive(Debug)]
+pub struct SiegeModel {
+    /// Siege progress per turn against a settlement without garrison.
+    progress_rate: f64,
+    /// Ratio of the strength lost by units out of supply, whether besieging or in the garrison.
+    out_of_supply_penalty: f64,
+    /// Strength bonus granted to the garrison by the buildings of the settlement, scaled by their health.
+    ///
+    /// Keys must be the `SimulationID`s of `BuildingTemplate`s.
+    fortifications: HashMap<SimulationID, f64>,
+    /// Loyalty lost by the population of a captured settlement.
+    capture_loyalty_loss: u16,
+    /// Ratio of their maximum health points lost by the buildings of a captured settlement.
+    capture_building_damage: f64,
+    /// Morale lost by all the units of a nation whose capital is captured.
+    capital_loss_morale: u16,
+}
+
+impl Default for SiegeModel {
+    fn default() -> Self {
+        Self {
+            progress_rate: 0.25,
+            out_of_supply_penalty: 0.5,
+            fortifications: HashMap::new(),
+            capture_loyalty_loss: 50,
+            capture_building_damage: 0.25,
+            capital_loss_morale: 20,
+        }
+    }
+}
+
+impl SiegeModel {
+    pub fn new(progress_rate: f64, capture_loyalty_loss: u16, capital_loss_morale: u16) -> Self {
+        assert!(progress_rate > 0.0);
+        Self {
+            progress_rate,
+            capture_loyalty_loss,
+            capital_loss_morale,
+            ..Self::default()
+        }
+    }
+
+    pub fn with_fortification(mut self, building: SimulationID, bonus: f64) -> Self {
+        assert!(matches!(building, SimulationID::Abstract(_)));
+        self.fortifications.insert(building, bonus);
+        self
+    }
+
+    /// Multiplier of the strength of the garrison of a settlement, given its fortifications.
+    pub fn fortification(&self, settlement: &Settlement, map: &HexMap) -> f64 {
+        let bonus: f64 = map
+            .tile(settlement.position())
+            .into_iter()
+            .flat_map(Building::all_on_tile)
+            .filter_map(|building| {
+                self.fortifications
+                    .get(building.template().id())
+                    .map(|bonus| bonus * building.efficiency())
+            })
+            .sum();
+        1.0 + bonus.max(0.0)
+    }
+
+    /// Weight of a unit in a siege, on either side. Air units cannot hold ground.
+    pub fn strength(&self, unit: &Unit) -> f64 {
+        if unit.is_destroyed() || unit_domain(unit.template()) == UnitDomain::Air {
+            return 0.0;
+        }
+        let supply = if unit.is_out_of_supply() {
+            1.0 - self.out_of_supply_penalty
+        } else {
+            1.0
+        };
+        unit.health_points() as f64 * unit.combat_effectiveness() * supply
+    }
+
+    /// Called every turn, after movement and combat. Advance the sieges of the settlements of `defender` by the
+    /// units of `besieger`, and hand the fallen settlements over to it. Nothing is besieged unless the nations are
+    /// at war, and the sieges of a war which ended are lifted.
+    pub fn update<'a>(
+        &self,
+        defender: &mut Nation<'a>,
+        besieger: &mut Nation<'a>,
+        diplomacy: &Diplomacy,
+        map: &HexMap,
+    ) -> Vec<SiegeEvent> {
+        let mut events = vec![];
+        if !diplomacy.are_hostile(defender.id(), besieger.id()) {
+            defender.sieges_mut().retain(|siege| {
+                let lifted = siege.besieger() == besieger.id();
+                if lifted {
+                    events.push(SiegeEvent::Lifted {
+                        settlement: siege.settlement().clone(),
+                        besieger: siege.besieger().clone(),
+                    });
+                }
+                !lifted
+            });
+            return events;
+        }
+        let mut fallen = vec![];
+        let settlements = defender.settlements().to_vec();
+        for settlement in settlements {
+            let position = *settlement.position();
+            let garrison = self.strength_around(defender, &position, 0);
+            let occupying = self.strength_around(besieger, &position, 0);
+            let besieging = self.strength_around(besieger, &position, 1);
+            let ongoing = defender.sieges().iter().position(|siege| {
+                siege.settlement() == settlement.id() && siege.besieger() == besieger.id()
+            });
+
+            if besieging <= 0.0 {
+                if let Some(index) = ongoing {
+                    defender.sieges_mut().remove(index);
+                    events.push(SiegeEvent::Lifted {
+                        settlement: settlement.id().clone(),
+                        besieger: besieger.id().clone(),
+                    });
+                }
+                continue;
+            }
+            if occupying > 0.0 && garrison <= 0.0 {
+                fallen.push(settlement.id().clone());
+                continue;
+            }
+            let index = ongoing.unwrap_or_else(|| {
+                events.push(SiegeEvent::Started {
+                    settlement: settlement.id().clone(),
+                    besieger: besieger.id().clone(),
+                });
+                defender.sieges_mut().push(Siege {
+                    settlement: settlement.id().clone(),
+                    besieger: besieger.id().clone(),
+                    progress: 0.0,
+                    turns: 0,
+                });
+                defender.sieges().len() - 1
+            });
+            let defense = garrison * self.fortification(settlement, map);
+            let siege = &mut defender.sieges_mut()[index];
+            siege.progress += self.progress_rate * besieging / (besieging + defense);
+            siege.turns += 1;
+            if siege.progress >= 1.0 {
+                fallen.push(settlement.id().clone());
+            }
+        }
+
+        for settlement in fallen {
+            let capital = defender
+                .capital()
+                .is_some_and(|capital| capital.id() == &settlement);
+            let Some(ceded) = defender.cede_settlement(&settlement) else {
+                continue;
+            };
+            besieger.annex_settlement(ceded);
+            if capital {
+                for hq in defender.headquarters_mut() {
+                    for unit in hq.attached_units_mut() {
+                        unit.lower_morale(self.capital_loss_morale);
+                    }
+                }
+            }
+            events.push(SiegeEvent::Captured {
+                settlement,
+                besieger: besieger.id().clone(),
+                capital,
+            });
+        }
+        events
+    }
+
+    /// Apply the effects of its capture on a settlement of `occupier`: its population loses loyalty, and its
+    /// buildings are damaged and taken over. Return `false` if the settlement does not belong to `occupier`.
+    pub fn occupy(&self, occupier: &Nation, settlement: &SimulationID, map: &mut HexMap) -> bool {
+        let Some(settlement) = occupier
+            .settlements()
+            .iter()
+            .find(|owned| owned.id() == settlement)
+        else {
+            return false;
+        };
+        for group in settlement.population() {
+            group.lower_loyalty(self.capture_loyalty_loss);
+        }
+        if let Some(tile) = map.tile_mut(settlement.position()) {
+            for building in tile
+                .layer_artificial_mut()
+                .buildings_mut()
+                .filter_map(Building::from_tile_building_mut)
+            {
+                let damage =
+                    building.template().max_health_points() as f64 * self.capture_building_damage;
+                building.damage(damage.round() as u16);
+                building.set_owner(Some(occupier.id().clone()));
+            }
+        }
+        true
+    }
+
+    /// Total strength of the units of a nation within a distance of a tile.
+    fn strength_around(&self, nation: &Nation, position: &CubeCoords, distance: u32) -> f64 {
+        nation
+            .order_of_battle()
+            .units()
+            .filter(|unit| {
+                unit.position()
+                    .as_cube_coords()
+                    .is_some_and(|at| at.distance_to(*position) <= distance as f64)
+            })
+            .map(|unit| self.strength(unit))
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::{
+        hex_map::{
+            coordinates::{CubeCoords, HexMapCoordinates},
+            layers::natural::HexMapTerrain,
+            tile::HexMapTile,
+            HexMap, HexMapStorage,
+        },
+        simulation::{
+            buildings::{Building, BuildingTemplate},
+            diplomacy::Diplomacy,
+            ids::{SimulationID, WithSimulationID},
+            military::{HqUnit, Unit, UnitTemplate, UNIT_MAX_MORALE},
+            nations::Nation,
+            people::population::PopulationGroup,
+            properties::SimulationPropertyStorage,
+            settlements::Settlement,
+            testing::build_mock_leader,
+        },
+    };
+
+    use super::{SiegeEvent, SiegeModel};
+
+    fn build_mock_nation<'a>(
+        id: &str,
+        capital: &'a Settlement,
+        template: &'a UnitTemplate,
+        units: &[(u32, (i16, i16))],
+    ) -> Nation<'a> {
+        let mut nation = Nation::new(
+            SimulationID::new_abstract_id(id),
+            id.into(),
+            build_mock_leader(1, vec![]),
+            capital,
+        );
+        let hq = SimulationID::new_map_entity_id(units[0].0 - 1);
+        nation
+            .add_headquarters(HqUnit::new(
+                hq.clone(),
+                HexMapCoordinates::Cube(*capital.position()),
+                None,
+                SimulationPropertyStorage::new(),
+            ))
+            .unwrap();
+        for (id, (q, r)) in units {
+            nation
+                .order_of_battle_mut()
+                .attach_unit(
+                    &hq,
+                    Unit::new(
+                        SimulationID::new_map_entity_id(*id),
+                        HexMapCoordinates::Cube(CubeCoords::from_axial_coords(*q, *r)),
+                        template,
+                        100,
+                    ),
+                )
+                .unwrap();
+        }
+        nation
+    }
+
+    #[test]
+    fn test_siege_and_capture() {
+        let mut tiles = HexMapStorage::new();
+        for q in 0..=6 {
+            tiles.insert(
+                CubeCoords::from_axial_coords(q, 0),
+                HexMapTile::from_terrain(0, HexMapTerrain::Plains),
+            );
+        }
+        let mut map = HexMap::from_tiles(tiles);
+        let walls: &BuildingTemplate = Box::leak(Box::new(BuildingTemplate::new(
+            SimulationID::new_abstract_id("walls"),
+            "walls".into(),
+            HashMap::new(),
+            HashMap::new(),
+            HashMap::new(),
+        )));
+        map.tile_mut(&CubeCoords::from_axial_coords(0, 0))
+            .unwrap()
+            .layer_artificial_mut()
+            .add_building(Box::new(
+                Building::new(SimulationID::new_map_entity_id(100), walls, 100)
+                    .with_owner(SimulationID::new_abstract_id("defender")),
+            ));
+
+        let template = UnitTemplate::new(
+            SimulationID::new_abstract_id("infantry"),
+            "infantry".into(),
+            HashMap::new(),
+            HashMap::new(),
+            SimulationPropertyStorage::new(),
+        );
+        let settlement = |id, name: &str, q| {
+            Settlement::new(
+                SimulationID::new_map_entity_id(id),
+                name.into(),
+                CubeCoords::from_axial_coords(q, 0),
+            )
+        };
+        let mut fortress = settlement(1, "Fortress", 0);
+        fortress.add_population_group(PopulationGroup::new(
+            SimulationID::new_entity_id(1),
+            1000,
+            HashMap::new(),
+        ));
+        let town = settlement(2, "Town", 3);
+        let enemy_capital = settlement(3, "Enemy", 6);
+
+        // a garrisoned and walled capital besieged by two units, an empty town occupied by a third one
+        let mut defender = build_mock_nation("defender", &fortress, &template, &[(10, (0, 0))]);
+        defender.annex_settlement(&town);
+        let mut besieger = build_mock_nation(
+            "besieger",
+            &enemy_capital,
+            &template,
+            &[(20, (1, 0)), (21, (1, 0)), (22, (3, 0))],
+        );
+        let model =
+            SiegeModel::default().with_fortification(SimulationID::new_abstract_id("walls"), 1.0);
+        assert_eq!(model.fortification(&fortress, &map), 2.0);
+
+        // nothing happens until war is declared
+        let mut diplomacy = Diplomacy::new();
+        assert!(model
+            .update(&mut defender, &mut besieger, &diplomacy, &map)
+            .is_empty());
+        assert_eq!(defender.settlements().len(), 2);
+        diplomacy.declare_war(besieger.id(), defender.id()).unwrap();
+
+        let events = model.update(&mut defender, &mut besieger, &diplomacy, &map);
+        assert_eq!(
+            events,
+            vec![
+                SiegeEvent::Started {
+                    settlement: fortress.id().clone(),
+                    besieger: besieger.id().clone(),
+                },
+                SiegeEvent::Captured {
+                    settlement: town.id().clone(),
+                    besieger: besieger.id().clone(),
+                    capital: false,
+                },
+            ]
+        );
+        // 0.25 x 200 / (200 + 100 x 2)
+        assert_eq!(defender.sieges()[0].progress(), 0.125);
+        assert_eq!(defender.settlements().len(), 1);
+        assert_eq!(besieger.settlements().len(), 2);
+
+        for _ in 0..6 {
+            assert!(model
+                .update(&mut defender, &mut besieger, &diplomacy, &map)
+                .is_empty());
+        }
+        let events = model.update(&mut defender, &mut besieger, &diplomacy, &map);
+        assert_eq!(
+            events,
+            vec![SiegeEvent::Captured {
+                settlement: fortress.id().clone(),
+                besieger: besieger.id().clone(),
+                capital: true,
+            }]
+        );
+        assert!(defender.capital().is_none());
+        assert!(defender.sieges().is_empty());
+        assert_eq!(
+            defender
+                .order_of_battle()
+                .unit(&SimulationID::new_map_entity_id(10))
+                .unwrap()
+                .morale(),
+            UNIT_MAX_MORALE - 20
+        );
+
+        // the occupation is applied by the new owner, while both nations hold their settlements
+        assert!(!model.occupy(&defender, fortress.id(), &mut map));
+        assert!(model.occupy(&besieger, fortress.id(), &mut map));
+        assert_eq!(fortress.loyalty(), 50);
+        let walls = map
+            .tile(fortress.position())
+            .and_then(|tile| Building::all_on_tile(tile).next())
+            .unwrap();
+        assert_eq!(walls.health_points(), 75);
+        assert_eq!(walls.owner(), Some(besieger.id()));
+    }
+}
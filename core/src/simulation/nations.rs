@@ -1,7 +1,19 @@
+use crate::hex_map::HexMap;
+
 use super::{
+    buildings::Building,
+    economy::budget::{BudgetReport, BudgetSpendingCategory, NationalBudget},
     ids::{SimulationID, WithSimulationID},
-    military::HqUnit,
+    infrastructure::{network::InfrastructureNetwork, Infrastructure},
+    logistics::{update_supply, SupplyReport},
+    military::{
+        order_of_battle::{OrderOfBattle, OrderOfBattleError},
+        recruitment::{self, RecruitmentError, RecruitmentEvent, RecruitmentQueue},
+        siege::Siege,
+        HqUnit, Unit, UnitTemplate,
+    },
     people::leaders::Leader,
+    resources::{Resource, ResourceDataStorage, ResourceDataStore},
     settlements::Settlement,
 };
 
@@ -11,9 +23,258 @@ pub struct Nation<'a> {
     id: SimulationID,
     name: String,
     leader: Leader,
-    headquarters: Vec<HqUnit<'a>>,
-    capital: &'a Settlement,
+    order_of_battle: OrderOfBattle<'a>,
+    recruitment: RecruitmentQueue<'a>,
+    /// `None` once the nation lost all its settlements.
+    capital: Option<&'a Settlement>,
     settlements: Vec<&'a Settlement>,
+    /// Sieges of the settlements of the nation by hostile nations.
+    sieges: Vec<Siege>,
+    /// National stockpile of resources.
+    ///
+    /// Its `Resource::Credits` are the nation's treasury.
+    resources: ResourceDataStorage,
+    budget: NationalBudget,
+}
+
+impl<'a> Nation<'a> {
+    pub fn new(id: SimulationID, name: String, leader: Leader, capital: &'a Settlement) -> Self {
+        assert!(matches!(id, SimulationID::Abstract(_)));
+        Self {
+            id,
+            name,
+            leader,
+            order_of_battle: OrderOfBattle::new(),
+            recruitment: RecruitmentQueue::new(),
+            capital: Some(capital),
+            settlements: vec![capital],
+            sieges: vec![],
+            resources: ResourceDataStorage::new(),
+            budget: NationalBudget::new(),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn leader(&self) -> &Leader {
+        &self.leader
+    }
+
+    pub fn capital(&self) -> Option<&'a Settlement> {
+        self.capital
+    }
+
+    pub fn settlements(&self) -> &[&'a Settlement] {
+        &self.settlements
+    }
+
+    /// Take control of a settlement. It becomes the capital if the nation had none left.
+    pub fn annex_settlement(&mut self, settlement: &'a Settlement) {
+        if self
+            .settlements
+            .iter()
+            .any(|owned| owned.id() == settlement.id())
+        {
+            return;
+        }
+        self.settlements.push(settlement);
+        self.capital.get_or_insert(settlement);
+    }
+
+    /// Lose control of a settlement, along with its sieges.
+    ///
+    /// If it was the capital, the most populous remaining settlement becomes the new capital.
+    pub fn cede_settlement(&mut self, settlement: &SimulationID) -> Option<&'a Settlement> {
+        let index = self
+            .settlements
+            .iter()
+            .position(|owned| owned.id() == settlement)?;
+        let ceded = self.settlements.remove(index);
+        self.sieges.retain(|siege| siege.settlement() != settlement);
+        if self
+            .capital
+            .is_some_and(|capital| capital.id() == settlement)
+        {
+            self.capital = self
+                .settlements
+                .iter()
+                .copied()
+                .reduce(|largest, settlement| {
+                    if settlement.inhabitants() > largest.inhabitants() {
+                        settlement
+                    } else {
+                        largest
+                    }
+                });
+        }
+        Some(ceded)
+    }
+
+    pub fn sieges(&self) -> &[Siege] {
+        &self.sieges
+    }
+
+    pub fn sieges_mut(&mut self) -> &mut Vec<Siege> {
+        &mut self.sieges
+    }
+
+    pub fn resources(&self) -> &ResourceDataStorage {
+        &self.resources
+    }
+
+    pub fn resources_mut(&mut self) -> &mut ResourceDataStorage {
+        &mut self.resources
+    }
+
+    pub fn order_of_battle(&self) -> &OrderOfBattle<'a> {
+        &self.order_of_battle
+    }
+
+    pub fn order_of_battle_mut(&mut self) -> &mut OrderOfBattle<'a> {
+        &mut self.order_of_battle
+    }
+
+    pub fn recruitment(&self) -> &RecruitmentQueue<'a> {
+        &self.recruitment
+    }
+
+    pub fn recruitment_mut(&mut self) -> &mut RecruitmentQueue<'a> {
+        &mut self.recruitment
+    }
+
+    pub fn headquarters(&self) -> &[HqUnit<'a>] {
+        self.order_of_battle.headquarters()
+    }
+
+    pub fn headquarters_mut(&mut self) -> &mut [HqUnit<'a>] {
+        self.order_of_battle.headquarters_mut()
+    }
+
+    /// Add an headquarters at the top of the chain of command.
+    pub fn add_headquarters(&mut self, headquarters: HqUnit<'a>) -> Result<(), OrderOfBattleError> {
+        self.order_of_battle.add(headquarters, None)
+    }
+
+    pub fn budget(&self) -> &NationalBudget {
+        &self.budget
+    }
+
+    pub fn budget_mut(&mut self) -> &mut NationalBudget {
+        &mut self.budget
+    }
+
+    /// Called every turn. Levy taxes, pay for all the spending and apply the resulting balance to the treasury.
+    ///
+    /// `infrastructure` and `buildings` are the ones maintained by the nation.
+    pub fn update_budget(
+        &mut self,
+        infrastructure: &[&Infrastructure],
+        buildings: &[&Building],
+    ) -> BudgetReport {
+        let mut report = self.budget.project(self, infrastructure, buildings);
+        self.budget.apply(&mut report, &mut self.resources);
+        report
+    }
+
+    fn settlement(&self, id: &SimulationID) -> Result<&'a Settlement, RecruitmentError> {
+        self.settlements
+            .iter()
+            .find(|settlement| settlement.id() == id)
+            .copied()
+            .ok_or_else(|| RecruitmentError::UnknownSettlement(id.clone()))
+    }
+
+    /// Credits spent from the treasury by an operation, recorded as spending of the turn.
+    fn record_spending<T>(
+        &mut self,
+        category: BudgetSpendingCategory,
+        operation: impl FnOnce(&mut Self) -> T,
+    ) -> T {
+        let before = self.resources.quantity_of(Resource::Credits);
+        let result = operation(self);
+        let spent = before.saturating_sub(self.resources.quantity_of(Resource::Credits));
+        self.budget.record_expense(category, spent as i64);
+        result
+    }
+
+    /// Order the recruitment of a unit at one of the settlements of the nation, drafting its manpower and paying
+    /// for it from the treasury right away (see `RecruitmentQueue::enqueue`).
+    pub fn recruit(
+        &mut self,
+        id: SimulationID,
+        template: &'a UnitTemplate,
+        settlement: &SimulationID,
+        headquarters: SimulationID,
+        map: &HexMap,
+    ) -> Result<(), RecruitmentError> {
+        let settlement = self.settlement(settlement)?;
+        self.record_spending(BudgetSpendingCategory::Recruitment, |nation| {
+            nation.recruitment.enqueue(
+                id,
+                template,
+                settlement,
+                headquarters,
+                map,
+                &mut nation.resources,
+            )
+        })
+    }
+
+    /// Reinforce a unit of the nation from one of its settlements (see `recruitment::reinforce`). Return the health
+    /// points regained.
+    pub fn reinforce(
+        &mut self,
+        unit: &SimulationID,
+        settlement: &SimulationID,
+    ) -> Result<u16, RecruitmentError> {
+        let settlement = self.settlement(settlement)?;
+        self.record_spending(BudgetSpendingCategory::Recruitment, |nation| {
+            let unit = nation.order_of_battle.unit_mut(unit).ok_or_else(|| {
+                RecruitmentError::OrderOfBattle(OrderOfBattleError::UnknownUnit(unit.clone()))
+            })?;
+            recruitment::reinforce(unit, settlement, &mut nation.resources)
+        })
+    }
+
+    /// Disband a unit of the nation, giving part of its manpower back to one of its settlements (see
+    /// `recruitment::disband`). Return the manpower given back.
+    pub fn disband(
+        &mut self,
+        unit: &SimulationID,
+        settlement: &SimulationID,
+    ) -> Result<u32, RecruitmentError> {
+        let settlement = self.settlement(settlement)?;
+        recruitment::disband(&mut self.order_of_battle, unit, settlement)
+    }
+
+    /// Called every turn. Train the units being recruited, and attach the trained ones to their headquarters.
+    pub fn update_recruitment(&mut self) -> Vec<RecruitmentEvent> {
+        self.recruitment.update(&mut self.order_of_battle)
+    }
+
+    /// Called every turn. Refill the supply nodes of the nation from its stockpile, then supply its units.
+    pub fn update_supply(
+        &mut self,
+        map: &mut HexMap,
+        network: &InfrastructureNetwork,
+    ) -> SupplyReport {
+        let mut units: Vec<&mut Unit<'a>> = self
+            .order_of_battle
+            .headquarters_mut()
+            .iter_mut()
+            .flat_map(|hq| hq.attached_units_mut().iter_mut())
+            .collect();
+        update_supply(
+            &self.id,
+            &self.settlements,
+            &mut self.resources,
+            &mut units,
+            map,
+            network,
+        )
+    }
 }
 
 impl<'a> WithSimulationID for Nation<'a> {
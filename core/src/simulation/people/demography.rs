@@ -0,0 +1,587 @@
+//! Demographics: births, deaths, aging and migration of the population groups.
+//!
+//! Every turn, adults have children, children grow up and adults grow old, and people of every age die. Births
+//! fall with hunger and insecurity, deaths rise with them and fall with healthcare. People then move between
+//! settlements: migrants leave for the places with more jobs, more safety and better living standards, while the
+//! inhabitants of combat zones flee to the nearest safe settlement.
+//!
+//! The conditions of every settlement (see `SettlementConditions`) are gathered before the update, from the
+//! buildings of its tile, its food supply and the combats of the turn.
+//!
+//! Births, deaths and aging are rounded up or down at random, in proportion to their fractional part, so that the
+//! population of small groups still changes over time.
+
+use std::collections::HashMap;
+
+use crate::{
+    hex_map::{
+        coordinates::{CubeCoords, HexMapCoordinatesSystem},
+        HexMap,
+    },
+    prng::CoreRandom,
+    simulation::{
+        buildings::Building,
+        ids::{SimulationID, WithSimulationID},
+        nations::Nation,
+        settlements::Settlement,
+    },
+};
+
+use super::population::AgeStructure;
+
+/// Living conditions in a settlement for a turn.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SettlementConditions {
+    /// Food supplied over food needed, `1.0` when the settlement is fed.
+    food: f64,
+    /// Share of the inhabitants with access to healthcare, from `0.0` to `1.0`.
+    healthcare: f64,
+    /// Jobs over adults, `1.0` at full employment.
+    jobs: f64,
+    /// From `0.0` (battlefield) to `1.0` (out of harm's way).
+    safety: f64,
+}
+
+impl SettlementConditions {
+    pub fn new(food: f64, healthcare: f64, jobs: f64, safety: f64) -> Self {
+        Self {
+            food: food.max(0.0),
+            healthcare: healthcare.clamp(0.0, 1.0),
+            jobs: jobs.max(0.0),
+            safety: safety.clamp(0.0, 1.0),
+        }
+    }
+
+    pub fn food(&self) -> f64 {
+        self.food
+    }
+
+    pub fn healthcare(&self) -> f64 {
+        self.healthcare
+    }
+
+    pub fn jobs(&self) -> f64 {
+        self.jobs
+    }
+
+    pub fn safety(&self) -> f64 {
+        self.safety
+    }
+
+    /// From `0.0` to `1.0`: food, healthcare and employment combined.
+    pub fn living_standards(&self) -> f64 {
+        (self.food.min(1.0) + self.healthcare + self.jobs.min(1.0)) / 3.0
+    }
+}
+
+/// Demographic figures of a settlement, or of several combined, for a turn.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Demographics {
+    /// After the update.
+    population: AgeStructure,
+    births: u32,
+    deaths: u32,
+    immigrants: u32,
+    emigrants: u32,
+    /// Refugees welcomed from combat zones.
+    refugees_in: u32,
+    /// Inhabitants who fled a combat zone.
+    refugees_out: u32,
+}
+
+impl Demographics {
+    pub fn population(&self) -> &AgeStructure {
+        &self.population
+    }
+
+    pub fn births(&self) -> u32 {
+        self.births
+    }
+
+    pub fn deaths(&self) -> u32 {
+        self.deaths
+    }
+
+    pub fn immigrants(&self) -> u32 {
+        self.immigrants
+    }
+
+    pub fn emigrants(&self) -> u32 {
+        self.emigrants
+    }
+
+    pub fn refugees_in(&self) -> u32 {
+        self.refugees_in
+    }
+
+    pub fn refugees_out(&self) -> u32 {
+        self.refugees_out
+    }
+
+    /// Arrivals minus departures, refugees included.
+    pub fn net_migration(&self) -> i64 {
+        (self.immigrants as i64 + self.refugees_in as i64)
+            - (self.emigrants as i64 + self.refugees_out as i64)
+    }
+
+    fn add(&mut self, other: &Demographics) {
+        self.population.add(&other.population);
+        self.births += other.births;
+        self.deaths += other.deaths;
+        self.immigrants += other.immigrants;
+        self.emigrants += other.emigrants;
+        self.refugees_in += other.refugees_in;
+        self.refugees_out += other.refugees_out;
+    }
+}
+
+/// Demographics of every settlement for a turn.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DemographyReport {
+    /// In the order of the updated settlements.
+    settlements: Vec<(SimulationID, Demographics)>,
+}
+
+impl DemographyReport {
+    pub fn settlement(&self, id: &SimulationID) -> Option<&Demographics> {
+        self.settlements
+            .iter()
+            .find(|(settlement, _)| settlement == id)
+            .map(|(_, demographics)| demographics)
+    }
+
+    /// Demographics of the settlements of a nation combined.
+    pub fn nation(&self, nation: &Nation) -> Demographics {
+        let mut total = Demographics::default();
+        for settlement in nation.settlements() {
+            if let Some(demographics) = self.settlement(settlement.id()) {
+                total.add(demographics);
+            }
+        }
+        total
+    }
+
+    /// Demographics of all the settlements combined.
+    pub fn total(&self) -> Demographics {
+        let mut total = Demographics::default();
+        for (_, demographics) in &self.settlements {
+            total.add(demographics);
+        }
+        total
+    }
+}
+
+/// Rules of the demographics, per turn.
+#[derive(Debug)]
+pub struct DemographyModel {
+    /// Children born per adult.
+    birth_rate: f64,
+    child_mortality: f64,
+    adult_mortality: f64,
+    elder_mortality: f64,
+    /// Share of the children becoming adults.
+    maturation_rate: f64,
+    /// Share of the adults becoming elders.
+    aging_rate: f64,
+    /// Mortality multiplier added per missing share of food.
+    starvation_mortality: f64,
+    /// Share of the mortality prevented by full healthcare coverage.
+    healthcare_effect: f64,
+    /// Mortality multiplier added per missing share of safety.
+    war_mortality: f64,
+    /// Share of the inhabitants leaving per unit of attractiveness gained by moving.
+    migration_rate: f64,
+    /// Distance, in tiles, beyond which migrants do not go.
+    migration_range: f64,
+    /// Share of the inhabitants fleeing per missing share of safety.
+    refugee_rate: f64,
+    /// Safety under which a settlement is a combat zone, its inhabitants fleeing and not welcoming anyone.
+    refugee_threshold: f64,
+    /// Distance, in tiles, within which a combat makes a settlement unsafe.
+    danger_radius: u32,
+    /// People covered by the buildings of a settlement, scaled by their health.
+    ///
+    /// Keys must be the `SimulationID`s of `BuildingTemplate`s.
+    healthcare_buildings: HashMap<SimulationID, f64>,
+}
+
+impl Default for DemographyModel {
+    fn default() -> Self {
+        Self {
+            birth_rate: 0.004,
+            child_mortality: 0.001,
+            adult_mortality: 0.001,
+            elder_mortality: 0.01,
+            maturation_rate: 0.02,
+            aging_rate: 0.005,
+            starvation_mortality: 5.0,
+            healthcare_effect: 0.5,
+            war_mortality: 2.0,
+            migration_rate: 0.02,
+            migration_range: 20.0,
+            refugee_rate: 0.1,
+            refugee_threshold: 0.5,
+            danger_radius: 3,
+            healthcare_buildings: HashMap::new(),
+        }
+    }
+}
+
+impl DemographyModel {
+    pub fn new(birth_rate: f64, migration_rate: f64, refugee_rate: f64) -> Self {
+        assert!(birth_rate >= 0.0);
+        assert!((0.0..=1.0).contains(&migration_rate));
+        assert!((0.0..=1.0).contains(&refugee_rate));
+        Self {
+            birth_rate,
+            migration_rate,
+            refugee_rate,
+            ..Self::default()
+        }
+    }
+
+    pub fn with_healthcare_building(mut self, building: SimulationID, coverage: f64) -> Self {
+        assert!(matches!(building, SimulationID::Abstract(_)));
+        self.healthcare_buildings.insert(building, coverage);
+        self
+    }
+
+    /// Conditions of a settlement: its jobs are the workforce of the buildings of its tile, its healthcare the
+    /// coverage of its healthcare buildings, and its safety falls with the proximity of `combat_zones` (e.g. the
+    /// positions of the combats of the turn).
+    ///
+    /// `food` is the food supplied to the settlement over its needs.
+    pub fn conditions(
+        &self,
+        settlement: &Settlement,
+        map: &HexMap,
+        food: f64,
+        combat_zones: &[CubeCoords],
+    ) -> SettlementConditions {
+        let buildings: Vec<&Building> = map
+            .tile(settlement.position())
+            .into_iter()
+            .flat_map(Building::all_on_tile)
+            .collect();
+        let workforce: f64 = buildings
+            .iter()
+            .map(|building| {
+                building
+                    .template()
+                    .recipes()
+                    .iter()
+                    .map(|recipe| recipe.workforce() as f64)
+                    .sum::<f64>()
+                    * building.efficiency()
+            })
+            .sum();
+        let coverage: f64 = buildings
+            .iter()
+            .filter_map(|building| {
+                self.healthcare_buildings
+                    .get(building.template().id())
+                    .map(|coverage| coverage * building.efficiency())
+            })
+            .sum();
+        let adults = settlement.age_structure().adults();
+        let inhabitants = settlement.inhabitants();
+        let jobs = if adults == 0 {
+            0.0
+        } else {
+            workforce / adults as f64
+        };
+        let healthcare = if inhabitants == 0 {
+            1.0
+        } else {
+            coverage / inhabitants as f64
+        };
+        let safety = combat_zones
+            .iter()
+            .map(|zone| zone.distance_to(*settlement.position()))
+            .filter(|distance| *distance <= self.danger_radius as f64)
+            .map(|distance| distance / (self.danger_radius + 1) as f64)
+            .fold(1.0, f64::min);
+        SettlementConditions::new(food, healthcare, jobs, safety)
+    }
+
+    /// How much a settlement draws migrants.
+    pub fn attractiveness(&self, conditions: &SettlementConditions) -> f64 {
+        (conditions.jobs.min(1.0) + conditions.safety + conditions.living_standards()) / 3.0
+    }
+
+    /// People of an age structure after a turn of births, deaths and aging, along with the births and deaths.
+    pub fn natural_change<R: CoreRandom>(
+        &self,
+        ages: &AgeStructure,
+        conditions: &SettlementConditions,
+        rng: &mut R,
+    ) -> (AgeStructure, u32, u32) {
+        let fed = conditions.food.min(1.0);
+        let mortality = (1.0 + (1.0 - fed) * self.starvation_mortality)
+            * (1.0 - conditions.healthcare * self.healthcare_effect)
+            * (1.0 + (1.0 - conditions.safety) * self.war_mortality);
+        let mut rounded = |people: u32, rate: f64| {
+            let share = people as f64 * rate;
+            let whole = share.floor();
+            let round_up = rng.random_ratio() < share - whole;
+            (whole as u32 + round_up as u32).min(people)
+        };
+
+        let births = rounded(
+            ages.adults(),
+            self.birth_rate * fed * (0.5 + 0.5 * conditions.safety),
+        );
+        let dead_children = rounded(ages.children(), self.child_mortality * mortality);
+        let dead_adults = rounded(ages.adults(), self.adult_mortality * mortality);
+        let dead_elders = rounded(ages.elders(), self.elder_mortality * mortality);
+
+        let children = ages.children() - dead_children;
+        let adults = ages.adults() - dead_adults;
+        let matured = rounded(children, self.maturation_rate);
+        let aged = rounded(adults, self.aging_rate);
+        (
+            AgeStructure::new(
+                children - matured + births,
+                adults - aged + matured,
+                ages.elders() - dead_elders + aged,
+            ),
+            births,
+            dead_children + dead_adults + dead_elders,
+        )
+    }
+
+    /// Called every turn. Update the population of the settlements, then move the migrants and refugees between
+    /// them. The settlements may be ruled by living nations meanwhile.
+    ///
+    /// `conditions` must be the conditions of the settlements, in the same order (see `conditions`).
+    pub fn update<R: CoreRandom>(
+        &self,
+        settlements: &[&Settlement],
+        conditions: &[SettlementConditions],
+        rng: &mut R,
+    ) -> DemographyReport {
+        assert_eq!(settlements.len(), conditions.len());
+        let mut demographics = vec![Demographics::default(); settlements.len()];
+
+        for ((settlement, conditions), demographics) in
+            settlements.iter().zip(conditions).zip(&mut demographics)
+        {
+            for group in settlement.population() {
+                let (ages, births, deaths) =
+                    self.natural_change(&group.age_structure(), conditions, rng);
+                group.set_age_structure(ages);
+                demographics.births += births;
+                demographics.deaths += deaths;
+            }
+        }
+
+        // flows (from, to, people, refugees), computed from the population after the natural change
+        let mut flows: Vec<(usize, usize, u32, bool)> = vec![];
+        let safe = |index: usize| {
+            conditions[index].safety >= self.refugee_threshold
+                && !settlements[index].population().is_empty()
+        };
+        for (from, settlement) in settlements.iter().enumerate() {
+            let inhabitants = settlement.inhabitants() as f64;
+            let position = *settlement.position();
+            let distance = |to: usize| position.distance_to(*settlements[to].position());
+            if conditions[from].safety < self.refugee_threshold {
+                let fleeing =
+                    (inhabitants * self.refugee_rate * (1.0 - conditions[from].safety)).round();
+                let shelter = (0..settlements.len())
+                    .filter(|to| *to != from && safe(*to))
+                    .min_by(|a, b| distance(*a).total_cmp(&distance(*b)));
+                if let Some(to) = shelter {
+                    flows.push((from, to, fleeing as u32, true));
+                }
+                continue;
+            }
+
+            let attractiveness = self.attractiveness(&conditions[from]);
+            let destinations: Vec<(usize, f64, f64)> = (0..settlements.len())
+                .filter(|to| *to != from && safe(*to) && distance(*to) <= self.migration_range)
+                .map(|to| {
+                    let gain = self.attractiveness(&conditions[to]) - attractiveness;
+                    (to, gain, gain / (1.0 + distance(to) / self.migration_range))
+                })
+                .filter(|(_, gain, _)| *gain > 0.0)
+                .collect();
+            let Some(best_gain) = destinations
+                .iter()
+                .map(|(_, gain, _)| *gain)
+                .reduce(f64::max)
+            else {
+                continue;
+            };
+            let migrants = inhabitants * self.migration_rate * best_gain;
+            let total_weight: f64 = destinations.iter().map(|(_, _, weight)| weight).sum();
+            for (to, _, weight) in destinations {
+                flows.push((
+                    from,
+                    to,
+                    (migrants * weight / total_weight).round() as u32,
+                    false,
+                ));
+            }
+        }
+
+        for (from, to, people, refugees) in flows {
+            let moving = settlements[from].emigrate(people);
+            let moved = moving.total();
+            if moved == 0 || !settlements[to].immigrate(&moving) {
+                settlements[from].immigrate(&moving);
+                continue;
+            }
+            if refugees {
+                demographics[from].refugees_out += moved;
+                demographics[to].refugees_in += moved;
+            } else {
+                demographics[from].emigrants += moved;
+                demographics[to].immigrants += moved;
+            }
+        }
+
+        DemographyReport {
+            settlements: settlements
+                .iter()
+                .zip(demographics)
+                .map(|(settlement, mut demographics)| {
+                    demographics.population = settlement.age_structure();
+                    (settlement.id().clone(), demographics)
+                })
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::{
+        hex_map::{
+            coordinates::CubeCoords, layers::natural::HexMapTerrain, tile::HexMapTile, HexMap,
+            HexMapStorage,
+        },
+        prng::{RandomGenerator, TestingHarnessRandomGenerator},
+        simulation::{
+            ids::SimulationID,
+            nations::Nation,
+            people::population::{AgeStructure, PopulationGroup},
+            settlements::Settlement,
+            testing::build_mock_leader,
+        },
+    };
+
+    use super::{DemographyModel, SettlementConditions};
+
+    fn build_mock_settlement(id: u32, q: i16, size: u32) -> Settlement {
+        let mut settlement = Settlement::new(
+            SimulationID::new_map_entity_id(id),
+            id.to_string(),
+            CubeCoords::from_axial_coords(q, 0),
+        );
+        settlement.add_population_group(PopulationGroup::new(
+            SimulationID::new_entity_id(id),
+            size,
+            HashMap::new(),
+        ));
+        settlement
+    }
+
+    #[test]
+    fn test_demography_update() {
+        let mut tiles = HexMapStorage::new();
+        for q in 0..=10 {
+            tiles.insert(
+                CubeCoords::from_axial_coords(q, 0),
+                HexMapTile::from_terrain(0, HexMapTerrain::Plains),
+            );
+        }
+        let map = HexMap::from_tiles(tiles);
+        let model = DemographyModel::default();
+        let settlements = [
+            build_mock_settlement(1, 0, 10000),
+            build_mock_settlement(2, 5, 10000),
+            build_mock_settlement(3, 10, 10000),
+        ];
+        assert_eq!(
+            settlements[0].age_structure(),
+            AgeStructure::new(2500, 6000, 1500)
+        );
+
+        // people leave and arrive across all ages, drafts and discharges only touch the adults
+        let group = PopulationGroup::new(SimulationID::new_entity_id(9), 1000, HashMap::new());
+        assert_eq!(group.shrink(100), 100);
+        assert_eq!(group.age_structure(), AgeStructure::new(225, 540, 135));
+        group.grow(100);
+        assert_eq!(group.age_structure(), AgeStructure::new(250, 600, 150));
+        assert_eq!(group.shrink_adults(100), 100);
+        group.grow_adults(50);
+        assert_eq!(group.age_structure(), AgeStructure::new(250, 550, 150));
+
+        // a battle next to the first settlement, the third one is starving
+        let combats = [CubeCoords::from_axial_coords(1, 0)];
+        let conditions: Vec<SettlementConditions> = settlements
+            .iter()
+            .zip([1.0, 1.0, 0.5])
+            .map(|(settlement, food)| model.conditions(settlement, &map, food, &combats))
+            .collect();
+        assert_eq!(conditions[0].safety(), 0.25);
+        assert_eq!(conditions[1].safety(), 1.0);
+
+        // fed and safe: 24 births, 2 + 6 + 15 deaths, halves rounded down
+        let mut rng = TestingHarnessRandomGenerator::new(vec![500]);
+        let fed_and_safe = SettlementConditions::new(1.0, 0.0, 0.0, 1.0);
+        let (_, births, deaths) = model.natural_change(
+            &AgeStructure::new(2500, 6000, 1500),
+            &fed_and_safe,
+            &mut rng,
+        );
+        assert_eq!((births, deaths), (24, 23));
+
+        // small groups have 0.4 births and 0.4 elder deaths per turn, which add up over time
+        let mut rng = RandomGenerator::new(42);
+        let (mut ages, mut births, mut deaths) = (AgeStructure::new(0, 100, 40), 0, 0);
+        for _ in 0..100 {
+            let (next, born, died) = model.natural_change(&ages, &fed_and_safe, &mut rng);
+            (ages, births, deaths) = (next, births + born, deaths + died);
+        }
+        assert!((20..=60).contains(&births));
+        assert!(deaths > 20);
+
+        // the nations keep ruling the settlements while their population changes
+        let mut nation = Nation::new(
+            SimulationID::new_abstract_id("a"),
+            "a".into(),
+            build_mock_leader(1, vec![]),
+            &settlements[0],
+        );
+        nation.annex_settlement(&settlements[1]);
+        let report = model.update(
+            &settlements.iter().collect::<Vec<_>>(),
+            &conditions,
+            &mut rng,
+        );
+        let [first, second, third] = [1, 2, 3].map(|id| {
+            report
+                .settlement(&SimulationID::new_map_entity_id(id))
+                .unwrap()
+        });
+        // refugees flee to the nearest safe settlement, the hungry leave for it too
+        assert_eq!(first.refugees_out(), second.refugees_in());
+        assert!(first.refugees_out() > 0 && second.refugees_in() > 0);
+        assert!(third.emigrants() > 0 && third.emigrants() == second.immigrants());
+        assert!(first.deaths() > second.deaths() && third.deaths() > second.deaths());
+        assert!(third.births() < second.births());
+        assert_eq!(
+            settlements[1].inhabitants(),
+            second.population().total() as u64
+        );
+
+        let national = report.nation(&nation);
+        assert_eq!(national.refugees_in(), national.refugees_out());
+        assert_eq!(national.net_migration(), second.immigrants() as i64);
+        assert_eq!(report.total().net_migration(), 0);
+    }
+}
@@ -34,6 +34,17 @@ pub struct IndividualIDCard {
     name: IndividualName,
 }
 
+impl IndividualIDCard {
+    pub fn new(id: SimulationID, name: IndividualName) -> Self {
+        assert!(matches!(id, SimulationID::EntityID(_)));
+        Self { id, name }
+    }
+
+    pub fn name(&self) -> &IndividualName {
+        &self.name
+    }
+}
+
 /// An ancestor of a Leader
 ///
 /// Leader present at game start will become an Ancestor if it dies (this may be rare, depending on turns scale).
@@ -86,6 +97,29 @@ pub struct Leader {
     upkeep: MaintenanceCosts,
 }
 
+impl Leader {
+    pub fn new(id_card: IndividualIDCard, traits: Vec<Trait>, upkeep: MaintenanceCosts) -> Self {
+        Self {
+            id_card,
+            lineage: None,
+            traits,
+            upkeep,
+        }
+    }
+
+    pub fn id_card(&self) -> &IndividualIDCard {
+        &self.id_card
+    }
+
+    pub fn traits(&self) -> &[Trait] {
+        &self.traits
+    }
+
+    pub fn upkeep(&self) -> &MaintenanceCosts {
+        &self.upkeep
+    }
+}
+
 impl WithSimulationID for Leader {
     fn id(&self) -> &SimulationID {
         &self.id_card.id
@@ -136,26 +170,61 @@ pub struct TraitPhysical {
     id: SimulationID,
 }
 
+impl TraitPhysical {
+    pub fn new(id: SimulationID) -> Self {
+        assert!(matches!(id, SimulationID::Abstract(_)));
+        Self { id }
+    }
+}
+
 #[derive(Debug)]
 pub struct TraitPersonality {
     /// Must be `SimulationID::SimulationAbstractID.
     id: SimulationID,
 }
 
+impl TraitPersonality {
+    pub fn new(id: SimulationID) -> Self {
+        assert!(matches!(id, SimulationID::Abstract(_)));
+        Self { id }
+    }
+}
+
 #[derive(Debug)]
 pub struct TraitAbility {
     /// Must be `SimulationID::SimulationAbstractID.
     id: SimulationID,
 }
 
+impl TraitAbility {
+    pub fn new(id: SimulationID) -> Self {
+        assert!(matches!(id, SimulationID::Abstract(_)));
+        Self { id }
+    }
+}
+
 #[derive(Debug)]
 pub struct TraitLifestyle {
     /// Must be `SimulationID::SimulationAbstractID.
     id: SimulationID,
 }
 
+impl TraitLifestyle {
+    pub fn new(id: SimulationID) -> Self {
+        assert!(matches!(id, SimulationID::Abstract(_)));
+        Self { id }
+    }
+}
+
 #[derive(Debug)]
 pub struct TraitCustom {
     /// Must be `SimulationID::SimulationAbstractID.
     id: SimulationID,
 }
+
+impl TraitCustom {
+    pub fn new(id: SimulationID) -> Self {
+        assert!(matches!(id, SimulationID::Abstract(_)));
+        Self { id }
+    }
+}
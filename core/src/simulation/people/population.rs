@@ -1,18 +1,203 @@
 //! People simulation at a group aggregate scale.
 
+use std::cell::Cell;
+
 use crate::simulation::{
     economy::MaintenanceCosts,
     ids::{SimulationID, WithSimulationID},
 };
 
+/// Loyalty of a population group fully devoted to the nation ruling it.
+pub const POPULATION_MAX_LOYALTY: u16 = 100;
+
+/// Share of children in a population group created without an age structure.
+pub const DEFAULT_CHILDREN_RATIO: f64 = 0.25;
+/// Share of elders in a population group created without an age structure.
+pub const DEFAULT_ELDERS_RATIO: f64 = 0.15;
+
+/// Number of people of each age cohort. Only adults work and can be drafted.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct AgeStructure {
+    children: u32,
+    adults: u32,
+    elders: u32,
+}
+
+impl AgeStructure {
+    pub fn new(children: u32, adults: u32, elders: u32) -> Self {
+        Self {
+            children,
+            adults,
+            elders,
+        }
+    }
+
+    /// Split people along `DEFAULT_CHILDREN_RATIO` and `DEFAULT_ELDERS_RATIO`.
+    pub fn from_size(size: u32) -> Self {
+        let children = (size as f64 * DEFAULT_CHILDREN_RATIO).round() as u32;
+        let elders = (size as f64 * DEFAULT_ELDERS_RATIO).round() as u32;
+        Self::new(children, size - children - elders, elders)
+    }
+
+    pub fn children(&self) -> u32 {
+        self.children
+    }
+
+    pub fn adults(&self) -> u32 {
+        self.adults
+    }
+
+    pub fn elders(&self) -> u32 {
+        self.elders
+    }
+
+    pub fn total(&self) -> u32 {
+        self.children
+            .saturating_add(self.adults)
+            .saturating_add(self.elders)
+    }
+
+    pub fn add(&mut self, other: &AgeStructure) {
+        self.children = self.children.saturating_add(other.children);
+        self.adults = self.adults.saturating_add(other.adults);
+        self.elders = self.elders.saturating_add(other.elders);
+    }
+
+    /// Take away people, proportionally to the cohorts. Return who was actually taken.
+    pub fn take_share(&mut self, amount: u32) -> AgeStructure {
+        let total = self.total();
+        if total == 0 {
+            return AgeStructure::default();
+        }
+        let amount = amount.min(total);
+        let share = |cohort: u32| (cohort as u64 * amount as u64 / total as u64) as u32;
+        let mut taken =
+            AgeStructure::new(share(self.children), share(self.adults), share(self.elders));
+        // rounding leftovers, from the adults first
+        let mut left = amount - taken.total();
+        for (cohort, taken) in [
+            (&mut self.adults, &mut taken.adults),
+            (&mut self.children, &mut taken.children),
+            (&mut self.elders, &mut taken.elders),
+        ] {
+            let extra = left.min(*cohort - *taken);
+            *taken += extra;
+            left -= extra;
+        }
+        self.children -= taken.children;
+        self.adults -= taken.adults;
+        self.elders -= taken.elders;
+        taken
+    }
+}
+
 /// A population group is an abstraction to represent the collective specificities and impact
 /// (eg. goods consumption, or voting tendencies).
+///
+/// Its people and loyalty change through shared references, since nations only borrow the settlements they rule.
 #[derive(Debug)]
 pub struct PopulationGroup {
     /// Must be `SimulationID::EntityID`.
     id: SimulationID,
-    size: u32,
+    ages: Cell<AgeStructure>,
     upkeep: MaintenanceCosts,
+    /// Devotion to the nation ruling the group, from `0` to `POPULATION_MAX_LOYALTY`.
+    loyalty: Cell<u16>,
+}
+
+impl PopulationGroup {
+    /// The people are split along the default age structure (see `AgeStructure::from_size`).
+    pub fn new(id: SimulationID, size: u32, upkeep: MaintenanceCosts) -> Self {
+        assert!(matches!(id, SimulationID::EntityID(_)));
+        Self {
+            id,
+            ages: Cell::new(AgeStructure::from_size(size)),
+            upkeep,
+            loyalty: Cell::new(POPULATION_MAX_LOYALTY),
+        }
+    }
+
+    pub fn with_age_structure(self, ages: AgeStructure) -> Self {
+        self.ages.set(ages);
+        self
+    }
+
+    pub fn size(&self) -> u32 {
+        self.ages.get().total()
+    }
+
+    pub fn age_structure(&self) -> AgeStructure {
+        self.ages.get()
+    }
+
+    /// Replace the age structure of the group, see `demography`.
+    pub fn set_age_structure(&self, ages: AgeStructure) {
+        self.ages.set(ages);
+    }
+
+    /// Take people away from the group, proportionally to the age cohorts. Return how many were actually taken.
+    pub fn shrink(&self, amount: u32) -> u32 {
+        let mut ages = self.ages.get();
+        let taken = ages.take_share(amount);
+        self.ages.set(ages);
+        taken.total()
+    }
+
+    /// Add people to the group, proportionally to the age cohorts, or along the default age structure if the group
+    /// is empty.
+    pub fn grow(&self, amount: u32) {
+        let mut ages = self.ages.get();
+        let total = ages.total();
+        let added = if total == 0 {
+            AgeStructure::from_size(amount)
+        } else {
+            let share = |cohort: u32| (cohort as u64 * amount as u64 / total as u64) as u32;
+            let (children, elders) = (share(ages.children), share(ages.elders));
+            AgeStructure::new(children, amount - children - elders, elders)
+        };
+        ages.add(&added);
+        self.ages.set(ages);
+    }
+
+    /// Take adults away from the group (e.g. drafted). Return how many were actually taken.
+    pub fn shrink_adults(&self, amount: u32) -> u32 {
+        let mut ages = self.ages.get();
+        let taken = amount.min(ages.adults);
+        ages.adults -= taken;
+        self.ages.set(ages);
+        taken
+    }
+
+    /// Add adults to the group (e.g. discharged soldiers).
+    pub fn grow_adults(&self, amount: u32) {
+        let mut ages = self.ages.get();
+        ages.adults = ages.adults.saturating_add(amount);
+        self.ages.set(ages);
+    }
+
+    pub fn upkeep(&self) -> &MaintenanceCosts {
+        &self.upkeep
+    }
+
+    pub fn loyalty(&self) -> u16 {
+        self.loyalty.get()
+    }
+
+    /// Return the loyalty actually lost.
+    pub fn lower_loyalty(&self, amount: u16) -> u16 {
+        let lost = amount.min(self.loyalty.get());
+        self.loyalty.set(self.loyalty.get() - lost);
+        lost
+    }
+
+    pub fn raise_loyalty(&self, amount: u16) {
+        self.loyalty.set(
+            self.loyalty
+                .get()
+                .saturating_add(amount)
+                .min(POPULATION_MAX_LOYALTY),
+        );
+    }
 }
 
 impl WithSimulationID for PopulationGroup {
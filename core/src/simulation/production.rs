@@ -0,0 +1,479 @@
+//! Production chains: resolution, every turn, of the `BuildingRecipe`s of a group of buildings sharing a stockpile.
+//!
+//! Buildings are resolved in dependency order, so that the outputs of a building are available to the buildings
+//! consuming them during the same turn. When inputs or workers are short, recipes run partially and the limiting
+//! factor is reported as the bottleneck of the building. Damaged buildings produce proportionally to their health,
+//! and adjacency bonuses scale the outputs.
+//!
+//! Electricity cannot be carried over from a turn to the next. Recipes producing it add it to the stockpile, for the
+//! buildings resolved after them during the same turn, and what is left at the end of the resolution is taken out of
+//! the stockpile and reported as the surplus of its generators, which feeds their power grids (see
+//! `economy::power_grid::record_surplus_electricity`). Recipes needing electricity draw it from the stockpile first,
+//! then from the power supply of their building.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use super::{
+    buildings::{adjacency::AdjacencyTarget, Building},
+    ids::{SimulationID, WithSimulationID},
+    resources::{Resource, ResourceDataStorage, ResourceDataStore, ResourceQuantity},
+};
+
+/// What prevented a recipe from running at full throughput.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ProductionBottleneck {
+    /// The building is damaged.
+    Damaged,
+    /// Not enough workers were available.
+    Workforce,
+    /// Not enough electricity was supplied by the power grid.
+    Power,
+    /// Not enough of the given input resource was available.
+    Input(Resource),
+}
+
+/// Outcome of a `BuildingRecipe` for a turn.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RecipeProductionReport {
+    runs: u16,
+    max_runs: u16,
+    bottleneck: Option<ProductionBottleneck>,
+}
+
+impl RecipeProductionReport {
+    pub fn runs(&self) -> u16 {
+        self.runs
+    }
+
+    pub fn max_runs(&self) -> u16 {
+        self.max_runs
+    }
+
+    pub fn bottleneck(&self) -> Option<ProductionBottleneck> {
+        self.bottleneck
+    }
+}
+
+/// Outcome of the production of a building for a turn, with one entry per recipe (in template order).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BuildingProductionReport {
+    building: SimulationID,
+    recipes: Vec<RecipeProductionReport>,
+    /// Electricity generated by the recipes and not consumed during the turn.
+    surplus_electricity: u32,
+}
+
+impl BuildingProductionReport {
+    pub fn building(&self) -> &SimulationID {
+        &self.building
+    }
+
+    pub fn recipes(&self) -> &[RecipeProductionReport] {
+        &self.recipes
+    }
+
+    pub fn surplus_electricity(&self) -> u32 {
+        self.surplus_electricity
+    }
+
+    /// The bottlenecks of all the recipes of the building, without duplicates.
+    pub fn bottlenecks(&self) -> Vec<ProductionBottleneck> {
+        let mut bottlenecks = vec![];
+        for bottleneck in self.recipes.iter().filter_map(|recipe| recipe.bottleneck) {
+            if !bottlenecks.contains(&bottleneck) {
+                bottlenecks.push(bottleneck);
+            }
+        }
+        bottlenecks
+    }
+}
+
+/// Order the buildings so that producers come before the consumers of their outputs.
+///
+/// Buildings involved in a dependency cycle keep their relative order, after all the others.
+pub fn dependency_order(buildings: &[&Building]) -> Vec<usize> {
+    let produced: Vec<HashSet<Resource>> = buildings
+        .iter()
+        .map(|building| {
+            let template = building.template();
+            template
+                .production()
+                .keys()
+                .chain(template.recipes().iter().flat_map(|r| r.outputs().keys()))
+                .copied()
+                .collect()
+        })
+        .collect();
+    let consumed: Vec<HashSet<Resource>> = buildings
+        .iter()
+        .map(|building| {
+            building
+                .template()
+                .recipes()
+                .iter()
+                .flat_map(|r| r.inputs().keys())
+                .copied()
+                .collect()
+        })
+        .collect();
+
+    let mut dependents: HashMap<usize, Vec<usize>> = HashMap::new();
+    let mut dependencies_count = vec![0usize; buildings.len()];
+    for (producer, outputs) in produced.iter().enumerate() {
+        for (consumer, inputs) in consumed.iter().enumerate() {
+            if producer != consumer && !outputs.is_disjoint(inputs) {
+                dependents.entry(producer).or_default().push(consumer);
+                dependencies_count[consumer] += 1;
+            }
+        }
+    }
+
+    let mut order = Vec::with_capacity(buildings.len());
+    let mut ready: VecDeque<usize> = (0..buildings.len())
+        .filter(|&index| dependencies_count[index] == 0)
+        .collect();
+    while let Some(index) = ready.pop_front() {
+        order.push(index);
+        for &dependent in dependents.get(&index).into_iter().flatten() {
+            dependencies_count[dependent] -= 1;
+            if dependencies_count[dependent] == 0 {
+                ready.push_back(dependent);
+            }
+        }
+    }
+    let cyclic: Vec<usize> = (0..buildings.len())
+        .filter(|index| !order.contains(index))
+        .collect();
+    order.extend(cyclic);
+    order
+}
+
+/// Called every turn. Run the production of the given buildings, drawing from and adding to a shared stockpile.
+///
+/// `workforce` is the number of workers available to all the buildings, allocated in dependency order to the runs
+/// actually executed.
+///
+/// Return one report per building, in the same order as `buildings`.
+pub fn resolve_production(
+    buildings: &[&Building],
+    stockpile: &mut ResourceDataStorage,
+    workforce: u32,
+) -> Vec<BuildingProductionReport> {
+    for building in buildings {
+        for (resource, quantity) in building.production() {
+            if resource == Resource::Electricity {
+                continue;
+            }
+            stockpile.replenish(resource, quantity);
+        }
+    }
+
+    let mut reports: Vec<Option<BuildingProductionReport>> = vec![None; buildings.len()];
+    let mut generated = vec![0u32; buildings.len()];
+    let mut remaining_workforce = workforce;
+    for index in dependency_order(buildings) {
+        let building = buildings[index];
+        let mut recipes = vec![];
+        for recipe in building.template().recipes() {
+            let mut runs = (recipe.throughput() as f64 * building.efficiency()) as u16;
+            let mut bottleneck = None;
+            if runs < recipe.throughput() {
+                bottleneck = Some(ProductionBottleneck::Damaged);
+            }
+
+            let workers = recipe.workforce().min(remaining_workforce);
+            if workers < recipe.workforce() {
+                let staffed_runs = (recipe.throughput() as u64 * workers as u64
+                    / recipe.workforce() as u64) as u16;
+                if staffed_runs < runs {
+                    runs = staffed_runs;
+                    bottleneck = Some(ProductionBottleneck::Workforce);
+                }
+            }
+
+            let mut generated_runs = 0;
+            if let Some(&quantity) = recipe
+                .inputs()
+                .get(&Resource::Electricity)
+                .filter(|quantity| **quantity > 0)
+            {
+                generated_runs = stockpile.quantity_of(Resource::Electricity) / quantity;
+                let powered_runs = generated_runs
+                    .saturating_add((recipe.throughput() as f64 * building.power_supply()) as u16);
+                if powered_runs < runs {
+                    runs = powered_runs;
+                    bottleneck = Some(ProductionBottleneck::Power);
+                }
+            }
+
+            let mut inputs: Vec<_> = recipe.inputs().iter().collect();
+            inputs.sort();
+            for (resource, quantity) in inputs {
+                if *quantity == 0 || *resource == Resource::Electricity {
+                    continue;
+                }
+                let affordable = stockpile.quantity_of(*resource) / quantity;
+                if affordable < runs {
+                    runs = affordable;
+                    bottleneck = Some(ProductionBottleneck::Input(*resource));
+                }
+            }
+
+            // only the workers of the runs actually executed are busy
+            remaining_workforce -= if recipe.throughput() == 0 {
+                0
+            } else {
+                (recipe.workforce() as u64 * runs as u64).div_ceil(recipe.throughput() as u64)
+                    as u32
+            }
+            .min(workers);
+
+            for (resource, quantity) in recipe.inputs() {
+                // the power grid supplies the electricity not generated during the turn
+                let consumed = if *resource == Resource::Electricity {
+                    scaled(*quantity, runs.min(generated_runs))
+                } else {
+                    scaled(*quantity, runs)
+                };
+                if consumed > 0 {
+                    stockpile.consume(*resource, consumed);
+                }
+            }
+            let bonus = building
+                .adjacency_bonus()
+                .multiplier(AdjacencyTarget::Production);
+            for (resource, quantity) in recipe.outputs() {
+                let produced = scaled_by(*quantity, runs, bonus);
+                if *resource == Resource::Electricity {
+                    generated[index] += produced as u32;
+                }
+                stockpile.replenish(*resource, produced);
+            }
+
+            recipes.push(RecipeProductionReport {
+                runs,
+                max_runs: recipe.throughput(),
+                bottleneck,
+            });
+        }
+        reports[index] = Some(BuildingProductionReport {
+            building: building.id().clone(),
+            recipes,
+            surplus_electricity: 0,
+        });
+    }
+
+    // the electricity left is shared between its generators, proportionally to their generation
+    let leftover = stockpile.quantity_of(Resource::Electricity);
+    stockpile.consume(Resource::Electricity, leftover);
+    let total: u32 = generated.iter().sum();
+    let surplus = (leftover as u32).min(total);
+    let mut remaining = surplus;
+    let mut reports: Vec<BuildingProductionReport> = reports.into_iter().flatten().collect();
+    for (report, generated) in reports.iter_mut().zip(&generated) {
+        if *generated > 0 {
+            report.surplus_electricity = (surplus as u64 * *generated as u64 / total as u64) as u32;
+            remaining -= report.surplus_electricity;
+        }
+    }
+    // rounding leftovers, fewer than the generators, to the first ones
+    for (report, _) in reports
+        .iter_mut()
+        .zip(&generated)
+        .filter(|(_, generated)| **generated > 0)
+        .take(remaining as usize)
+    {
+        report.surplus_electricity += 1;
+    }
+    reports
+}
+
+fn scaled(quantity: ResourceQuantity, runs: u16) -> ResourceQuantity {
+    (quantity as u32 * runs as u32).min(ResourceQuantity::MAX as u32) as ResourceQuantity
+}
+
+/// Total quantity over several runs, scaled by a (positive) multiplier and rounded once.
+fn scaled_by(quantity: ResourceQuantity, runs: u16, multiplier: f64) -> ResourceQuantity {
+    (quantity as f64 * runs as f64 * multiplier)
+        .round()
+        .min(ResourceQuantity::MAX as f64) as ResourceQuantity
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::{
+        hex_map::{
+            coordinates::CubeCoords, layers::natural::HexMapTerrain, tile::HexMapTile, HexMap,
+            HexMapStorage,
+        },
+        simulation::{
+            buildings::{
+                adjacency::{AdjacencyBonus, AdjacencyCondition, AdjacencyRule, AdjacencyTarget},
+                Building, BuildingRecipe, BuildingTemplate,
+            },
+            ids::SimulationID,
+            resources::{Resource, ResourceDataStorage, ResourceDataStore},
+        },
+    };
+
+    use super::{dependency_order, resolve_production, ProductionBottleneck};
+
+    fn build_mock_templates() -> (BuildingTemplate, BuildingTemplate, BuildingTemplate) {
+        let mine = BuildingTemplate::new(
+            SimulationID::new_abstract_id("mine"),
+            "mine".into(),
+            HashMap::new(),
+            HashMap::new(),
+            HashMap::from([(Resource::Metals, 4)]),
+        );
+        let power_plant = BuildingTemplate::new(
+            SimulationID::new_abstract_id("power_plant"),
+            "power_plant".into(),
+            HashMap::new(),
+            HashMap::new(),
+            HashMap::new(),
+        )
+        .with_recipe(BuildingRecipe::new(
+            HashMap::from([(Resource::Oil, 1)]),
+            HashMap::from([(Resource::Electricity, 5)]),
+            4,
+            100,
+        ));
+        let steel_mill = BuildingTemplate::new(
+            SimulationID::new_abstract_id("steel_mill"),
+            "steel_mill".into(),
+            HashMap::new(),
+            HashMap::new(),
+            HashMap::new(),
+        )
+        .with_recipe(BuildingRecipe::new(
+            HashMap::from([(Resource::Metals, 2), (Resource::Electricity, 5)]),
+            HashMap::from([(Resource::Credits, 10)]),
+            5,
+            200,
+        ));
+        (mine, power_plant, steel_mill)
+    }
+
+    #[test]
+    fn test_production_dependency_order() {
+        let (mine, power_plant, steel_mill) = build_mock_templates();
+        let steel_mill = Building::new(SimulationID::new_map_entity_id(1), &steel_mill, 100);
+        let power_plant = Building::new(SimulationID::new_map_entity_id(2), &power_plant, 100);
+        let mine = Building::new(SimulationID::new_map_entity_id(3), &mine, 100);
+
+        let order = dependency_order(&[&steel_mill, &power_plant, &mine]);
+        assert_eq!(order.last(), Some(&0));
+    }
+
+    #[test]
+    fn test_production_resolution() {
+        let (mine, power_plant, steel_mill) = build_mock_templates();
+        let steel_mill = Building::new(SimulationID::new_map_entity_id(1), &steel_mill, 100);
+        let power_plant = Building::new(SimulationID::new_map_entity_id(2), &power_plant, 100);
+        let mine = Building::new(SimulationID::new_map_entity_id(3), &mine, 100);
+        let buildings = [&steel_mill, &power_plant, &mine];
+
+        // the power plant lacks oil: only 3 runs for 15 electricity, while the steel mill lacks metals
+        let mut stockpile = ResourceDataStorage::new();
+        stockpile.replenish(Resource::Oil, 3);
+        let reports = resolve_production(&buildings, &mut stockpile, 1000);
+        assert_eq!(reports[1].recipes()[0].runs(), 3);
+        assert_eq!(
+            reports[1].bottlenecks(),
+            vec![ProductionBottleneck::Input(Resource::Oil)]
+        );
+        assert_eq!(reports[0].recipes()[0].runs(), 2);
+        assert_eq!(
+            reports[0].bottlenecks(),
+            vec![ProductionBottleneck::Input(Resource::Metals)]
+        );
+        assert!(reports[2].recipes().is_empty());
+        assert_eq!(stockpile.quantity_of(Resource::Credits), 20);
+        // the electricity left cannot be kept: it goes to the power grid
+        assert_eq!(stockpile.quantity_of(Resource::Electricity), 0);
+        assert_eq!(reports[1].surplus_electricity(), 5);
+        assert_eq!(reports[0].surplus_electricity(), 0);
+
+        // not enough workers for the steel mill
+        let mut stockpile = ResourceDataStorage::new();
+        stockpile.replenish(Resource::Oil, 10);
+        stockpile.replenish(Resource::Metals, 10);
+        let reports = resolve_production(&buildings, &mut stockpile, 200);
+        assert_eq!(reports[1].recipes()[0].runs(), 4);
+        assert_eq!(reports[0].recipes()[0].runs(), 2);
+        assert_eq!(
+            reports[0].bottlenecks(),
+            vec![ProductionBottleneck::Workforce]
+        );
+    }
+
+    #[test]
+    fn test_production_workforce_and_power() {
+        let (_, power_plant, steel_mill) = build_mock_templates();
+        let mut steel_mill = Building::new(SimulationID::new_map_entity_id(1), &steel_mill, 100);
+        let power_plant = Building::new(SimulationID::new_map_entity_id(2), &power_plant, 100);
+        let buildings = [&steel_mill, &power_plant];
+
+        // the power plant lacks oil: its idle workers staff the steel mill instead
+        let mut stockpile = ResourceDataStorage::new();
+        stockpile.replenish(Resource::Oil, 2);
+        stockpile.replenish(Resource::Metals, 10);
+        let reports = resolve_production(&buildings, &mut stockpile, 250);
+        assert_eq!(reports[1].recipes()[0].runs(), 2);
+        assert_eq!(reports[0].recipes()[0].runs(), 5);
+        assert!(reports[0].bottlenecks().is_empty());
+        // the electricity of the power plant came first, the power grid supplied the rest
+        assert_eq!(stockpile.quantity_of(Resource::Electricity), 0);
+
+        // brownout of the steel mill, without a power plant
+        steel_mill.set_power_supply(0.5);
+        let mut stockpile = ResourceDataStorage::new();
+        stockpile.replenish(Resource::Metals, 10);
+        let reports = resolve_production(&[&steel_mill], &mut stockpile, 1000);
+        assert_eq!(reports[0].recipes()[0].runs(), 2);
+        assert_eq!(reports[0].bottlenecks(), vec![ProductionBottleneck::Power]);
+    }
+
+    #[test]
+    fn test_production_adjacency_bonus() {
+        let foundry = BuildingTemplate::new(
+            SimulationID::new_abstract_id("foundry"),
+            "foundry".into(),
+            HashMap::new(),
+            HashMap::new(),
+            HashMap::new(),
+        )
+        .with_recipe(BuildingRecipe::new(
+            HashMap::new(),
+            HashMap::from([(Resource::Metals, 2)]),
+            4,
+            0,
+        ))
+        .with_adjacency_rule(AdjacencyRule::new(
+            SimulationID::new_abstract_id("foundry_hills"),
+            AdjacencyCondition::Terrain(HexMapTerrain::Hills),
+            1,
+            AdjacencyTarget::Production,
+            0.1,
+            None,
+        ));
+        let center = CubeCoords::from_axial_coords(0, 0);
+        let mut tiles = HexMapStorage::new();
+        tiles.insert(center, HexMapTile::from_terrain(0, HexMapTerrain::Plains));
+        tiles.insert(
+            CubeCoords::from_axial_coords(1, 0),
+            HexMapTile::from_terrain(0, HexMapTerrain::Hills),
+        );
+        let map = HexMap::from_tiles(tiles);
+        let mut foundry = Building::new(SimulationID::new_map_entity_id(1), &foundry, 100);
+        foundry.set_adjacency_bonus(AdjacencyBonus::compute(&map, &center, &foundry));
+
+        // 4 runs of 2 metals, +10%: 8.8 rounded once, not 2.2 rounded every run
+        let mut stockpile = ResourceDataStorage::new();
+        resolve_production(&[&foundry], &mut stockpile, 0);
+        assert_eq!(stockpile.quantity_of(Resource::Metals), 9);
+    }
+}
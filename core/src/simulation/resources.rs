@@ -1,12 +1,14 @@
 use std::collections::HashMap;
 
+use serde::Deserialize;
+
 use crate::hex_map::layers::natural::HexMapTileDeposit;
 
 use super::ids::{SimulationID, WithSimulationID};
 
 pub type ResourceQuantity = u16;
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize)]
 pub enum Resource {
     Credits,
     Water,
@@ -15,6 +17,7 @@ pub enum Resource {
     Metals,
     Oil,
     Uranium,
+    Ammunition,
     // TODO: CustomResource for scripts
 }
 
@@ -27,18 +30,33 @@ pub struct ResourceData {
     depletion: Option<ResourceQuantity>,
 }
 
+impl ResourceData {
+    pub fn quantity(&self) -> ResourceQuantity {
+        self.quantity
+    }
+}
+
 pub type ResourceDataStorage = HashMap<Resource, ResourceData>;
 
 pub trait ResourceDataStore {
+    /// Currently stocked quantity of a resource, `0` if never stocked.
+    fn quantity_of(&self, resource: Resource) -> ResourceQuantity;
     fn set_income(&mut self, resource: Resource, income: ResourceQuantity) -> bool;
     fn set_depletion(&mut self, resource: Resource, depletion: ResourceQuantity) -> bool;
     fn replenish(&mut self, resource: Resource, amount: ResourceQuantity);
     fn consume(&mut self, resource: Resource, amount: ResourceQuantity) -> bool;
+    /// Consume all the given resources if they can all be afforded, otherwise consume nothing and return the first missing one.
+    fn consume_all(&mut self, costs: &HashMap<Resource, ResourceQuantity>) -> Result<(), Resource>;
     /// Called every turn. If a resource lacks the quantity compared to its per-turn depletion, return its key.
     fn update(&mut self) -> Option<Resource>;
 }
 
 impl ResourceDataStore for ResourceDataStorage {
+    fn quantity_of(&self, resource: Resource) -> ResourceQuantity {
+        self.get(&resource)
+            .map_or(0, |resource_datum| resource_datum.quantity)
+    }
+
     fn set_income(&mut self, resource: Resource, income: ResourceQuantity) -> bool {
         if let Some(resource_datum) = self.get_mut(&resource) {
             resource_datum.income = Some(income);
@@ -59,7 +77,7 @@ impl ResourceDataStore for ResourceDataStorage {
 
     fn replenish(&mut self, resource: Resource, amount: ResourceQuantity) {
         if let Some(resource_datum) = self.get_mut(&resource) {
-            resource_datum.quantity += amount;
+            resource_datum.quantity = resource_datum.quantity.saturating_add(amount);
         } else {
             self.insert(
                 resource,
@@ -74,7 +92,7 @@ impl ResourceDataStore for ResourceDataStorage {
 
     fn consume(&mut self, resource: Resource, amount: ResourceQuantity) -> bool {
         if let Some(resource_datum) = self.get_mut(&resource) {
-            if resource_datum.quantity > amount {
+            if resource_datum.quantity >= amount {
                 resource_datum.quantity -= amount;
                 true
             } else {
@@ -85,6 +103,21 @@ impl ResourceDataStore for ResourceDataStorage {
         }
     }
 
+    fn consume_all(&mut self, costs: &HashMap<Resource, ResourceQuantity>) -> Result<(), Resource> {
+        let mut costs: Vec<_> = costs.iter().filter(|(_, amount)| **amount > 0).collect();
+        costs.sort();
+        if let Some((resource, _)) = costs
+            .iter()
+            .find(|(resource, amount)| self.quantity_of(**resource) < **amount)
+        {
+            return Err(**resource);
+        }
+        for (resource, amount) in costs {
+            self.consume(*resource, *amount);
+        }
+        Ok(())
+    }
+
     fn update(&mut self) -> Option<Resource> {
         for (resource, resource_datum) in self.iter_mut() {
             resource_datum.quantity += resource_datum.income.unwrap_or(0);
@@ -137,6 +170,10 @@ mod tests {
         assert_eq!(resources.get(&Resource::Water).unwrap().quantity, 50);
         assert!(!resources.consume(Resource::Food, 300));
         assert_eq!(resources.get(&Resource::Food).unwrap().quantity, 250);
+        // the whole stock can be consumed, as `consume_all` allows
+        assert!(resources.consume(Resource::Credits, 50));
+        assert_eq!(resources.get(&Resource::Credits).unwrap().quantity, 0);
+        assert!(!resources.consume(Resource::Credits, 1));
     }
 
     #[test]
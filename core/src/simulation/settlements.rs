@@ -1,17 +1,175 @@
-use crate::hex_map::layers::dynamic::HexMapTileSettlement;
+use crate::hex_map::{coordinates::CubeCoords, layers::dynamic::HexMapTileSettlement};
 
 use super::{
-    ids::SimulationID,
-    people::{leaders::Leader, population::PopulationGroup},
+    ids::{SimulationID, WithSimulationID},
+    people::{
+        leaders::Leader,
+        population::{AgeStructure, PopulationGroup, POPULATION_MAX_LOYALTY},
+    },
 };
 
+/// Share of the inhabitants of a settlement which can be drafted at once.
+pub const SETTLEMENT_MANPOWER_RATIO: f64 = 0.1;
+
 #[derive(Debug)]
 pub struct Settlement {
     /// Must be `SimulationID::SimulationMapEntityID`.
     id: SimulationID,
     name: String,
+    position: CubeCoords,
     leader: Option<Leader>,
     population: Vec<PopulationGroup>,
 }
 
+impl Settlement {
+    pub fn new(id: SimulationID, name: String, position: CubeCoords) -> Self {
+        assert!(matches!(id, SimulationID::MapEntityID(_)));
+        Self {
+            id,
+            name,
+            position,
+            leader: None,
+            population: vec![],
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn position(&self) -> &CubeCoords {
+        &self.position
+    }
+
+    pub fn leader(&self) -> Option<&Leader> {
+        self.leader.as_ref()
+    }
+
+    pub fn population(&self) -> &[PopulationGroup] {
+        &self.population
+    }
+
+    pub fn add_population_group(&mut self, group: PopulationGroup) {
+        self.population.push(group);
+    }
+
+    /// Total number of inhabitants, all population groups combined.
+    pub fn inhabitants(&self) -> u64 {
+        self.population
+            .iter()
+            .map(|group| group.size() as u64)
+            .sum()
+    }
+
+    /// Loyalty of the inhabitants, averaged over the population groups by their size.
+    pub fn loyalty(&self) -> u16 {
+        let inhabitants = self.inhabitants();
+        if inhabitants == 0 {
+            return POPULATION_MAX_LOYALTY;
+        }
+        let total: u64 = self
+            .population
+            .iter()
+            .map(|group| group.size() as u64 * group.loyalty() as u64)
+            .sum();
+        (total / inhabitants) as u16
+    }
+
+    /// Number of inhabitants which can currently be drafted into the military.
+    pub fn available_manpower(&self) -> u32 {
+        (self.inhabitants() as f64 * SETTLEMENT_MANPOWER_RATIO) as u32
+    }
+
+    /// Draft adults, from the largest population groups first. Return how many were actually drafted.
+    pub fn draft_manpower(&self, amount: u32) -> u32 {
+        let mut drafted = 0;
+        while drafted < amount {
+            let Some(group) =
+                self.largest_population_group(|group| group.age_structure().adults() > 0)
+            else {
+                break;
+            };
+            drafted += group.shrink_adults(amount - drafted);
+        }
+        drafted
+    }
+
+    /// Give adults back to the largest population group, e.g. when a unit is disbanded.
+    pub fn return_manpower(&self, amount: u32) {
+        if let Some(group) = self.largest_population_group(|_| true) {
+            group.grow_adults(amount);
+        }
+    }
+
+    /// Age structure of the inhabitants, all population groups combined.
+    pub fn age_structure(&self) -> AgeStructure {
+        let mut ages = AgeStructure::default();
+        for group in &self.population {
+            ages.add(&group.age_structure());
+        }
+        ages
+    }
+
+    /// The first of the largest population groups among the accepted ones.
+    fn largest_population_group(
+        &self,
+        accepts: impl Fn(&PopulationGroup) -> bool,
+    ) -> Option<&PopulationGroup> {
+        self.population
+            .iter()
+            .filter(|group| accepts(group))
+            .reduce(|largest, group| {
+                if group.size() > largest.size() {
+                    group
+                } else {
+                    largest
+                }
+            })
+    }
+
+    /// Take inhabitants away (e.g. migrants), from every population group proportionally to its size. Return who
+    /// actually left.
+    pub fn emigrate(&self, amount: u32) -> AgeStructure {
+        let inhabitants = self.inhabitants();
+        let mut left = AgeStructure::default();
+        if inhabitants == 0 {
+            return left;
+        }
+        let amount = (amount as u64).min(inhabitants);
+        let mut remaining = amount;
+        let last = self.population.len() - 1;
+        for (index, group) in self.population.iter().enumerate() {
+            let share = if index == last {
+                remaining
+            } else {
+                (amount * group.size() as u64 / inhabitants).min(remaining)
+            };
+            let mut ages = group.age_structure();
+            let taken = ages.take_share(share as u32);
+            group.set_age_structure(ages);
+            remaining -= taken.total() as u64;
+            left.add(&taken);
+        }
+        left
+    }
+
+    /// Welcome people (e.g. migrants) into the largest population group. Return `false`, and welcome no one, if
+    /// the settlement has no population group.
+    pub fn immigrate(&self, people: &AgeStructure) -> bool {
+        let Some(group) = self.largest_population_group(|_| true) else {
+            return false;
+        };
+        let mut ages = group.age_structure();
+        ages.add(people);
+        group.set_age_structure(ages);
+        true
+    }
+}
+
+impl WithSimulationID for Settlement {
+    fn id(&self) -> &SimulationID {
+        &self.id
+    }
+}
+
 impl HexMapTileSettlement for Settlement {}
@@ -0,0 +1,255 @@
+//! Templates registry: the building and unit templates available to a game, loaded from data files.
+//!
+//! Content is meant to be authored in RON, TOML or JSON files (see `loader::TemplateLoader`) rather than hard-coded,
+//! and once loaded every template is borrowed from the registry by the simulation and the client.
+
+use std::{collections::HashMap, fmt::Display};
+
+use super::{
+    buildings::{adjacency::AdjacencyCondition, BuildingTemplate},
+    ids::{SimulationID, WithSimulationID},
+    military::UnitTemplate,
+};
+
+pub mod loader;
+
+/// Where a template was defined.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TemplateOrigin {
+    file: String,
+    /// Starting from 1, if known.
+    line: Option<usize>,
+}
+
+impl TemplateOrigin {
+    pub fn new(file: String, line: Option<usize>) -> Self {
+        Self { file, line }
+    }
+
+    pub fn file(&self) -> &str {
+        &self.file
+    }
+
+    pub fn line(&self) -> Option<usize> {
+        self.line
+    }
+}
+
+impl Display for TemplateOrigin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{}:{}", self.file, line),
+            None => write!(f, "{}", self.file),
+        }
+    }
+}
+
+/// Why a template file, or a template in it, was rejected.
+#[derive(Clone, Debug, PartialEq)]
+pub enum TemplateLoadErrorKind {
+    /// The file could not be read.
+    Io(String),
+    /// The file extension is not one of `ron`, `toml` or `json`.
+    UnsupportedFormat,
+    /// The file is malformed, or does not follow the expected structure.
+    Syntax(String),
+    /// A template is well-formed but has an invalid value.
+    Invalid(String),
+    /// The ID of a template is already used by another template.
+    DuplicateID {
+        id: SimulationID,
+        first_definition: TemplateOrigin,
+    },
+    /// A template references a template that does not exist.
+    UnknownReference(SimulationID),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TemplateLoadError {
+    origin: TemplateOrigin,
+    kind: TemplateLoadErrorKind,
+}
+
+impl TemplateLoadError {
+    pub fn new(origin: TemplateOrigin, kind: TemplateLoadErrorKind) -> Self {
+        Self { origin, kind }
+    }
+
+    pub fn origin(&self) -> &TemplateOrigin {
+        &self.origin
+    }
+
+    pub fn kind(&self) -> &TemplateLoadErrorKind {
+        &self.kind
+    }
+}
+
+impl Display for TemplateLoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: ", self.origin)?;
+        match &self.kind {
+            TemplateLoadErrorKind::Io(error) => write!(f, "cannot read the file ({})", error),
+            TemplateLoadErrorKind::UnsupportedFormat => {
+                write!(f, "unsupported format (expected ron, toml or json)")
+            }
+            TemplateLoadErrorKind::Syntax(error) => write!(f, "syntax error ({})", error),
+            TemplateLoadErrorKind::Invalid(error) => write!(f, "invalid template ({})", error),
+            TemplateLoadErrorKind::DuplicateID {
+                id,
+                first_definition,
+            } => write!(
+                f,
+                "duplicate ID {:?}, first defined at {}",
+                id, first_definition
+            ),
+            TemplateLoadErrorKind::UnknownReference(id) => {
+                write!(f, "reference to unknown template {:?}", id)
+            }
+        }
+    }
+}
+
+/// Every template available to a game, queryable by `SimulationID`.
+///
+/// Building and unit templates share the same ID space.
+///
+/// Templates are borrowed from the registry. Only the buildings stored on the `HexMap` need `'static` ones (see
+/// `ConstructionQueues::update`), so a game keeps its registry for its whole duration.
+#[derive(Debug, Default)]
+pub struct TemplateRegistry {
+    buildings: HashMap<SimulationID, BuildingTemplate>,
+    units: HashMap<SimulationID, UnitTemplate>,
+    origins: HashMap<SimulationID, TemplateOrigin>,
+    /// Registration order, for deterministic iteration.
+    order: Vec<SimulationID>,
+}
+
+impl TemplateRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a building template, unless its ID is already taken.
+    pub fn register_building(
+        &mut self,
+        template: BuildingTemplate,
+        origin: TemplateOrigin,
+    ) -> Result<(), TemplateLoadError> {
+        self.check_available(template.id(), &origin)?;
+        self.register_origin(template.id(), origin);
+        self.buildings.insert(template.id().clone(), template);
+        Ok(())
+    }
+
+    /// Register a unit template, unless its ID is already taken.
+    pub fn register_unit(
+        &mut self,
+        template: UnitTemplate,
+        origin: TemplateOrigin,
+    ) -> Result<(), TemplateLoadError> {
+        self.check_available(template.id(), &origin)?;
+        self.register_origin(template.id(), origin);
+        self.units.insert(template.id().clone(), template);
+        Ok(())
+    }
+
+    pub fn building(&self, id: &SimulationID) -> Option<&BuildingTemplate> {
+        self.buildings.get(id)
+    }
+
+    pub fn unit(&self, id: &SimulationID) -> Option<&UnitTemplate> {
+        self.units.get(id)
+    }
+
+    pub fn contains(&self, id: &SimulationID) -> bool {
+        self.origins.contains_key(id)
+    }
+
+    /// Where the template of the given ID was defined.
+    pub fn origin(&self, id: &SimulationID) -> Option<&TemplateOrigin> {
+        self.origins.get(id)
+    }
+
+    /// All the building templates, in registration order.
+    pub fn buildings(&self) -> impl Iterator<Item = &BuildingTemplate> {
+        self.order.iter().filter_map(|id| self.buildings.get(id))
+    }
+
+    /// All the unit templates, in registration order.
+    pub fn units(&self) -> impl Iterator<Item = &UnitTemplate> {
+        self.order.iter().filter_map(|id| self.units.get(id))
+    }
+
+    /// All the building templates of the given type, in registration order.
+    pub fn buildings_of_type<'a>(
+        &'a self,
+        r#type: &'a str,
+    ) -> impl Iterator<Item = &'a BuildingTemplate> + 'a {
+        self.buildings()
+            .filter(move |template| template.r#type() == r#type)
+    }
+
+    /// All the unit templates of the given type, in registration order.
+    pub fn units_of_type<'a>(
+        &'a self,
+        r#type: &'a str,
+    ) -> impl Iterator<Item = &'a UnitTemplate> + 'a {
+        self.units()
+            .filter(move |template| template.r#type() == r#type)
+    }
+
+    pub fn len(&self) -> usize {
+        self.order.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.order.is_empty()
+    }
+
+    /// Check that every template referenced by a building template (upgrade targets, adjacency conditions) exists.
+    pub fn validate_references(&self) -> Vec<TemplateLoadError> {
+        let mut errors = vec![];
+        for template in self.buildings() {
+            let origin = &self.origins[template.id()];
+            let upgrade_targets = template.upgrades().iter().map(|upgrade| upgrade.target());
+            let adjacency_targets = template
+                .adjacency_rules()
+                .iter()
+                .filter_map(|rule| match rule.condition() {
+                    AdjacencyCondition::Building(id) => Some(id),
+                    _ => None,
+                });
+            for target in upgrade_targets.chain(adjacency_targets) {
+                if !self.buildings.contains_key(target) {
+                    errors.push(TemplateLoadError::new(
+                        origin.clone(),
+                        TemplateLoadErrorKind::UnknownReference(target.clone()),
+                    ));
+                }
+            }
+        }
+        errors
+    }
+
+    fn check_available(
+        &self,
+        id: &SimulationID,
+        origin: &TemplateOrigin,
+    ) -> Result<(), TemplateLoadError> {
+        match self.origins.get(id) {
+            Some(first_definition) => Err(TemplateLoadError::new(
+                origin.clone(),
+                TemplateLoadErrorKind::DuplicateID {
+                    id: id.clone(),
+                    first_definition: first_definition.clone(),
+                },
+            )),
+            None => Ok(()),
+        }
+    }
+
+    fn register_origin(&mut self, id: &SimulationID, origin: TemplateOrigin) {
+        self.origins.insert(id.clone(), origin);
+        self.order.push(id.clone());
+    }
+}
@@ -0,0 +1,615 @@
+//! Loading of building and unit templates from RON, TOML or JSON files.
+//!
+//! A templates file holds a list of `buildings` and a list of `units`, both optional. For instance, in TOML:
+//!
+//! ```toml
+//! [[buildings]]
+//! id = "farm"
+//! type = "agriculture"
+//! cost = { Credits = 50 }
+//! maintenance = { Credits = 2 }
+//! production = { Food = 10 }
+//!
+//! [[units]]
+//! id = "infantry"
+//! type = "land"
+//! cost = { Credits = 20, Metals = 5 }
+//! upkeep = { Credits = 1 }
+//! attributes = { movement_points = 2, name = "Infantry" }
+//! supply = { Food = 2, Ammunition = 1 }
+//! ```
+//!
+//! Every error cites the file and, whenever it can be determined, the line it comes from. Templates with errors
+//! are not registered, and the whole loading fails if any error was found.
+
+use std::{collections::HashMap, fs, path::Path};
+
+use serde::Deserialize;
+
+use crate::{
+    hex_map::layers::natural::HexMapTerrain,
+    simulation::{
+        buildings::{
+            adjacency::{AdjacencyCondition, AdjacencyRule, AdjacencyTarget},
+            BuildingPlacementRules, BuildingRecipe, BuildingTemplate, BuildingUpgradePath,
+        },
+        economy::{ConstructionCosts, MaintenanceCosts},
+        ids::SimulationID,
+        military::{UnitSupplyNeeds, UnitTemplate},
+        properties::{SimulationPropertyStorage, SimulationPropertyValue},
+        resources::{Resource, ResourceQuantity},
+    },
+};
+
+use super::{TemplateLoadError, TemplateLoadErrorKind, TemplateOrigin, TemplateRegistry};
+
+/// Supported formats of a templates file.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TemplateFileFormat {
+    Ron,
+    Toml,
+    Json,
+}
+
+impl TemplateFileFormat {
+    /// Deduce the format from the extension of a file.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()?.to_ascii_lowercase().as_str() {
+            "ron" => Some(Self::Ron),
+            "toml" => Some(Self::Toml),
+            "json" => Some(Self::Json),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TemplateFileDefinition {
+    #[serde(default)]
+    buildings: Vec<BuildingTemplateDefinition>,
+    #[serde(default)]
+    units: Vec<UnitTemplateDefinition>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BuildingTemplateDefinition {
+    id: String,
+    r#type: String,
+    #[serde(default)]
+    cost: ConstructionCosts,
+    #[serde(default)]
+    maintenance: MaintenanceCosts,
+    #[serde(default)]
+    production: HashMap<Resource, ResourceQuantity>,
+    #[serde(default)]
+    recipes: Vec<BuildingRecipeDefinition>,
+    build_time: Option<u16>,
+    max_health_points: Option<u16>,
+    placement: Option<BuildingPlacementDefinition>,
+    #[serde(default)]
+    upgrades: Vec<BuildingUpgradeDefinition>,
+    #[serde(default)]
+    adjacency: Vec<AdjacencyRuleDefinition>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BuildingRecipeDefinition {
+    #[serde(default)]
+    inputs: HashMap<Resource, ResourceQuantity>,
+    #[serde(default)]
+    outputs: HashMap<Resource, ResourceQuantity>,
+    throughput: u16,
+    #[serde(default)]
+    workforce: u32,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BuildingPlacementDefinition {
+    #[serde(default)]
+    terrains: Vec<HexMapTerrain>,
+    min_elevation: Option<i16>,
+    max_elevation: Option<i16>,
+    #[serde(default)]
+    requires_deposit: bool,
+    #[serde(default)]
+    exclusive: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BuildingUpgradeDefinition {
+    target: String,
+    #[serde(default)]
+    cost: ConstructionCosts,
+    build_time: u16,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct AdjacencyRuleDefinition {
+    id: String,
+    condition: AdjacencyConditionDefinition,
+    radius: u16,
+    target: AdjacencyTarget,
+    modifier_per_match: f64,
+    max_matches: Option<u16>,
+}
+
+/// Mirror of `AdjacencyCondition`, with template IDs as plain strings.
+#[derive(Deserialize)]
+enum AdjacencyConditionDefinition {
+    Building(String),
+    BuildingType(String),
+    Deposit,
+    Elevation { min: Option<i16>, max: Option<i16> },
+    Infrastructure,
+    Terrain(HexMapTerrain),
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct UnitTemplateDefinition {
+    id: String,
+    r#type: String,
+    #[serde(default)]
+    cost: ConstructionCosts,
+    #[serde(default)]
+    upkeep: MaintenanceCosts,
+    #[serde(default)]
+    attributes: HashMap<String, AttributeDefinition>,
+    #[serde(default)]
+    supply: UnitSupplyNeeds,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum AttributeDefinition {
+    Integer(i64),
+    Float(f64),
+    Text(String),
+}
+
+/// Loads templates from files into a `TemplateRegistry`, collecting every error found along the way.
+///
+/// References between templates (e.g. upgrade targets) are only checked by `finish`, so that a template can
+/// reference another one defined in a file loaded later.
+#[derive(Debug, Default)]
+pub struct TemplateLoader {
+    registry: TemplateRegistry,
+    errors: Vec<TemplateLoadError>,
+}
+
+impl TemplateLoader {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load the templates of a file, its format being deduced from its extension.
+    ///
+    /// Return true if the file was loaded without errors.
+    pub fn load_file(&mut self, path: &Path) -> bool {
+        let file = path.display().to_string();
+        let Some(format) = TemplateFileFormat::from_path(path) else {
+            return self.fail(file, None, TemplateLoadErrorKind::UnsupportedFormat);
+        };
+        match fs::read_to_string(path) {
+            Ok(source) => self.load_str(&file, format, &source),
+            Err(error) => self.fail(file, None, TemplateLoadErrorKind::Io(error.to_string())),
+        }
+    }
+
+    /// Load the templates of an in-memory source, `file` being only used to cite the origin of templates and errors.
+    ///
+    /// Return true if the source was loaded without errors.
+    pub fn load_str(&mut self, file: &str, format: TemplateFileFormat, source: &str) -> bool {
+        let definition = match parse(format, source) {
+            Ok(definition) => definition,
+            Err((error, line)) => {
+                return self.fail(file.into(), line, TemplateLoadErrorKind::Syntax(error))
+            }
+        };
+
+        let errors_count = self.errors.len();
+        let mut occurrences = HashMap::new();
+        for building in &definition.buildings {
+            let origin = origin_of(file, source, &building.id, &mut occurrences);
+            let result = building_template(building)
+                .and_then(|template| self.registry.register_building(template, origin.clone()));
+            if let Err(error) = result {
+                self.errors.push(error.with_origin(origin));
+            }
+        }
+        for unit in &definition.units {
+            let origin = origin_of(file, source, &unit.id, &mut occurrences);
+            let result = unit_template(unit)
+                .and_then(|template| self.registry.register_unit(template, origin.clone()));
+            if let Err(error) = result {
+                self.errors.push(error.with_origin(origin));
+            }
+        }
+        self.errors.len() == errors_count
+    }
+
+    /// Errors found so far.
+    pub fn errors(&self) -> &[TemplateLoadError] {
+        &self.errors
+    }
+
+    /// Check the references between templates, and return the registry if no error was found.
+    pub fn finish(mut self) -> Result<TemplateRegistry, Vec<TemplateLoadError>> {
+        self.errors.extend(self.registry.validate_references());
+        if self.errors.is_empty() {
+            Ok(self.registry)
+        } else {
+            Err(self.errors)
+        }
+    }
+
+    fn fail(&mut self, file: String, line: Option<usize>, kind: TemplateLoadErrorKind) -> bool {
+        self.errors.push(TemplateLoadError::new(
+            TemplateOrigin::new(file, line),
+            kind,
+        ));
+        false
+    }
+}
+
+impl TemplateLoadError {
+    /// Errors raised while converting a definition do not know where it comes from.
+    fn with_origin(self, origin: TemplateOrigin) -> Self {
+        Self { origin, ..self }
+    }
+}
+
+/// Parse a templates file, returning the error message and line on failure.
+fn parse(
+    format: TemplateFileFormat,
+    source: &str,
+) -> Result<TemplateFileDefinition, (String, Option<usize>)> {
+    match format {
+        TemplateFileFormat::Ron => ron::from_str(source)
+            .map_err(|error| (error.code.to_string(), Some(error.position.line))),
+        TemplateFileFormat::Toml => toml::from_str(source).map_err(|error| {
+            let line = error
+                .span()
+                .map(|span| source[..span.start].matches('\n').count() + 1);
+            (error.message().to_string(), line)
+        }),
+        TemplateFileFormat::Json => serde_json::from_str(source).map_err(|error| {
+            let line = Some(error.line()).filter(|&line| line > 0);
+            (error.to_string(), line)
+        }),
+    }
+}
+
+/// Origin of the next template of the given ID in a source, `occurrences` counting the ones already seen.
+fn origin_of<'a>(
+    file: &str,
+    source: &str,
+    id: &'a str,
+    occurrences: &mut HashMap<&'a str, usize>,
+) -> TemplateOrigin {
+    let occurrence = occurrences.entry(id).or_insert(0);
+    let line = definition_line(source, id, *occurrence);
+    *occurrence += 1;
+    TemplateOrigin::new(file.into(), line)
+}
+
+/// Find the line (starting from 1) of the `occurrence`-th `id` field set to the given value.
+///
+/// Works for the three formats, since all of them quote strings and separate keys from values with `:` or `=`.
+fn definition_line(source: &str, id: &str, occurrence: usize) -> Option<usize> {
+    source
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| has_id_field(line, id))
+        .nth(occurrence)
+        .map(|(index, _)| index + 1)
+}
+
+fn has_id_field(line: &str, id: &str) -> bool {
+    line.match_indices("id").any(|(start, _)| {
+        let before = line[..start].chars().next_back();
+        if before.is_some_and(|c| c.is_alphanumeric() || c == '_') {
+            return false;
+        }
+        let rest = line[start + 2..].trim_start_matches('"').trim_start();
+        let Some(rest) = rest.strip_prefix([':', '=']) else {
+            return false;
+        };
+        let rest = rest.trim_start();
+        ['"', '\''].iter().any(|quote| {
+            rest.strip_prefix(*quote)
+                .and_then(|rest| rest.strip_prefix(id))
+                .is_some_and(|rest| rest.starts_with(*quote))
+        })
+    })
+}
+
+fn invalid(message: String) -> TemplateLoadError {
+    TemplateLoadError::new(
+        TemplateOrigin::new(String::new(), None),
+        TemplateLoadErrorKind::Invalid(message),
+    )
+}
+
+fn template_id(id: &str) -> Result<SimulationID, TemplateLoadError> {
+    if id.trim().is_empty() {
+        return Err(invalid("empty ID".into()));
+    }
+    Ok(SimulationID::new_abstract_id(id))
+}
+
+fn building_template(
+    definition: &BuildingTemplateDefinition,
+) -> Result<BuildingTemplate, TemplateLoadError> {
+    let id = template_id(&definition.id)?;
+    if definition.r#type.trim().is_empty() {
+        return Err(invalid("empty type".into()));
+    }
+
+    let mut template = BuildingTemplate::new(
+        id,
+        definition.r#type.clone(),
+        definition.cost.clone(),
+        definition.maintenance.clone(),
+        definition.production.clone(),
+    );
+    for recipe in &definition.recipes {
+        template = template.with_recipe(BuildingRecipe::new(
+            recipe.inputs.clone(),
+            recipe.outputs.clone(),
+            recipe.throughput,
+            recipe.workforce,
+        ));
+    }
+    if let Some(build_time) = definition.build_time {
+        if build_time == 0 {
+            return Err(invalid("build_time must be positive".into()));
+        }
+        template = template.with_build_time(build_time);
+    }
+    if let Some(max_health_points) = definition.max_health_points {
+        if max_health_points == 0 {
+            return Err(invalid("max_health_points must be positive".into()));
+        }
+        template = template.with_max_health_points(max_health_points);
+    }
+    if let Some(placement) = &definition.placement {
+        if let (Some(min), Some(max)) = (placement.min_elevation, placement.max_elevation) {
+            if min > max {
+                return Err(invalid(format!(
+                    "placement min_elevation {} above max_elevation {}",
+                    min, max
+                )));
+            }
+        }
+        template = template.with_placement(BuildingPlacementRules::new(
+            placement.terrains.clone(),
+            placement.min_elevation,
+            placement.max_elevation,
+            placement.requires_deposit,
+            placement.exclusive,
+        ));
+    }
+    for upgrade in &definition.upgrades {
+        if upgrade.build_time == 0 {
+            return Err(invalid(format!(
+                "build_time of the upgrade to {:?} must be positive",
+                upgrade.target
+            )));
+        }
+        template = template.with_upgrade(BuildingUpgradePath::new(
+            template_id(&upgrade.target)?,
+            upgrade.cost.clone(),
+            upgrade.build_time,
+        ));
+    }
+    for rule in &definition.adjacency {
+        if rule.radius == 0 {
+            return Err(invalid(format!(
+                "radius of the adjacency rule {:?} must be positive",
+                rule.id
+            )));
+        }
+        let condition = match &rule.condition {
+            AdjacencyConditionDefinition::Building(id) => {
+                AdjacencyCondition::Building(template_id(id)?)
+            }
+            AdjacencyConditionDefinition::BuildingType(r#type) => {
+                AdjacencyCondition::BuildingType(r#type.clone())
+            }
+            AdjacencyConditionDefinition::Deposit => AdjacencyCondition::Deposit,
+            AdjacencyConditionDefinition::Elevation { min, max } => AdjacencyCondition::Elevation {
+                min: *min,
+                max: *max,
+            },
+            AdjacencyConditionDefinition::Infrastructure => AdjacencyCondition::Infrastructure,
+            AdjacencyConditionDefinition::Terrain(terrain) => AdjacencyCondition::Terrain(*terrain),
+        };
+        template = template.with_adjacency_rule(AdjacencyRule::new(
+            template_id(&rule.id)?,
+            condition,
+            rule.radius,
+            rule.target,
+            rule.modifier_per_match,
+            rule.max_matches,
+        ));
+    }
+    Ok(template)
+}
+
+fn unit_template(definition: &UnitTemplateDefinition) -> Result<UnitTemplate, TemplateLoadError> {
+    let id = template_id(&definition.id)?;
+    if definition.r#type.trim().is_empty() {
+        return Err(invalid("empty type".into()));
+    }
+
+    // sorted for deterministic error reporting
+    let mut attributes: Vec<_> = definition.attributes.iter().collect();
+    attributes.sort_by_key(|(name, _)| name.as_str());
+    let mut storage = SimulationPropertyStorage::new();
+    for (name, value) in attributes {
+        if name.trim().is_empty() {
+            return Err(invalid("empty attribute name".into()));
+        }
+        let value = match value {
+            AttributeDefinition::Integer(value) => {
+                let Ok(value) = i32::try_from(*value) else {
+                    return Err(invalid(format!("attribute {:?} out of range", name)));
+                };
+                SimulationPropertyValue::Integer(value)
+            }
+            AttributeDefinition::Float(value) => SimulationPropertyValue::Float(*value),
+            AttributeDefinition::Text(value) => SimulationPropertyValue::Text(value.clone()),
+        };
+        storage = storage.register_new(SimulationID::new_property_id(name.clone()), value);
+    }
+
+    Ok(UnitTemplate::new(
+        id,
+        definition.r#type.clone(),
+        definition.cost.clone(),
+        definition.upkeep.clone(),
+        storage,
+    )
+    .with_supply_needs(definition.supply.clone()))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::simulation::{
+        ids::{SimulationID, WithSimulationID},
+        properties::SimulationPropertyValue,
+        resources::Resource,
+        templates::TemplateLoadErrorKind,
+    };
+
+    use super::{TemplateFileFormat, TemplateLoader};
+
+    const BUILDINGS_RON: &str = r#"(
+    buildings: [
+        (
+            id: "farm",
+            type: "agriculture",
+            cost: { Credits: 50 },
+            maintenance: { Credits: 2 },
+            production: { Food: 10 },
+            upgrades: [(target: "large_farm", cost: { Credits: 80 }, build_time: 3)],
+        ),
+        (
+            id: "large_farm",
+            type: "agriculture",
+            production: { Food: 25 },
+            build_time: Some(4),
+        ),
+    ],
+)"#;
+
+    const UNITS_TOML: &str = r#"
+[[units]]
+id = "infantry"
+type = "land"
+cost = { Credits = 20, Metals = 5 }
+upkeep = { Credits = 1 }
+attributes = { movement_points = 2, name = "Infantry" }
+"#;
+
+    const DUPLICATES_JSON: &str = r#"{
+    "units": [
+        { "id": "tank", "type": "land" },
+        {
+            "id": "farm",
+            "type": "land",
+            "attributes": { "armor": 3.5 }
+        },
+        { "id": "zero", "type": "" }
+    ]
+}"#;
+
+    #[test]
+    fn test_templates_loading() {
+        let mut loader = TemplateLoader::new();
+        assert!(loader.load_str("buildings.ron", TemplateFileFormat::Ron, BUILDINGS_RON));
+        assert!(loader.load_str("units.toml", TemplateFileFormat::Toml, UNITS_TOML));
+        let registry = loader.finish().unwrap();
+
+        assert_eq!(registry.len(), 3);
+        let farm = registry
+            .building(&SimulationID::new_abstract_id("farm"))
+            .unwrap();
+        assert_eq!(farm.production().get(&Resource::Food), Some(&10));
+        assert!(farm
+            .upgrade_to(&SimulationID::new_abstract_id("large_farm"))
+            .is_some());
+        assert_eq!(registry.buildings_of_type("agriculture").count(), 2);
+        assert_eq!(
+            registry
+                .origin(&SimulationID::new_abstract_id("large_farm"))
+                .unwrap()
+                .to_string(),
+            "buildings.ron:12"
+        );
+
+        let infantry = registry
+            .unit(&SimulationID::new_abstract_id("infantry"))
+            .unwrap();
+        assert_eq!(infantry.id(), &SimulationID::new_abstract_id("infantry"));
+        assert_eq!(infantry.cost().get(&Resource::Metals), Some(&5));
+        assert!(matches!(
+            infantry
+                .attributes()
+                .get_from_id(&SimulationID::new_property_id("movement_points".into())),
+            Some(SimulationPropertyValue::Integer(2))
+        ));
+    }
+
+    #[test]
+    fn test_templates_loading_errors() {
+        let mut loader = TemplateLoader::new();
+        assert!(loader.load_str("buildings.ron", TemplateFileFormat::Ron, BUILDINGS_RON));
+        assert!(!loader.load_str("units.json", TemplateFileFormat::Json, DUPLICATES_JSON));
+        assert!(!loader.load_str(
+            "broken.toml",
+            TemplateFileFormat::Toml,
+            "[[units]]\nid = \"scout\"\ntype = \"land\"\nspeed = 3\n"
+        ));
+
+        let errors = loader.errors();
+        assert_eq!(errors.len(), 3);
+        assert!(matches!(
+            errors[0].kind(),
+            TemplateLoadErrorKind::DuplicateID { first_definition, .. }
+                if first_definition.to_string() == "buildings.ron:4"
+        ));
+        assert_eq!(errors[0].origin().to_string(), "units.json:5");
+        assert!(matches!(
+            errors[1].kind(),
+            TemplateLoadErrorKind::Invalid(_)
+        ));
+        assert_eq!(errors[1].origin().line(), Some(9));
+        assert!(matches!(errors[2].kind(), TemplateLoadErrorKind::Syntax(_)));
+        assert_eq!(errors[2].origin().line(), Some(4));
+
+        // finishing fails with every error found
+        let errors = loader.finish().unwrap_err();
+        assert_eq!(errors.len(), 3);
+
+        // references are checked once every file is loaded
+        let mut loader = TemplateLoader::new();
+        assert!(loader.load_str(
+            "upgrade.json",
+            TemplateFileFormat::Json,
+            r#"{"buildings": [{"id": "mill", "type": "industry", "upgrades": [{"target": "factory", "build_time": 2}]}]}"#
+        ));
+        let errors = loader.finish().unwrap_err();
+        assert_eq!(
+            errors[0].kind(),
+            &TemplateLoadErrorKind::UnknownReference(SimulationID::new_abstract_id("factory"))
+        );
+    }
+}
@@ -0,0 +1,420 @@
+//! Territory of `Nation`s on the `HexMap`.
+//!
+//! Every settlement radiates influence over the tiles around it, growing with its population, the cultural
+//! buildings built on its tile and its status of capital, and fading with distance. Each tile belongs to the nation
+//! with the most influence over it (see `Territory`), as long as that influence reaches a
+//! threshold. Tiles where other nations come close are contested: their current owner keeps them if it is one of
+//! the contenders, otherwise the strongest claim wins. Settlement tiles always belong to the owner of the
+//! settlement.
+
+use std::collections::HashMap;
+
+use crate::hex_map::{
+    coordinates::{CubeCoords, HexMapCoordinatesSystem, HexMapDirection, HEX_MAP_DIRECTIONS},
+    HexMap,
+};
+
+use super::{
+    buildings::Building,
+    ids::{SimulationID, WithSimulationID},
+    nations::Nation,
+    settlements::Settlement,
+};
+
+/// Owners of the tiles claimed by nations.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Territory {
+    /// Values must be the `SimulationID`s of `Nation`s.
+    owners: HashMap<CubeCoords, SimulationID>,
+}
+
+impl Territory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Nation whose territory includes the tile, if any.
+    pub fn owner(&self, tile: &CubeCoords) -> Option<&SimulationID> {
+        self.owners.get(tile)
+    }
+
+    /// Change the owner of a tile, returning the previous one.
+    pub fn set_owner(
+        &mut self,
+        tile: CubeCoords,
+        owner: Option<SimulationID>,
+    ) -> Option<SimulationID> {
+        match owner {
+            Some(owner) => self.owners.insert(tile, owner),
+            None => self.owners.remove(&tile),
+        }
+    }
+
+    /// Every owned tile with its owner, in no particular order.
+    pub fn owners(&self) -> impl Iterator<Item = (&CubeCoords, &SimulationID)> {
+        self.owners.iter()
+    }
+}
+
+/// A tile which changed hands.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BorderChange {
+    tile: CubeCoords,
+    /// Must be the `SimulationID` of a `Nation`.
+    from: Option<SimulationID>,
+    /// Must be the `SimulationID` of a `Nation`.
+    to: Option<SimulationID>,
+}
+
+impl BorderChange {
+    pub fn tile(&self) -> &CubeCoords {
+        &self.tile
+    }
+
+    pub fn from(&self) -> Option<&SimulationID> {
+        self.from.as_ref()
+    }
+
+    pub fn to(&self) -> Option<&SimulationID> {
+        self.to.as_ref()
+    }
+}
+
+/// Changes of the borders for a turn, sorted by tile.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TerritoryReport {
+    changes: Vec<BorderChange>,
+    /// Tiles claimed by several nations.
+    contested: Vec<CubeCoords>,
+}
+
+impl TerritoryReport {
+    pub fn changes(&self) -> &[BorderChange] {
+        &self.changes
+    }
+
+    pub fn contested(&self) -> &[CubeCoords] {
+        &self.contested
+    }
+}
+
+/// A side of a tile, where the territory of a nation ends.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BorderEdge {
+    tile: CubeCoords,
+    /// Side of the tile, toward its neighbor.
+    direction: HexMapDirection,
+    /// Must be the `SimulationID` of a `Nation`.
+    owner: SimulationID,
+    /// Owner of the neighboring tile, `None` if it is unclaimed or outside the map.
+    neighbor_owner: Option<SimulationID>,
+}
+
+impl BorderEdge {
+    pub fn tile(&self) -> &CubeCoords {
+        &self.tile
+    }
+
+    pub fn direction(&self) -> HexMapDirection {
+        self.direction
+    }
+
+    pub fn owner(&self) -> &SimulationID {
+        &self.owner
+    }
+
+    pub fn neighbor_owner(&self) -> Option<&SimulationID> {
+        self.neighbor_owner.as_ref()
+    }
+}
+
+/// Tiles owned by a nation, sorted.
+pub fn territory_of(territory: &Territory, nation: &SimulationID) -> Vec<CubeCoords> {
+    let mut tiles: Vec<CubeCoords> = territory
+        .owners()
+        .filter(|(_, owner)| *owner == nation)
+        .map(|(position, _)| *position)
+        .collect();
+    tiles.sort();
+    tiles
+}
+
+/// Every side of an owned tile facing a tile with another owner, or no owner, sorted by tile and direction.
+///
+/// Meant to be rendered as the outlines of the territories: each tile is visited once, and only its six neighbors
+/// are looked up.
+pub fn border_edges(territory: &Territory) -> Vec<BorderEdge> {
+    let mut edges = vec![];
+    for (position, owner) in territory.owners() {
+        for (direction, neighbor) in HEX_MAP_DIRECTIONS.into_iter().zip(position.neighbors()) {
+            let neighbor_owner = territory.owner(&neighbor);
+            if neighbor_owner != Some(owner) {
+                edges.push(BorderEdge {
+                    tile: *position,
+                    direction,
+                    owner: owner.clone(),
+                    neighbor_owner: neighbor_owner.cloned(),
+                });
+            }
+        }
+    }
+    edges.sort_by_key(|edge| (edge.tile, edge.direction as u8));
+    edges
+}
+
+/// Rules of the growth of the territories.
+#[derive(Debug)]
+pub struct TerritoryModel {
+    /// Influence of any settlement.
+    settlement_influence: f64,
+    /// Additional influence per inhabitant of the settlement.
+    influence_per_inhabitant: f64,
+    /// Multiplier of the influence of the capital of a nation.
+    capital_multiplier: f64,
+    /// Influence lost per tile of distance from the settlement.
+    distance_decay: f64,
+    /// Influence needed to claim a tile.
+    min_influence: f64,
+    /// A tile is contested when another nation has at least `1 - contest_margin` of the strongest influence over it.
+    contest_margin: f64,
+    /// Additional influence granted by the buildings of a settlement, scaled by their health.
+    ///
+    /// Keys must be the `SimulationID`s of `BuildingTemplate`s.
+    cultural_buildings: HashMap<SimulationID, f64>,
+}
+
+impl Default for TerritoryModel {
+    fn default() -> Self {
+        Self {
+            settlement_influence: 10.0,
+            influence_per_inhabitant: 0.001,
+            capital_multiplier: 1.5,
+            distance_decay: 2.0,
+            min_influence: 1.0,
+            contest_margin: 0.2,
+            cultural_buildings: HashMap::new(),
+        }
+    }
+}
+
+impl TerritoryModel {
+    pub fn new(settlement_influence: f64, distance_decay: f64, contest_margin: f64) -> Self {
+        assert!(distance_decay > 0.0);
+        assert!((0.0..1.0).contains(&contest_margin));
+        Self {
+            settlement_influence,
+            distance_decay,
+            contest_margin,
+            ..Self::default()
+        }
+    }
+
+    pub fn with_cultural_building(mut self, building: SimulationID, influence: f64) -> Self {
+        assert!(matches!(building, SimulationID::Abstract(_)));
+        self.cultural_buildings.insert(building, influence);
+        self
+    }
+
+    /// Influence of a settlement on its own tile.
+    pub fn influence(&self, settlement: &Settlement, capital: bool, map: &HexMap) -> f64 {
+        let buildings: f64 = map
+            .tile(settlement.position())
+            .into_iter()
+            .flat_map(Building::all_on_tile)
+            .filter_map(|building| {
+                self.cultural_buildings
+                    .get(building.template().id())
+                    .map(|influence| influence * building.efficiency())
+            })
+            .sum();
+        let influence = self.settlement_influence
+            + settlement.inhabitants() as f64 * self.influence_per_inhabitant
+            + buildings;
+        if capital {
+            influence * self.capital_multiplier
+        } else {
+            influence
+        }
+    }
+
+    /// Called every turn, after the settlements changed hands. Redraw the borders of the nations.
+    pub fn update(
+        &self,
+        nations: &[Nation],
+        map: &HexMap,
+        territory: &mut Territory,
+    ) -> TerritoryReport {
+        // influence of every nation over every tile in reach of its settlements
+        let mut influences: HashMap<CubeCoords, Vec<f64>> = HashMap::new();
+        let mut seats: HashMap<CubeCoords, usize> = HashMap::new();
+        for (index, nation) in nations.iter().enumerate() {
+            for settlement in nation.settlements() {
+                let center = *settlement.position();
+                if !map.contains(&center) {
+                    continue;
+                }
+                let capital = nation
+                    .capital()
+                    .is_some_and(|capital| capital.id() == settlement.id());
+                let influence = self.influence(settlement, capital, map);
+                seats.insert(center, index);
+                let radius = ((influence - self.min_influence) / self.distance_decay).max(0.0);
+                for tile in std::iter::once(center).chain(map.within_radius(&center, radius as u16))
+                {
+                    let value = influence - self.distance_decay * center.distance_to(tile);
+                    if value >= self.min_influence {
+                        influences
+                            .entry(tile)
+                            .or_insert_with(|| vec![0.0; nations.len()])[index] += value;
+                    }
+                }
+            }
+        }
+
+        let mut tiles: Vec<CubeCoords> = influences
+            .keys()
+            .copied()
+            .chain(territory.owners().map(|(position, _)| *position))
+            .collect();
+        tiles.sort();
+        tiles.dedup();
+
+        let mut report = TerritoryReport::default();
+        for position in tiles {
+            let current = territory.owner(&position).cloned();
+            let owner = match (seats.get(&position), influences.get(&position)) {
+                (Some(seat), _) => Some(*seat),
+                (None, Some(influence)) => {
+                    let (contenders, contested) = self.contenders(influence);
+                    if contested {
+                        report.contested.push(position);
+                    }
+                    contenders
+                        .iter()
+                        .copied()
+                        .find(|index| current.as_ref() == Some(nations[*index].id()))
+                        .or(contenders.first().copied())
+                }
+                (None, None) => None,
+            };
+            let owner = owner.map(|index| nations[index].id().clone());
+            if owner != current {
+                territory.set_owner(position, owner.clone());
+                report.changes.push(BorderChange {
+                    tile: position,
+                    from: current,
+                    to: owner,
+                });
+            }
+        }
+        report
+    }
+
+    /// Indexes of the nations with a claim on a tile, from the strongest, and whether the tile is contested.
+    fn contenders(&self, influence: &[f64]) -> (Vec<usize>, bool) {
+        let mut ranked: Vec<usize> = (0..influence.len())
+            .filter(|index| influence[*index] >= self.min_influence)
+            .collect();
+        // stable: ties go to the first nation
+        ranked.sort_by(|a, b| influence[*b].total_cmp(&influence[*a]));
+        let Some(strongest) = ranked.first().map(|index| influence[*index]) else {
+            return (ranked, false);
+        };
+        ranked.retain(|index| influence[*index] >= strongest * (1.0 - self.contest_margin));
+        let contested = ranked.len() > 1;
+        (ranked, contested)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::{
+        hex_map::{
+            coordinates::CubeCoords, layers::natural::HexMapTerrain, tile::HexMapTile, HexMap,
+            HexMapStorage,
+        },
+        simulation::{
+            buildings::{Building, BuildingTemplate},
+            ids::{SimulationID, WithSimulationID},
+            nations::Nation,
+            settlements::Settlement,
+            testing::build_mock_leader,
+        },
+    };
+
+    use super::{border_edges, territory_of, Territory, TerritoryModel};
+
+    #[test]
+    fn test_territory_update() {
+        let mut tiles = HexMapStorage::new();
+        for q in 0..=8 {
+            tiles.insert(
+                CubeCoords::from_axial_coords(q, 0),
+                HexMapTile::from_terrain(0, HexMapTerrain::Plains),
+            );
+        }
+        let mut map = HexMap::from_tiles(tiles);
+        let tile = CubeCoords::from_axial_coords;
+        let capital_a = Settlement::new(SimulationID::new_map_entity_id(1), "A".into(), tile(0, 0));
+        let capital_b = Settlement::new(SimulationID::new_map_entity_id(2), "B".into(), tile(8, 0));
+        let nations = [("a", &capital_a), ("b", &capital_b)]
+            .into_iter()
+            .enumerate()
+            .map(|(index, (id, capital))| {
+                Nation::new(
+                    SimulationID::new_abstract_id(id),
+                    id.into(),
+                    build_mock_leader(index as u32, vec![]),
+                    capital,
+                )
+            })
+            .collect::<Vec<_>>();
+        let [a, b] = ["a", "b"].map(SimulationID::new_abstract_id);
+        let model = TerritoryModel::default()
+            .with_cultural_building(SimulationID::new_abstract_id("temple"), 4.0);
+        let mut territory = Territory::new();
+
+        // equal capitals: 15 influence, minus 2 per tile, tied in the middle
+        let report = model.update(&nations, &map, &mut territory);
+        assert_eq!(report.changes().len(), 9);
+        assert_eq!(report.contested(), &[tile(4, 0)]);
+        assert_eq!(
+            territory_of(&territory, &a),
+            (0..=4).map(|q| tile(q, 0)).collect::<Vec<_>>()
+        );
+        let edges = border_edges(&territory);
+        let facing: Vec<_> = edges
+            .iter()
+            .filter(|edge| edge.neighbor_owner().is_some())
+            .map(|edge| (edge.tile(), edge.owner()))
+            .collect();
+        assert_eq!(facing, vec![(&tile(4, 0), &a), (&tile(5, 0), &b)]);
+        assert_eq!(edges.len(), 9 * 6 - 8 * 2 + 2);
+
+        // a temple raises the influence of b to 21: it takes the middle, a holds on to its contested tiles
+        let temple: &BuildingTemplate = Box::leak(Box::new(BuildingTemplate::new(
+            SimulationID::new_abstract_id("temple"),
+            "temple".into(),
+            HashMap::new(),
+            HashMap::new(),
+            HashMap::new(),
+        )));
+        map.tile_mut(capital_b.position())
+            .unwrap()
+            .layer_artificial_mut()
+            .add_building(Box::new(Building::new(
+                SimulationID::new_map_entity_id(3),
+                temple,
+                100,
+            )));
+        let report = model.update(&nations, &map, &mut territory);
+        assert_eq!(report.changes().len(), 1);
+        assert_eq!(report.changes()[0].tile(), &tile(4, 0));
+        assert_eq!(report.changes()[0].from(), Some(&a));
+        assert_eq!(report.changes()[0].to(), Some(&b));
+        assert_eq!(report.contested(), &[tile(2, 0), tile(3, 0)]);
+        assert_eq!(territory.owner(&tile(3, 0)), Some(nations[0].id()));
+    }
+}
@@ -0,0 +1,19 @@
+//! Mocks shared by the tests of the simulation.
+
+use std::collections::HashMap;
+
+use super::{
+    ids::SimulationID,
+    people::leaders::{IndividualIDCard, IndividualName, Leader, Trait},
+};
+
+pub fn build_mock_leader(id: u32, traits: Vec<Trait>) -> Leader {
+    Leader::new(
+        IndividualIDCard::new(
+            SimulationID::new_entity_id(id),
+            IndividualName::HumanLike("John".into(), "Doe".into()),
+        ),
+        traits,
+        HashMap::new(),
+    )
+}
@@ -0,0 +1,640 @@
+//! Trade between `Nation`s.
+//!
+//! A trade agreement specifies recurring resource flows, from an exporting nation to an importing one, at an agreed price.
+//! It is physically realised as a trade route on the `HexMap`, either over land (following infrastructure) or over sea
+//! (between coastal tiles), whose throughput can be cut by blockades or captured tiles.
+//!
+//! An agreement whose route stays severed for too many consecutive turns is broken.
+
+use std::collections::HashMap;
+
+use crate::hex_map::{
+    coordinates::CubeCoords,
+    pathfinding::{find_path, find_path_by_steps, HexMapPath},
+    HexMap,
+};
+
+use super::{
+    ids::{SimulationID, WithSimulationID},
+    infrastructure::{network::InfrastructureNetwork, InfrastructureKind},
+    nations::Nation,
+    resources::{Resource, ResourceDataStore, ResourceQuantity},
+};
+
+/// A recurring resource flow of a trade agreement.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TradeFlow {
+    resource: Resource,
+    /// Per-turn quantity.
+    quantity: ResourceQuantity,
+    /// Price of one unit of the resource, in `Resource::Credits` paid by the importer.
+    unit_price: ResourceQuantity,
+}
+
+impl TradeFlow {
+    pub fn new(
+        resource: Resource,
+        quantity: ResourceQuantity,
+        unit_price: ResourceQuantity,
+    ) -> Self {
+        assert!(resource != Resource::Credits);
+        Self {
+            resource,
+            quantity,
+            unit_price,
+        }
+    }
+
+    pub fn resource(&self) -> Resource {
+        self.resource
+    }
+
+    pub fn quantity(&self) -> ResourceQuantity {
+        self.quantity
+    }
+
+    pub fn unit_price(&self) -> ResourceQuantity {
+        self.unit_price
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TradeRouteKind {
+    /// Over land tiles, following roads and rails.
+    Land,
+    /// Over water tiles, between coastal tiles.
+    Sea,
+}
+
+/// Disruption of a trade route, on a given tile.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TradeRouteDisruption {
+    /// Cuts the given percentage (from 0 to 100) of the route's throughput.
+    Blockade { strength: u8 },
+    /// The tile was captured by a party hostile to the trade, fully severing the route.
+    Captured,
+}
+
+/// Disruptions, indexed by tile.
+pub type TradeRouteDisruptions = HashMap<CubeCoords, TradeRouteDisruption>;
+
+/// The physical route on the `HexMap` of a trade agreement.
+#[derive(Debug)]
+pub struct TradeRoute {
+    kind: TradeRouteKind,
+    path: HexMapPath,
+    /// Maximum quantity of resources, all flows combined, which can be transported per turn.
+    capacity: ResourceQuantity,
+}
+
+impl TradeRoute {
+    /// Plan the shortest route of the given kind between two settlement tiles, if any.
+    ///
+    /// Land routes only move between tiles directly linked by a road or a rail segment of the network.
+    pub fn plan(
+        map: &HexMap,
+        network: &InfrastructureNetwork,
+        kind: TradeRouteKind,
+        from: CubeCoords,
+        to: CubeCoords,
+        capacity: ResourceQuantity,
+    ) -> Option<Self> {
+        let path = match kind {
+            TradeRouteKind::Land => find_path_by_steps(map, from, to, |current, next, tile| {
+                let linked = [InfrastructureKind::Road, InfrastructureKind::Rail]
+                    .into_iter()
+                    .any(|kind| network.connects(kind, current, next));
+                if linked && !tile.terrain().is_water() {
+                    Some(1)
+                } else {
+                    None
+                }
+            }),
+            TradeRouteKind::Sea => {
+                if !map.is_coastal(&from) || !map.is_coastal(&to) {
+                    return None;
+                }
+                find_path(map, from, to, |coords, tile| {
+                    if tile.terrain().is_water() || *coords == to {
+                        Some(1)
+                    } else {
+                        None
+                    }
+                })
+            }
+        }?;
+        Some(Self {
+            kind,
+            path,
+            capacity,
+        })
+    }
+
+    pub fn kind(&self) -> TradeRouteKind {
+        self.kind
+    }
+
+    pub fn path(&self) -> &HexMapPath {
+        &self.path
+    }
+
+    pub fn capacity(&self) -> ResourceQuantity {
+        self.capacity
+    }
+
+    /// Compute the per-turn throughput of the route under the given disruptions.
+    pub fn effective_throughput(&self, disruptions: &TradeRouteDisruptions) -> ResourceQuantity {
+        let mut throughput = self.capacity as u32;
+        for coords in self.path.tiles() {
+            match disruptions.get(coords) {
+                Some(TradeRouteDisruption::Captured) => return 0,
+                Some(TradeRouteDisruption::Blockade { strength }) => {
+                    throughput = throughput * (100 - (*strength).min(100) as u32) / 100;
+                }
+                None => {}
+            }
+        }
+        throughput as ResourceQuantity
+    }
+}
+
+#[derive(Debug)]
+pub struct TradeAgreement {
+    /// Must be `SimulationID::EntityID`.
+    id: SimulationID,
+    /// Must be the `SimulationID` of a `Nation`.
+    exporter: SimulationID,
+    /// Must be the `SimulationID` of a `Nation`.
+    importer: SimulationID,
+    flows: Vec<TradeFlow>,
+    route: TradeRoute,
+    /// Number of consecutive turns the route can stay severed before the agreement is broken.
+    max_severed_turns: u8,
+    severed_turns: u8,
+}
+
+impl TradeAgreement {
+    pub fn new(
+        id: SimulationID,
+        exporter: SimulationID,
+        importer: SimulationID,
+        flows: Vec<TradeFlow>,
+        route: TradeRoute,
+        max_severed_turns: u8,
+    ) -> Self {
+        assert!(matches!(id, SimulationID::EntityID(_)));
+        assert!(exporter != importer);
+        Self {
+            id,
+            exporter,
+            importer,
+            flows,
+            route,
+            max_severed_turns,
+            severed_turns: 0,
+        }
+    }
+
+    pub fn exporter(&self) -> &SimulationID {
+        &self.exporter
+    }
+
+    pub fn importer(&self) -> &SimulationID {
+        &self.importer
+    }
+
+    pub fn flows(&self) -> &[TradeFlow] {
+        &self.flows
+    }
+
+    pub fn route(&self) -> &TradeRoute {
+        &self.route
+    }
+
+    pub fn severed_turns(&self) -> u8 {
+        self.severed_turns
+    }
+
+    pub fn involves(&self, nation: &SimulationID) -> bool {
+        &self.exporter == nation || &self.importer == nation
+    }
+}
+
+impl WithSimulationID for TradeAgreement {
+    fn id(&self) -> &SimulationID {
+        &self.id
+    }
+}
+
+/// Why a trade flow could not happen this turn.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TradeFlowFailure {
+    ImporterLacksCredits,
+    ExporterLacksResource,
+    /// The price of the flow does not fit in a `ResourceQuantity`.
+    PriceOverflow,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TradeEvent {
+    Delivered {
+        agreement: SimulationID,
+        resource: Resource,
+        quantity: ResourceQuantity,
+        price: ResourceQuantity,
+    },
+    FlowFailed {
+        agreement: SimulationID,
+        resource: Resource,
+        reason: TradeFlowFailure,
+    },
+    RouteSevered {
+        agreement: SimulationID,
+        turns: u8,
+    },
+    AgreementBroken {
+        agreement: SimulationID,
+    },
+}
+
+/// All the active trade agreements in the simulation.
+#[derive(Debug, Default)]
+pub struct TradeAgreements {
+    agreements: Vec<TradeAgreement>,
+}
+
+impl TradeAgreements {
+    pub fn new() -> Self {
+        Self { agreements: vec![] }
+    }
+
+    pub fn sign(&mut self, agreement: TradeAgreement) {
+        assert!(self.get(agreement.id()).is_none());
+        self.agreements.push(agreement);
+    }
+
+    pub fn cancel(&mut self, id: &SimulationID) -> Option<TradeAgreement> {
+        let index = self
+            .agreements
+            .iter()
+            .position(|agreement| agreement.id() == id)?;
+        Some(self.agreements.remove(index))
+    }
+
+    pub fn get(&self, id: &SimulationID) -> Option<&TradeAgreement> {
+        self.agreements
+            .iter()
+            .find(|agreement| agreement.id() == id)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &TradeAgreement> {
+        self.agreements.iter()
+    }
+
+    /// Iterate over the agreements a nation is part of, as exporter or importer.
+    pub fn involving<'s>(
+        &'s self,
+        nation: &'s SimulationID,
+    ) -> impl Iterator<Item = &'s TradeAgreement> + 's {
+        self.agreements
+            .iter()
+            .filter(move |agreement| agreement.involves(nation))
+    }
+
+    /// Called every turn. Move the traded resources and payments between the nations' stockpiles.
+    pub fn update(
+        &mut self,
+        nations: &mut [Nation],
+        disruptions: &TradeRouteDisruptions,
+    ) -> Vec<TradeEvent> {
+        let mut events = vec![];
+        self.agreements.retain_mut(|agreement| {
+            let throughput = agreement.route.effective_throughput(disruptions);
+            if throughput == 0 {
+                agreement.severed_turns = agreement.severed_turns.saturating_add(1);
+                events.push(TradeEvent::RouteSevered {
+                    agreement: agreement.id.clone(),
+                    turns: agreement.severed_turns,
+                });
+                if agreement.severed_turns > agreement.max_severed_turns {
+                    events.push(TradeEvent::AgreementBroken {
+                        agreement: agreement.id.clone(),
+                    });
+                    return false;
+                }
+                return true;
+            }
+            agreement.severed_turns = 0;
+
+            let parties_exist = [&agreement.exporter, &agreement.importer]
+                .into_iter()
+                .all(|party| nations.iter().any(|nation| nation.id() == party));
+            if !parties_exist {
+                events.push(TradeEvent::AgreementBroken {
+                    agreement: agreement.id.clone(),
+                });
+                return false;
+            }
+
+            let mut remaining_throughput = throughput;
+            for flow in &agreement.flows {
+                let quantity = flow.quantity.min(remaining_throughput);
+                if quantity == 0 {
+                    continue;
+                }
+                let exchanged = quantity
+                    .checked_mul(flow.unit_price)
+                    .ok_or(TradeFlowFailure::PriceOverflow)
+                    .and_then(|price| {
+                        Self::exchange(nations, agreement, flow.resource, quantity, price)
+                            .map(|_| price)
+                    });
+                let price = match exchanged {
+                    Ok(price) => price,
+                    Err(reason) => {
+                        events.push(TradeEvent::FlowFailed {
+                            agreement: agreement.id.clone(),
+                            resource: flow.resource,
+                            reason,
+                        });
+                        continue;
+                    }
+                };
+                remaining_throughput -= quantity;
+                events.push(TradeEvent::Delivered {
+                    agreement: agreement.id.clone(),
+                    resource: flow.resource,
+                    quantity,
+                    price,
+                });
+            }
+            true
+        });
+        events
+    }
+
+    fn find_nation<'n, 'a>(nations: &'n mut [Nation<'a>], id: &SimulationID) -> &'n mut Nation<'a> {
+        nations
+            .iter_mut()
+            .find(|nation| nation.id() == id)
+            .expect("trade party must exist")
+    }
+
+    fn exchange(
+        nations: &mut [Nation],
+        agreement: &TradeAgreement,
+        resource: Resource,
+        quantity: ResourceQuantity,
+        price: ResourceQuantity,
+    ) -> Result<(), TradeFlowFailure> {
+        let importer = Self::find_nation(nations, &agreement.importer);
+        if price > 0 && !importer.resources_mut().consume(Resource::Credits, price) {
+            return Err(TradeFlowFailure::ImporterLacksCredits);
+        }
+        let exporter = Self::find_nation(nations, &agreement.exporter);
+        if !exporter.resources_mut().consume(resource, quantity) {
+            let importer = Self::find_nation(nations, &agreement.importer);
+            importer.resources_mut().replenish(Resource::Credits, price);
+            return Err(TradeFlowFailure::ExporterLacksResource);
+        }
+        exporter.resources_mut().replenish(Resource::Credits, price);
+        let importer = Self::find_nation(nations, &agreement.importer);
+        importer.resources_mut().replenish(resource, quantity);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::{
+        hex_map::{
+            coordinates::CubeCoords, layers::natural::HexMapTerrain, tile::HexMapTile, HexMap,
+            HexMapStorage,
+        },
+        simulation::{
+            ids::{SimulationID, WithSimulationID},
+            infrastructure::{
+                network::InfrastructureNetwork, Infrastructure, InfrastructureKind,
+                InfrastructureSegment,
+            },
+            nations::Nation,
+            resources::{Resource, ResourceDataStore},
+            settlements::Settlement,
+            testing::build_mock_leader,
+        },
+    };
+
+    use super::{
+        TradeAgreement, TradeAgreements, TradeEvent, TradeFlow, TradeFlowFailure, TradeRoute,
+        TradeRouteDisruption, TradeRouteDisruptions, TradeRouteKind,
+    };
+
+    /// Land strip from `q = 0` to `q = 4` (with infrastructure of the given kind from every tile to the next one),
+    /// bordered by a sea strip at `r = 1`.
+    fn build_mock_map(kind: InfrastructureKind) -> HexMap {
+        let mut tiles = HexMapStorage::new();
+        for q in 0..=4 {
+            let mut land = HexMapTile::from_properties(10);
+            if q != 4 {
+                land.layer_artificial_mut().add_infrastructure(Box::new(
+                    Infrastructure::new(
+                        SimulationID::new_map_entity_id(100 + q as u32),
+                        "segment".into(),
+                        HashMap::new(),
+                    )
+                    .with_segment(InfrastructureSegment::new(
+                        kind,
+                        CubeCoords::from_axial_coords(q, 0),
+                        CubeCoords::from_axial_coords(q + 1, 0),
+                        10,
+                    )),
+                ));
+            }
+            tiles.insert(CubeCoords::from_axial_coords(q, 0), land);
+            tiles.insert(
+                CubeCoords::from_axial_coords(q, 1),
+                HexMapTile::from_terrain(-50, HexMapTerrain::Sea),
+            );
+        }
+        HexMap::from_tiles(tiles)
+    }
+
+    #[test]
+    fn test_trade_route_planning() {
+        // only roads and rails carry land trade
+        let pipelines = build_mock_map(InfrastructureKind::Pipeline);
+        let network = InfrastructureNetwork::from_map(&pipelines);
+        let from = CubeCoords::from_axial_coords(0, 0);
+        let to = CubeCoords::from_axial_coords(4, 0);
+        assert!(
+            TradeRoute::plan(&pipelines, &network, TradeRouteKind::Land, from, to, 10).is_none()
+        );
+
+        let map = build_mock_map(InfrastructureKind::Road);
+        let mut network = InfrastructureNetwork::from_map(&map);
+        let from = CubeCoords::from_axial_coords(0, 0);
+        let to = CubeCoords::from_axial_coords(4, 0);
+
+        let land = TradeRoute::plan(&map, &network, TradeRouteKind::Land, from, to, 10).unwrap();
+        // segments are travelled both ways
+        let back = TradeRoute::plan(&map, &network, TradeRouteKind::Land, to, from, 10).unwrap();
+        assert_eq!(back.path().tiles().len(), 5);
+        assert_eq!(land.path().tiles().len(), 5);
+        assert!(land.path().tiles().iter().all(|coords| !map
+            .tile(coords)
+            .unwrap()
+            .terrain()
+            .is_water()));
+
+        let sea = TradeRoute::plan(&map, &network, TradeRouteKind::Sea, from, to, 10).unwrap();
+        assert_eq!(sea.path().start(), &from);
+        assert_eq!(sea.path().goal(), &to);
+
+        let mut disruptions = TradeRouteDisruptions::new();
+        disruptions.insert(
+            CubeCoords::from_axial_coords(2, 0),
+            TradeRouteDisruption::Blockade { strength: 50 },
+        );
+        assert_eq!(land.effective_throughput(&disruptions), 5);
+        disruptions.insert(
+            CubeCoords::from_axial_coords(3, 0),
+            TradeRouteDisruption::Captured,
+        );
+        assert_eq!(land.effective_throughput(&disruptions), 0);
+
+        // adjacent road tiles, not linked by a segment, do not carry trade
+        network.remove(&SimulationID::new_map_entity_id(102));
+        assert!(network.contains(
+            InfrastructureKind::Road,
+            &CubeCoords::from_axial_coords(2, 0)
+        ));
+        assert!(network.contains(
+            InfrastructureKind::Road,
+            &CubeCoords::from_axial_coords(3, 0)
+        ));
+        assert!(TradeRoute::plan(&map, &network, TradeRouteKind::Land, from, to, 10).is_none());
+    }
+
+    #[test]
+    fn test_trade_agreements_update() {
+        let map = build_mock_map(InfrastructureKind::Rail);
+        let capital_a = Settlement::new(
+            SimulationID::new_map_entity_id(1),
+            "A".into(),
+            CubeCoords::from_axial_coords(0, 0),
+        );
+        let capital_b = Settlement::new(
+            SimulationID::new_map_entity_id(2),
+            "B".into(),
+            CubeCoords::from_axial_coords(4, 0),
+        );
+        let nation_a_id = SimulationID::new_abstract_id("nation_a");
+        let nation_b_id = SimulationID::new_abstract_id("nation_b");
+        let mut nations = vec![
+            Nation::new(
+                nation_a_id.clone(),
+                "A".into(),
+                build_mock_leader(1, vec![]),
+                &capital_a,
+            ),
+            Nation::new(
+                nation_b_id.clone(),
+                "B".into(),
+                build_mock_leader(2, vec![]),
+                &capital_b,
+            ),
+        ];
+        nations[0].resources_mut().replenish(Resource::Oil, 100);
+        nations[1].resources_mut().replenish(Resource::Credits, 100);
+
+        let route = TradeRoute::plan(
+            &map,
+            &InfrastructureNetwork::from_map(&map),
+            TradeRouteKind::Land,
+            *capital_a.position(),
+            *capital_b.position(),
+            20,
+        )
+        .unwrap();
+        let agreement_id = SimulationID::new_entity_id(10);
+        let mut agreements = TradeAgreements::new();
+        agreements.sign(TradeAgreement::new(
+            agreement_id.clone(),
+            nation_a_id,
+            nation_b_id,
+            vec![TradeFlow::new(Resource::Oil, 10, 2)],
+            route,
+            1,
+        ));
+
+        let events = agreements.update(&mut nations, &TradeRouteDisruptions::new());
+        assert_eq!(
+            events,
+            vec![TradeEvent::Delivered {
+                agreement: agreement_id.clone(),
+                resource: Resource::Oil,
+                quantity: 10,
+                price: 20,
+            }]
+        );
+        assert_eq!(nations[0].resources().quantity_of(Resource::Oil), 90);
+        assert_eq!(nations[0].resources().quantity_of(Resource::Credits), 20);
+        assert_eq!(nations[1].resources().quantity_of(Resource::Oil), 10);
+        assert_eq!(nations[1].resources().quantity_of(Resource::Credits), 80);
+
+        let mut disruptions = TradeRouteDisruptions::new();
+        disruptions.insert(
+            CubeCoords::from_axial_coords(2, 0),
+            TradeRouteDisruption::Captured,
+        );
+        let events = agreements.update(&mut nations, &disruptions);
+        assert_eq!(
+            events,
+            vec![TradeEvent::RouteSevered {
+                agreement: agreement_id.clone(),
+                turns: 1,
+            }]
+        );
+        let events = agreements.update(&mut nations, &disruptions);
+        assert_eq!(
+            events.last(),
+            Some(&TradeEvent::AgreementBroken {
+                agreement: agreement_id.clone(),
+            })
+        );
+        assert!(agreements.get(&agreement_id).is_none());
+
+        // prices which do not fit a resource quantity are refused, rather than capped
+        let route = TradeRoute::plan(
+            &map,
+            &InfrastructureNetwork::from_map(&map),
+            TradeRouteKind::Land,
+            *capital_a.position(),
+            *capital_b.position(),
+            20,
+        )
+        .unwrap();
+        let agreement_id = SimulationID::new_entity_id(11);
+        agreements.sign(TradeAgreement::new(
+            agreement_id.clone(),
+            nations[0].id().clone(),
+            nations[1].id().clone(),
+            vec![TradeFlow::new(Resource::Oil, 10, 10_000)],
+            route,
+            1,
+        ));
+        let events = agreements.update(&mut nations, &TradeRouteDisruptions::new());
+        assert_eq!(
+            events,
+            vec![TradeEvent::FlowFailed {
+                agreement: agreement_id,
+                resource: Resource::Oil,
+                reason: TradeFlowFailure::PriceOverflow,
+            }]
+        );
+        assert_eq!(nations[0].resources().quantity_of(Resource::Oil), 90);
+        assert_eq!(nations[1].resources().quantity_of(Resource::Credits), 80);
+    }
+}
@@ -0,0 +1,565 @@
+//! Victory conditions and end of the game.
+//!
+//! Every turn, each `VictoryCondition` of the game reports the progress of every `Nation` toward it, from `0.0` to
+//! `1.0`. The game ends as soon as a nation reaches `1.0`: if several nations complete a condition on the same turn,
+//! the one with the highest score wins. The final `GameResult` ranks all the nations, the winner first, then the
+//! others by score, nations without any settlement left last.
+
+use std::{collections::HashMap, fmt::Debug};
+
+use crate::hex_map::HexMap;
+
+use super::{
+    economy::budget::BudgetAmount,
+    ids::{SimulationID, WithSimulationID},
+    nations::Nation,
+    resources::{Resource, ResourceDataStore},
+    territory::{territory_of, Territory},
+};
+
+/// Score granted per owned tile.
+pub const SCORE_PER_TILE: f64 = 1.0;
+/// Score granted per settlement.
+pub const SCORE_PER_SETTLEMENT: f64 = 10.0;
+/// Score granted per thousand inhabitants.
+pub const SCORE_PER_THOUSAND_INHABITANTS: f64 = 1.0;
+/// Score granted per hundred credits of net worth (may be negative).
+pub const SCORE_PER_HUNDRED_CREDITS: f64 = 1.0;
+
+/// State of the game a `VictoryCondition` is evaluated against.
+#[derive(Clone, Copy)]
+pub struct VictoryContext<'c, 'a> {
+    turn: u32,
+    nations: &'c [Nation<'a>],
+    map: &'c HexMap,
+    territory: &'c Territory,
+}
+
+impl<'c, 'a> VictoryContext<'c, 'a> {
+    pub fn new(
+        turn: u32,
+        nations: &'c [Nation<'a>],
+        map: &'c HexMap,
+        territory: &'c Territory,
+    ) -> Self {
+        Self {
+            turn,
+            nations,
+            map,
+            territory,
+        }
+    }
+
+    pub fn turn(&self) -> u32 {
+        self.turn
+    }
+
+    pub fn nations(&self) -> &'c [Nation<'a>] {
+        self.nations
+    }
+
+    pub fn map(&self) -> &'c HexMap {
+        self.map
+    }
+
+    pub fn territory(&self) -> &'c Territory {
+        self.territory
+    }
+}
+
+/// A way to win the game.
+pub trait VictoryCondition: Debug {
+    /// Shown to the players, and reported in the `GameResult`.
+    fn name(&self) -> &str;
+    /// Progress of a nation toward the victory, from `0.0` to `1.0` (won).
+    fn progress(&self, nation: &Nation, context: &VictoryContext) -> f64;
+}
+
+/// Hold the original capitals of all the other nations.
+#[derive(Debug)]
+pub struct CapitalConquest {
+    /// Keys are the `SimulationID`s of the `Nation`s, values the ones of their original capitals.
+    capitals: HashMap<SimulationID, SimulationID>,
+}
+
+impl CapitalConquest {
+    /// Record the current capitals of the nations as the ones to conquer.
+    pub fn new(nations: &[Nation]) -> Self {
+        Self {
+            capitals: nations
+                .iter()
+                .filter_map(|nation| {
+                    nation
+                        .capital()
+                        .map(|capital| (nation.id().clone(), capital.id().clone()))
+                })
+                .collect(),
+        }
+    }
+}
+
+impl VictoryCondition for CapitalConquest {
+    fn name(&self) -> &str {
+        "capital conquest"
+    }
+
+    fn progress(&self, nation: &Nation, _context: &VictoryContext) -> f64 {
+        let targets: Vec<&SimulationID> = self
+            .capitals
+            .iter()
+            .filter(|(owner, _)| *owner != nation.id())
+            .map(|(_, capital)| capital)
+            .collect();
+        if targets.is_empty() {
+            return 0.0;
+        }
+        let held = targets
+            .iter()
+            .filter(|capital| {
+                nation
+                    .settlements()
+                    .iter()
+                    .any(|settlement| settlement.id() == **capital)
+            })
+            .count();
+        held as f64 / targets.len() as f64
+    }
+}
+
+/// Own a share of the tiles of the map.
+#[derive(Debug)]
+pub struct TerritoryControl {
+    /// Between `0.0` (excluded) and `1.0`.
+    share: f64,
+}
+
+impl TerritoryControl {
+    pub fn new(share: f64) -> Self {
+        assert!(share > 0.0 && share <= 1.0);
+        Self { share }
+    }
+}
+
+impl VictoryCondition for TerritoryControl {
+    fn name(&self) -> &str {
+        "territory control"
+    }
+
+    fn progress(&self, nation: &Nation, context: &VictoryContext) -> f64 {
+        let tiles = context.map().tiles().count();
+        if tiles == 0 {
+            return 0.0;
+        }
+        let owned = territory_of(context.territory(), nation.id()).len();
+        (owned as f64 / tiles as f64 / self.share).min(1.0)
+    }
+}
+
+/// Accumulate a net worth: the treasury minus the debt.
+#[derive(Debug)]
+pub struct NetWorth {
+    /// Strictly positive.
+    target: BudgetAmount,
+}
+
+impl NetWorth {
+    pub fn new(target: BudgetAmount) -> Self {
+        assert!(target > 0);
+        Self { target }
+    }
+}
+
+impl VictoryCondition for NetWorth {
+    fn name(&self) -> &str {
+        "net worth"
+    }
+
+    fn progress(&self, nation: &Nation, _context: &VictoryContext) -> f64 {
+        (net_worth(nation) as f64 / self.target as f64).clamp(0.0, 1.0)
+    }
+}
+
+/// Survive a number of turns: the nation with the highest score then wins.
+#[derive(Debug)]
+pub struct ScoreAfterTurns {
+    /// Strictly positive.
+    turns: u32,
+}
+
+impl ScoreAfterTurns {
+    pub fn new(turns: u32) -> Self {
+        assert!(turns > 0);
+        Self { turns }
+    }
+}
+
+impl VictoryCondition for ScoreAfterTurns {
+    fn name(&self) -> &str {
+        "score"
+    }
+
+    fn progress(&self, nation: &Nation, context: &VictoryContext) -> f64 {
+        if nation.settlements().is_empty() {
+            return 0.0;
+        }
+        (context.turn() as f64 / self.turns as f64).min(1.0)
+    }
+}
+
+/// Signature of the progress of a `ScriptedVictory`.
+pub type ScriptedProgress = Box<dyn Fn(&Nation, &VictoryContext) -> f64>;
+
+/// Condition defined by a script (scenario, mod...).
+pub struct ScriptedVictory {
+    name: String,
+    progress: ScriptedProgress,
+}
+
+impl ScriptedVictory {
+    pub fn new(name: String, progress: ScriptedProgress) -> Self {
+        Self { name, progress }
+    }
+}
+
+impl Debug for ScriptedVictory {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ScriptedVictory")
+            .field("name", &self.name)
+            .finish_non_exhaustive()
+    }
+}
+
+impl VictoryCondition for ScriptedVictory {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn progress(&self, nation: &Nation, context: &VictoryContext) -> f64 {
+        (self.progress)(nation, context).clamp(0.0, 1.0)
+    }
+}
+
+/// Treasury minus debt.
+pub fn net_worth(nation: &Nation) -> BudgetAmount {
+    nation.resources().quantity_of(Resource::Credits) as BudgetAmount - nation.budget().debt()
+}
+
+/// Overall standing of a nation, used to rank the nations and to break ties between winners.
+pub fn nation_score(nation: &Nation, territory: &Territory) -> f64 {
+    let inhabitants: u64 = nation
+        .settlements()
+        .iter()
+        .map(|settlement| settlement.inhabitants())
+        .sum();
+    territory_of(territory, nation.id()).len() as f64 * SCORE_PER_TILE
+        + nation.settlements().len() as f64 * SCORE_PER_SETTLEMENT
+        + inhabitants as f64 / 1000.0 * SCORE_PER_THOUSAND_INHABITANTS
+        + net_worth(nation) as f64 / 100.0 * SCORE_PER_HUNDRED_CREDITS
+}
+
+/// Progress of a nation toward a condition.
+#[derive(Clone, Debug, PartialEq)]
+pub struct VictoryProgress {
+    /// Must be the `SimulationID` of a `Nation`.
+    nation: SimulationID,
+    /// Name of the `VictoryCondition`.
+    condition: String,
+    /// From `0.0` to `1.0`.
+    progress: f64,
+}
+
+impl VictoryProgress {
+    pub fn nation(&self) -> &SimulationID {
+        &self.nation
+    }
+
+    pub fn condition(&self) -> &str {
+        &self.condition
+    }
+
+    pub fn progress(&self) -> f64 {
+        self.progress
+    }
+}
+
+/// Place of a nation at the end of the game.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Ranking {
+    /// Must be the `SimulationID` of a `Nation`.
+    nation: SimulationID,
+    score: f64,
+    /// Whether the nation still had a settlement.
+    alive: bool,
+}
+
+impl Ranking {
+    pub fn nation(&self) -> &SimulationID {
+        &self.nation
+    }
+
+    pub fn score(&self) -> f64 {
+        self.score
+    }
+
+    pub fn alive(&self) -> bool {
+        self.alive
+    }
+}
+
+/// Outcome of the game.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GameResult {
+    turn: u32,
+    /// Must be the `SimulationID` of a `Nation`.
+    winner: SimulationID,
+    /// Name of the fulfilled `VictoryCondition`.
+    condition: String,
+    /// Best first, starting with the winner.
+    rankings: Vec<Ranking>,
+}
+
+impl GameResult {
+    pub fn turn(&self) -> u32 {
+        self.turn
+    }
+
+    pub fn winner(&self) -> &SimulationID {
+        &self.winner
+    }
+
+    pub fn condition(&self) -> &str {
+        &self.condition
+    }
+
+    pub fn rankings(&self) -> &[Ranking] {
+        &self.rankings
+    }
+}
+
+/// Progress of all the nations for a turn, and the result of the game if it ended.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct VictoryReport {
+    /// By nation, then by condition, in the order of the game.
+    progress: Vec<VictoryProgress>,
+    result: Option<GameResult>,
+}
+
+impl VictoryReport {
+    pub fn progress(&self) -> &[VictoryProgress] {
+        &self.progress
+    }
+
+    /// Progress of a nation toward every condition.
+    pub fn progress_of<'r>(
+        &'r self,
+        nation: &'r SimulationID,
+    ) -> impl Iterator<Item = &'r VictoryProgress> + 'r {
+        self.progress
+            .iter()
+            .filter(move |progress| progress.nation() == nation)
+    }
+
+    pub fn result(&self) -> Option<&GameResult> {
+        self.result.as_ref()
+    }
+
+    pub fn is_game_over(&self) -> bool {
+        self.result.is_some()
+    }
+}
+
+/// Victory conditions of a game.
+#[derive(Debug, Default)]
+pub struct VictoryConditions {
+    conditions: Vec<Box<dyn VictoryCondition>>,
+}
+
+impl VictoryConditions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_condition(mut self, condition: Box<dyn VictoryCondition>) -> Self {
+        self.conditions.push(condition);
+        self
+    }
+
+    pub fn conditions(&self) -> &[Box<dyn VictoryCondition>] {
+        &self.conditions
+    }
+
+    /// Called every turn, once everything else is updated. Report the progress of the nations, and end the game if
+    /// one of them fulfilled a condition.
+    pub fn update(&self, context: &VictoryContext) -> VictoryReport {
+        let mut report = VictoryReport::default();
+        // (nation index, condition index) of every fulfilled condition
+        let mut fulfilled: Vec<(usize, usize)> = vec![];
+        for (nation_index, nation) in context.nations().iter().enumerate() {
+            for (condition_index, condition) in self.conditions.iter().enumerate() {
+                let progress = condition.progress(nation, context).clamp(0.0, 1.0);
+                if progress >= 1.0 {
+                    fulfilled.push((nation_index, condition_index));
+                }
+                report.progress.push(VictoryProgress {
+                    nation: nation.id().clone(),
+                    condition: condition.name().into(),
+                    progress,
+                });
+            }
+        }
+        if fulfilled.is_empty() {
+            return report;
+        }
+
+        let scores: Vec<f64> = context
+            .nations()
+            .iter()
+            .map(|nation| nation_score(nation, context.territory()))
+            .collect();
+        // the first best score wins
+        let (winner, condition) = fulfilled
+            .into_iter()
+            .reduce(|best, candidate| {
+                if scores[candidate.0] > scores[best.0] {
+                    candidate
+                } else {
+                    best
+                }
+            })
+            .unwrap();
+
+        let mut rankings: Vec<(usize, Ranking)> = context
+            .nations()
+            .iter()
+            .enumerate()
+            .map(|(index, nation)| {
+                (
+                    index,
+                    Ranking {
+                        nation: nation.id().clone(),
+                        score: scores[index],
+                        alive: !nation.settlements().is_empty(),
+                    },
+                )
+            })
+            .collect();
+        rankings.sort_by(|(a_index, a), (b_index, b)| {
+            (*b_index == winner)
+                .cmp(&(*a_index == winner))
+                .then(b.alive.cmp(&a.alive))
+                .then(b.score.total_cmp(&a.score))
+        });
+
+        report.result = Some(GameResult {
+            turn: context.turn(),
+            winner: context.nations()[winner].id().clone(),
+            condition: self.conditions[condition].name().into(),
+            rankings: rankings.into_iter().map(|(_, ranking)| ranking).collect(),
+        });
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        hex_map::{
+            coordinates::CubeCoords, layers::natural::HexMapTerrain, tile::HexMapTile, HexMap,
+            HexMapStorage,
+        },
+        simulation::{
+            ids::{SimulationID, WithSimulationID},
+            nations::Nation,
+            resources::{Resource, ResourceDataStore},
+            settlements::Settlement,
+            territory::Territory,
+            testing::build_mock_leader,
+        },
+    };
+
+    use super::{
+        CapitalConquest, NetWorth, ScoreAfterTurns, ScriptedVictory, VictoryConditions,
+        VictoryContext,
+    };
+
+    #[test]
+    fn test_victory_conditions() {
+        let mut tiles = HexMapStorage::new();
+        for q in 0..=4 {
+            tiles.insert(
+                CubeCoords::from_axial_coords(q, 0),
+                HexMapTile::from_terrain(0, HexMapTerrain::Plains),
+            );
+        }
+        let map = HexMap::from_tiles(tiles);
+        let territory = Territory::new();
+        let tile = CubeCoords::from_axial_coords;
+        let capitals = [
+            Settlement::new(SimulationID::new_map_entity_id(1), "A".into(), tile(0, 0)),
+            Settlement::new(SimulationID::new_map_entity_id(2), "B".into(), tile(2, 0)),
+            Settlement::new(SimulationID::new_map_entity_id(3), "C".into(), tile(4, 0)),
+        ];
+        let mut nations = ["a", "b", "c"]
+            .into_iter()
+            .zip(&capitals)
+            .enumerate()
+            .map(|(index, (id, capital))| {
+                Nation::new(
+                    SimulationID::new_abstract_id(id),
+                    id.into(),
+                    build_mock_leader(index as u32, vec![]),
+                    capital,
+                )
+            })
+            .collect::<Vec<_>>();
+        let [a, b, c] = ["a", "b", "c"].map(SimulationID::new_abstract_id);
+        let conditions = VictoryConditions::new()
+            .with_condition(Box::new(CapitalConquest::new(&nations)))
+            .with_condition(Box::new(NetWorth::new(1000)))
+            .with_condition(Box::new(ScoreAfterTurns::new(100)))
+            .with_condition(Box::new(ScriptedVictory::new(
+                "last one standing".into(),
+                Box::new(|nation, context| {
+                    let alive = context
+                        .nations()
+                        .iter()
+                        .filter(|other| !other.settlements().is_empty())
+                        .count();
+                    if alive == 1 && !nation.settlements().is_empty() {
+                        1.0
+                    } else {
+                        0.0
+                    }
+                }),
+            )));
+
+        // a takes the capital of b and amasses half the target
+        let captured = nations[1].cede_settlement(capitals[1].id()).unwrap();
+        nations[0].annex_settlement(captured);
+        nations[0].resources_mut().replenish(Resource::Credits, 500);
+        let report = conditions.update(&VictoryContext::new(50, &nations, &map, &territory));
+        assert!(!report.is_game_over());
+        let progress_of_a: Vec<f64> = report.progress_of(&a).map(|p| p.progress()).collect();
+        assert_eq!(progress_of_a, vec![0.5, 0.5, 0.5, 0.0]);
+        let progress_of_b: Vec<f64> = report.progress_of(&b).map(|p| p.progress()).collect();
+        assert_eq!(progress_of_b, vec![0.0, 0.0, 0.0, 0.0]);
+
+        // turn limit: a and c both reach it, a has the best score
+        let report = conditions.update(&VictoryContext::new(100, &nations, &map, &territory));
+        let result = report.result().unwrap();
+        assert_eq!(result.winner(), &a);
+        assert_eq!(result.condition(), "score");
+        let ranked: Vec<_> = result.rankings().iter().map(|r| r.nation()).collect();
+        assert_eq!(ranked, vec![&a, &c, &b]);
+        assert!(!result.rankings()[2].alive());
+
+        // c falls: a wins by conquest, before the scripted condition
+        let captured = nations[2].cede_settlement(capitals[2].id()).unwrap();
+        nations[0].annex_settlement(captured);
+        let report = conditions.update(&VictoryContext::new(60, &nations, &map, &territory));
+        let result = report.result().unwrap();
+        assert_eq!(result.winner(), &a);
+        assert_eq!(result.condition(), "capital conquest");
+        assert_eq!(result.rankings()[0].score(), 30.0 + 5.0);
+    }
+}
@@ -0,0 +1,65 @@
+//! Weather over the `HexMap`, affecting military units (attrition, movement...).
+
+use std::collections::HashMap;
+
+use crate::hex_map::coordinates::CubeCoords;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Weather {
+    #[default]
+    Clear,
+    Rain,
+    Snow,
+    Storm,
+    Heatwave,
+}
+
+impl Weather {
+    /// Multiplier of the attrition suffered by units out of supply.
+    pub fn attrition_multiplier(&self) -> f64 {
+        match self {
+            Self::Clear => 1.0,
+            Self::Rain => 1.25,
+            Self::Heatwave => 1.5,
+            Self::Snow => 1.75,
+            Self::Storm => 2.0,
+        }
+    }
+}
+
+/// Weather of every tile of the map for the current turn.
+#[derive(Debug, Default)]
+pub struct WeatherMap {
+    /// Weather of the tiles without a specific one.
+    prevailing: Weather,
+    local: HashMap<CubeCoords, Weather>,
+}
+
+impl WeatherMap {
+    pub fn new(prevailing: Weather) -> Self {
+        Self {
+            prevailing,
+            local: HashMap::new(),
+        }
+    }
+
+    pub fn prevailing(&self) -> Weather {
+        self.prevailing
+    }
+
+    pub fn set_prevailing(&mut self, weather: Weather) {
+        self.prevailing = weather;
+    }
+
+    pub fn set_local(&mut self, position: CubeCoords, weather: Weather) {
+        self.local.insert(position, weather);
+    }
+
+    pub fn clear_local(&mut self) {
+        self.local.clear();
+    }
+
+    pub fn weather_at(&self, position: &CubeCoords) -> Weather {
+        self.local.get(position).copied().unwrap_or(self.prevailing)
+    }
+}